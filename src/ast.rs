@@ -1,7 +1,12 @@
+use std::borrow::Cow;
 use std::fmt;
+use crate::datetime::format_datetime;
+use crate::filesystem::FileStat;
+use std::time::{Duration, SystemTime};
+use regex::Regex;
 
 /// The AST for the find command parser
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Expr {
     /// A unary expression with a not operator
     Not(Box<Expr>),
@@ -11,10 +16,257 @@ pub enum Expr {
     Or(Box<Expr>, Box<Expr>),
     /// A test expression
     Test(Test),
+    /// An action expression
+    Action(Action),
+}
+
+impl Expr {
+    /// Returns true if the expression contains an action, which suppresses the implicit -print.
+    /// As in GNU find, -prune and -quit don't count, since they print nothing themselves.
+    /// The implicit -print isn't part of the expression: it prints every entry the whole
+    /// expression matches, as if the expression were `\( expr \) -print`
+    pub fn has_action(&self) -> bool {
+        match self {
+            Expr::Not(inner) => inner.has_action(),
+            Expr::And(left, right) | Expr::Or(left, right) => left.has_action() || right.has_action(),
+            Expr::Test(_) | Expr::Action(Action::Prune | Action::Quit) => false,
+            Expr::Action(_) => true,
+        }
+    }
+
+    /// Returns the -maxdepth limit for the walk; if it is given more than once the last wins
+    pub fn max_depth(&self) -> Option<usize> {
+        match self {
+            Expr::Not(inner) => inner.max_depth(),
+            Expr::And(left, right) | Expr::Or(left, right) => right.max_depth().or(left.max_depth()),
+            Expr::Test(Test::MaxDepth(depth)) => Some(*depth),
+            Expr::Test(_) | Expr::Action(_) => None,
+        }
+    }
+
+    /// Returns true if the expression contains -delete. As in GNU find that makes the
+    /// walk visit each directory's contents before the directory itself, so a directory
+    /// is emptied before -delete gets to it. By then -prune is too late to skip anything,
+    /// so the two together are refused rather than deleting what -prune was to protect
+    pub fn deletes(&self) -> bool {
+        match self {
+            Expr::Not(inner) => inner.deletes(),
+            Expr::And(left, right) | Expr::Or(left, right) => left.deletes() || right.deletes(),
+            Expr::Action(Action::Delete) => true,
+            Expr::Test(_) | Expr::Action(_) => false,
+        }
+    }
+
+    /// Returns true if the expression contains -prune
+    pub fn prunes(&self) -> bool {
+        match self {
+            Expr::Not(inner) => inner.prunes(),
+            Expr::And(left, right) | Expr::Or(left, right) => left.prunes() || right.prunes(),
+            Expr::Action(Action::Prune) => true,
+            Expr::Test(_) | Expr::Action(_) => false,
+        }
+    }
+
+    /// Folds away constants that can't change the result: `X -a -true` and
+    /// `-true -a X` become `X`, `X -o -false` and `-false -o X` become `X`, and
+    /// `-not -not X` becomes `X`. `-name '*'` and `-iname '*'` match every basename,
+    /// dotfiles included, so they fold like `-true`. Every remaining test and action
+    /// is still evaluated exactly when it would have been, so side effects are unchanged.
+    ///
+    /// Constants that decide the result fold too: `-false -a X` is `-false` and
+    /// `-true -o X` is `-true`, since X never runs, and `-not` of a constant is the
+    /// other one. `X -a -false` and `X -o -true` fold only if X has no side effects,
+    /// i.e. no actions, -maxdepth or custom predicates, and can't report an error the
+    /// way -empty does for a directory it can't list.
+    pub fn simplify(self) -> Expr {
+        match self {
+            Expr::Not(inner) => match inner.simplify() {
+                Expr::Not(inner) => *inner,
+                Expr::Test(Test::True) => Expr::Test(Test::False),
+                Expr::Test(Test::False) => Expr::Test(Test::True),
+                inner => Expr::Not(Box::new(inner)),
+            },
+            Expr::And(left, right) => match (left.simplify(), right.simplify()) {
+                (Expr::Test(Test::True), other) | (other, Expr::Test(Test::True)) => other,
+                (Expr::Test(Test::False), _) => Expr::Test(Test::False),
+                (left, Expr::Test(Test::False)) if left.can_be_skipped() => Expr::Test(Test::False),
+                (left, right) => Expr::And(Box::new(left), Box::new(right)),
+            },
+            Expr::Or(left, right) => match (left.simplify(), right.simplify()) {
+                (Expr::Test(Test::False), other) | (other, Expr::Test(Test::False)) => other,
+                (Expr::Test(Test::True), _) => Expr::Test(Test::True),
+                (left, Expr::Test(Test::True)) if left.can_be_skipped() => Expr::Test(Test::True),
+                (left, right) => Expr::Or(Box::new(left), Box::new(right)),
+            },
+            Expr::Test(Test::Name(ref pattern) | Test::Iname(ref pattern)) if pattern == "*" => {
+                Expr::Test(Test::True)
+            }
+            Expr::Test(_) | Expr::Action(_) => self,
+        }
+    }
+
+    /// Returns true if evaluating the expression may stat an entry or otherwise ask the
+    /// file system about it. When false, tests and actions make do with the path and
+    /// with what the walk learned reading the directory, which on most systems includes
+    /// the file type, so e.g. `-name '*.rs' -print` stats nothing.
+    pub fn needs_metadata(&self) -> bool {
+        match self {
+            Expr::Not(inner) => inner.needs_metadata(),
+            Expr::And(left, right) | Expr::Or(left, right) => left.needs_metadata() || right.needs_metadata(),
+            Expr::Test(test) => test.needs_metadata(),
+            Expr::Action(Action::Printf(directives)) => directives.iter().any(FormatDirective::needs_metadata),
+            Expr::Action(Action::Print | Action::Prune | Action::Quit | Action::Exec(_) | Action::Delete) => false,
+            Expr::Action(Action::Ls) => true,
+        }
+    }
+
+    /// Reorders the operands of each `-a` chain so cheaper tests run first, letting the
+    /// short-circuit skip expensive tests for entries a cheap one already ruled out. The
+    /// costs are the tiers in `Test::cost`; a `-not`, `-a` or `-o` costs as much as the
+    /// tests inside it together, and operands of equal cost keep their order.
+    ///
+    /// Only side-effect-free operands are moved. Actions (-prune included), -maxdepth,
+    /// -empty and custom predicates, or anything containing them, stay where they are,
+    /// and nothing is moved across them, so each action still sees exactly the entries
+    /// the tests written before it let through, and -empty reports the same errors.
+    pub fn optimize(self) -> Expr {
+        match self {
+            Expr::And(..) => {
+                let mut operands = Vec::new();
+                self.and_operands(&mut operands);
+                let mut operands: Vec<Expr> = operands.into_iter().map(Expr::optimize).collect();
+                for movable in operands.split_mut(|operand| operand.cost().is_none()) {
+                    movable.sort_by_key(Expr::cost);
+                }
+                Expr::chain(operands, Expr::And)
+            }
+            Expr::Or(left, right) => Expr::Or(Box::new(left.optimize()), Box::new(right.optimize())),
+            Expr::Not(inner) => Expr::Not(Box::new(inner.optimize())),
+            Expr::Test(_) | Expr::Action(_) => self,
+        }
+    }
+
+    /// Joins operands with `operator`, -a or -o, into a tree as shallow as it can be,
+    /// keeping their order, so `a b c` becomes `(a b) c` and a chain of n is only about
+    /// log2(n) levels deep. Both operators are associative, so the shape changes neither
+    /// the result nor the order operands are evaluated in.
+    ///
+    /// # Panics
+    ///
+    /// If there are no operands.
+    pub fn chain(mut operands: Vec<Expr>, operator: fn(Box<Expr>, Box<Expr>) -> Expr) -> Expr {
+        if operands.len() == 1 {
+            return operands.pop().unwrap();
+        }
+        assert!(!operands.is_empty(), "a chain needs at least one operand");
+        let right = operands.split_off(operands.len().div_ceil(2));
+        operator(Box::new(Expr::chain(operands, operator)), Box::new(Expr::chain(right, operator)))
+    }
+
+    // Collects the operands of nested -a expressions in evaluation order
+    fn and_operands(self, operands: &mut Vec<Expr>) {
+        match self {
+            Expr::And(left, right) => {
+                left.and_operands(operands);
+                right.and_operands(operands);
+            }
+            other => operands.push(other),
+        }
+    }
+
+    // Whether leaving the expression unevaluated makes no difference other than to its
+    // result: it has no side effects and reports no errors
+    fn can_be_skipped(&self) -> bool {
+        match self {
+            Expr::Not(inner) => inner.can_be_skipped(),
+            Expr::And(left, right) | Expr::Or(left, right) => left.can_be_skipped() && right.can_be_skipped(),
+            Expr::Test(test) => test.cost().is_some(),
+            Expr::Action(_) => false,
+        }
+    }
+
+    // Estimated cost of evaluating the expression, or None if it must not be moved
+    fn cost(&self) -> Option<u32> {
+        match self {
+            Expr::Not(inner) => inner.cost(),
+            Expr::And(left, right) | Expr::Or(left, right) => Some(left.cost()? + right.cost()?),
+            Expr::Test(test) => test.cost(),
+            Expr::Action(_) => None,
+        }
+    }
+}
+
+/// Action expressions for the find command
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    /// Print the entry using a format string
+    Printf(Vec<FormatDirective>),
+    /// Print the entry's path followed by a newline
+    Print,
+    /// Don't descend into the entry if it is a directory; always true
+    Prune,
+    /// List the entry in `ls -dils` format
+    Ls,
+    /// Stop the whole run, after this entry's earlier actions; always true
+    Quit,
+    /// Run a command, given as its name and arguments with every `{}` in them standing
+    /// for the path; true if it exits successfully
+    Exec(Vec<String>),
+    /// Remove the entry, a directory only if it is empty; true if it was removed
+    Delete,
+}
+
+/// A parsed piece of a -printf format string
+#[derive(Debug, Clone, PartialEq)]
+pub enum FormatDirective {
+    /// Literal text, with escapes already resolved
+    Literal(String),
+    /// File path (%p)
+    Path,
+    /// File path with the start path it was found under removed (%P)
+    RelativePath,
+    /// Start path the file was found under (%H)
+    StartPath,
+    /// File name with leading directories removed (%f)
+    Basename,
+    /// Leading directories of the file name (%h)
+    Dirname,
+    /// File size in bytes, or in 1024-based units such as 1.5K with --human-sizes (%s)
+    Size,
+    /// Space allocated divided by size, below 1 for sparse files (%S)
+    Sparseness,
+    /// Depth in the directory tree (%d)
+    Depth,
+    /// Inode number (%i)
+    Inode,
+    /// Number of hard links (%n)
+    LinkCount,
+    /// Type of the file as a -type letter (%y)
+    Type,
+    /// Type of the file with symlinks followed, "?" for broken links (%Y)
+    TargetType,
+    /// One of the file's timestamps in ctime(3) format, e.g. `Wed Jun 30 21:49:08 2021`
+    /// (%a, %c and %t)
+    Time(TimeField),
+}
+
+impl FormatDirective {
+    /// Whether rendering the directive stats the file
+    pub fn needs_metadata(&self) -> bool {
+        matches!(
+            self,
+            FormatDirective::Size
+                | FormatDirective::Sparseness
+                | FormatDirective::Inode
+                | FormatDirective::LinkCount
+                | FormatDirective::TargetType
+                | FormatDirective::Time(_)
+        )
+    }
 }
 
 /// Test expressions for the find command
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Test {
     /// Match paths by name (case-sensitive)
     Path(String),
@@ -23,7 +275,7 @@ pub enum Test {
     /// Match files by name (case-insensitive)
     Iname(String),
     /// Match files by regex pattern
-    Regex(String),
+    Regex(RegexPattern),
     /// Always evaluates to true
     True,
     /// Always evaluates to false
@@ -34,30 +286,45 @@ pub enum Test {
     Size(SizeSpec),
     /// Match files by size
     Empty,
+    /// Match files the current user may read, as access(2) decides
+    Readable,
+    /// Match files the current user may write to
+    Writable,
+    /// Match files the current user may execute, and directories they may search
+    Executable,
     /// Match files by access time in minutes
     Amin(TimeSpec),
     /// Match files by access time in days
     Atime(TimeSpec),
-    /// Match files by creation time in days
+    /// Match files by status change time in days
     Ctime(TimeSpec),
-    /// Match files by creation time in minutes
+    /// Match files by status change time in minutes
     Cmin(TimeSpec),
     /// Match files by modification time in minutes
     Mmin(TimeSpec),
     /// Match files by modification time in days
     Mtime(TimeSpec),
+    /// Match files last accessed the given number of days after their status changed,
+    /// by the same change time -ctime reads
+    Used(TimeSpec),
     /// Match files accessed more recently than the reference file
-    Anewer(String),
-    /// Match files created more recently than the reference file
-    Cnewer(String),
+    Anewer(ReferenceFile),
+    /// Match files whose status changed more recently than the reference file was modified
+    Cnewer(ReferenceFile),
     /// Match files modified more recently than the reference file
-    Mnewer(String),
+    Mnewer(ReferenceFile),
     /// Match files modified more recently than the reference file (alias for Mnewer)
-    Newer(String),
+    Newer(ReferenceFile),
+    /// Match files accessed after the given time (-newerat)
+    NewerAt(SystemTime),
+    /// Match files whose status changed after the given time (-newerct)
+    NewerCt(SystemTime),
+    /// Match files modified after the given time (-newermt)
+    NewerMt(SystemTime),
     /// Match paths by glob pattern (case-insensitive)
     Ipath(String),
     /// Match files by regex pattern (case-insensitive)
-    Iregex(String),
+    Iregex(RegexPattern),
     /// Match files by owner username or UID
     User(String),
     /// Match files by group name or GID
@@ -66,12 +333,243 @@ pub enum Test {
     Uid(u32),
     /// Match files by numeric GID
     Gid(u32),
+    /// Match files by their number of hard links. On most Unix file systems a directory
+    /// has two plus one for each subdirectory, so `-links 2` finds directories without
+    /// subdirectories, but btrfs and some others give every directory one
+    Links(CountSpec),
     /// Match files by permissions
     Perm(PermSpec),
+    /// Match regular files whose content sniffs as the given MIME type
+    Magic(String),
+    /// Match regular text files with a line matching the regex anywhere in it
+    Grep(RegexPattern),
+    /// Match directories by the total size of the files below them
+    TreeSize(SizeSpec),
+    /// Descend at most this many levels below the start paths; always true
+    MaxDepth(usize),
+    /// Match entries exactly this many levels below their start path
+    AtDepth(usize),
+    /// Match hard links to the reference file: entries with its device and inode
+    SameFile(ReferenceFile),
+    /// Match using a predicate registered under this name
+    Custom(String),
 }
 
-/// File types for the -type test
+impl Test {
+    /// The predicate as written on the command line, e.g. "-name"
+    pub fn name(&self) -> &'static str {
+        match self {
+            Test::Path(_) => "-path",
+            Test::Name(_) => "-name",
+            Test::Iname(_) => "-iname",
+            Test::Regex(_) => "-regex",
+            Test::True => "-true",
+            Test::False => "-false",
+            Test::Type(_) => "-type",
+            Test::Size(_) => "-size",
+            Test::Empty => "-empty",
+            Test::Readable => "-readable",
+            Test::Writable => "-writable",
+            Test::Executable => "-executable",
+            Test::Amin(_) => "-amin",
+            Test::Atime(_) => "-atime",
+            Test::Used(_) => "-used",
+            Test::Ctime(_) => "-ctime",
+            Test::Cmin(_) => "-cmin",
+            Test::Mmin(_) => "-mmin",
+            Test::Mtime(_) => "-mtime",
+            Test::Anewer(_) => "-anewer",
+            Test::Cnewer(_) => "-cnewer",
+            Test::Mnewer(_) => "-mnewer",
+            Test::Newer(_) => "-newer",
+            Test::NewerAt(_) => "-newerat",
+            Test::NewerCt(_) => "-newerct",
+            Test::NewerMt(_) => "-newermt",
+            Test::Ipath(_) => "-ipath",
+            Test::Iregex(_) => "-iregex",
+            Test::User(_) => "-user",
+            Test::Group(_) => "-group",
+            Test::Uid(_) => "-uid",
+            Test::Gid(_) => "-gid",
+            Test::Links(_) => "-links",
+            Test::Perm(_) => "-perm",
+            Test::Magic(_) => "-magic",
+            Test::Grep(_) => "-grep",
+            Test::TreeSize(_) => "-treesize",
+            Test::MaxDepth(_) => "-maxdepth",
+            Test::AtDepth(_) => "-atdepth",
+            Test::SameFile(_) => "-samefile",
+            Test::Custom(_) => "-custom",
+        }
+    }
+
+    /// The argument the test was given, as it could be written on the command line, or
+    /// None for tests that take none. -newerXt times are given back in the form
+    /// `format_datetime` writes, whatever form they were written in
+    fn argument(&self) -> Option<String> {
+        match self {
+            Test::Path(pattern)
+            | Test::Name(pattern)
+            | Test::Iname(pattern)
+            | Test::Ipath(pattern)
+            | Test::Magic(pattern)
+            | Test::User(pattern)
+            | Test::Group(pattern)
+            | Test::Custom(pattern) => Some(pattern.clone()),
+            Test::Regex(pattern) | Test::Iregex(pattern) | Test::Grep(pattern) => Some(pattern.source.clone()),
+            Test::Type(file_type) => Some(file_type.to_string()),
+            Test::Size(spec) | Test::TreeSize(spec) => Some(spec.to_string()),
+            Test::Amin(spec)
+            | Test::Atime(spec)
+            | Test::Used(spec)
+            | Test::Ctime(spec)
+            | Test::Cmin(spec)
+            | Test::Mmin(spec)
+            | Test::Mtime(spec) => Some(spec.to_string()),
+            Test::Anewer(reference)
+            | Test::Cnewer(reference)
+            | Test::Mnewer(reference)
+            | Test::Newer(reference)
+            | Test::SameFile(reference) => Some(reference.path.clone()),
+            Test::NewerAt(time) | Test::NewerCt(time) | Test::NewerMt(time) => {
+                Some(format_datetime(*time).unwrap_or_else(|| "?".to_string()))
+            }
+            Test::Uid(id) | Test::Gid(id) => Some(id.to_string()),
+            Test::Links(spec) => Some(spec.to_string()),
+            Test::Perm(spec) => Some(spec.to_string()),
+            Test::MaxDepth(depth) | Test::AtDepth(depth) => Some(depth.to_string()),
+            Test::True | Test::False | Test::Empty | Test::Readable | Test::Writable | Test::Executable => None,
+        }
+    }
+
+    /// Whether the test stats the entry or otherwise asks the file system about it. Only
+    /// -type f and -type d stat among the -type tests, since the walk can't tell a
+    /// followed symlink to a file from the file itself
+    pub fn needs_metadata(&self) -> bool {
+        match self {
+            Test::True
+            | Test::False
+            | Test::Name(_)
+            | Test::Iname(_)
+            | Test::Path(_)
+            | Test::Ipath(_)
+            | Test::Regex(_)
+            | Test::Iregex(_)
+            | Test::MaxDepth(_)
+            | Test::AtDepth(_) => false,
+            Test::Type(file_type) => matches!(file_type, FileType::RegularFile | FileType::Directory),
+            _ => true,
+        }
+    }
+
+    /// Rough relative cost of evaluating the test once, used by `Expr::optimize`:
+    ///
+    /// - 0: constants and depth checks, which need nothing but the entry
+    /// - 1: -name, -iname and -type, a glob on the basename or a look at the file type
+    /// - 2: -path and -ipath, a glob over the whole path
+    /// - 3: -regex and -iregex, a regex over the whole path
+    /// - 4: tests of size, times, permissions, access, owner ids and file identity, one
+    ///   stat or access(2) call each
+    /// - 5: -user and -group, a stat plus a user database lookup
+    /// - 6: -magic, -grep and -treesize, which read file contents or a whole subtree
+    ///
+    /// None for -maxdepth, which applies to the walk wherever it appears, for -empty,
+    /// which reports the directories it can't list, so the tests before it decide which
+    /// are reported, and for custom predicates, which may do anything.
+    fn cost(&self) -> Option<u32> {
+        match self {
+            Test::True | Test::False | Test::AtDepth(_) => Some(0),
+            Test::Name(_) | Test::Iname(_) | Test::Type(_) => Some(1),
+            Test::Path(_) | Test::Ipath(_) => Some(2),
+            Test::Regex(_) | Test::Iregex(_) => Some(3),
+            Test::Size(_)
+            | Test::Amin(_)
+            | Test::Atime(_)
+            | Test::Used(_)
+            | Test::Ctime(_)
+            | Test::Cmin(_)
+            | Test::Mmin(_)
+            | Test::Mtime(_)
+            | Test::Anewer(_)
+            | Test::Cnewer(_)
+            | Test::Mnewer(_)
+            | Test::Newer(_)
+            | Test::NewerAt(_)
+            | Test::NewerCt(_)
+            | Test::NewerMt(_)
+            | Test::Uid(_)
+            | Test::Gid(_)
+            | Test::Links(_)
+            | Test::Perm(_)
+            | Test::Readable
+            | Test::Writable
+            | Test::Executable
+            | Test::SameFile(_) => Some(4),
+            Test::User(_) | Test::Group(_) => Some(5),
+            Test::Magic(_) | Test::Grep(_) | Test::TreeSize(_) => Some(6),
+            Test::MaxDepth(_) | Test::Empty | Test::Custom(_) => None,
+        }
+    }
+}
+
+/// Writes the test as find syntax, e.g. `-name a.txt` or `-regex '.*\.rs'`, quoting the
+/// argument where a shell would need it
+impl fmt::Display for Test {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.argument() {
+            Some(argument) => write!(f, "{} {}", self.name(), shell_quote(&argument)),
+            None => write!(f, "{}", self.name()),
+        }
+    }
+}
+
+// The argument as one shell word: as it is if it is made only of characters no shell
+// treats specially, otherwise in single quotes, with each ' written as '\''
+fn shell_quote(argument: &str) -> Cow<'_, str> {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "-_./,:=@+%".contains(c);
+    if !argument.is_empty() && argument.chars().all(plain) {
+        Cow::Borrowed(argument)
+    } else {
+        Cow::Owned(format!("'{}'", argument.replace('\'', "'\\''")))
+    }
+}
+
+/// Regular expression for the -regex, -iregex and -grep tests, compiled once at parse time
+#[derive(Debug, Clone)]
+pub struct RegexPattern {
+    /// Pattern as given on the command line
+    pub source: String,
+    /// Compiled pattern, anchored to match the whole path except for -grep
+    pub regex: Regex,
+}
+
+impl PartialEq for RegexPattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.source == other.source && self.regex.as_str() == other.regex.as_str()
+    }
+}
+
+/// Reference file for the -anewer, -cnewer, -mnewer and -newer tests.
+///
+/// The path is resolved relative to the current working directory, not the start
+/// path, and is stat'ed once while parsing so a missing or unreadable reference is
+/// reported up front instead of silently matching nothing.
 #[derive(Debug, Clone)]
+pub struct ReferenceFile {
+    /// Path as given on the command line
+    pub path: String,
+    /// Metadata of the reference file, read when the expression was parsed
+    pub metadata: FileStat,
+}
+
+impl PartialEq for ReferenceFile {
+    fn eq(&self, other: &Self) -> bool {
+        self.path == other.path
+    }
+}
+
+/// File types for the -type test
+#[derive(Debug, Clone, PartialEq)]
 pub enum FileType {
     /// Block special file
     BlockFile,
@@ -103,8 +601,21 @@ impl fmt::Display for FileType {
     }
 }
 
+/// Which of a file's timestamps a time test reads
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimeField {
+    /// Last access (the a in -amin, -anewer, -newerat)
+    Access,
+    /// Last status change (the c in -cmin, -cnewer, -newerct)
+    Change,
+    /// Last modification (the m in -mmin, -newer, -newermt)
+    Modify,
+    /// Creation, where the file system records it
+    Birth,
+}
+
 /// Sign for size specifications
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Sign {
     /// Exactly this size
     None,
@@ -114,8 +625,9 @@ pub enum Sign {
     Minus,
 }
 
-/// Size suffix for size specifications
-#[derive(Debug, Clone)]
+/// Size suffix for size specifications. Each displays as GNU find spells it, which is
+/// case sensitive, although the parser also takes `K`, `m` and `g`
+#[derive(Debug, Clone, PartialEq)]
 pub enum SizeSuffix {
     /// 512-byte blocks (default)
     Blocks,
@@ -123,11 +635,11 @@ pub enum SizeSuffix {
     Bytes,
     /// 2-byte words
     Words,
-    /// Kilobytes (1024 bytes)
+    /// Kilobytes (1024 bytes), written `k`
     Kb,
-    /// Megabytes (1024 * 1024 bytes)
+    /// Megabytes (1024 * 1024 bytes), written `M`
     Mb,
-    /// Gigabytes (1024 * 1024 * 1024 bytes)
+    /// Gigabytes (1024 * 1024 * 1024 bytes), written `G`
     Gb,
 }
 
@@ -144,8 +656,18 @@ impl fmt::Display for SizeSuffix {
     }
 }
 
+impl fmt::Display for Sign {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Sign::None => Ok(()),
+            Sign::Plus => write!(f, "+"),
+            Sign::Minus => write!(f, "-"),
+        }
+    }
+}
+
 /// Size specification for the -size test
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct SizeSpec {
     /// Sign (none, +, -)
     pub sign: Sign,
@@ -155,8 +677,32 @@ pub struct SizeSpec {
     pub suffix: Option<SizeSuffix>,
 }
 
+impl fmt::Display for SizeSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.sign, self.value)?;
+        match &self.suffix {
+            Some(suffix) => write!(f, "{}", suffix),
+            None => Ok(()),
+        }
+    }
+}
+
+impl SizeSpec {
+    /// The size of one unit of the spec in bytes; with no suffix it's a 512-byte block
+    pub fn unit(&self) -> u64 {
+        match self.suffix.as_ref() {
+            Some(SizeSuffix::Bytes) => 1,
+            Some(SizeSuffix::Words) => 2,
+            Some(SizeSuffix::Kb) => 1024,
+            Some(SizeSuffix::Mb) => 1024 * 1024,
+            Some(SizeSuffix::Gb) => 1024 * 1024 * 1024,
+            Some(SizeSuffix::Blocks) | None => 512,
+        }
+    }
+}
+
 /// Time specification for time-based tests (like -amin, -atime, -ctime, -cmin, -mmin, -mtime)
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct TimeSpec {
     /// Sign (none, +, -)
     pub sign: Sign,
@@ -164,8 +710,63 @@ pub struct TimeSpec {
     pub value: u64,
 }
 
+impl fmt::Display for TimeSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.sign, self.value)
+    }
+}
+
+impl TimeSpec {
+    /// Tests an age against the spec in the given unit, using GNU find's intervals:
+    ///
+    /// - `n` matches `n * unit <= age < (n + 1) * unit`, the unit-long period that
+    ///   ended n units ago, so `-mtime 0` is anything changed in the last 24 hours
+    /// - `+n` matches `age >= (n + 1) * unit`, so `-mtime +1` needs two whole days
+    /// - `-n` matches `age < n * unit`
+    ///
+    /// The age keeps its sub-second part, so a file exactly n units old is in the
+    /// `n` period and one a nanosecond younger is not.
+    pub fn matches_age(&self, age: Duration, unit: Duration) -> bool {
+        // None is a bound past what Duration can hold, which no age reaches
+        let bound = |units: u64| u32::try_from(units).ok().and_then(|units| unit.checked_mul(units));
+        let start = bound(self.value);
+        let end = bound(self.value.saturating_add(1));
+        let reached = |bound: Option<Duration>| bound.is_some_and(|bound| age >= bound);
+
+        match self.sign {
+            Sign::None => reached(start) && !reached(end),
+            Sign::Plus => reached(end),
+            Sign::Minus => !reached(start),
+        }
+    }
+}
+
+/// A count compared as GNU find compares numbers: exactly `n`, more than `n` (`+n`)
+/// or fewer than `n` (`-n`)
+#[derive(Debug, Clone, PartialEq)]
+pub struct CountSpec {
+    pub sign: Sign,
+    pub value: u64,
+}
+
+impl fmt::Display for CountSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.sign, self.value)
+    }
+}
+
+impl CountSpec {
+    pub fn matches(&self, count: u64) -> bool {
+        match self.sign {
+            Sign::None => count == self.value,
+            Sign::Plus => count > self.value,
+            Sign::Minus => count < self.value,
+        }
+    }
+}
+
 /// Permission specification for the -perm test
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct PermSpec {
     /// Permission prefix (none, -, /)
     pub prefix: Option<PermPrefix>,
@@ -173,17 +774,55 @@ pub struct PermSpec {
     pub term: PermTerm,
 }
 
+/// Writes the mode as -perm takes it: numeric modes in octal, symbolic ones as clauses
+/// like `u+rw,g=r`
+impl fmt::Display for PermSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.prefix {
+            Some(PermPrefix::AllMode) => write!(f, "-")?,
+            Some(PermPrefix::AnyMode) => write!(f, "/")?,
+            None => {}
+        }
+        match &self.term {
+            PermTerm::Numeric(mode) => write!(f, "{:o}", mode),
+            PermTerm::Symbolic(statements) => {
+                for (i, statement) in statements.iter().enumerate() {
+                    let principal = match statement.principal {
+                        SymPrincipal::User => 'u',
+                        SymPrincipal::Group => 'g',
+                        SymPrincipal::Other => 'o',
+                        SymPrincipal::All => 'a',
+                    };
+                    let operator = match statement.operator {
+                        SymPermOperator::Add => '+',
+                        SymPermOperator::Remove => '-',
+                        SymPermOperator::Set => '=',
+                    };
+                    let privileges: String = statement.privileges.iter().map(|privilege| match privilege {
+                        SymPermPriv::Read => 'r',
+                        SymPermPriv::Write => 'w',
+                        SymPermPriv::Execute => 'x',
+                    }).collect();
+                    let separator = if i == 0 { "" } else { "," };
+                    write!(f, "{}{}{}{}", separator, principal, operator, privileges)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
 /// Permission prefix for permission matching
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum PermPrefix {
     /// All permissions must match (-)
     AllMode,
-    /// Any permissions can match (/)
+    /// Any permissions can match (/); with no bits given every file matches, as in GNU find
     AnyMode,
 }
 
 /// Permission term (numeric or symbolic)
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum PermTerm {
     /// Numeric permission (e.g., 755, 0644)
     Numeric(u32),
@@ -192,7 +831,7 @@ pub enum PermTerm {
 }
 
 /// Symbolic permission statement
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct SymPermStatement {
     /// Principal (user, group, other, all)
     pub principal: SymPrincipal,
@@ -203,7 +842,7 @@ pub struct SymPermStatement {
 }
 
 /// Symbolic permission principal
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum SymPrincipal {
     /// User (u)
     User,
@@ -216,7 +855,7 @@ pub enum SymPrincipal {
 }
 
 /// Symbolic permission operator
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum SymPermOperator {
     /// Add permission (+)
     Add,
@@ -227,7 +866,7 @@ pub enum SymPermOperator {
 }
 
 /// Symbolic permission privilege
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum SymPermPriv {
     /// Read permission (r)
     Read,
@@ -235,4 +874,194 @@ pub enum SymPermPriv {
     Write,
     /// Execute permission (x)
     Execute,
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name(pattern: &str) -> Expr {
+        Expr::Test(Test::Name(pattern.to_string()))
+    }
+
+    fn and(left: Expr, right: Expr) -> Expr {
+        Expr::And(Box::new(left), Box::new(right))
+    }
+
+    fn or(left: Expr, right: Expr) -> Expr {
+        Expr::Or(Box::new(left), Box::new(right))
+    }
+
+    fn not(inner: Expr) -> Expr {
+        Expr::Not(Box::new(inner))
+    }
+
+    #[test]
+    fn test_time_spec_boundaries() {
+        const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+        const JUST: Duration = Duration::from_nanos(1);
+        let spec = |sign, value| TimeSpec { sign, value };
+
+        // Exactly 2 means at least two days old but not yet three
+        let exact = spec(Sign::None, 2);
+        assert!(!exact.matches_age(DAY * 2 - JUST, DAY));
+        assert!(exact.matches_age(DAY * 2, DAY));
+        assert!(exact.matches_age(DAY * 3 - JUST, DAY));
+        assert!(!exact.matches_age(DAY * 3, DAY));
+
+        // +2 starts where the exact period ends
+        let more = spec(Sign::Plus, 2);
+        assert!(!more.matches_age(DAY * 3 - JUST, DAY));
+        assert!(more.matches_age(DAY * 3, DAY));
+
+        // -2 ends where the exact period starts
+        let less = spec(Sign::Minus, 2);
+        assert!(less.matches_age(DAY * 2 - JUST, DAY));
+        assert!(!less.matches_age(DAY * 2, DAY));
+
+        // 0 is the most recent period
+        assert!(spec(Sign::None, 0).matches_age(Duration::ZERO, DAY));
+        assert!(!spec(Sign::Minus, 0).matches_age(Duration::ZERO, DAY));
+        assert!(!spec(Sign::Plus, u64::MAX).matches_age(Duration::MAX, DAY));
+    }
+
+    const TRUE: Expr = Expr::Test(Test::True);
+    const FALSE: Expr = Expr::Test(Test::False);
+
+    #[test]
+    fn test_simplify_and_true() {
+        assert_eq!(and(name("x"), TRUE).simplify(), name("x"));
+        assert_eq!(and(TRUE, name("x")).simplify(), name("x"));
+        assert_eq!(and(TRUE, TRUE).simplify(), TRUE);
+        // -false short-circuits the right side, so it never runs
+        assert_eq!(and(FALSE, name("x")).simplify(), FALSE);
+        assert_eq!(and(name("x"), FALSE).simplify(), FALSE);
+        // but whatever comes before the -false still does
+        let print = Expr::Action(Action::Print);
+        assert_eq!(and(print.clone(), FALSE).simplify(), and(print, FALSE));
+    }
+
+    #[test]
+    fn test_simplify_or_false() {
+        assert_eq!(or(name("x"), FALSE).simplify(), name("x"));
+        assert_eq!(or(FALSE, name("x")).simplify(), name("x"));
+        assert_eq!(or(FALSE, FALSE).simplify(), FALSE);
+        assert_eq!(or(TRUE, name("x")).simplify(), TRUE);
+        assert_eq!(or(name("x"), TRUE).simplify(), TRUE);
+        let max_depth = Expr::Test(Test::MaxDepth(1));
+        assert_eq!(or(max_depth.clone(), TRUE).simplify(), or(max_depth, TRUE));
+        // -empty still has to run to report a directory it can't list
+        let empty = test(Test::Empty);
+        assert_eq!(or(not(empty.clone()), TRUE).simplify(), or(not(empty.clone()), TRUE));
+        assert_eq!(and(and(name("x"), empty.clone()), FALSE).simplify(), and(and(name("x"), empty), FALSE));
+    }
+
+    #[test]
+    fn test_simplify_double_negation() {
+        assert_eq!(not(not(name("x"))).simplify(), name("x"));
+        assert_eq!(not(not(not(name("x")))).simplify(), not(name("x")));
+        assert_eq!(not(name("x")).simplify(), not(name("x")));
+    }
+
+    #[test]
+    fn test_simplify_match_all_name() {
+        assert_eq!(name("*").simplify(), TRUE);
+        assert_eq!(and(name("*"), name("x")).simplify(), name("x"));
+        assert_eq!(Expr::Test(Test::Iname("*".to_string())).simplify(), TRUE);
+        assert_eq!(name("*.*").simplify(), name("*.*"));
+        assert_eq!(name(".*").simplify(), name(".*"));
+    }
+
+    #[test]
+    fn test_simplify_negated_constants() {
+        assert_eq!(not(TRUE).simplify(), FALSE);
+        assert_eq!(not(FALSE).simplify(), TRUE);
+        assert_eq!(and(name("x"), not(or(TRUE, name("y")))).simplify(), FALSE);
+        assert_eq!(or(not(and(FALSE, name("y"))), name("x")).simplify(), TRUE);
+    }
+
+    #[test]
+    fn test_needs_metadata() {
+        let printf = |directives| Expr::Action(Action::Printf(directives));
+        assert!(!and(name("*.rs"), Expr::Action(Action::Print)).needs_metadata());
+        assert!(!or(test(Test::Type(FileType::SymbolicLink)), not(test(Test::AtDepth(1)))).needs_metadata());
+        assert!(!printf(vec![FormatDirective::Path, FormatDirective::Depth]).needs_metadata());
+        assert!(printf(vec![FormatDirective::Path, FormatDirective::Size]).needs_metadata());
+        assert!(and(name("x"), test(Test::Type(FileType::RegularFile))).needs_metadata());
+        assert!(and(name("x"), test(Test::Empty)).needs_metadata());
+        assert!(Expr::Action(Action::Ls).needs_metadata());
+    }
+
+    #[test]
+    fn test_simplify_nested() {
+        // Folding one level can expose another
+        let expr = or(and(not(not(TRUE)), FALSE), and(name("x"), or(FALSE, TRUE)));
+        assert_eq!(expr.simplify(), name("x"));
+    }
+
+    fn test(test: Test) -> Expr {
+        Expr::Test(test)
+    }
+
+    fn grep(pattern: &str) -> Expr {
+        test(Test::Grep(RegexPattern { source: pattern.to_string(), regex: Regex::new(pattern).unwrap() }))
+    }
+
+    fn regex(pattern: &str) -> Expr {
+        test(Test::Regex(RegexPattern { source: pattern.to_string(), regex: Regex::new(pattern).unwrap() }))
+    }
+
+    const PRINT: Expr = Expr::Action(Action::Print);
+    const PRUNE: Expr = Expr::Action(Action::Prune);
+
+    #[test]
+    fn test_optimize_orders_by_cost() {
+        let file = test(Test::Type(FileType::RegularFile));
+        let expr = and(and(and(grep("x"), regex("y")), name("z")), file.clone());
+        let optimized = and(and(name("z"), file.clone()), and(regex("y"), grep("x")));
+        assert_eq!(expr.optimize(), optimized);
+
+        // Equal costs keep their order, and cheap chains are left alone
+        assert_eq!(and(name("a"), file.clone()).optimize(), and(name("a"), file.clone()));
+        assert_eq!(and(file.clone(), name("a")).optimize(), and(file, name("a")));
+
+        // Subexpressions are optimized too, and cost what is inside them
+        let expr = and(not(and(grep("x"), name("a"))), or(regex("y"), and(grep("x"), name("b"))));
+        let optimized = and(not(and(name("a"), grep("x"))), or(regex("y"), and(name("b"), grep("x"))));
+        assert_eq!(expr.optimize(), optimized);
+        let expr = and(or(grep("x"), name("a")), name("b"));
+        assert_eq!(expr.optimize(), and(name("b"), or(grep("x"), name("a"))));
+    }
+
+    #[test]
+    fn test_optimize_never_moves_actions() {
+        // Tests only move among their neighbours between actions
+        let expr = and(and(and(and(grep("x"), name("a")), PRINT), grep("y")), name("b"));
+        let optimized = and(and(and(name("a"), grep("x")), PRINT), and(name("b"), grep("y")));
+        assert_eq!(expr.optimize(), optimized);
+
+        // -path x -prune keeps its shape
+        let expr = or(and(regex("x"), PRUNE), PRINT);
+        assert_eq!(expr.clone().optimize(), expr);
+
+        // An operand with an action inside is pinned as a whole
+        let expr = and(and(grep("x"), or(name("a"), PRINT)), name("b"));
+        assert_eq!(expr.clone().optimize(), expr);
+
+        // As are -maxdepth and custom predicates
+        let expr = and(and(grep("x"), test(Test::MaxDepth(1))), name("a"));
+        assert_eq!(expr.clone().optimize(), expr);
+        let expr = and(grep("x"), test(Test::Custom("slow".to_string())));
+        assert_eq!(expr.clone().optimize(), expr);
+
+        // -empty reports directories it can't list, so it only lists those the tests
+        // before it let through, and tests after it aren't moved ahead of it
+        let empty = test(Test::Empty);
+        let expr = and(and(name("a"), empty.clone()), grep("x"));
+        assert_eq!(expr.clone().optimize(), expr);
+        let expr = and(and(grep("x"), empty.clone()), name("a"));
+        assert_eq!(expr.clone().optimize(), expr);
+        assert_eq!(and(empty.clone(), name("a")).optimize(), and(empty, name("a")));
+    }
+}
+
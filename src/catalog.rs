@@ -0,0 +1,187 @@
+//! The predicates the expression grammar accepts, described for tools such as shell
+//! completions and linters that need to know them without running findr.
+
+/// Whether a predicate is a test, which matches entries, or an action, which does
+/// something with them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PredicateKind {
+    Test,
+    Action,
+}
+
+/// What follows a predicate on the command line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArgumentKind {
+    /// Nothing, e.g. -empty
+    None,
+    /// A shell glob, e.g. `*.rs`
+    Glob,
+    /// A regular expression
+    Regex,
+    /// A non-negative integer, e.g. a depth or a uid
+    Number,
+    /// A count with an optional sign, e.g. `+1`
+    Count,
+    /// A size with an optional sign and unit suffix, e.g. `+10M`
+    Size,
+    /// A count of minutes or days with an optional sign, e.g. `-7`
+    Time,
+    /// A date and time, e.g. `2024-01-31T12:00` or `@1706702400`
+    Datetime,
+    /// A numeric or symbolic mode with an optional `-` or `/` prefix, e.g. `/u+x`
+    Perm,
+    /// A -type letter: b, c, d, p, f, l or s
+    FileType,
+    /// The path of an existing file to compare against
+    Path,
+    /// A user name
+    User,
+    /// A group name
+    Group,
+    /// A MIME type, e.g. `image/png`
+    MimeType,
+    /// A -printf format string
+    Format,
+    /// The name of a predicate registered from Rust code
+    CustomName,
+    /// A command and its arguments, ended by `;`
+    Command,
+}
+
+/// One predicate as the grammar spells it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PredicateInfo {
+    /// The usual spelling, e.g. "-name"
+    pub name: &'static str,
+    /// Other spellings accepted for the same predicate
+    pub aliases: &'static [&'static str],
+    pub kind: PredicateKind,
+    pub argument: ArgumentKind,
+}
+
+const fn test(name: &'static str, argument: ArgumentKind) -> PredicateInfo {
+    PredicateInfo { name, aliases: &[], kind: PredicateKind::Test, argument }
+}
+
+const fn action(name: &'static str, argument: ArgumentKind) -> PredicateInfo {
+    PredicateInfo { name, aliases: &[], kind: PredicateKind::Action, argument }
+}
+
+const PREDICATES: &[PredicateInfo] = &[
+    PredicateInfo { aliases: &["--name", "-n"], ..test("-name", ArgumentKind::Glob) },
+    test("-iname", ArgumentKind::Glob),
+    test("-path", ArgumentKind::Glob),
+    test("-ipath", ArgumentKind::Glob),
+    test("-regex", ArgumentKind::Regex),
+    test("-iregex", ArgumentKind::Regex),
+    test("-true", ArgumentKind::None),
+    test("-false", ArgumentKind::None),
+    PredicateInfo { aliases: &["--type", "-t"], ..test("-type", ArgumentKind::FileType) },
+    test("-size", ArgumentKind::Size),
+    test("-treesize", ArgumentKind::Size),
+    test("-empty", ArgumentKind::None),
+    test("-readable", ArgumentKind::None),
+    test("-writable", ArgumentKind::None),
+    test("-executable", ArgumentKind::None),
+    test("-amin", ArgumentKind::Time),
+    test("-atime", ArgumentKind::Time),
+    test("-cmin", ArgumentKind::Time),
+    test("-ctime", ArgumentKind::Time),
+    test("-mmin", ArgumentKind::Time),
+    test("-mtime", ArgumentKind::Time),
+    test("-used", ArgumentKind::Time),
+    test("-anewer", ArgumentKind::Path),
+    test("-cnewer", ArgumentKind::Path),
+    test("-mnewer", ArgumentKind::Path),
+    test("-newer", ArgumentKind::Path),
+    test("-newerat", ArgumentKind::Datetime),
+    test("-newerct", ArgumentKind::Datetime),
+    test("-newermt", ArgumentKind::Datetime),
+    test("-samefile", ArgumentKind::Path),
+    test("-user", ArgumentKind::User),
+    test("-group", ArgumentKind::Group),
+    test("-uid", ArgumentKind::Number),
+    test("-gid", ArgumentKind::Number),
+    test("-links", ArgumentKind::Count),
+    test("-perm", ArgumentKind::Perm),
+    test("-magic", ArgumentKind::MimeType),
+    test("-grep", ArgumentKind::Regex),
+    test("-maxdepth", ArgumentKind::Number),
+    test("-atdepth", ArgumentKind::Number),
+    test("-custom", ArgumentKind::CustomName),
+    action("-print", ArgumentKind::None),
+    action("-printf", ArgumentKind::Format),
+    action("-prune", ArgumentKind::None),
+    action("-ls", ArgumentKind::None),
+    action("-quit", ArgumentKind::None),
+    action("-exec", ArgumentKind::Command),
+    action("-delete", ArgumentKind::None),
+];
+
+/// Every test and action the expression grammar accepts, in no particular order.
+/// Operators such as -and and parentheses aren't included.
+pub fn predicates() -> &'static [PredicateInfo] {
+    PREDICATES
+}
+
+/// Looks up a predicate by its name or one of its aliases
+pub fn find_predicate(name: &str) -> Option<&'static PredicateInfo> {
+    PREDICATES.iter().find(|info| info.name == name || info.aliases.contains(&name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Expr;
+    use crate::parser::parse_expression;
+
+    fn sample(argument: ArgumentKind) -> &'static str {
+        match argument {
+            ArgumentKind::None => "",
+            ArgumentKind::Glob => "*.rs",
+            ArgumentKind::Regex => ".*",
+            ArgumentKind::Number => "1",
+            ArgumentKind::Count => "+1",
+            ArgumentKind::Size => "+1k",
+            ArgumentKind::Time => "-7",
+            ArgumentKind::Datetime => "2024-01-31",
+            ArgumentKind::Perm => "/u+x",
+            ArgumentKind::FileType => "f",
+            ArgumentKind::Path => "Cargo.toml",
+            ArgumentKind::User | ArgumentKind::Group => "root",
+            ArgumentKind::MimeType => "image/png",
+            ArgumentKind::Format => "%p",
+            ArgumentKind::CustomName => "x",
+            ArgumentKind::Command => "echo {} ;",
+        }
+    }
+
+    #[test]
+    fn test_known_predicates_are_listed() {
+        for name in ["-name", "-regex", "-type", "-mtime", "-perm", "-newer", "-print", "-printf", "-ls"] {
+            assert!(predicates().iter().any(|info| info.name == name), "{}", name);
+        }
+        assert_eq!(find_predicate("-n").map(|info| info.name), Some("-name"));
+        assert_eq!(find_predicate("-t").unwrap().argument, ArgumentKind::FileType);
+        assert_eq!(find_predicate("-quit").unwrap().kind, PredicateKind::Action);
+        assert!(find_predicate("-and").is_none());
+        assert_eq!(find_predicate("-exec").unwrap().argument, ArgumentKind::Command);
+        assert!(find_predicate("-ok").is_none());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_listed_predicates_parse() {
+        for info in predicates() {
+            for name in std::iter::once(&info.name).chain(info.aliases) {
+                let input = format!("{} {}", name, sample(info.argument));
+                let expr = parse_expression(input.trim()).unwrap_or_else(|e| panic!("{}: {}", input, e));
+                match (expr, info.kind) {
+                    (Expr::Test(test), PredicateKind::Test) => assert_eq!(test.name(), info.name),
+                    (Expr::Action(_), PredicateKind::Action) => {}
+                    (expr, kind) => panic!("{} parsed as {:?}, listed as a {:?}", input, expr, kind),
+                }
+            }
+        }
+    }
+}
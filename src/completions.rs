@@ -0,0 +1,204 @@
+//! Shell completion scripts for `findr --completions <shell>`, generated from the
+//! predicate catalog and the option flags so they list whatever this build accepts.
+
+use crate::catalog::{predicates, ArgumentKind, PredicateInfo};
+use crate::options::{FLAGS, VALUE_FLAGS};
+use crate::regextype::REGEX_TYPES;
+
+/// Shells `completion_script` has a script for
+pub const SHELLS: &[&str] = &["bash", "zsh", "fish"];
+
+const FILE_TYPES: &str = "b c d p f l s";
+const ORDERS: &str = "name none";
+
+/// Returns the completion script for `shell`, or None if it isn't one of `SHELLS`
+pub fn completion_script(shell: &str) -> Option<String> {
+    match shell {
+        "bash" => Some(bash()),
+        "zsh" => Some(zsh()),
+        "fish" => Some(fish()),
+        _ => None,
+    }
+}
+
+// Every spelling of every predicate, then the flags
+fn words() -> Vec<&'static str> {
+    let spellings = predicates().iter().flat_map(|info| std::iter::once(info.name).chain(info.aliases.iter().copied()));
+    spellings.chain(FLAGS.iter().copied()).collect()
+}
+
+// The spellings of the predicates taking the given kind of argument, as a case pattern
+fn taking(argument: ArgumentKind) -> String {
+    predicates().iter()
+        .filter(|info| info.argument == argument)
+        .flat_map(|info| std::iter::once(info.name).chain(info.aliases.iter().copied()))
+        .collect::<Vec<_>>()
+        .join("|")
+}
+
+fn bash() -> String {
+    format!(
+        r#"# bash completion for findr
+_findr() {{
+    local cur="${{COMP_WORDS[COMP_CWORD]}}"
+    local prev="${{COMP_WORDS[COMP_CWORD-1]}}"
+    case "$prev" in
+        {types})
+            COMPREPLY=($(compgen -W "{file_types}" -- "$cur")); return ;;
+        {paths}|--output-file)
+            COMPREPLY=($(compgen -f -- "$cur")); return ;;
+        {users})
+            COMPREPLY=($(compgen -u -- "$cur")); return ;;
+        {groups})
+            COMPREPLY=($(compgen -g -- "$cur")); return ;;
+        --order)
+            COMPREPLY=($(compgen -W "{orders}" -- "$cur")); return ;;
+        --regextype|-regextype)
+            COMPREPLY=($(compgen -W "{regex_types}" -- "$cur")); return ;;
+        {values})
+            COMPREPLY=(); return ;;
+    esac
+    if [[ "$cur" == -* ]]; then
+        COMPREPLY=($(compgen -W "{words}" -- "$cur"))
+    else
+        COMPREPLY=($(compgen -f -- "$cur"))
+    fi
+}}
+complete -o filenames -F _findr findr
+"#,
+        types = taking(ArgumentKind::FileType),
+        file_types = FILE_TYPES,
+        paths = taking(ArgumentKind::Path),
+        users = taking(ArgumentKind::User),
+        groups = taking(ArgumentKind::Group),
+        orders = ORDERS,
+        regex_types = REGEX_TYPES.join(" "),
+        values = other_values().join("|"),
+        words = words().join(" "),
+    )
+}
+
+fn zsh() -> String {
+    format!(
+        r#"#compdef findr
+_findr() {{
+    case "$words[CURRENT-1]" in
+        ({types})
+            compadd -- {file_types}; return ;;
+        ({paths}|--output-file)
+            _files; return ;;
+        ({users})
+            _users; return ;;
+        ({groups})
+            _groups; return ;;
+        (--order)
+            compadd -- {orders}; return ;;
+        (--regextype|-regextype)
+            compadd -- {regex_types}; return ;;
+        ({values})
+            return ;;
+    esac
+    if [[ "$PREFIX" == -* ]]; then
+        compadd -- {words}
+    else
+        _files
+    fi
+}}
+_findr "$@"
+"#,
+        types = taking(ArgumentKind::FileType),
+        file_types = FILE_TYPES,
+        paths = taking(ArgumentKind::Path),
+        users = taking(ArgumentKind::User),
+        groups = taking(ArgumentKind::Group),
+        orders = ORDERS,
+        regex_types = REGEX_TYPES.join(" "),
+        values = other_values().join("|"),
+        words = words().join(" "),
+    )
+}
+
+// Fish completes each predicate on its own line, with what its argument can be
+fn fish() -> String {
+    let mut script = String::from("# fish completion for findr\n");
+    for info in predicates() {
+        for spelling in std::iter::once(info.name).chain(info.aliases.iter().copied()) {
+            script.push_str(&format!("complete -c findr {}{}\n", fish_option(spelling), fish_argument(info)));
+        }
+    }
+    for flag in FLAGS {
+        let argument = match *flag {
+            "--order" => format!(" -x -a '{}'", ORDERS),
+            "--output-file" => " -r -F".to_string(),
+            "--regextype" => format!(" -x -a '{}'", REGEX_TYPES.join(" ")),
+            flag if VALUE_FLAGS.contains(&flag) => " -x".to_string(),
+            _ => String::new(),
+        };
+        script.push_str(&format!("complete -c findr {}{}\n", fish_option(flag), argument));
+    }
+    script
+}
+
+// -l for GNU style --long options, -o for find's single-dash long ones, -s for letters
+fn fish_option(spelling: &str) -> String {
+    match spelling.strip_prefix("--") {
+        Some(long) => format!("-l {}", long),
+        None if spelling.len() == 2 => format!("-s {}", &spelling[1..]),
+        None => format!("-o {}", &spelling[1..]),
+    }
+}
+
+fn fish_argument(info: &PredicateInfo) -> String {
+    match info.argument {
+        ArgumentKind::None => String::new(),
+        ArgumentKind::FileType => format!(" -x -a '{}'", FILE_TYPES),
+        ArgumentKind::Path => " -r -F".to_string(),
+        ArgumentKind::User => " -x -a '(__fish_complete_users)'".to_string(),
+        ArgumentKind::Group => " -x -a '(__fish_complete_groups)'".to_string(),
+        _ => " -x".to_string(),
+    }
+}
+
+// Predicates and flags whose argument has nothing to complete, so the shell offers
+// nothing rather than file names
+fn other_values() -> Vec<&'static str> {
+    let predicates = predicates().iter().filter(|info| {
+        !matches!(
+            info.argument,
+            ArgumentKind::None | ArgumentKind::FileType | ArgumentKind::Path | ArgumentKind::User | ArgumentKind::Group
+        )
+    });
+    let flags = VALUE_FLAGS.iter().copied().filter(|flag| !["--order", "--output-file", "--regextype"].contains(flag));
+    predicates
+        .flat_map(|info| std::iter::once(info.name).chain(info.aliases.iter().copied()))
+        .chain(flags)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scripts_list_predicates_and_options() {
+        for shell in SHELLS {
+            let script = completion_script(shell).unwrap();
+            for word in ["name", "type", "mtime", "printf", "sort-buffer"] {
+                assert!(script.contains(word), "{} script lacks {}", shell, word);
+            }
+        }
+        let bash = completion_script("bash").unwrap();
+        assert!(bash.contains("-name --name -n -iname"));
+        assert!(bash.contains("-type|--type|-t)"));
+        assert!(bash.contains("complete -o filenames -F _findr findr"));
+        let zsh = completion_script("zsh").unwrap();
+        assert!(zsh.starts_with("#compdef findr\n"));
+        assert!(zsh.contains("(-type|--type|-t)"));
+        let fish = completion_script("fish").unwrap();
+        assert!(fish.contains("complete -c findr -o type -x -a 'b c d p f l s'\n"));
+        assert!(fish.contains("complete -c findr -s n -x\n"));
+        assert!(fish.contains("complete -c findr -l sort\n"));
+        assert!(fish.contains("complete -c findr -l regextype -x -a 'native findutils-default emacs"));
+        assert!(completion_script("powershell").is_none());
+    }
+}
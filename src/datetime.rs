@@ -0,0 +1,279 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use chrono::{Datelike, DateTime, Local, LocalResult, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta, TimeZone, Timelike};
+
+/// Parses the time argument of -newerat, -newerct and -newermt.
+///
+/// Accepted forms are `@SECONDS` since the epoch, a date `YYYY-MM-DD`, or a date
+/// and time `YYYY-MM-DDTHH:MM[:SS]`, where a space may stand in for the `T`,
+/// optionally followed by `Z` or an offset such as `+02:00`. Without an offset the
+/// time is local, as in GNU find (UTC where the local time zone isn't available).
+pub fn parse_datetime(input: &str) -> Option<SystemTime> {
+    if let Some(seconds) = input.strip_prefix('@') {
+        let seconds = seconds.parse::<i64>().ok()?;
+        return from_epoch_seconds(seconds);
+    }
+
+    let (date, rest) = input.split_at_checked(10)?;
+    let (year, month, day) = parse_date(date)?;
+    let (time, offset) = match rest.strip_prefix(['T', ' ']) {
+        Some(rest) => {
+            let zone_start = rest.find(['Z', '+', '-']).unwrap_or(rest.len());
+            let (time, zone) = rest.split_at(zone_start);
+            (parse_time(time)?, parse_offset(zone)?)
+        }
+        None if rest.is_empty() => ((0, 0, 0), None),
+        None => return None,
+    };
+
+    let seconds = match offset {
+        Some(offset) => as_if_utc(year, month, day, time) - offset,
+        None => local_to_epoch(year, month, day, time)?,
+    };
+    from_epoch_seconds(seconds)
+}
+
+// Seconds since the epoch if the date and time were in UTC
+fn as_if_utc(year: i64, month: u32, day: u32, time: (u32, u32, u32)) -> i64 {
+    days_from_civil(year, month, day) * 86_400
+        + i64::from(time.0) * 3600
+        + i64::from(time.1) * 60
+        + i64::from(time.2)
+}
+
+fn from_epoch_seconds(seconds: i64) -> Option<SystemTime> {
+    let magnitude = Duration::from_secs(seconds.unsigned_abs());
+    if seconds >= 0 {
+        UNIX_EPOCH.checked_add(magnitude)
+    } else {
+        UNIX_EPOCH.checked_sub(magnitude)
+    }
+}
+
+// YYYY-MM-DD, checking the day against the length of the month
+fn parse_date(date: &str) -> Option<(i64, u32, u32)> {
+    let mut parts = date.split('-');
+    let year = number(parts.next()?, 4)?;
+    let month = number(parts.next()?, 2)?;
+    let day = number(parts.next()?, 2)?;
+    let leap = (year % 4 == 0 && year % 100 != 0) || year % 400 == 0;
+    let days_in_month = match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        1..=12 => 31,
+        _ => return None,
+    };
+    (1..=days_in_month).contains(&day).then_some((i64::from(year), month, day))
+}
+
+// HH:MM or HH:MM:SS
+fn parse_time(time: &str) -> Option<(u32, u32, u32)> {
+    let mut parts = time.split(':');
+    let hour = number(parts.next()?, 2)?;
+    let minute = number(parts.next()?, 2)?;
+    let second = match parts.next() {
+        Some(second) => number(second, 2)?,
+        None => 0,
+    };
+    (parts.next().is_none() && hour < 24 && minute < 60 && second < 60).then_some((hour, minute, second))
+}
+
+// Nothing, Z, or +HH:MM / -HH:MM / +HHMM as seconds east of UTC
+fn parse_offset(zone: &str) -> Option<Option<i64>> {
+    if zone.is_empty() {
+        return Some(None);
+    }
+    if zone == "Z" {
+        return Some(Some(0));
+    }
+    let (sign, digits) = match zone.split_at_checked(1)? {
+        ("+", digits) => (1, digits),
+        ("-", digits) => (-1, digits),
+        _ => return None,
+    };
+    let digits = digits.replacen(':', "", 1);
+    let hours = number(digits.get(..2)?, 2)?;
+    let minutes = number(digits.get(2..)?, 2)?;
+    (hours < 24 && minutes < 60).then_some(Some(sign * i64::from(hours * 3600 + minutes * 60)))
+}
+
+// Exactly `len` ASCII digits
+fn number(digits: &str, len: usize) -> Option<u32> {
+    (digits.len() == len && digits.bytes().all(|b| b.is_ascii_digit())).then(|| digits.parse().ok())?
+}
+
+// Days since 1970-01-01 in the proleptic Gregorian calendar
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = i64::from(month);
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+// Seconds since the epoch of a date and time in the local time zone
+fn local_to_epoch(year: i64, month: u32, day: u32, time: (u32, u32, u32)) -> Option<i64> {
+    let date = NaiveDate::from_ymd_opt(i32::try_from(year).ok()?, month, day)?;
+    let local = from_local(date.and_hms_opt(time.0, time.1, time.2)?)?;
+    Some(local.timestamp())
+}
+
+// The instant a wall-clock time in the local time zone stands for. Like mktime, a time
+// repeated when the clocks go back is its first occurrence, and one skipped when they
+// go forward is taken an hour later
+fn from_local(naive: NaiveDateTime) -> Option<DateTime<Local>> {
+    match Local.from_local_datetime(&naive) {
+        LocalResult::Single(time) => Some(time),
+        // chrono doesn't promise which of the two comes first
+        LocalResult::Ambiguous(one, other) => Some(one.min(other)),
+        LocalResult::None => {
+            let later = naive.checked_add_signed(TimeDelta::hours(1))?;
+            Local.from_local_datetime(&later).earliest()
+        }
+    }
+}
+
+// A time in the local time zone, or None if it is beyond what chrono can represent
+fn to_local(time: SystemTime) -> Option<DateTime<Local>> {
+    let utc = match time.duration_since(UNIX_EPOCH) {
+        Ok(after) => DateTime::from_timestamp(i64::try_from(after.as_secs()).ok()?, after.subsec_nanos())?,
+        Err(before) => DateTime::UNIX_EPOCH.checked_sub_signed(TimeDelta::from_std(before.duration()).ok()?)?,
+    };
+    Some(utc.with_timezone(&Local))
+}
+
+/// Returns the start of the local calendar day `time` falls in, for -daystart. On a day
+/// that starts with the clocks going forward, that is the first time the day has
+pub fn local_midnight(time: SystemTime) -> Option<SystemTime> {
+    let midnight = to_local(time)?.date_naive().and_time(NaiveTime::MIN);
+    Some(SystemTime::from(from_local(midnight)?))
+}
+
+/// Three-letter month names, as -ls and ctime(3) print them
+pub const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+/// Local calendar date and time of day, to the second
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LocalTime {
+    pub year: i64,
+    /// 1 to 12
+    pub month: u32,
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+    /// 0 for Sunday to 6 for Saturday
+    pub weekday: u32,
+}
+
+/// Formats a time like ctime(3), without the newline: `Wed Jun 30 21:49:08 2021`.
+/// This is what the bare %a, %c and %t directives of -printf print
+pub fn format_ctime(time: SystemTime) -> Option<String> {
+    Some(to_local(time)?.format("%a %b %e %H:%M:%S %Y").to_string())
+}
+
+/// Formats a local time as `2021-06-30T21:49:08`, which `parse_datetime` reads back
+pub fn format_datetime(time: SystemTime) -> Option<String> {
+    Some(to_local(time)?.format("%Y-%m-%dT%H:%M:%S").to_string())
+}
+
+/// Breaks a time down into the local time zone's date and time, as -ls prints it.
+/// Where the local time zone isn't available this is UTC, as parsing assumes
+pub fn to_local_time(time: SystemTime) -> Option<LocalTime> {
+    let local = to_local(time)?;
+    Some(LocalTime {
+        year: i64::from(local.year()),
+        month: local.month(),
+        day: local.day(),
+        hour: local.hour(),
+        minute: local.minute(),
+        second: local.second(),
+        weekday: local.weekday().num_days_from_sunday(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn epoch_seconds(input: &str) -> Option<i64> {
+        let time = parse_datetime(input)?;
+        Some(match time.duration_since(UNIX_EPOCH) {
+            Ok(after) => after.as_secs() as i64,
+            Err(before) => -(before.duration().as_secs() as i64),
+        })
+    }
+
+    #[test]
+    fn test_utc_and_offsets() {
+        assert_eq!(epoch_seconds("1970-01-01T00:00Z"), Some(0));
+        assert_eq!(epoch_seconds("2024-02-29T12:34:56Z"), Some(1_709_210_096));
+        assert_eq!(epoch_seconds("2024-02-29T14:34:56+02:00"), Some(1_709_210_096));
+        assert_eq!(epoch_seconds("2024-02-29 14:34:56+02:00"), Some(1_709_210_096));
+        assert_eq!(epoch_seconds("2024-02-29T07:04:56-0530"), Some(1_709_210_096));
+        assert_eq!(epoch_seconds("1969-12-31T23:59:59Z"), Some(-1));
+        assert_eq!(epoch_seconds("@1709210096"), Some(1_709_210_096));
+        assert_eq!(epoch_seconds("@-1"), Some(-1));
+    }
+
+    #[test]
+    fn test_local_time() {
+        // Whatever the local zone, a date is midnight local time, and a day later is
+        // 24 hours later unless a daylight saving change falls in between
+        let day = epoch_seconds("2024-01-10").unwrap();
+        assert_eq!(epoch_seconds("2024-01-10T00:00:00"), Some(day));
+        assert_eq!(epoch_seconds("2024-01-11").unwrap() - day, 86_400);
+        assert!((day - epoch_seconds("2024-01-10T00:00Z").unwrap()).abs() <= 14 * 3600);
+    }
+
+    #[test]
+    fn test_local_midnight() {
+        let midnight = parse_datetime("2024-03-14").unwrap();
+        for input in ["2024-03-14T00:00:00", "2024-03-14T00:00:01", "2024-03-14T12:00", "2024-03-14T23:59:59"] {
+            assert_eq!(local_midnight(parse_datetime(input).unwrap()), Some(midnight), "{}", input);
+        }
+        assert_eq!(local_midnight(midnight - Duration::from_secs(1)), parse_datetime("2024-03-13"));
+    }
+
+    #[test]
+    fn test_format_ctime() {
+        let format = |input| format_ctime(parse_datetime(input).unwrap()).unwrap();
+        assert_eq!(format("2021-06-30T21:49:08"), "Wed Jun 30 21:49:08 2021");
+        // The day is padded with a space, as ctime does
+        assert_eq!(format("2024-03-05T07:08:09"), "Tue Mar  5 07:08:09 2024");
+        assert_eq!(format("1970-01-01T00:00"), "Thu Jan  1 00:00:00 1970");
+        assert_eq!(format("1969-07-20T20:17:40"), "Sun Jul 20 20:17:40 1969");
+    }
+
+    #[test]
+    fn test_format_datetime_round_trips() {
+        for input in ["2021-06-30T21:49:08", "2024-03-05T07:08:09", "1969-07-20T20:17:40"] {
+            assert_eq!(format_datetime(parse_datetime(input).unwrap()).as_deref(), Some(input));
+        }
+    }
+
+    #[test]
+    fn test_invalid_dates() {
+        for input in [
+            "", "2024", "2024-1-01", "2024-13-01", "2023-02-29", "2024-04-31", "2024-01-01 ",
+            "2024-01-01T", "2024-01-01T24:00", "2024-01-01T12:60", "2024-01-01T12:00:00:00",
+            "2024-01-01T12:00X", "2024-01-01T12:00+2", "2024-01-01Z", "@", "@soon", "yesterday",
+        ] {
+            assert!(parse_datetime(input).is_none(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_to_local_time_round_trips() {
+        for input in ["2024-02-29T12:34", "1999-12-31T23:59", "2024-07-01T00:00", "1969-07-20T20:17"] {
+            let local = to_local_time(parse_datetime(input).unwrap()).unwrap();
+            let printed = format!(
+                "{:04}-{:02}-{:02}T{:02}:{:02}",
+                local.year, local.month, local.day, local.hour, local.minute
+            );
+            assert_eq!(printed, input);
+        }
+    }
+}
@@ -0,0 +1,462 @@
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+#[cfg(unix)]
+use std::time::{Duration, UNIX_EPOCH};
+use crate::ast::{FileType, TimeField};
+
+#[cfg(unix)]
+use std::os::unix::fs::MetadataExt;
+
+/// The parts of a file's metadata that tests look at, taken from a single stat.
+///
+/// Unlike `std::fs::Metadata` this can be built by hand, so tests can describe files
+/// that would be awkward to create for real. Timestamps the file system doesn't record
+/// are None, and the Unix-only fields are 0 on other platforms.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FileStat {
+    /// None for anything -type has no letter for
+    pub file_type: Option<FileType>,
+    pub len: u64,
+    pub accessed: Option<SystemTime>,
+    pub modified: Option<SystemTime>,
+    /// Last status change, st_ctime, which only Unix records
+    pub changed: Option<SystemTime>,
+    pub created: Option<SystemTime>,
+    /// Permission and file type bits, as in st_mode
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub dev: u64,
+    pub ino: u64,
+    pub nlink: u64,
+    /// Space allocated, in 512-byte blocks as in st_blocks
+    pub blocks: u64,
+}
+
+impl FileStat {
+    pub fn is_dir(&self) -> bool {
+        self.file_type == Some(FileType::Directory)
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.file_type == Some(FileType::RegularFile)
+    }
+
+    /// Returns the given timestamp, if it is recorded
+    pub fn time(&self, field: TimeField) -> Option<SystemTime> {
+        match field {
+            TimeField::Access => self.accessed,
+            TimeField::Modify => self.modified,
+            TimeField::Change => self.changed,
+            TimeField::Birth => self.created,
+        }
+    }
+}
+
+impl From<&fs::Metadata> for FileStat {
+    fn from(metadata: &fs::Metadata) -> Self {
+        #[cfg(unix)]
+        let (mode, uid, gid, dev, ino, nlink, blocks) = (
+            metadata.mode(),
+            metadata.uid(),
+            metadata.gid(),
+            metadata.dev(),
+            metadata.ino(),
+            metadata.nlink(),
+            metadata.blocks(),
+        );
+        #[cfg(not(unix))]
+        let (mode, uid, gid, dev, ino, nlink, blocks) = (0, 0, 0, 0, 0, 0, 0);
+        #[cfg(unix)]
+        let changed = change_time(metadata);
+        #[cfg(not(unix))]
+        let changed = None;
+
+        FileStat {
+            file_type: classify_file_type(metadata.file_type()),
+            len: metadata.len(),
+            accessed: metadata.accessed().ok(),
+            modified: metadata.modified().ok(),
+            changed,
+            created: metadata.created().ok(),
+            mode,
+            uid,
+            gid,
+            dev,
+            ino,
+            nlink,
+            blocks,
+        }
+    }
+}
+
+// st_ctime, whose seconds may count back from the epoch as well as forward
+#[cfg(unix)]
+fn change_time(metadata: &fs::Metadata) -> Option<SystemTime> {
+    let seconds = Duration::from_secs(metadata.ctime().unsigned_abs());
+    let whole = if metadata.ctime() >= 0 { UNIX_EPOCH.checked_add(seconds) } else { UNIX_EPOCH.checked_sub(seconds) };
+    whole?.checked_add(Duration::from_nanos(u64::try_from(metadata.ctime_nsec()).ok()?))
+}
+
+/// Maps a file system type onto the -type letters
+pub(crate) fn classify_file_type(file_type: fs::FileType) -> Option<FileType> {
+    if file_type.is_symlink() {
+        return Some(FileType::SymbolicLink);
+    }
+    if file_type.is_dir() {
+        return Some(FileType::Directory);
+    }
+    if file_type.is_file() {
+        return Some(FileType::RegularFile);
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::FileTypeExt;
+        if file_type.is_block_device() {
+            return Some(FileType::BlockFile);
+        }
+        if file_type.is_char_device() {
+            return Some(FileType::CharFile);
+        }
+        if file_type.is_fifo() {
+            return Some(FileType::NamedPipe);
+        }
+        if file_type.is_socket() {
+            return Some(FileType::Socket);
+        }
+    }
+
+    None
+}
+
+/// What -readable, -writable and -executable ask about an entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    Execute,
+}
+
+/// Lookups the interpreter makes about the entries it is given.
+///
+/// The walk itself still reads directories through walkdir; this covers what tests ask
+/// about an entry once it has been found, and what content tests like -grep read, so
+/// they can be run against `MockFileSystem`.
+pub trait FileSystem {
+    /// Stats a path, following symlinks
+    fn metadata(&self, path: &Path) -> io::Result<FileStat>;
+    /// Stats a path without following a symlink at the end of it
+    fn symlink_metadata(&self, path: &Path) -> io::Result<FileStat>;
+    /// Returns where a symlink points
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf>;
+    /// Lists the paths in a directory, in no particular order
+    fn read_dir(&self, path: &Path) -> io::Result<Box<dyn Iterator<Item = io::Result<PathBuf>> + '_>>;
+    /// Opens a file to read its contents, following symlinks
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + '_>>;
+    /// Whether the current user may read, write or execute a path, following symlinks.
+    /// False if it doesn't exist or can't be checked
+    fn access(&self, path: &Path, access: Access) -> bool;
+}
+
+/// The file system the program actually runs against
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RealFileSystem;
+
+impl FileSystem for RealFileSystem {
+    fn metadata(&self, path: &Path) -> io::Result<FileStat> {
+        fs::metadata(path).map(|metadata| FileStat::from(&metadata))
+    }
+
+    fn symlink_metadata(&self, path: &Path) -> io::Result<FileStat> {
+        fs::symlink_metadata(path).map(|metadata| FileStat::from(&metadata))
+    }
+
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+        fs::read_link(path)
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Box<dyn Iterator<Item = io::Result<PathBuf>> + '_>> {
+        let entries = fs::read_dir(path)?;
+        Ok(Box::new(entries.map(|entry| entry.map(|entry| entry.path()))))
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + '_>> {
+        Ok(Box::new(fs::File::open(path)?))
+    }
+
+    // access(2) answers for the real user rather than going by the permission bits
+    // alone, so ACLs, read-only mounts and root's privileges are all taken into account
+    #[cfg(unix)]
+    fn access(&self, path: &Path, access: Access) -> bool {
+        use std::os::unix::ffi::OsStrExt;
+        let Ok(path) = std::ffi::CString::new(path.as_os_str().as_bytes()) else { return false };
+        let mode = match access {
+            Access::Read => libc::R_OK,
+            Access::Write => libc::W_OK,
+            Access::Execute => libc::X_OK,
+        };
+        // SAFETY: path is a valid NUL-terminated string, which access doesn't keep
+        unsafe { libc::access(path.as_ptr(), mode) == 0 }
+    }
+
+    // Without access(2), anything that can be stat'ed is readable, writable unless
+    // marked read-only, and only directories count as executable, for searching
+    #[cfg(not(unix))]
+    fn access(&self, path: &Path, access: Access) -> bool {
+        fs::metadata(path).is_ok_and(|metadata| match access {
+            Access::Read => true,
+            Access::Write => !metadata.permissions().readonly(),
+            Access::Execute => metadata.is_dir(),
+        })
+    }
+}
+
+#[cfg(test)]
+pub use mock::MockFileSystem;
+
+#[cfg(test)]
+mod mock {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    enum MockEntry {
+        File(FileStat),
+        Symlink(PathBuf),
+        Error(io::ErrorKind),
+    }
+
+    /// In-memory file system for tests. Paths that weren't added don't exist, and a
+    /// path added as an error fails every lookup with that error, as an unreadable
+    /// file would. Access is decided by the owner's permission bits. A directory given
+    /// a read_dir error can be stat'ed but not listed, like one without read
+    /// permission. Files read empty unless given contents. Every lookup is counted, so
+    /// a test can check that something never touched the file system.
+    #[derive(Default)]
+    pub struct MockFileSystem {
+        entries: HashMap<PathBuf, MockEntry>,
+        read_dir_errors: HashMap<PathBuf, io::ErrorKind>,
+        contents: HashMap<PathBuf, Vec<u8>>,
+        lookups: AtomicUsize,
+    }
+
+    impl MockFileSystem {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        pub fn with_file(mut self, path: impl Into<PathBuf>, stat: FileStat) -> Self {
+            self.entries.insert(path.into(), MockEntry::File(stat));
+            self
+        }
+
+        /// Adds a regular file holding `contents`, with the size to match
+        pub fn with_contents(mut self, path: impl Into<PathBuf>, contents: impl Into<Vec<u8>>) -> Self {
+            let (path, contents) = (path.into(), contents.into());
+            let stat = FileStat {
+                file_type: Some(FileType::RegularFile),
+                len: contents.len() as u64,
+                ..FileStat::default()
+            };
+            self.contents.insert(path.clone(), contents);
+            self.with_file(path, stat)
+        }
+
+        pub fn with_symlink(mut self, path: impl Into<PathBuf>, target: impl Into<PathBuf>) -> Self {
+            self.entries.insert(path.into(), MockEntry::Symlink(target.into()));
+            self
+        }
+
+        pub fn with_error(mut self, path: impl Into<PathBuf>, kind: io::ErrorKind) -> Self {
+            self.entries.insert(path.into(), MockEntry::Error(kind));
+            self
+        }
+
+        pub fn with_read_dir_error(mut self, path: impl Into<PathBuf>, kind: io::ErrorKind) -> Self {
+            self.read_dir_errors.insert(path.into(), kind);
+            self
+        }
+
+        /// How many paths have been stat'ed, listed, read as links, opened or checked
+        /// for access so far
+        pub fn lookups(&self) -> usize {
+            self.lookups.load(Ordering::Relaxed)
+        }
+
+        fn lookup(&self, path: &Path) -> io::Result<&MockEntry> {
+            self.lookups.fetch_add(1, Ordering::Relaxed);
+            match self.entries.get(path) {
+                Some(MockEntry::Error(kind)) => Err(io::Error::from(*kind)),
+                Some(entry) => Ok(entry),
+                None => Err(io::Error::from(io::ErrorKind::NotFound)),
+            }
+        }
+
+        // Follows symlinks to what they finally point at
+        fn resolve(&self, path: &Path) -> io::Result<(PathBuf, &MockEntry)> {
+            let mut path = path.to_path_buf();
+            // Give up on chains this long, the way the kernel reports a link loop
+            for _ in 0..40 {
+                match self.lookup(&path)? {
+                    MockEntry::Symlink(target) => {
+                        path = path.parent().unwrap_or(Path::new("")).join(target)
+                    }
+                    entry => return Ok((path, entry)),
+                }
+            }
+            Err(io::Error::other("too many levels of symbolic links"))
+        }
+    }
+
+    impl FileSystem for MockFileSystem {
+        fn metadata(&self, path: &Path) -> io::Result<FileStat> {
+            let (path, _) = self.resolve(path)?;
+            self.symlink_metadata(&path)
+        }
+
+        fn symlink_metadata(&self, path: &Path) -> io::Result<FileStat> {
+            match self.lookup(path)? {
+                MockEntry::File(stat) => Ok(stat.clone()),
+                MockEntry::Symlink(target) => Ok(FileStat {
+                    file_type: Some(FileType::SymbolicLink),
+                    len: target.as_os_str().len() as u64,
+                    ..FileStat::default()
+                }),
+                MockEntry::Error(_) => unreachable!("lookup returns errors itself"),
+            }
+        }
+
+        fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+            match self.lookup(path)? {
+                MockEntry::Symlink(target) => Ok(target.clone()),
+                _ => Err(io::Error::from(io::ErrorKind::InvalidInput)),
+            }
+        }
+
+        fn read_dir(&self, path: &Path) -> io::Result<Box<dyn Iterator<Item = io::Result<PathBuf>> + '_>> {
+            if !self.symlink_metadata(path)?.is_dir() {
+                return Err(io::Error::from(io::ErrorKind::NotADirectory));
+            }
+            if let Some(kind) = self.read_dir_errors.get(path) {
+                return Err(io::Error::from(*kind));
+            }
+            let path = path.to_path_buf();
+            let children = self.entries.keys().filter(move |child| child.parent() == Some(&path));
+            Ok(Box::new(children.map(|child| Ok(child.clone()))))
+        }
+
+        fn open(&self, path: &Path) -> io::Result<Box<dyn Read + '_>> {
+            match self.resolve(path)? {
+                (_, MockEntry::File(stat)) if stat.is_dir() => Err(io::Error::from(io::ErrorKind::IsADirectory)),
+                (path, _) => Ok(Box::new(self.contents.get(&path).map_or(&[][..], Vec::as_slice))),
+            }
+        }
+
+        fn access(&self, path: &Path, access: Access) -> bool {
+            let bit = match access {
+                Access::Read => 0o400,
+                Access::Write => 0o200,
+                Access::Execute => 0o100,
+            };
+            self.metadata(path).is_ok_and(|stat| stat.mode & bit != 0)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn dir() -> FileStat {
+        FileStat { file_type: Some(FileType::Directory), ..FileStat::default() }
+    }
+
+    #[test]
+    fn test_real_metadata() {
+        let stat = RealFileSystem.metadata(Path::new("Cargo.toml")).unwrap();
+        let metadata = fs::metadata("Cargo.toml").unwrap();
+        assert!(stat.is_file());
+        assert_eq!(stat.len, metadata.len());
+        assert_eq!(stat.modified, metadata.modified().ok());
+        assert!(RealFileSystem.metadata(Path::new("src")).unwrap().is_dir());
+
+        let listed: Vec<PathBuf> = RealFileSystem.read_dir(Path::new("src")).unwrap()
+            .map(|path| path.unwrap())
+            .collect();
+        assert!(listed.contains(&PathBuf::from("src/filesystem.rs")));
+
+        assert!(RealFileSystem.access(Path::new("Cargo.toml"), Access::Read));
+        assert!(RealFileSystem.access(Path::new("src"), Access::Execute));
+        assert!(!RealFileSystem.access(Path::new("no/such/file"), Access::Read));
+    }
+
+    #[test]
+    fn test_mock_lookups() {
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let file = FileStat {
+            file_type: Some(FileType::RegularFile),
+            len: 3,
+            modified: Some(modified),
+            ..FileStat::default()
+        };
+        let mock = MockFileSystem::new()
+            .with_file("dir", dir())
+            .with_file("dir/file", file.clone())
+            .with_symlink("dir/link", "file")
+            .with_symlink("dir/broken", "missing")
+            .with_symlink("dir/loop", "loop")
+            .with_error("secret", io::ErrorKind::PermissionDenied)
+            .with_file("locked", dir())
+            .with_read_dir_error("locked", io::ErrorKind::PermissionDenied);
+
+        assert_eq!(mock.metadata(Path::new("dir/file")).unwrap(), file);
+        assert_eq!(mock.metadata(Path::new("dir/link")).unwrap(), file);
+        let link = mock.symlink_metadata(Path::new("dir/link")).unwrap();
+        assert_eq!(link.file_type, Some(FileType::SymbolicLink));
+        assert_eq!(mock.read_link(Path::new("dir/link")).unwrap(), PathBuf::from("file"));
+
+        let not_found = mock.metadata(Path::new("dir/broken")).unwrap_err();
+        assert_eq!(not_found.kind(), io::ErrorKind::NotFound);
+        assert!(mock.symlink_metadata(Path::new("dir/broken")).is_ok());
+        assert!(mock.metadata(Path::new("dir/loop")).is_err());
+        let denied = mock.metadata(Path::new("secret")).unwrap_err();
+        assert_eq!(denied.kind(), io::ErrorKind::PermissionDenied);
+
+        let mut listed: Vec<PathBuf> = mock.read_dir(Path::new("dir")).unwrap()
+            .map(|path| path.unwrap())
+            .collect();
+        listed.sort();
+        assert_eq!(listed, ["dir/broken", "dir/file", "dir/link", "dir/loop"].map(PathBuf::from));
+        assert!(mock.read_dir(Path::new("dir/file")).is_err());
+        assert!(mock.metadata(Path::new("locked")).unwrap().is_dir());
+        let denied = mock.read_dir(Path::new("locked")).err().unwrap();
+        assert_eq!(denied.kind(), io::ErrorKind::PermissionDenied);
+    }
+
+    #[test]
+    fn test_open() {
+        let read = |file_system: &dyn FileSystem, path: &str| {
+            let mut contents = String::new();
+            file_system.open(Path::new(path))?.read_to_string(&mut contents)?;
+            io::Result::Ok(contents)
+        };
+        assert!(read(&RealFileSystem, "Cargo.toml").unwrap().contains("[package]"));
+        assert!(read(&RealFileSystem, "no/such/file").is_err());
+
+        let mock = MockFileSystem::new()
+            .with_file("dir", dir())
+            .with_contents("dir/notes.txt", "hello")
+            .with_symlink("dir/link", "notes.txt")
+            .with_file("dir/blank", FileStat { file_type: Some(FileType::RegularFile), ..FileStat::default() });
+        assert_eq!(mock.metadata(Path::new("dir/notes.txt")).unwrap().len, 5);
+        assert_eq!(read(&mock, "dir/notes.txt").unwrap(), "hello");
+        assert_eq!(read(&mock, "dir/link").unwrap(), "hello");
+        assert_eq!(read(&mock, "dir/blank").unwrap(), "");
+        assert!(read(&mock, "dir").is_err());
+        assert!(read(&mock, "dir/missing").is_err());
+    }
+}
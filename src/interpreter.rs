@@ -1,330 +1,1215 @@
 use walkdir::DirEntry;
-use std::time::SystemTime;
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
 use std::fs;
-use regex::Regex;
-use glob::Pattern;
+use std::io::{self, BufRead, BufReader, Read};
+use std::process::{Command, Stdio};
+use glob::{MatchOptions, Pattern};
+use std::rc::Rc;
+use std::sync::Arc;
 use crate::ast::*;
+use crate::csv;
+use crate::datetime::{format_ctime, format_datetime, local_midnight};
+use crate::filesystem::{classify_file_type, Access, FileStat, FileSystem, RealFileSystem};
+use crate::listing::{mode_string, Listing};
+use crate::output::os_str_bytes;
+use crate::predicates::PredicateRegistry;
+use crate::profile::Profile;
 //use libc;
 
-#[cfg(unix)]
-use std::os::unix::fs::MetadataExt;
 #[cfg(unix)]
 use file_owner::PathExt;
 
-/// Interpreter for evaluating AST expressions against directory entries
-pub struct Interpreter;
+// Units for the -Xmin and -Xtime tests, in seconds
+const MINUTE: u64 = 60;
+const DAY: u64 = 24 * 60 * 60;
+
+/// Called before each -exec command is started with the output actions have printed
+/// for the entry so far, to write it out ahead of the command's own. Returns where the
+/// command's standard output should go
+pub type ExecStdout = dyn Fn(&[u8]) -> io::Result<Stdio>;
+
+/// What evaluating an expression against one entry came to
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EvalOutcome {
+    /// Whether the expression was true for the entry
+    pub matched: bool,
+    /// -prune was evaluated, so the walk shouldn't descend into the entry if it is a
+    /// directory
+    pub prune: bool,
+    /// -quit was evaluated, so the walk should stop, along with the walks of any other
+    /// start paths. Actions after it in the expression were skipped
+    pub quit: bool,
+}
+
+/// How many entries -delete removed, and how many it tried to but couldn't
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DeleteSummary {
+    pub files: u64,
+    pub directories: u64,
+    pub failed: u64,
+}
+
+impl DeleteSummary {
+    /// Adds the counts from another summary, e.g. one kept for another start path
+    pub fn merge(&mut self, other: DeleteSummary) {
+        self.files += other.files;
+        self.directories += other.directories;
+        self.failed += other.failed;
+    }
+}
+
+impl fmt::Display for DeleteSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let plural = |count: u64, one: &'static str, many: &'static str| if count == 1 { one } else { many };
+        write!(
+            f,
+            "deleted {} {} and {} {}, {} failed",
+            self.files,
+            plural(self.files, "file", "files"),
+            self.directories,
+            plural(self.directories, "directory", "directories"),
+            self.failed,
+        )
+    }
+}
+
+/// Replaces every `{}` in an -exec word with the path, so `{}.bak` and `prefix-{}` work
+/// as well as a bare `{}`, as in GNU find. The path is kept as the OS gave it, even if
+/// it isn't valid UTF-8
+fn substitute_path(word: &str, path: &OsStr) -> OsString {
+    let mut substituted = OsString::with_capacity(word.len());
+    for (i, part) in word.split("{}").enumerate() {
+        if i > 0 {
+            substituted.push(path);
+        }
+        substituted.push(part);
+    }
+    substituted
+}
+
+/// Interpreter for evaluating AST expressions against the entries found under one
+/// start path
+pub struct Interpreter {
+    /// Start path as given on the command line, for %H and %P
+    start: PathBuf,
+    /// When the run started, captured once so every entry is judged against the same time
+    now: SystemTime,
+    /// What the -amin/-atime family measure ages from: `now`, or with -daystart the end
+    /// of the local day `now` falls in
+    ages_from: SystemTime,
+    /// Predicates for -custom tests, if any were registered
+    predicates: Option<Arc<PredicateRegistry>>,
+    /// Files bigger than this many bytes are never read by content tests like -magic
+    max_content_size: Option<u64>,
+    /// Whether -regex and -iregex see the path with the start path stripped, as in %P
+    relative_regex: bool,
+    /// Whether -path and -ipath also try the path without a leading "./"
+    strip_dot_slash: bool,
+    /// Whether -printf's %s prints sizes like 1.5K rather than in bytes
+    human_sizes: bool,
+    /// Bytes written by -print, -printf and -ls, until collected with take_output
+    output: RefCell<Vec<u8>>,
+    /// Errors met while evaluating tests, such as a directory -empty couldn't list,
+    /// until collected with take_errors
+    errors: RefCell<Vec<String>>,
+    /// Column widths and owner names for -ls
+    listing: Listing,
+    /// Where tests look up the metadata of the entries they are given
+    file_system: Arc<dyn FileSystem>,
+    /// Answers to -readable, -writable and -executable for the entry being evaluated,
+    /// so an expression that asks the same twice makes one access(2) call. Forgotten
+    /// when the next evaluation starts
+    access_checks: RefCell<Vec<(Access, bool)>>,
+    /// The last entry stat'ed and what the stat gave, so the tests, -printf and -ls of
+    /// one evaluation share a single stat, as in GNU find. Forgotten when the next
+    /// evaluation starts, so a file changed since is stat'ed again
+    last_metadata: RefCell<Option<(PathBuf, Option<FileStat>)>>,
+    /// Total size in bytes of each directory -treesize has summed. Summing a directory
+    /// walks its whole subtree, so every directory total found along the way is kept.
+    /// The walk is pre-order, so a directory is summed before its subdirectories are
+    /// visited, and their own tests are then answered from here
+    tree_totals: RefCell<HashMap<PathBuf, u64>>,
+    /// What -delete has removed and failed to, until collected with take_deletions
+    deletions: RefCell<DeleteSummary>,
+    /// Where -exec commands write, if not into `output`
+    exec_stdout: Option<Rc<ExecStdout>>,
+    /// Whether -print and %p print paths made absolute, as --absolute-paths asks
+    absolute_paths: bool,
+    /// Whether those absolute paths also have symlinks resolved, as with -L
+    canonical_paths: bool,
+    /// The device -xdev keeps the walk on, if it does
+    walk_device: Option<u64>,
+    /// Counts and timings per predicate, only kept when profiling
+    profile: Option<RefCell<Profile>>,
+}
 
 impl Interpreter {
-    /// Evaluates an AST expression against a directory entry
-    pub fn evaluate(expr: &Expr, entry: &DirEntry) -> bool {
+    pub fn new(start: &Path, now: SystemTime) -> Self {
+        Interpreter {
+            start: start.to_path_buf(),
+            now,
+            ages_from: now,
+            predicates: None,
+            max_content_size: None,
+            relative_regex: false,
+            strip_dot_slash: false,
+            human_sizes: false,
+            output: RefCell::new(Vec::new()),
+            errors: RefCell::new(Vec::new()),
+            listing: Listing::default(),
+            file_system: Arc::new(RealFileSystem),
+            access_checks: RefCell::new(Vec::new()),
+            last_metadata: RefCell::new(None),
+            tree_totals: RefCell::new(HashMap::new()),
+            deletions: RefCell::new(DeleteSummary::default()),
+            exec_stdout: None,
+            absolute_paths: false,
+            canonical_paths: false,
+            walk_device: None,
+            profile: None,
+        }
+    }
+
+    /// Uses the registry to evaluate -custom tests, which otherwise never match
+    pub fn with_predicates(mut self, predicates: Arc<PredicateRegistry>) -> Self {
+        self.predicates = Some(predicates);
+        self
+    }
+
+    /// Makes content tests like -magic skip, and not match, files over `limit` bytes
+    pub fn with_max_content_size(mut self, limit: u64) -> Self {
+        self.max_content_size = Some(limit);
+        self
+    }
+
+    /// Makes -regex and -iregex match the path below the start path, as %P prints it,
+    /// so `src/.*` matches ./src/main.rs when the start path is "."
+    pub fn with_relative_regex(mut self) -> Self {
+        self.relative_regex = true;
+        self
+    }
+
+    /// Makes -path and -ipath also match the path with a leading "./" stripped, so
+    /// `src/*` matches ./src/main.rs as well as `./src/*` does. GNU find has no such
+    /// option, and there only `./src/*` matches
+    pub fn with_dot_slash_stripped(mut self) -> Self {
+        self.strip_dot_slash = true;
+        self
+    }
+
+    /// Makes the -amin/-atime family measure ages from the end of today rather than from
+    /// now, so `-mtime 0` is anything changed today and `-mtime 1` yesterday. As in GNU
+    /// find the day ends 24 hours after local midnight
+    pub fn with_day_start(mut self) -> Self {
+        if let Some(midnight) = local_midnight(self.now) {
+            // The last instant of the day, so a file changed at midnight is 0 days old
+            self.ages_from = midnight + Duration::from_secs(24 * 60 * 60) - Duration::from_nanos(1);
+        }
+        self
+    }
+
+    /// Makes -printf's %s print sizes in 1024-based units like `ls -h`, such as 1.5K
+    /// or 3.0G, rather than in bytes
+    pub fn with_human_sizes(mut self) -> Self {
+        self.human_sizes = true;
+        self
+    }
+
+    /// Makes -ls print owners and groups as numeric ids, like `ls -n`, without looking
+    /// up their names
+    pub fn with_numeric_ids(mut self) -> Self {
+        self.listing = Listing::new(true);
+        self
+    }
+
+    /// Lets -exec commands write straight to where `exec_stdout` says, as they run,
+    /// instead of having what they print collected with take_output once they finish
+    pub fn with_exec_stdout(mut self, exec_stdout: Rc<ExecStdout>) -> Self {
+        self.exec_stdout = Some(exec_stdout);
+        self
+    }
+
+    /// Makes -print and %p print absolute paths, joined onto the working directory, or
+    /// with `canonicalize` also with symlinks resolved
+    pub fn with_absolute_paths(mut self, canonicalize: bool) -> Self {
+        self.absolute_paths = true;
+        self.canonical_paths = canonicalize;
+        self
+    }
+
+    /// Tells -samefile that the walk stays on `device`, as with -xdev, so when the
+    /// reference file is on another device only directories, which may be mount points,
+    /// and entries reached through symlinks need a stat. This assumes a file is on its
+    /// directory's device, which a file bind-mounted from elsewhere is not; -xdev only
+    /// checks directories, so it misses those too
+    pub fn with_walk_device(mut self, device: u64) -> Self {
+        self.walk_device = Some(device);
+        self
+    }
+
+    /// Looks up entry metadata in `file_system` instead of on disk, mostly for tests
+    pub fn with_file_system(mut self, file_system: Arc<dyn FileSystem>) -> Self {
+        self.file_system = file_system;
+        self
+    }
+
+    /// Times every test evaluation, for collecting with take_profile. Without this
+    /// nothing is counted or timed
+    pub fn with_profiling(mut self) -> Self {
+        self.profile = Some(RefCell::new(Profile::new()));
+        self
+    }
+
+    /// Returns the profile collected so far, leaving it empty, or None if not profiling
+    pub fn take_profile(&self) -> Option<Profile> {
+        self.profile.as_ref().map(|profile| profile.take())
+    }
+
+    /// Returns the bytes printed by actions since the last call, leaving the buffer empty.
+    /// They are kept as bytes so a NUL from `-printf '\0'` reaches the output intact
+    pub fn take_output(&self) -> Vec<u8> {
+        self.output.take()
+    }
+
+    /// Returns the errors met since the last call, each naming the path it is about
+    pub fn take_errors(&self) -> Vec<String> {
+        self.errors.take()
+    }
+
+    /// Returns what -delete has done since the last call, leaving the counts at zero
+    pub fn take_deletions(&self) -> DeleteSummary {
+        self.deletions.take()
+    }
+
+    /// Evaluates an AST expression against a directory entry, returning whether it
+    /// matched along with what -prune and -quit asked the walk to do
+    pub fn evaluate(&self, expr: &Expr, entry: &DirEntry) -> EvalOutcome {
+        self.access_checks.borrow_mut().clear();
+        self.last_metadata.take();
+        let mut outcome = EvalOutcome::default();
+        outcome.matched = self.evaluate_into(expr, entry, &mut outcome);
+        outcome
+    }
+
+    /// Returns whether an AST expression matches a directory entry, for callers with no
+    /// walk for -prune or -quit to steer
+    pub fn matches(&self, expr: &Expr, entry: &DirEntry) -> bool {
+        self.evaluate(expr, entry).matched
+    }
+
+    fn evaluate_into(&self, expr: &Expr, entry: &DirEntry, outcome: &mut EvalOutcome) -> bool {
         match expr {
-            Expr::Not(inner) => !Self::evaluate(inner, entry),
-            Expr::And(left, right) => Self::evaluate(left, entry) && Self::evaluate(right, entry),
-            Expr::Or(left, right) => Self::evaluate(left, entry) || Self::evaluate(right, entry),
-            Expr::Test(test) => Self::evaluate_test(test, entry),
+            Expr::Not(inner) => !self.evaluate_into(inner, entry, outcome),
+            Expr::And(left, right) => {
+                self.evaluate_into(left, entry, outcome) && self.evaluate_into(right, entry, outcome)
+            }
+            Expr::Or(left, right) => {
+                self.evaluate_into(left, entry, outcome) || self.evaluate_into(right, entry, outcome)
+            }
+            Expr::Test(test) => self.evaluate_test(test, entry),
+            Expr::Action(action) => self.perform_action(action, entry, outcome),
         }
     }
 
-    fn evaluate_test(test: &Test, entry: &DirEntry) -> bool {
-        match test {
-            Test::Path(pattern) => Self::match_path(pattern, entry, false),
-            Test::Name(pattern) => Self::match_name(pattern, entry, false),
-            Test::Iname(pattern) => Self::match_name(pattern, entry, true),
-            Test::Regex(pattern) => Self::match_regex(pattern, entry),
-            Test::True => true,
-            Test::False => false,
-            Test::Type(file_type) => Self::match_type(file_type, entry),
-            Test::Size(size_spec) => Self::match_size(size_spec, entry),
-            Test::Empty => Self::match_empty(entry),
-            Test::Amin(time_spec) => Self::match_amin(time_spec, entry),
-            Test::Atime(time_spec) => Self::match_atime(time_spec, entry),
-            Test::Ctime(time_spec) => Self::match_ctime(time_spec, entry),
-            Test::Cmin(time_spec) => Self::match_cmin(time_spec, entry),
-            Test::Mmin(time_spec) => Self::match_mmin(time_spec, entry),
-            Test::Mtime(time_spec) => Self::match_mtime(time_spec, entry),
-            Test::Anewer(filepath) => Self::match_anewer(filepath, entry),
-            Test::Cnewer(filepath) => Self::match_cnewer(filepath, entry),
-            Test::Mnewer(filepath) => Self::match_mnewer(filepath, entry),
-            Test::Newer(filepath) => Self::match_newer(filepath, entry),
-            Test::Ipath(pattern) => Self::match_path(pattern, entry, true),
-            Test::Iregex(pattern) => Self::match_iregex(pattern, entry),
-            Test::User(username) => Self::match_user(username, entry),
-            Test::Group(groupname) => Self::match_group(groupname, entry),
-            Test::Uid(uid) => Self::match_uid(*uid, entry),
-            Test::Gid(gid) => Self::match_gid(*gid, entry),
-            Test::Perm(perm_spec) => Self::match_perm(perm_spec, entry),
-        }
-    }
-
-    // Helper function for glob pattern matching
-    fn match_glob_pattern(pattern: &str, target: &str, case_insensitive: bool) -> bool {
-        match Pattern::new(pattern) {
-            Ok(glob_pattern) => {
-                if case_insensitive {
-                    glob_pattern.matches(&target.to_lowercase())
-                } else {
-                    glob_pattern.matches(target)
+    /// Evaluates like `evaluate`, but on a match also returns the leaf tests that made it
+    /// true: both sides of an -and, and the first true side of an -or. Tests under a
+    /// satisfied -not were false, so they are not listed, and nothing is listed when
+    /// the entry doesn't match. This is slower than `evaluate` and only used for --explain.
+    pub fn explain<'a>(&self, expr: &'a Expr, entry: &DirEntry) -> (EvalOutcome, Vec<&'a Test>) {
+        self.access_checks.borrow_mut().clear();
+        self.last_metadata.take();
+        let mut outcome = EvalOutcome::default();
+        let mut tests = Vec::new();
+        outcome.matched = self.explain_into(expr, entry, &mut tests, &mut outcome);
+        (outcome, tests)
+    }
+
+    fn explain_into<'a>(
+        &self,
+        expr: &'a Expr,
+        entry: &DirEntry,
+        tests: &mut Vec<&'a Test>,
+        outcome: &mut EvalOutcome,
+    ) -> bool {
+        let mark = tests.len();
+        let matched = match expr {
+            Expr::Not(inner) => !self.explain_into(inner, entry, &mut Vec::new(), outcome),
+            Expr::And(left, right) => {
+                self.explain_into(left, entry, tests, outcome) && self.explain_into(right, entry, tests, outcome)
+            }
+            Expr::Or(left, right) => {
+                self.explain_into(left, entry, tests, outcome) || {
+                    tests.truncate(mark);
+                    self.explain_into(right, entry, tests, outcome)
                 }
             }
-            Err(_) => false,
+            Expr::Test(test) => {
+                let matched = self.evaluate_test(test, entry);
+                if matched {
+                    tests.push(test);
+                }
+                matched
+            }
+            Expr::Action(action) => self.perform_action(action, entry, outcome),
+        };
+        if !matched {
+            tests.truncate(mark);
         }
+        matched
     }
 
-    // Helper function for getting file metadata
-    fn get_metadata(entry: &DirEntry) -> Option<std::fs::Metadata> {
-        entry.metadata().ok()
+    // Once -quit has been evaluated actions do nothing and are false, so nothing after
+    // it in the expression takes effect
+    fn perform_action(&self, action: &Action, entry: &DirEntry, outcome: &mut EvalOutcome) -> bool {
+        if outcome.quit {
+            return false;
+        }
+        match action {
+            Action::Printf(directives) => {
+                let rendered = self.render_format(directives, entry);
+                self.output.borrow_mut().extend_from_slice(&rendered);
+                true
+            }
+            Action::Print => {
+                let mut output = self.output.borrow_mut();
+                output.extend_from_slice(&os_str_bytes(self.printed_path(entry).as_os_str()));
+                output.push(b'\n');
+                true
+            }
+            Action::Prune => {
+                outcome.prune = true;
+                true
+            }
+            Action::Quit => {
+                outcome.quit = true;
+                true
+            }
+            Action::Ls => {
+                let line = self.render_ls(entry);
+                let mut output = self.output.borrow_mut();
+                output.extend_from_slice(line.as_bytes());
+                output.push(b'\n');
+                true
+            }
+            Action::Exec(command) => self.run_command(command, entry),
+            Action::Delete => self.delete(entry),
+        }
     }
 
-    // Helper function for time-based comparisons
-    fn compare_time_spec(file_time: SystemTime, time_spec: &TimeSpec, time_unit_seconds: u64) -> bool {
-        let now = SystemTime::now();
-        let time_diff = match now.duration_since(file_time) {
-            Ok(duration) => duration,
-            Err(_) => return false, // File time in the future
+    // Removes the entry for -delete. A symlink is removed itself, even when the walk
+    // follows it, and a directory only if it is empty, which it is by now unless some
+    // of its contents weren't deleted. As in GNU find a start path such as . that names
+    // no entry of its own is left alone, and is true without being counted
+    fn delete(&self, entry: &DirEntry) -> bool {
+        let path = entry.path();
+        if path.file_name().is_none() {
+            return true;
+        }
+        let is_dir = entry.file_type().is_dir() && !entry.path_is_symlink();
+        let result = if is_dir { fs::remove_dir(path) } else { fs::remove_file(path) };
+        let mut deletions = self.deletions.borrow_mut();
+        match result {
+            Ok(()) if is_dir => deletions.directories += 1,
+            Ok(()) => deletions.files += 1,
+            Err(e) => {
+                deletions.failed += 1;
+                self.errors.borrow_mut().push(format!("cannot delete {}: {}", path.display(), e));
+                return false;
+            }
+        }
+        true
+    }
+
+    // Runs an -exec command for the entry. Its output goes where with_exec_stdout says,
+    // after what -print and the like have written so far, or else is collected with
+    // theirs; stdin and stderr are findr's own. A command that can't be started is
+    // reported and is false, like one that fails
+    fn run_command(&self, command: &[String], entry: &DirEntry) -> bool {
+        let Some((program, arguments)) = command.split_first() else { return false };
+        let path = entry.path().as_os_str();
+        let stdout = match &self.exec_stdout {
+            Some(exec_stdout) => exec_stdout(&self.output.take()),
+            None => Ok(Stdio::piped()),
         };
+        let result = stdout.and_then(|stdout| {
+            Command::new(substitute_path(program, path))
+                .args(arguments.iter().map(|argument| substitute_path(argument, path)))
+                .stdin(Stdio::inherit())
+                .stdout(stdout)
+                .stderr(Stdio::inherit())
+                .spawn()
+        }).and_then(|child| child.wait_with_output());
+        match result {
+            Ok(output) => {
+                self.output.borrow_mut().extend_from_slice(&output.stdout);
+                output.status.success()
+            }
+            Err(e) => {
+                self.errors.borrow_mut().push(format!("{}: {}", program, e));
+                false
+            }
+        }
+    }
 
-        let time_ago = time_diff.as_secs() / time_unit_seconds;
-        let target_time = time_spec.value;
+    /// Returns the path -print and %p print for the entry, which is the path as found
+    /// unless with_absolute_paths was used. A path that can't be made absolute, e.g.
+    /// because the entry has just been removed, is printed as found
+    pub fn printed_path<'a>(&self, entry: &'a DirEntry) -> Cow<'a, Path> {
+        if !self.absolute_paths {
+            return Cow::Borrowed(entry.path());
+        }
+        let absolute = if self.canonical_paths { fs::canonicalize(entry.path()) } else { std::path::absolute(entry.path()) };
+        absolute.map_or(Cow::Borrowed(entry.path()), Cow::Owned)
+    }
 
-        match time_spec.sign {
-            Sign::None => time_ago == target_time,
-            Sign::Plus => time_ago > target_time,
-            Sign::Minus => time_ago < target_time,
+    /// Renders the -ls line for a directory entry, without the newline. An entry that
+    /// can't be stat'ed is listed with "?" for everything but its path
+    pub fn render_ls(&self, entry: &DirEntry) -> String {
+        let mut path = entry.path().to_string_lossy().into_owned();
+        if entry.file_type().is_symlink()
+            && let Ok(target) = self.file_system.read_link(entry.path()) {
+            path.push_str(" -> ");
+            path.push_str(&target.to_string_lossy());
+        }
+        match self.get_metadata(entry) {
+            Some(metadata) => self.listing.line(&metadata, &path, self.now),
+            None => format!("? {}", path),
         }
     }
 
-    // Helper function for newer-style comparisons
-    fn compare_file_times<F>(entry: &DirEntry, filepath: &str, time_getter: F) -> bool 
-    where
-        F: Fn(&std::fs::Metadata) -> Result<SystemTime, std::io::Error>,
-    {
-        let entry_metadata = match Self::get_metadata(entry) {
-            Some(metadata) => metadata,
-            None => return false,
-        };
-        let entry_time = match time_getter(&entry_metadata) {
-            Ok(time) => time,
-            Err(_) => return false,
+    /// Renders the --csv row for a directory entry, in the columns of `csv::HEADER`,
+    /// without the newline. `path` is printed as given, e.g. made absolute, as the bytes
+    /// `os_str_bytes` gives. An entry that can't be stat'ed has only its path and type
+    /// filled in
+    pub fn render_csv(&self, entry: &DirEntry, path: &OsStr) -> Vec<u8> {
+        let path = os_str_bytes(path);
+        let file_type = Self::file_type_letter(classify_file_type(entry.file_type()));
+        let Some(metadata) = self.get_metadata(entry) else {
+            return csv::record(&[&path, b"", file_type.as_bytes(), b"", b"", b"", b""]);
         };
+        let modified = metadata.modified.and_then(format_datetime).unwrap_or_default();
+        csv::record(&[
+            &path,
+            metadata.len.to_string().as_bytes(),
+            file_type.as_bytes(),
+            modified.as_bytes(),
+            metadata.uid.to_string().as_bytes(),
+            metadata.gid.to_string().as_bytes(),
+            mode_string(&metadata).as_bytes(),
+        ])
+    }
 
-        let reference_metadata = match fs::metadata(filepath) {
-            Ok(metadata) => metadata,
-            Err(_) => return false,
-        };
-        let reference_time = match time_getter(&reference_metadata) {
-            Ok(time) => time,
-            Err(_) => return false,
+    /// Renders parsed -printf directives for a directory entry. Paths and names are
+    /// rendered as the bytes `os_str_bytes` gives
+    pub fn render_format(&self, directives: &[FormatDirective], entry: &DirEntry) -> Vec<u8> {
+        // A format of paths and names alone is rendered without a stat
+        let metadata = if directives.iter().any(FormatDirective::needs_metadata) {
+            self.get_metadata(entry)
+        } else {
+            None
         };
+        let mut out = Vec::new();
+
+        for directive in directives {
+            match directive {
+                FormatDirective::Literal(text) => out.extend_from_slice(text.as_bytes()),
+                FormatDirective::Path => out.extend_from_slice(&os_str_bytes(self.printed_path(entry).as_os_str())),
+                FormatDirective::StartPath => out.extend_from_slice(&os_str_bytes(self.start.as_os_str())),
+                FormatDirective::RelativePath => {
+                    // The start path itself renders as an empty string, like GNU find
+                    let relative = entry.path().strip_prefix(&self.start).unwrap_or(entry.path());
+                    out.extend_from_slice(&os_str_bytes(relative.as_os_str()))
+                }
+                FormatDirective::Basename => out.extend_from_slice(&os_str_bytes(entry.file_name())),
+                FormatDirective::Dirname => {
+                    // Like GNU find, a path without a directory part renders as "."
+                    match entry.path().parent() {
+                        Some(parent) if !parent.as_os_str().is_empty() => {
+                            out.extend_from_slice(&os_str_bytes(parent.as_os_str()))
+                        }
+                        _ => out.push(b'.'),
+                    }
+                }
+                FormatDirective::Size => match &metadata {
+                    Some(metadata) if self.human_sizes => out.extend_from_slice(Self::humanize(metadata.len).as_bytes()),
+                    Some(metadata) => out.extend_from_slice(metadata.len.to_string().as_bytes()),
+                    None => out.push(b'?'),
+                },
+                FormatDirective::Sparseness => match &metadata {
+                    Some(metadata) => out.extend_from_slice(Self::sparseness(metadata).as_bytes()),
+                    None => out.push(b'?'),
+                },
+                FormatDirective::Depth => out.extend_from_slice(entry.depth().to_string().as_bytes()),
+                FormatDirective::Inode | FormatDirective::LinkCount => {
+                    out.extend_from_slice(Self::format_inode_field(metadata.as_ref(), directive).as_bytes())
+                }
+                FormatDirective::Type => {
+                    out.extend_from_slice(Self::file_type_letter(classify_file_type(entry.file_type())).as_bytes())
+                }
+                FormatDirective::TargetType => {
+                    let target = self.file_system.metadata(entry.path()).ok()
+                        .and_then(|metadata| metadata.file_type);
+                    out.extend_from_slice(Self::file_type_letter(target).as_bytes())
+                }
+                FormatDirective::Time(field) => {
+                    match metadata.as_ref().and_then(|metadata| metadata.time(*field)).and_then(format_ctime) {
+                        Some(time) => out.extend_from_slice(time.as_bytes()),
+                        None => out.push(b'?'),
+                    }
+                }
+            }
+        }
+
+        out
+    }
+
+    /// Formats a byte count in 1024-based units like `ls -h`: plain bytes below 1K,
+    /// one decimal place below 10 units and whole units above that
+    fn humanize(bytes: u64) -> String {
+        const UNITS: [&str; 6] = ["K", "M", "G", "T", "P", "E"];
+
+        if bytes < 1024 {
+            return bytes.to_string();
+        }
+
+        let mut value = bytes as f64 / 1024.0;
+        let mut unit = 0;
+        // Promote before rounding could print something like "1024K"
+        while value >= 1023.5 && unit < UNITS.len() - 1 {
+            value /= 1024.0;
+            unit += 1;
+        }
 
-        entry_time > reference_time
+        if value < 9.95 {
+            format!("{:.1}{}", value, UNITS[unit])
+        } else {
+            format!("{:.0}{}", value, UNITS[unit])
+        }
     }
 
-    fn match_path(pattern: &str, entry: &DirEntry, case_insensitive: bool) -> bool {
-        let file_name = entry.path().to_string_lossy();
-        Self::match_glob_pattern(pattern, &file_name, case_insensitive)
+    /// Formats the allocated space over the size as GNU find's %S does, with C's %g:
+    /// 1 for an empty file without blocks, and "inf" for one with them
+    fn sparseness(metadata: &FileStat) -> String {
+        if metadata.len == 0 {
+            return if metadata.blocks == 0 { "1" } else { "inf" }.to_string();
+        }
+        let ratio = (metadata.blocks as f64 * 512.0) / metadata.len as f64;
+
+        // %g keeps 6 significant digits, switching to an exponent for very small or large
+        // values, and drops trailing zeros either way
+        let trim = |digits: String| {
+            if digits.contains('.') {
+                digits.trim_end_matches('0').trim_end_matches('.').to_string()
+            } else {
+                digits
+            }
+        };
+        let scientific = format!("{:.5e}", ratio);
+        let (mantissa, exponent) = scientific.split_once('e').unwrap_or((&scientific, "0"));
+        let exponent: i32 = exponent.parse().unwrap_or(0);
+        if (-4..6).contains(&exponent) {
+            trim(format!("{:.*}", (5 - exponent) as usize, ratio))
+        } else {
+            let sign = if exponent < 0 { '-' } else { '+' };
+            format!("{}e{}{:02}", trim(mantissa.to_string()), sign, exponent.abs())
+        }
     }
 
-    fn match_name(pattern: &str, entry: &DirEntry, case_insensitive: bool) -> bool {
-        let file_name = entry.file_name().to_string_lossy();
-        Self::match_glob_pattern(pattern, &file_name, case_insensitive)
+    fn file_type_letter(file_type: Option<FileType>) -> String {
+        match file_type {
+            Some(file_type) => file_type.to_string(),
+            None => "?".to_string(),
+        }
     }
 
-    fn match_regex(pattern: &str, entry: &DirEntry) -> bool {
-        let anchored_pattern = format!("^{}$", pattern);
-        match Regex::new(&anchored_pattern) {
-            Ok(regex) => {
-                let path_str = entry.path().to_string_lossy();
-                regex.is_match(&path_str)
+    // Helper function for Unix-only inode fields, which render as "?" elsewhere
+    fn format_inode_field(metadata: Option<&FileStat>, directive: &FormatDirective) -> String {
+        #[cfg(unix)]
+        {
+            match (metadata, directive) {
+                (Some(metadata), FormatDirective::Inode) => metadata.ino.to_string(),
+                (Some(metadata), FormatDirective::LinkCount) => metadata.nlink.to_string(),
+                _ => "?".to_string(),
             }
-            Err(_) => false,
+        }
+
+        #[cfg(not(unix))]
+        {
+            let _ = (metadata, directive);
+            "?".to_string()
         }
     }
 
-    fn match_iregex(pattern: &str, entry: &DirEntry) -> bool {
-        // Create case-insensitive regex by prefixing with (?i)
-        let case_insensitive_pattern = format!("(?i)^{}$", pattern);
-        match Regex::new(&case_insensitive_pattern) {
-            Ok(regex) => {
-                let path_str = entry.path().to_string_lossy();
-                regex.is_match(&path_str)
+    fn evaluate_test(&self, test: &Test, entry: &DirEntry) -> bool {
+        match &self.profile {
+            None => self.run_test(test, entry),
+            Some(profile) => {
+                let start = Instant::now();
+                let matched = self.run_test(test, entry);
+                profile.borrow_mut().record(test.name(), matched, start.elapsed());
+                matched
+            }
+        }
+    }
+
+    fn run_test(&self, test: &Test, entry: &DirEntry) -> bool {
+        match test {
+            Test::Path(pattern) => Self::match_path(pattern, entry.path(), false, self.strip_dot_slash),
+            Test::Name(pattern) => Self::match_name(pattern, entry, false),
+            Test::Iname(pattern) => Self::match_name(pattern, entry, true),
+            Test::Regex(pattern) => self.match_regex(pattern, entry),
+            Test::True => true,
+            Test::False => false,
+            Test::Type(file_type) => self.match_type(file_type, entry),
+            Test::Size(size_spec) => self.match_size(size_spec, entry),
+            Test::Empty => self.match_empty(entry),
+            Test::Readable => self.match_access(Access::Read, entry),
+            Test::Writable => self.match_access(Access::Write, entry),
+            Test::Executable => self.match_access(Access::Execute, entry),
+            Test::Amin(time_spec) => self.match_age(TimeField::Access, time_spec, MINUTE, entry),
+            Test::Atime(time_spec) => self.match_age(TimeField::Access, time_spec, DAY, entry),
+            Test::Ctime(time_spec) => self.match_age(TimeField::Change, time_spec, DAY, entry),
+            Test::Cmin(time_spec) => self.match_age(TimeField::Change, time_spec, MINUTE, entry),
+            Test::Mmin(time_spec) => self.match_age(TimeField::Modify, time_spec, MINUTE, entry),
+            Test::Mtime(time_spec) => self.match_age(TimeField::Modify, time_spec, DAY, entry),
+            Test::Used(time_spec) => self.match_used(time_spec, entry),
+            Test::Anewer(reference) => self.match_newer_generic(TimeField::Access, reference, entry),
+            Test::Cnewer(reference) => self.match_newer_generic(TimeField::Change, reference, entry),
+            // -newer is an alias for -mnewer
+            Test::Mnewer(reference) | Test::Newer(reference) => {
+                self.match_newer_generic(TimeField::Modify, reference, entry)
+            }
+            Test::NewerAt(time) => self.compare_to_time(TimeField::Access, *time, entry),
+            Test::NewerCt(time) => self.compare_to_time(TimeField::Change, *time, entry),
+            Test::NewerMt(time) => self.compare_to_time(TimeField::Modify, *time, entry),
+            Test::Ipath(pattern) => Self::match_path(pattern, entry.path(), true, self.strip_dot_slash),
+            Test::Iregex(pattern) => self.match_regex(pattern, entry),
+            Test::User(username) => self.match_user(username, entry),
+            Test::Group(groupname) => self.match_group(groupname, entry),
+            Test::Uid(uid) => self.match_uid(*uid, entry),
+            Test::Links(spec) => self.get_metadata(entry).is_some_and(|metadata| spec.matches(metadata.nlink)),
+            Test::Gid(gid) => self.match_gid(*gid, entry),
+            Test::Perm(perm_spec) => self.match_perm(perm_spec, entry),
+            Test::Magic(mime_type) => self.match_magic(mime_type, entry),
+            Test::Grep(pattern) => self.match_grep(pattern, entry),
+            Test::TreeSize(size_spec) => self.match_tree_size(size_spec, entry),
+            // Applied to the walk itself, so deeper entries never get here
+            Test::MaxDepth(_) => true,
+            Test::AtDepth(depth) => entry.depth() == *depth,
+            Test::SameFile(reference) => self.match_same_file(reference, entry),
+            Test::Custom(name) => {
+                self.predicates.as_ref().is_some_and(|predicates| predicates.evaluate(name, entry))
             }
-            Err(_) => false,
         }
     }
 
-    fn match_type(file_type: &FileType, entry: &DirEntry) -> bool {
-        let metadata = match entry.metadata() {
-            Ok(metadata) => metadata,
-            Err(_) => return false,
+    // Helper function for glob pattern matching. Case-insensitive matching is left to
+    // the glob crate so that both sides are folded and escapes stay intact.
+    fn match_glob_pattern(pattern: &str, target: &str, case_insensitive: bool) -> bool {
+        // Names and paths are never empty, so an empty pattern matches nothing
+        if pattern.is_empty() {
+            return false;
+        }
+        let options = MatchOptions {
+            case_sensitive: !case_insensitive,
+            ..MatchOptions::new()
         };
+        match Pattern::new(&Self::translate_glob(pattern)) {
+            Ok(glob_pattern) => glob_pattern.matches_with(target, options),
+            Err(_) => false,
+        }
+    }
 
-        match file_type {
-            FileType::RegularFile => metadata.is_file(),
-            FileType::Directory => metadata.is_dir(),
-            FileType::SymbolicLink => metadata.file_type().is_symlink(),
-            FileType::BlockFile => {
-                #[cfg(unix)]
-                {
-                    use std::os::unix::fs::FileTypeExt;
-                    metadata.file_type().is_block_device()
+    // The glob crate has no backslash escapes, so rewrite fnmatch-style patterns the way
+    // it spells them: "\*" becomes "[*]", and any other escaped character is just itself.
+    // Bracket expressions are rebuilt by translate_bracket, and a "[" that is never
+    // closed is a literal, as in fnmatch, rather than an error. Like fnmatch without
+    // FNM_PATHNAME, which is how GNU find calls it, * and ? match "/" too; a run of stars
+    // is one star, since the glob crate would read "**" as any number of whole
+    // directories, or reject it next to other characters
+    fn translate_glob(pattern: &str) -> String {
+        let chars: Vec<char> = pattern.chars().collect();
+        let mut translated = String::with_capacity(pattern.len());
+        let mut i = 0;
+        while i < chars.len() {
+            match chars[i] {
+                '\\' if i + 1 < chars.len() => {
+                    match chars[i + 1] {
+                        escaped @ ('*' | '?' | '[' | ']') => {
+                            translated.push('[');
+                            translated.push(escaped);
+                            translated.push(']');
+                        }
+                        escaped => translated.push(escaped),
+                    }
+                    i += 2;
                 }
-                #[cfg(not(unix))]
-                false
-            }
-            FileType::CharFile => {
-                #[cfg(unix)]
-                {
-                    use std::os::unix::fs::FileTypeExt;
-                    metadata.file_type().is_char_device()
+                '[' => match Self::translate_bracket(&chars[i..]) {
+                    Some((bracket, len)) => {
+                        translated.push_str(&bracket);
+                        i += len;
+                    }
+                    None => {
+                        translated.push_str("[[]");
+                        i += 1;
+                    }
+                },
+                '*' => {
+                    if !translated.ends_with('*') {
+                        translated.push('*');
+                    }
+                    i += 1;
                 }
-                #[cfg(not(unix))]
-                false
-            }
-            FileType::NamedPipe => {
-                #[cfg(unix)]
-                {
-                    use std::os::unix::fs::FileTypeExt;
-                    metadata.file_type().is_fifo()
+                c => {
+                    translated.push(c);
+                    i += 1;
                 }
-                #[cfg(not(unix))]
-                false
             }
-            FileType::Socket => {
-                #[cfg(unix)]
-                {
-                    use std::os::unix::fs::FileTypeExt;
-                    metadata.file_type().is_socket()
+        }
+        translated
+    }
+
+    // Rewrites the bracket expression at the start of `chars` for the glob crate,
+    // returning it and the number of characters it spanned, or None if it is never
+    // closed. fnmatch also negates with "^", names POSIX classes like [:digit:] and
+    // allows escapes inside; the glob crate only knows "!" and ranges, and needs "]"
+    // as the first member, "-" as the last and "!" anywhere but first
+    fn translate_bracket(chars: &[char]) -> Option<(String, usize)> {
+        let negated = matches!(chars.get(1), Some('!' | '^'));
+        let first = if negated { 2 } else { 1 };
+        let mut ranges = String::new();
+        let mut singles = Vec::new();
+        let mut i = first;
+        loop {
+            let c = *chars.get(i)?;
+            // A "]" right at the start is a member rather than the end
+            if c == ']' && i > first {
+                break;
+            }
+            if c == '[' && chars.get(i + 1) == Some(&':')
+                && let Some((class, len)) = Self::posix_class(&chars[i..]) {
+                ranges.push_str(class);
+                i += len;
+                continue;
+            }
+            let (c, len) = match (c, chars.get(i + 1)) {
+                ('\\', Some(&escaped)) => (escaped, 2),
+                _ => (c, 1),
+            };
+            i += len;
+            // A "-" between two members makes a range, but not a "-" at the end
+            match (chars.get(i), chars.get(i + 1)) {
+                (Some('-'), Some(&end)) if end != ']' => {
+                    ranges.push(c);
+                    ranges.push('-');
+                    ranges.push(end);
+                    i += 2;
                 }
-                #[cfg(not(unix))]
-                false
+                _ => singles.push(c),
             }
         }
+
+        let mut members = String::new();
+        if singles.contains(&']') {
+            members.push(']');
+        }
+        members.push_str(&ranges);
+        members.extend(singles.iter().filter(|c| !matches!(c, ']' | '!' | '-')));
+        if singles.contains(&'!') {
+            members.push('!');
+        }
+        if singles.contains(&'-') {
+            members.push('-');
+        }
+        let bracket = match (negated, members.as_str()) {
+            (true, _) => format!("[!{}]", members),
+            // "[!]" is no expression at all, and "[!-]" would be negated
+            (false, "!") => "!".to_string(),
+            (false, "!-") => "[-!]".to_string(),
+            (false, _) => format!("[{}]", members),
+        };
+        Some((bracket, i + 1))
     }
 
-    fn match_size(size_spec: &SizeSpec, entry: &DirEntry) -> bool {
-        let metadata = match Self::get_metadata(entry) {
-            Some(metadata) => metadata,
-            None => return false,
+    // The members of a POSIX class such as [:alpha:] at the start of `chars`, in the C
+    // locale, with the number of characters the name took
+    fn posix_class(chars: &[char]) -> Option<(&'static str, usize)> {
+        let end = 2 + chars.get(2..)?.windows(2).position(|pair| pair == [':', ']'])?;
+        let name: String = chars[2..end].iter().collect();
+        let members = match name.as_str() {
+            "alnum" => "0-9A-Za-z",
+            "alpha" => "A-Za-z",
+            "blank" => " \t",
+            "cntrl" => "\x00-\x1f\x7f",
+            "digit" => "0-9",
+            "graph" => "\"-~!",
+            "lower" => "a-z",
+            "print" => " -~",
+            // Ordered so the expression doesn't start with "!"
+            "punct" => ":-@!-/[-`{-~",
+            "space" => " \t\n\x0b\x0c\r",
+            "upper" => "A-Z",
+            "xdigit" => "0-9A-Fa-f",
+            _ => return None,
         };
+        Some((members, end + 2))
+    }
 
-        let file_size = metadata.len();
-        let target_size = Self::calculate_size_in_bytes(size_spec);
+    /// Returns what the last evaluation stat'ed for the entry, stat'ing it now if
+    /// nothing in the expression needed to. Like DirEntry::metadata, this describes a
+    /// symlink's target only when the walk followed the link to get here
+    pub fn metadata(&self, entry: &DirEntry) -> Option<FileStat> {
+        self.get_metadata(entry)
+    }
 
-        match size_spec.sign {
-            Sign::None => file_size == target_size,
-            Sign::Plus => file_size > target_size,
-            Sign::Minus => file_size < target_size,
+    // Helper function for getting file metadata. Like DirEntry::metadata, this describes
+    // the link target only when the walk followed the link to get here. The entry is
+    // stat'ed once per evaluation, failures included
+    fn get_metadata(&self, entry: &DirEntry) -> Option<FileStat> {
+        if let Some((path, metadata)) = &*self.last_metadata.borrow()
+            && path == entry.path() {
+            return metadata.clone();
         }
-    }
-    
-    fn match_empty(entry: &DirEntry) -> bool {
-        let metadata = match Self::get_metadata(entry) {
-            Some(metadata) => metadata,
-            None => return false,
+        let followed = entry.path_is_symlink() && !entry.file_type().is_symlink();
+        let metadata = if followed {
+            self.file_system.metadata(entry.path())
+        } else {
+            self.file_system.symlink_metadata(entry.path())
         };
+        let metadata = metadata.ok();
+        *self.last_metadata.borrow_mut() = Some((entry.path().to_path_buf(), metadata.clone()));
+        metadata
+    }
 
-        let file_size = metadata.len();
-        return file_size == 0;
+    // Asks the file system whether the entry may be read, written or executed, once per
+    // kind of access for each evaluation
+    fn match_access(&self, access: Access, entry: &DirEntry) -> bool {
+        if let Some(&(_, allowed)) = self.access_checks.borrow().iter().find(|(checked, _)| *checked == access) {
+            return allowed;
+        }
+        let allowed = self.file_system.access(entry.path(), access);
+        self.access_checks.borrow_mut().push((access, allowed));
+        allowed
     }
 
-    fn match_amin(time_spec: &TimeSpec, entry: &DirEntry) -> bool {
-        let metadata = match Self::get_metadata(entry) {
-            Some(metadata) => metadata,
+    // Helper function for time-based comparisons
+    // Ages are measured from the time captured when the run started (or the end of its
+    // day with -daystart), not the time each entry happens to be reached, so a long walk
+    // buckets every file the same way
+    fn match_age(&self, field: TimeField, time_spec: &TimeSpec, unit_seconds: u64, entry: &DirEntry) -> bool {
+        let file_time = match self.get_metadata(entry).and_then(|metadata| metadata.time(field)) {
+            Some(time) => time,
             None => return false,
         };
-        let accessed_time = match metadata.accessed() {
-            Ok(time) => time,
-            Err(_) => return false,
+        let age = match self.ages_from.duration_since(file_time) {
+            Ok(duration) => duration,
+            Err(_) => return false, // File time in the future
         };
-        Self::compare_time_spec(accessed_time, time_spec, 60)
+
+        time_spec.matches_age(age, Duration::from_secs(unit_seconds))
     }
 
-    fn match_atime(time_spec: &TimeSpec, entry: &DirEntry) -> bool {
-        let metadata = match Self::get_metadata(entry) {
-            Some(metadata) => metadata,
-            None => return false,
+    // -used measures the time from the status change to the last access, which is
+    // independent of when the run started, so -daystart doesn't affect it. A file last
+    // read before its status changed matches nothing, as in GNU find
+    fn match_used(&self, time_spec: &TimeSpec, entry: &DirEntry) -> bool {
+        let Some(metadata) = self.get_metadata(entry) else { return false };
+        let (Some(accessed), Some(changed)) = (metadata.time(TimeField::Access), metadata.time(TimeField::Change)) else {
+            return false;
         };
-        let accessed_time = match metadata.accessed() {
-            Ok(time) => time,
-            Err(_) => return false,
-        };
-        Self::compare_time_spec(accessed_time, time_spec, 24 * 60 * 60)
+        accessed.duration_since(changed)
+            .is_ok_and(|delay| time_spec.matches_age(delay, Duration::from_secs(DAY)))
     }
 
-    fn match_ctime(time_spec: &TimeSpec, entry: &DirEntry) -> bool {
-        let metadata = match Self::get_metadata(entry) {
-            Some(metadata) => metadata,
-            None => return false,
-        };
-        let created_time = match metadata.created() {
-            Ok(time) => time,
-            Err(_) => return false,
-        };
-        Self::compare_time_spec(created_time, time_spec, 24 * 60 * 60)
+    // Helper function for the -newer family, comparing one timestamp of the entry with
+    // the reference's modification time. As in GNU find, -anewer and -cnewer compare the
+    // entry's access or change time with when the reference was modified, not with the
+    // reference's own access or change time.
+    fn match_newer_generic(&self, field: TimeField, reference: &ReferenceFile, entry: &DirEntry) -> bool {
+        // The reference was stat'ed once when the expression was parsed
+        match reference.metadata.modified {
+            Some(reference_time) => self.compare_to_time(field, reference_time, entry),
+            None => false,
+        }
     }
 
-    fn match_cmin(time_spec: &TimeSpec, entry: &DirEntry) -> bool {
-        let metadata = match Self::get_metadata(entry) {
-            Some(metadata) => metadata,
-            None => return false,
-        };
-        let created_time = match metadata.created() {
-            Ok(time) => time,
-            Err(_) => return false,
+    // True if the entry's timestamp is after the given time
+    fn compare_to_time(&self, field: TimeField, time: SystemTime, entry: &DirEntry) -> bool {
+        self.get_metadata(entry)
+            .and_then(|metadata| metadata.time(field))
+            .is_some_and(|entry_time| entry_time > time)
+    }
+
+    // Like -name, the glob has to match the whole string, not just part of it, so
+    // "-path foo" only matches a path that is exactly "foo", not "./foo", and finding
+    // foo anywhere needs "*foo*". A "*" also matches "/" here, as in GNU find. With
+    // `strip_dot_slash` a path starting "./" is also tried without it.
+    pub(crate) fn match_path(pattern: &str, path: &Path, case_insensitive: bool, strip_dot_slash: bool) -> bool {
+        let file_name = path.to_string_lossy();
+
+        // Windows paths use backslashes, but patterns are usually written with forward
+        // slashes, so compare both sides in the same normal form
+        #[cfg(windows)]
+        let (pattern, file_name) = (normalize_windows_pattern(pattern), normalize_windows_path(&file_name));
+        #[cfg(windows)]
+        let pattern = pattern.as_str();
+
+        if Self::match_glob_pattern(pattern, &file_name, case_insensitive) {
+            return true;
+        }
+
+        // Walking from "." yields paths like "./src/main.rs", so --strip-dot-slash also
+        // tries the path without the leading "./" to let "src/*" match as "./src/*" does
+        match file_name.strip_prefix("./") {
+            Some(stripped) if strip_dot_slash => Self::match_glob_pattern(pattern, stripped, case_insensitive),
+            _ => false,
+        }
+    }
+
+    fn match_name(pattern: &str, entry: &DirEntry, case_insensitive: bool) -> bool {
+        Self::match_glob_pattern(pattern, &Self::entry_name(entry), case_insensitive)
+    }
+
+    // The name -name and -iname match. Below the start path that is the entry's file
+    // name. For a start path without one, such as `./` or `//`, walkdir gives the whole
+    // path, and it drops the `.` from `dir/.`, so like GNU find the start path is named
+    // by its last component as written, less any trailing slashes.
+    fn entry_name(entry: &DirEntry) -> Cow<'_, str> {
+        if entry.depth() > 0 {
+            return entry.file_name().to_string_lossy();
+        }
+        let path = entry.path().to_string_lossy();
+        let trimmed = path.trim_end_matches(std::path::is_separator);
+        match trimmed.rsplit(std::path::is_separator).next() {
+            // A path of nothing but slashes is the root, named /
+            _ if trimmed.is_empty() && !path.is_empty() => Cow::Borrowed("/"),
+            Some(name) => Cow::Owned(name.to_string()),
+            None => path,
+        }
+    }
+
+    fn match_regex(&self, pattern: &RegexPattern, entry: &DirEntry) -> bool {
+        // The pattern was anchored (and made case-insensitive for -iregex) when parsed
+        let path = if self.relative_regex {
+            // The start path itself is matched as an empty string, like %P renders it
+            entry.path().strip_prefix(&self.start).unwrap_or(entry.path())
+        } else {
+            entry.path()
         };
-        Self::compare_time_spec(created_time, time_spec, 60)
+        pattern.regex.is_match(&path.to_string_lossy())
+    }
+
+    fn match_type(&self, file_type: &FileType, entry: &DirEntry) -> bool {
+        match file_type {
+            // Ask the entry rather than the metadata, which describes the link
+            // target when the walk follows symlinks
+            FileType::SymbolicLink => entry.path_is_symlink(),
+            // The walk already knows these from reading the directory, or from the stat
+            // it made to follow a link, so they cost no stat of their own. Device, pipe
+            // and socket types are only ever classified on Unix
+            FileType::BlockFile | FileType::CharFile | FileType::NamedPipe | FileType::Socket => {
+                classify_file_type(entry.file_type()).as_ref() == Some(file_type)
+            }
+            FileType::RegularFile | FileType::Directory => self.get_metadata(entry)
+                .is_some_and(|metadata| metadata.file_type.as_ref() == Some(file_type)),
+        }
     }
 
-    fn match_mmin(time_spec: &TimeSpec, entry: &DirEntry) -> bool {
-        let metadata = match Self::get_metadata(entry) {
+    fn match_size(&self, size_spec: &SizeSpec, entry: &DirEntry) -> bool {
+        let metadata = match self.get_metadata(entry) {
             Some(metadata) => metadata,
             None => return false,
         };
-        let modified_time = match metadata.modified() {
-            Ok(time) => time,
-            Err(_) => return false,
+
+        Self::compare_size(metadata.len, size_spec)
+    }
+
+    // Like GNU find, the size is rounded up to whole units before comparing, so a 1-byte
+    // file is -size 1 (one 512-byte block) and only empty files are -size -1. With the
+    // c suffix the unit is one byte, so rounding leaves the size exact.
+    fn compare_size(size: u64, size_spec: &SizeSpec) -> bool {
+        let units = size.div_ceil(size_spec.unit());
+
+        match size_spec.sign {
+            Sign::None => units == size_spec.value,
+            Sign::Plus => units > size_spec.value,
+            Sign::Minus => units < size_spec.value,
+        }
+    }
+
+    // Only directories match. The total counts regular files only and never follows
+    // symlinks, so a link cycle can't make the sum walk forever.
+    fn match_tree_size(&self, size_spec: &SizeSpec, entry: &DirEntry) -> bool {
+        if !entry.file_type().is_dir() {
+            return false;
+        }
+
+        let cached = self.tree_totals.borrow().get(entry.path()).copied();
+        let total = match cached {
+            Some(total) => total,
+            None => self.sum_tree(entry.path(), &mut self.tree_totals.borrow_mut()),
         };
-        Self::compare_time_spec(modified_time, time_spec, 60)
+        Self::compare_size(total, size_spec)
     }
 
-    fn match_mtime(time_spec: &TimeSpec, entry: &DirEntry) -> bool {
-        let metadata = match Self::get_metadata(entry) {
+    // Sums a directory bottom-up, recording the total of every directory inside it.
+    // Directories are listed top-down first, each with the size of the files directly
+    // in it, so going through them in reverse adds every subdirectory to its parent
+    // before the parent is added to its own
+    fn sum_tree(&self, dir: &Path, totals: &mut HashMap<PathBuf, u64>) -> u64 {
+        let mut listed = Vec::new();
+        let mut unlisted = vec![dir.to_path_buf()];
+        while let Some(current) = unlisted.pop() {
+            let mut files = 0;
+            // Like an unreadable file, an unreadable directory adds nothing
+            for child in self.file_system.read_dir(&current).into_iter().flatten().flatten() {
+                match self.file_system.symlink_metadata(&child) {
+                    Ok(metadata) if metadata.is_dir() => unlisted.push(child),
+                    Ok(metadata) if metadata.is_file() => files += metadata.len,
+                    _ => {}
+                }
+            }
+            listed.push((current, files));
+        }
+
+        let mut below: HashMap<PathBuf, u64> = HashMap::new();
+        for (current, files) in listed.into_iter().rev() {
+            let total = files + below.remove(&current).unwrap_or(0);
+            if current != dir
+                && let Some(parent) = current.parent() {
+                *below.entry(parent.to_path_buf()).or_insert(0) += total;
+            }
+            totals.insert(current, total);
+        }
+
+        totals.get(dir).copied().unwrap_or(0)
+    }
+    
+    // Like GNU find, only regular files and directories can be empty. A directory's own
+    // size says nothing about its contents, so it is read instead; this works the same
+    // for a start path as for anything found below one.
+    fn match_empty(&self, entry: &DirEntry) -> bool {
+        let metadata = match self.get_metadata(entry) {
             Some(metadata) => metadata,
             None => return false,
         };
-        let modified_time = match metadata.modified() {
-            Ok(time) => time,
-            Err(_) => return false,
-        };
-        Self::compare_time_spec(modified_time, time_spec, 24 * 60 * 60)
+
+        if metadata.is_dir() {
+            // A directory that can't be listed isn't known to be empty, so it doesn't
+            // match, but unlike a non-empty one it is reported
+            match self.file_system.read_dir(entry.path()) {
+                Ok(mut entries) => entries.next().is_none(),
+                Err(e) => {
+                    self.errors.borrow_mut().push(format!("{}: {}", entry.path().display(), e));
+                    false
+                }
+            }
+        } else {
+            metadata.is_file() && metadata.len == 0
+        }
     }
 
-    fn match_anewer(filepath: &str, entry: &DirEntry) -> bool {
-        Self::compare_file_times(entry, filepath, |metadata| metadata.accessed())
+    // Unlike the metadata tests this opens and reads every regular file it is asked
+    // about, so it is much slower; put cheaper tests like -name or -size before it
+    fn match_magic(&self, mime_type: &str, entry: &DirEntry) -> bool {
+        // Enough for the signatures infer knows about, which sit near the start of a file
+        const HEADER_LEN: u64 = 8192;
+
+        if !entry.file_type().is_file() || !self.within_content_limit(entry) {
+            return false;
+        }
+
+        let mut header = Vec::new();
+        let read = self.file_system.open(entry.path())
+            .and_then(|file| file.take(HEADER_LEN).read_to_end(&mut header));
+        if read.is_err() {
+            return false;
+        }
+
+        infer::get(&header).is_some_and(|kind| kind.mime_type().eq_ignore_ascii_case(mime_type))
     }
 
-    fn match_cnewer(filepath: &str, entry: &DirEntry) -> bool {
-        Self::compare_file_times(entry, filepath, |metadata| metadata.created())
+    // Reads a line at a time and stops at the first matching line. Binary files are
+    // skipped rather than searched, like grep -I: a NUL in the first BINARY_CHECK_LEN
+    // bytes marks one before any line is matched, and a NUL further in, or a line
+    // longer than MAX_GREP_LINE, marks one as soon as it is read
+    fn match_grep(&self, pattern: &RegexPattern, entry: &DirEntry) -> bool {
+        // As much of the start of a file as git looks at to tell binary files from text
+        const BINARY_CHECK_LEN: u64 = 8000;
+        // Longer lines aren't text, and reading one whole could take any amount of memory
+        const MAX_GREP_LINE: u64 = 1 << 20;
+
+        if !entry.file_type().is_file() || !self.within_content_limit(entry) {
+            return false;
+        }
+        let Ok(mut file) = self.file_system.open(entry.path()) else { return false };
+        let mut start = Vec::new();
+        if (&mut file).take(BINARY_CHECK_LEN).read_to_end(&mut start).is_err() || start.contains(&0) {
+            return false;
+        }
+        let mut reader = BufReader::new(io::Cursor::new(start).chain(file));
+
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            match (&mut reader).take(MAX_GREP_LINE).read_until(b'\n', &mut line) {
+                Ok(0) | Err(_) => return false,
+                Ok(_) if line.contains(&0) => return false,
+                Ok(read) if read as u64 == MAX_GREP_LINE && line.last() != Some(&b'\n') => return false,
+                Ok(_) => {
+                    let text = String::from_utf8_lossy(&line);
+                    if pattern.regex.is_match(text.trim_end_matches(['\n', '\r'])) {
+                        return true;
+                    }
+                }
+            }
+        }
     }
 
-    fn match_mnewer(filepath: &str, entry: &DirEntry) -> bool {
-        Self::compare_file_times(entry, filepath, |metadata| metadata.modified())
+    // Checked before any content test opens a file, so --max-filesize can keep huge
+    // files from being read at all
+    fn within_content_limit(&self, entry: &DirEntry) -> bool {
+        match self.max_content_size {
+            Some(limit) => self.get_metadata(entry).is_some_and(|metadata| metadata.len <= limit),
+            None => true,
+        }
     }
 
-    fn match_newer(filepath: &str, entry: &DirEntry) -> bool {
-        // -newer is an alias for -mnewer (modification time comparison)
-        Self::match_mnewer(filepath, entry)
+    // Hard links share a device and inode; without inodes nothing can be compared
+    fn match_same_file(&self, reference: &ReferenceFile, entry: &DirEntry) -> bool {
+        #[cfg(unix)]
+        {
+            let on_walk_device = !entry.file_type().is_dir() && !entry.path_is_symlink();
+            if on_walk_device && self.walk_device.is_some_and(|device| device != reference.metadata.dev) {
+                return false;
+            }
+            self.get_metadata(entry).is_some_and(|metadata| {
+                (metadata.dev, metadata.ino) == (reference.metadata.dev, reference.metadata.ino)
+            })
+        }
+
+        #[cfg(not(unix))]
+        {
+            let _ = (reference, entry);
+            false
+        }
     }
 
-    fn match_user(username: &str, entry: &DirEntry) -> bool {
-        let metadata = match Self::get_metadata(entry) {
+    fn match_user(&self, username: &str, entry: &DirEntry) -> bool {
+        let metadata = match self.get_metadata(entry) {
             Some(metadata) => metadata,
             None => return false,
         };
 
         #[cfg(unix)]
         {
-            let file_uid = metadata.uid();
+            let file_uid = metadata.uid;
             
             // Try to parse as numeric UID first
             if let Ok(target_uid) = username.parse::<u32>() {
@@ -335,7 +1220,7 @@ impl Interpreter {
             // This is a simplified implementation - in practice you'd use getpwnam
             // For now, we'll just do string comparison with the current user
             let o = entry.path().owner().unwrap();
-            return username == o.name().unwrap().unwrap().to_string();
+            username == o.name().unwrap().unwrap()
 /*            if let Ok(current_user) = std::env::var("USER") {
                 if username == current_user {
                     // Get current user's UID
@@ -356,15 +1241,15 @@ impl Interpreter {
         }
     }
 
-    fn match_group(groupname: &str, entry: &DirEntry) -> bool {
-        let metadata = match Self::get_metadata(entry) {
+    fn match_group(&self, groupname: &str, entry: &DirEntry) -> bool {
+        let metadata = match self.get_metadata(entry) {
             Some(metadata) => metadata,
             None => return false,
         };
 
         #[cfg(unix)]
         {
-            let file_gid = metadata.gid();
+            let file_gid = metadata.gid;
             
             // Try to parse as numeric GID first
             if let Ok(target_gid) = groupname.parse::<u32>() {
@@ -375,7 +1260,7 @@ impl Interpreter {
             // This is a simplified implementation - in practice you'd use getgrnam
             // For now, we'll just do string comparison with the current group
             let g = entry.path().owner().unwrap();
-            return groupname == g.name().unwrap().unwrap().to_string();
+            groupname == g.name().unwrap().unwrap()
 /*
             if let Ok(current_group) = std::env::var("GROUP") {
                 if groupname == current_group {
@@ -397,15 +1282,15 @@ impl Interpreter {
         }
     }
 
-    fn match_uid(target_uid: u32, entry: &DirEntry) -> bool {
-        let metadata = match Self::get_metadata(entry) {
+    fn match_uid(&self, target_uid: u32, entry: &DirEntry) -> bool {
+        let metadata = match self.get_metadata(entry) {
             Some(metadata) => metadata,
             None => return false,
         };
 
         #[cfg(unix)]
         {
-            let file_uid = metadata.uid();
+            let file_uid = metadata.uid;
             file_uid == target_uid
         }
         
@@ -416,15 +1301,15 @@ impl Interpreter {
         }
     }
 
-    fn match_gid(target_gid: u32, entry: &DirEntry) -> bool {
-        let metadata = match Self::get_metadata(entry) {
+    fn match_gid(&self, target_gid: u32, entry: &DirEntry) -> bool {
+        let metadata = match self.get_metadata(entry) {
             Some(metadata) => metadata,
             None => return false,
         };
 
         #[cfg(unix)]
         {
-            let file_gid = metadata.gid();
+            let file_gid = metadata.gid;
             file_gid == target_gid
         }
         
@@ -436,15 +1321,15 @@ impl Interpreter {
     }
 
 
-    fn match_perm(perm_spec: &PermSpec, entry: &DirEntry) -> bool {
-        let metadata = match Self::get_metadata(entry) {
+    fn match_perm(&self, perm_spec: &PermSpec, entry: &DirEntry) -> bool {
+        let metadata = match self.get_metadata(entry) {
             Some(metadata) => metadata,
             None => return false,
         };
 
         #[cfg(unix)]
         {
-            let file_mode = metadata.mode();
+            let file_mode = metadata.mode;
             let file_perms = file_mode & 0o777; // Extract permission bits
             
             match &perm_spec.term {
@@ -476,8 +1361,9 @@ impl Interpreter {
                 (file_perms & target_perms) == target_perms
             }
             Some(PermPrefix::AnyMode) => {
-                // Any of the specified bits can be set
-                (file_perms & target_perms) != 0
+                // Any of the specified bits can be set. As in GNU find, a mask with no
+                // bits at all matches every file, like -perm -000, rather than none
+                target_perms == 0 || (file_perms & target_perms) != 0
             }
         }
     }
@@ -485,13 +1371,15 @@ impl Interpreter {
     #[cfg(unix)]
     fn match_symbolic_perm(statements: &[SymPermStatement], file_perms: u32, prefix: &Option<PermPrefix>) -> bool {
         // Convert symbolic statements to numeric representation
-        let target_perms = Self::symbolic_to_numeric(statements, file_perms);
+        let target_perms = Self::symbolic_to_numeric(statements);
         Self::match_numeric_perm(target_perms, file_perms, prefix)
     }
 
     #[cfg(unix)]
-    fn symbolic_to_numeric(statements: &[SymPermStatement], current_perms: u32) -> u32 {
-        let mut result_perms = current_perms;
+    fn symbolic_to_numeric(statements: &[SymPermStatement]) -> u32 {
+        // Like GNU find, the statements are applied to mode 000 rather than to the file's
+        // own mode, so u+r alone means 0400 and the file is then compared against that
+        let mut result_perms = 0;
         
         for statement in statements {
             let perm_mask = Self::get_permission_mask(&statement.principal, &statement.privileges);
@@ -550,27 +1438,101 @@ impl Interpreter {
             SymPrincipal::All => 0o777,
         }
     }
+}
 
-    fn calculate_size_in_bytes(size_spec: &SizeSpec) -> u64 {
-        let multiplier = match size_spec.suffix.as_ref() {
-            Some(SizeSuffix::Bytes) => 1,
-            Some(SizeSuffix::Words) => 2,
-            Some(SizeSuffix::Kb) => 1024,
-            Some(SizeSuffix::Mb) => 1024 * 1024,
-            Some(SizeSuffix::Gb) => 1024 * 1024 * 1024,
-            Some(SizeSuffix::Blocks) | None => 512, // Default is 512-byte blocks
-        };
-        
-        size_spec.value * multiplier
+// Rewrites a Windows path, or a -path pattern for one, the way both are compared:
+// with forward slashes, without the verbatim prefix canonicalize adds (\\?\C:\x is
+// C:/x and \\?\UNC\server\share is //server/share), and with an upper case drive
+// letter, since drive letters are never case sensitive. So `C:/Users/*` and
+// `//server/share/*` match however the path was spelled.
+#[cfg(any(windows, test))]
+fn normalize_windows_path(path: &str) -> String {
+    windows_path_form(path.replace('\\', "/"))
+}
+
+// The same for a -path pattern, where a backslash before *, ?, [ or ] is an escape, as
+// in any other glob, and is kept; only the other backslashes are separators. So
+// `src\*.rs` matches a file named `*.rs` in src, and every file in src is `src/*`.
+#[cfg(any(windows, test))]
+fn normalize_windows_pattern(pattern: &str) -> String {
+    let (mut converted, rest) = match pattern.strip_prefix("\\\\?\\") {
+        Some(rest) => ("//?/".to_string(), rest),
+        None => (String::with_capacity(pattern.len()), pattern),
+    };
+    let mut chars = rest.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('\\', Some(&escaped @ ('*' | '?' | '[' | ']'))) => {
+                converted.push('\\');
+                converted.push(escaped);
+                chars.next();
+            }
+            ('\\', _) => converted.push('/'),
+            (c, _) => converted.push(c),
+        }
+    }
+    windows_path_form(converted)
+}
+
+#[cfg(any(windows, test))]
+fn windows_path_form(path: String) -> String {
+    let path = if let Some(share) = path.strip_prefix("//?/UNC/") {
+        format!("//{}", share)
+    } else {
+        path.strip_prefix("//?/").map(str::to_string).unwrap_or(path)
+    };
+    let mut chars = path.chars();
+    match (chars.next(), chars.next()) {
+        (Some(drive), Some(':')) if drive.is_ascii_alphabetic() => {
+            format!("{}{}", drive.to_ascii_uppercase(), &path[1..])
+        }
+        _ => path,
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::filesystem::MockFileSystem;
+    use walkdir::WalkDir;
     use std::fs;
+    use std::io;
     use tempfile::TempDir;
 
+    fn interpreter() -> Interpreter {
+        Interpreter::new(Path::new("."), SystemTime::now())
+    }
+
+    fn mock_interpreter(now: SystemTime, mock: MockFileSystem) -> Interpreter {
+        Interpreter::new(Path::new("."), now).with_file_system(Arc::new(mock))
+    }
+
+    // The interpreter needs a real DirEntry, but with a mock file system everything it
+    // looks up about the entry comes from the mock, so any existing path will do
+    fn stand_in(path: &str) -> DirEntry {
+        walkdir::WalkDir::new(path).into_iter().next().unwrap().unwrap()
+    }
+
+    // render_format, for formats that only render UTF-8
+    fn render_text(interpreter: &Interpreter, directives: &[FormatDirective], entry: &DirEntry) -> String {
+        String::from_utf8(interpreter.render_format(directives, entry)).unwrap()
+    }
+
+    fn mock_file(len: u64, modified: SystemTime) -> FileStat {
+        FileStat {
+            file_type: Some(FileType::RegularFile),
+            len,
+            accessed: Some(modified),
+            modified: Some(modified),
+            ..FileStat::default()
+        }
+    }
+
+    // A fixed reference time, so time tests don't depend on when they run
+    fn fixed_now() -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000)
+    }
+
     #[test]
     fn test_true_false() {
         let temp_dir = TempDir::new().unwrap();
@@ -583,8 +1545,99 @@ mod tests {
             .unwrap()
             .unwrap();
 
-        assert!(Interpreter::evaluate(&Expr::Test(Test::True), &entry));
-        assert!(!Interpreter::evaluate(&Expr::Test(Test::False), &entry));
+        assert!(interpreter().matches(&Expr::Test(Test::True), &entry));
+        assert!(!interpreter().matches(&Expr::Test(Test::False), &entry));
+    }
+
+    #[test]
+    fn test_at_depth() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir_all(temp_dir.path().join("a/b")).unwrap();
+
+        for depth in 0..=2 {
+            let expr = Expr::Test(Test::AtDepth(depth));
+            let matched: Vec<usize> = walkdir::WalkDir::new(temp_dir.path())
+                .into_iter()
+                .map(|entry| entry.unwrap())
+                .filter(|entry| interpreter().matches(&expr, entry))
+                .map(|entry| entry.depth())
+                .collect();
+            assert_eq!(matched, vec![depth]);
+        }
+    }
+
+    #[test]
+    fn test_explain_lists_true_tests() {
+        let temp_dir = TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("x");
+        fs::write(&file_path, "").unwrap();
+        let entry = walkdir::WalkDir::new(&file_path).into_iter().next().unwrap().unwrap();
+
+        let name = Test::Name("x".to_string());
+        let file = Test::Type(FileType::RegularFile);
+        let expr = Expr::And(Box::new(Expr::Test(name.clone())), Box::new(Expr::Test(file.clone())));
+        let (outcome, tests) = interpreter().explain(&expr, &entry);
+        assert!(outcome.matched);
+        assert_eq!(tests, [&name, &file]);
+
+        // Only the branch of an -or that matched is listed
+        let dir = Test::Type(FileType::Directory);
+        let expr = Expr::Or(
+            Box::new(Expr::And(Box::new(Expr::Test(Test::True)), Box::new(Expr::Test(dir.clone())))),
+            Box::new(Expr::Not(Box::new(Expr::Test(dir.clone())))),
+        );
+        let (outcome, tests) = interpreter().explain(&expr, &entry);
+        assert!(outcome.matched && tests.is_empty());
+        let expr = Expr::Test(dir);
+        let (outcome, tests) = interpreter().explain(&expr, &entry);
+        assert!(!outcome.matched && tests.is_empty());
+    }
+
+    #[test]
+    fn test_profile_counts_evaluations() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(temp_dir.path().join("a.rs"), "").unwrap();
+        fs::write(temp_dir.path().join("b.txt"), "").unwrap();
+        fs::create_dir(temp_dir.path().join("sub")).unwrap();
+
+        let expr = Expr::And(
+            Box::new(Expr::Test(Test::Type(FileType::RegularFile))),
+            Box::new(Expr::Test(Test::Name("*.rs".to_string()))),
+        );
+        let interpreter = interpreter().with_profiling();
+        for entry in walkdir::WalkDir::new(temp_dir.path()) {
+            interpreter.matches(&expr, &entry.unwrap());
+        }
+
+        // -name only runs for the two files, since -and stops at the first false test
+        let profile = interpreter.take_profile().unwrap();
+        let file_type = profile.get("-type").unwrap();
+        assert_eq!((file_type.evaluations, file_type.matches), (4, 2));
+        let name = profile.get("-name").unwrap();
+        assert_eq!((name.evaluations, name.matches), (2, 1));
+
+        assert_eq!(interpreter.take_profile(), Some(Profile::new()));
+        assert_eq!(self::interpreter().take_profile(), None);
+    }
+
+    #[test]
+    fn test_name_star_matches_everything() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir(temp_dir.path().join(".hidden_dir")).unwrap();
+        fs::write(temp_dir.path().join(".hidden"), "").unwrap();
+        fs::write(temp_dir.path().join("visible"), "").unwrap();
+        fs::write(temp_dir.path().join("no_extension."), "").unwrap();
+
+        // Unlike a shell glob, * matches a leading dot, as in GNU find
+        let star = Expr::Test(Test::Name("*".to_string()));
+        let entries: Vec<_> = walkdir::WalkDir::new(temp_dir.path())
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .collect();
+        assert_eq!(entries.len(), 5);
+        for entry in &entries {
+            assert!(interpreter().matches(&star, entry), "{}", entry.path().display());
+        }
     }
 
     #[test]
@@ -600,13 +1653,325 @@ mod tests {
             .unwrap();
 
         let name_test = Test::Name("test.txt".to_string());
-        assert!(Interpreter::evaluate(&Expr::Test(name_test), &entry));
+        assert!(interpreter().matches(&Expr::Test(name_test), &entry));
 
         let wildcard_test = Test::Name("*.txt".to_string());
-        assert!(Interpreter::evaluate(&Expr::Test(wildcard_test), &entry));
+        assert!(interpreter().matches(&Expr::Test(wildcard_test), &entry));
 
         let no_match_test = Test::Name("*.md".to_string());
-        assert!(!Interpreter::evaluate(&Expr::Test(no_match_test), &entry));
+        assert!(!interpreter().matches(&Expr::Test(no_match_test), &entry));
+    }
+
+    #[test]
+    fn test_start_path_names_ignore_trailing_slashes() {
+        let name = |pattern: &str| Expr::Test(Test::Name(pattern.to_string()));
+        for (start, pattern) in [("src/", "src"), ("src//", "src"), ("./", "."), ("src/.", "."), ("src/./", "."), ("../", "..")] {
+            assert!(interpreter().matches(&name(pattern), &stand_in(start)), "{} -name {}", start, pattern);
+            assert!(!interpreter().matches(&name("*/"), &stand_in(start)), "{}", start);
+        }
+        assert!(!interpreter().matches(&name("src"), &stand_in("src/.")));
+        #[cfg(unix)]
+        assert!(interpreter().matches(&name("/"), &stand_in("//")));
+        assert!(interpreter().matches(&Expr::Test(Test::Iname("SRC".to_string())), &stand_in("src/")));
+    }
+
+    #[test]
+    fn test_case_insensitive_patterns_are_folded() {
+        let temp_dir = TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("Test.TXT");
+        fs::write(&file_path, "test content").unwrap();
+
+        let entry = walkdir::WalkDir::new(&file_path)
+            .into_iter()
+            .next()
+            .unwrap()
+            .unwrap();
+
+        let iname = |pattern: &str| Expr::Test(Test::Iname(pattern.to_string()));
+        assert!(interpreter().matches(&iname("test.txt"), &entry));
+        assert!(interpreter().matches(&iname("TEST.*"), &entry));
+        assert!(!interpreter().matches(&Expr::Test(Test::Name("TEST.*".to_string())), &entry));
+    }
+
+    #[test]
+    fn test_name_character_classes() {
+        let temp_dir = TempDir::new().unwrap();
+        let names = ["Makefile", "readme", "main.c", "util.h", "main.o", "2024.log", "apple", "[draft]"];
+        for name in names {
+            fs::write(temp_dir.path().join(name), "").unwrap();
+        }
+        let matching = |test: fn(String) -> Test, pattern: &str| -> Vec<&str> {
+            let expr = Expr::Test(test(pattern.to_string()));
+            names.iter()
+                .filter(|name| {
+                    let entry = walkdir::WalkDir::new(temp_dir.path().join(name)).into_iter().next().unwrap().unwrap();
+                    interpreter().matches(&expr, &entry)
+                })
+                .copied()
+                .collect()
+        };
+
+        assert_eq!(matching(Test::Name, "[A-Z]*"), ["Makefile"]);
+        assert_eq!(matching(Test::Name, "*.[ch]"), ["main.c", "util.h"]);
+        assert_eq!(matching(Test::Name, "[!a-z]*"), ["Makefile", "2024.log", "[draft]"]);
+        assert_eq!(matching(Test::Name, "[^a-z]*"), ["Makefile", "2024.log", "[draft]"]);
+        assert_eq!(matching(Test::Name, "[[:digit:]]*"), ["2024.log"]);
+        assert_eq!(matching(Test::Name, "[[:upper:][:digit:]]*"), ["Makefile", "2024.log"]);
+        assert_eq!(matching(Test::Name, "*[[:punct:]]?"), ["main.c", "util.h", "main.o"]);
+        assert_eq!(matching(Test::Name, "[[]*"), ["[draft]"]);
+        assert_eq!(matching(Test::Name, "[draft"), Vec::<&str>::new());
+
+        // Case-insensitive matching folds ranges and classes of letters too
+        assert_eq!(matching(Test::Iname, "[A-Z]*"), ["Makefile", "readme", "main.c", "util.h", "main.o", "apple"]);
+        assert_eq!(matching(Test::Iname, "*.[CH]"), ["main.c", "util.h"]);
+        assert_eq!(matching(Test::Iname, "[!A-L]*"), ["Makefile", "readme", "main.c", "util.h", "main.o", "2024.log", "[draft]"]);
+        assert_eq!(matching(Test::Iname, "[[:upper:]]*"), matching(Test::Iname, "[[:lower:]]*"));
+        assert_eq!(matching(Test::Iname, "[^[:alpha:]]*"), ["2024.log", "[draft]"]);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_escaped_glob_characters() {
+        let temp_dir = TempDir::new().unwrap();
+        let star_path = temp_dir.path().join("Star*");
+        let plain_path = temp_dir.path().join("Star");
+        fs::write(&star_path, "").unwrap();
+        fs::write(&plain_path, "").unwrap();
+
+        let entry_for = |path: &std::path::Path| {
+            walkdir::WalkDir::new(path).into_iter().next().unwrap().unwrap()
+        };
+        let name = |pattern: &str| Expr::Test(Test::Name(pattern.to_string()));
+        let iname = |pattern: &str| Expr::Test(Test::Iname(pattern.to_string()));
+
+        // An escaped * only matches a literal asterisk
+        assert!(interpreter().matches(&name("*\\*"), &entry_for(&star_path)));
+        assert!(!interpreter().matches(&name("*\\*"), &entry_for(&plain_path)));
+        assert!(interpreter().matches(&name("Star\\*"), &entry_for(&star_path)));
+        assert!(!interpreter().matches(&name("Sta\\*"), &entry_for(&star_path)));
+
+        // Case folding keeps the escape intact
+        assert!(interpreter().matches(&iname("STAR\\*"), &entry_for(&star_path)));
+        assert!(!interpreter().matches(&iname("STAR\\*"), &entry_for(&plain_path)));
+
+        // Other escaped characters stand for themselves
+        assert!(interpreter().matches(&name("\\Sta\\r"), &entry_for(&plain_path)));
+    }
+
+    #[test]
+    fn test_translate_escapes() {
+        assert_eq!(Interpreter::translate_glob("a\\*b\\?\\[c\\]"), "a[*]b[?][[]c[]]");
+        assert_eq!(Interpreter::translate_glob("\\a\\\\"), "a\\");
+        assert_eq!(Interpreter::translate_glob("trailing\\"), "trailing\\");
+        assert_eq!(Interpreter::translate_glob("*.txt"), "*.txt");
+    }
+
+    #[test]
+    fn test_translate_brackets() {
+        let translate = Interpreter::translate_glob;
+        // What the glob crate already understands is left alone
+        assert_eq!(translate("[A-Z]*"), "[A-Z]*");
+        assert_eq!(translate("*.[ch]"), "*.[ch]");
+        assert_eq!(translate("[!a-c]x"), "[!a-c]x");
+        assert_eq!(translate("[]a]"), "[]a]");
+
+        // fnmatch spellings become the glob crate's
+        assert_eq!(translate("[^a-c]x"), "[!a-c]x");
+        assert_eq!(translate("[[:digit:]]*"), "[0-9]*");
+        assert_eq!(translate("[![:alpha:]_]"), "[!A-Za-z_]");
+        assert_eq!(translate("[[:punct:]]"), "[:-@!-/[-`{-~]");
+        assert_eq!(translate("[\\*\\]]"), "[]*]");
+        assert_eq!(translate("[-a]"), "[a-]");
+        assert_eq!(translate("[a!]"), "[a!]");
+        assert_eq!(translate("[!!]"), "[!!]");
+        assert_eq!(translate("[\\!]"), "!");
+        assert_eq!(translate("[!-]"), "[!-]");
+        assert_eq!(translate("[-!]"), "[-!]");
+
+        // An unclosed "[" is taken literally, and an unknown class is just its characters
+        assert_eq!(translate("[abc"), "[[]abc");
+        assert_eq!(translate("a[!]"), "a[[]!]");
+        assert_eq!(translate("[[:nope:]]"), "[[:nope:]]");
+    }
+
+    #[test]
+    fn test_normalize_windows_path() {
+        assert_eq!(normalize_windows_path("c:\\Users\\me\\a.txt"), "C:/Users/me/a.txt");
+        assert_eq!(normalize_windows_path("\\\\?\\C:\\Users"), "C:/Users");
+        assert_eq!(normalize_windows_path("\\\\server\\share\\a.txt"), "//server/share/a.txt");
+        assert_eq!(normalize_windows_path("\\\\?\\UNC\\server\\share\\a.txt"), "//server/share/a.txt");
+        // Drive-relative paths keep their drive, and relative paths are left alone
+        assert_eq!(normalize_windows_path("d:a.txt"), "D:a.txt");
+        assert_eq!(normalize_windows_path("src\\*.rs"), "src/*.rs");
+        assert_eq!(normalize_windows_path("?:/x"), "?:/x");
+    }
+
+    #[test]
+    fn test_normalize_windows_pattern() {
+        assert_eq!(normalize_windows_pattern("c:\\Users\\a*.txt"), "C:/Users/a*.txt");
+        assert_eq!(normalize_windows_pattern("\\\\?\\C:\\Users\\a*"), "C:/Users/a*");
+        assert_eq!(normalize_windows_pattern("\\\\server\\share\\a*"), "//server/share/a*");
+        // Escaped glob characters stay escaped, next to separators or not
+        assert_eq!(normalize_windows_pattern("src\\\\*.rs"), "src/\\*.rs");
+        assert_eq!(normalize_windows_pattern("src\\*.rs"), "src\\*.rs");
+        assert_eq!(normalize_windows_pattern("a\\[b\\]\\c\\?"), "a\\[b\\]/c\\?");
+    }
+
+    #[test]
+    #[cfg(windows)]
+    fn test_path_matching_drive_and_unc() {
+        let temp_dir = TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("test.txt");
+        fs::write(&file_path, "").unwrap();
+        // canonicalize gives the verbatim form, \\?\C:\...
+        let verbatim = fs::canonicalize(&file_path).unwrap();
+        let entry = walkdir::WalkDir::new(&verbatim).into_iter().next().unwrap().unwrap();
+        let drive = normalize_windows_path(&verbatim.to_string_lossy())[..1].to_string();
+
+        let path = |pattern: String| Expr::Test(Test::Path(pattern));
+        assert!(interpreter().matches(&path(format!("{}:/*/test.txt", drive)), &entry));
+        assert!(interpreter().matches(&path(format!("{}:/*\\test.txt", drive.to_ascii_lowercase())), &entry));
+        // A backslash before a glob character escapes it, so this is a directory named *
+        assert!(!interpreter().matches(&path(format!("{}:\\*\\test.txt", drive)), &entry));
+        assert!(!interpreter().matches(&path("//*/test.txt".to_string()), &entry));
+
+        // The same file through the administrative share is a UNC path
+        let unc = format!("\\\\localhost\\{}$\\{}", drive, &verbatim.to_string_lossy()[7..]);
+        if let Some(Ok(entry)) = walkdir::WalkDir::new(&unc).into_iter().next() {
+            assert!(interpreter().matches(&path("//localhost/*/test.txt".to_string()), &entry));
+            assert!(!interpreter().matches(&path(format!("{}:/*", drive)), &entry));
+        }
+    }
+
+    #[test]
+    #[cfg(windows)]
+    fn test_path_matching_with_forward_slashes() {
+        let temp_dir = TempDir::new().unwrap();
+        let sub_dir = temp_dir.path().join("sub");
+        fs::create_dir(&sub_dir).unwrap();
+        let file_path = sub_dir.join("test.txt");
+        fs::write(&file_path, "test content").unwrap();
+
+        let entry = walkdir::WalkDir::new(&file_path)
+            .into_iter()
+            .next()
+            .unwrap()
+            .unwrap();
+
+        let slash_test = Test::Path("*/sub/*.txt".to_string());
+        assert!(interpreter().matches(&Expr::Test(slash_test), &entry));
+
+        let backslash_test = Test::Path("*\\sub\\*.txt".to_string());
+        assert!(interpreter().matches(&Expr::Test(backslash_test), &entry));
+
+        let ipath_test = Test::Ipath("*/SUB/*.TXT".to_string());
+        assert!(interpreter().matches(&Expr::Test(ipath_test), &entry));
+    }
+
+    #[test]
+    fn test_path_matching_with_leading_dot_slash() {
+        let entry = walkdir::WalkDir::new("./tests/inputs/a/a.txt")
+            .into_iter()
+            .next()
+            .unwrap()
+            .unwrap();
+
+        let dotted_test = Expr::Test(Test::Path("./tests/inputs/a/*".to_string()));
+        assert!(interpreter().matches(&dotted_test, &entry));
+
+        // As in GNU find, the "./" is part of the path
+        let bare_test = Expr::Test(Test::Path("tests/inputs/a/*".to_string()));
+        assert!(!interpreter().matches(&bare_test, &entry));
+        let ipath_test = Expr::Test(Test::Ipath("tests/inputs/a/*".to_string()));
+        assert!(!interpreter().matches(&ipath_test, &entry));
+
+        // Unless it is stripped
+        let stripping = interpreter().with_dot_slash_stripped();
+        assert!(stripping.matches(&dotted_test, &entry));
+        assert!(stripping.matches(&bare_test, &entry));
+        assert!(stripping.matches(&ipath_test, &entry));
+        let no_match_test = Test::Path("inputs/a/*".to_string());
+        assert!(!stripping.matches(&Expr::Test(no_match_test), &entry));
+    }
+
+    #[test]
+    fn test_path_matches_whole_path() {
+        let entry_for = |path: &str| walkdir::WalkDir::new(path).into_iter().next().unwrap().unwrap();
+        let path_matches = |pattern: &str, path: &str| {
+            interpreter().matches(&Expr::Test(Test::Path(pattern.to_string())), &entry_for(path))
+        };
+
+        // Without wildcards only the exact path matches, never a part of it
+        assert!(path_matches("tests/inputs/a", "tests/inputs/a"));
+        assert!(!path_matches("tests/inputs/a", "./tests/inputs/a"));
+        assert!(!path_matches("a", "tests/inputs/a"));
+        assert!(!path_matches("tests/inputs", "tests/inputs/a"));
+        assert!(!path_matches("inputs", "./tests/inputs"));
+
+        // Wildcards are needed to match within the path, and * spans directories
+        assert!(path_matches("*inputs*", "tests/inputs/a/a.txt"));
+        assert!(path_matches("*/a", "tests/inputs/a"));
+        assert!(path_matches("tests*.txt", "tests/inputs/a/a.txt"));
+        assert!(!path_matches("*inputs", "tests/inputs/a"));
+        assert!(path_matches("*a*c*", "tests/inputs/a/b/c/c.mp3"));
+        assert!(path_matches("tests?inputs?a", "tests/inputs/a"));
+
+        // ** is no more than *, as in fnmatch: it may match within a name, and it
+        // doesn't stand for no directories at all
+        assert!(path_matches("tests**mp3", "tests/inputs/a/b/c/c.mp3"));
+        assert!(path_matches("tests/**/c.mp3", "tests/inputs/a/b/c/c.mp3"));
+        assert!(!path_matches("tests/inputs/**/g.csv", "tests/inputs/g.csv"));
+        assert!(path_matches("tests/inputs/***", "tests/inputs/g.csv"));
+        // An escaped star is a literal one, not part of a run
+        assert!(!path_matches("*\\**", "tests/inputs/a"));
+
+        // -name is anchored the same way, but to the basename
+        let name = |pattern: &str| Expr::Test(Test::Name(pattern.to_string()));
+        assert!(interpreter().matches(&name("a.txt"), &entry_for("tests/inputs/a/a.txt")));
+        assert!(!interpreter().matches(&name("a"), &entry_for("tests/inputs/a/a.txt")));
+    }
+
+    #[test]
+    fn test_empty_patterns_match_nothing() {
+        let empty = RegexPattern { source: String::new(), regex: regex::Regex::new("^(?:)$").unwrap() };
+        let tests = [
+            Test::Name(String::new()),
+            Test::Iname(String::new()),
+            Test::Path(String::new()),
+            Test::Ipath(String::new()),
+            Test::Regex(empty.clone()),
+            Test::Iregex(empty),
+        ];
+        for path in [".", "tests/inputs/a", "tests/inputs/a/a.txt"] {
+            let entry = walkdir::WalkDir::new(path).into_iter().next().unwrap().unwrap();
+            for test in &tests {
+                assert!(!interpreter().matches(&Expr::Test(test.clone()), &entry), "{:?} {}", test, path);
+            }
+        }
+    }
+
+    #[test]
+    fn test_relative_regex() {
+        let regex = |re: &str| crate::parser::parse_expression(&format!("-regex {}", re)).unwrap();
+        let full = Interpreter::new(Path::new("."), SystemTime::now());
+        let relative = Interpreter::new(Path::new("."), SystemTime::now()).with_relative_regex();
+        let entry = stand_in("./src/main.rs");
+
+        // The full path has to account for the ./ the walk starts with
+        assert!(!full.matches(&regex(r"src/.*\.rs"), &entry));
+        assert!(full.matches(&regex(r"\./src/.*\.rs"), &entry));
+        assert!(relative.matches(&regex(r"src/.*\.rs"), &entry));
+        assert!(!relative.matches(&regex(r"\./src/.*\.rs"), &entry));
+
+        // Still anchored at both ends, and the start path itself is the empty path
+        assert!(!relative.matches(&regex("src"), &entry));
+        assert!(!relative.matches(&regex(".+"), &stand_in(".")));
+        assert!(relative.matches(&regex(".*"), &stand_in(".")));
+
+        // Other start paths are stripped as given
+        let nested = Interpreter::new(Path::new("tests/inputs"), SystemTime::now()).with_relative_regex();
+        assert!(nested.matches(&regex("a/b/.*"), &stand_in("tests/inputs/a/b/b.csv")));
+        assert!(!nested.matches(&regex("inputs/.*"), &stand_in("tests/inputs/a/b/b.csv")));
     }
 
     #[test]
@@ -628,10 +1993,129 @@ mod tests {
             .unwrap();
 
         let file_test = Test::Type(FileType::RegularFile);
-        assert!(Interpreter::evaluate(&Expr::Test(file_test), &file_entry));
+        assert!(interpreter().matches(&Expr::Test(file_test), &file_entry));
         
         let dir_test = Test::Type(FileType::Directory);
-        assert!(Interpreter::evaluate(&Expr::Test(dir_test), &dir_entry));
+        assert!(interpreter().matches(&Expr::Test(dir_test), &dir_entry));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_symlink_type_matching() {
+        let temp_dir = TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("test.txt");
+        let link_path = temp_dir.path().join("link.txt");
+        fs::write(&file_path, "test content").unwrap();
+        std::os::unix::fs::symlink(&file_path, &link_path).unwrap();
+
+        let link_test = Expr::Test(Test::Type(FileType::SymbolicLink));
+        let file_test = Expr::Test(Test::Type(FileType::RegularFile));
+
+        // Default (-P) mode: the link is reported as a link, not as its target
+        let entry = walkdir::WalkDir::new(&link_path)
+            .into_iter()
+            .next()
+            .unwrap()
+            .unwrap();
+        assert!(interpreter().matches(&link_test, &entry));
+        assert!(!interpreter().matches(&file_test, &entry));
+
+        // The link itself is still detected when the walk follows links
+        let followed = walkdir::WalkDir::new(&link_path)
+            .follow_links(true)
+            .into_iter()
+            .next()
+            .unwrap()
+            .unwrap();
+        assert!(interpreter().matches(&link_test, &followed));
+
+        let file_entry = walkdir::WalkDir::new(&file_path)
+            .into_iter()
+            .next()
+            .unwrap()
+            .unwrap();
+        assert!(!interpreter().matches(&link_test, &file_entry));
+    }
+
+    #[test]
+    fn test_newer_uses_parsed_reference() {
+        let now = fixed_now();
+        let mock = MockFileSystem::new()
+            .with_file("src/ast.rs", mock_file(3, now - Duration::from_secs(3600)))
+            .with_file("src/lib.rs", mock_file(3, now));
+
+        let reference = ReferenceFile {
+            path: "old.txt".to_string(),
+            metadata: mock_file(3, now - Duration::from_secs(3600)),
+        };
+        let newer_test = Expr::Test(Test::Newer(reference));
+
+        // The reference was stat'ed when parsing, so it needn't exist any more
+        let interpreter = mock_interpreter(now, mock);
+        assert!(interpreter.matches(&newer_test, &stand_in("src/lib.rs")));
+        assert!(!interpreter.matches(&newer_test, &stand_in("src/ast.rs")));
+    }
+
+    #[test]
+    fn test_daystart_measures_from_midnight() {
+        let at = |input: &str| crate::datetime::parse_datetime(input).unwrap();
+        let mock = MockFileSystem::new()
+            .with_file("src/ast.rs", mock_file(3, at("2024-03-13T23:59:59")))
+            .with_file("src/lib.rs", mock_file(3, at("2024-03-14T00:00:00")))
+            .with_file("src/main.rs", mock_file(3, at("2024-03-12T23:59:59")));
+        let mock = Arc::new(mock);
+        let mtime = |sign, value| Expr::Test(Test::Mtime(TimeSpec { sign, value }));
+        let mmin = |sign, value| Expr::Test(Test::Mmin(TimeSpec { sign, value }));
+
+        // Wherever in the day the run starts, today's files are -mtime 0 and yesterday's
+        // -mtime 1, right up to the midnight between them
+        for now in ["2024-03-14T00:00:01", "2024-03-14T09:30", "2024-03-14T23:59:59"] {
+            let interpreter = Interpreter::new(Path::new("."), at(now))
+                .with_file_system(mock.clone())
+                .with_day_start();
+            let matches = |expr: &Expr, path| interpreter.matches(expr, &stand_in(path));
+            assert!(matches(&mtime(Sign::None, 0), "src/lib.rs"), "{}", now);
+            assert!(!matches(&mtime(Sign::None, 0), "src/ast.rs"), "{}", now);
+            assert!(matches(&mtime(Sign::None, 1), "src/ast.rs"), "{}", now);
+            assert!(matches(&mtime(Sign::None, 2), "src/main.rs"), "{}", now);
+            assert!(matches(&mtime(Sign::Minus, 1), "src/lib.rs"), "{}", now);
+            // The minute tests count from the end of today too
+            assert!(matches(&mmin(Sign::None, 24 * 60), "src/ast.rs"), "{}", now);
+        }
+
+        // Without it, a minute before midnight is still within the last 24 hours
+        let interpreter = Interpreter::new(Path::new("."), at("2024-03-14T00:00:01")).with_file_system(mock);
+        assert!(interpreter.matches(&mtime(Sign::None, 0), &stand_in("src/ast.rs")));
+    }
+
+    #[test]
+    fn test_newer_than_time() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("file.txt");
+        fs::write(&path, "").unwrap();
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let file = fs::File::options().write(true).open(&path).unwrap();
+        file.set_times(fs::FileTimes::new().set_accessed(modified).set_modified(modified)).unwrap();
+        let entry = walkdir::WalkDir::new(&path).into_iter().next().unwrap().unwrap();
+
+        let before = modified - Duration::from_secs(1);
+        let newer_mt = |time| Expr::Test(Test::NewerMt(time));
+        assert!(interpreter().matches(&newer_mt(before), &entry));
+        // Strictly newer, as with -newer
+        assert!(!interpreter().matches(&newer_mt(modified), &entry));
+
+        let newer_at = |time| Expr::Test(Test::NewerAt(time));
+        assert!(interpreter().matches(&newer_at(before), &entry));
+        assert!(!interpreter().matches(&newer_at(modified + Duration::from_secs(1)), &entry));
+
+        // Setting the times above changed the file's status just now, after the
+        // modification time. Not every platform records a status change time
+        if let Some(changed) = FileStat::from(&fs::metadata(&path).unwrap()).changed {
+            assert!(changed > modified);
+            let newer_ct = |time| Expr::Test(Test::NewerCt(time));
+            assert!(interpreter().matches(&newer_ct(changed - Duration::from_secs(1)), &entry));
+            assert!(!interpreter().matches(&newer_ct(changed), &entry));
+        }
     }
 
     #[test]
@@ -651,18 +2135,867 @@ mod tests {
             Box::new(Expr::Test(Test::True)),
             Box::new(Expr::Test(Test::Name("*.txt".to_string()))),
         );
-        assert!(Interpreter::evaluate(&and_expr, &entry));
+        assert!(interpreter().matches(&and_expr, &entry));
 
         // Test OR
         let or_expr = Expr::Or(
             Box::new(Expr::Test(Test::False)),
             Box::new(Expr::Test(Test::Name("*.txt".to_string()))),
         );
-        assert!(Interpreter::evaluate(&or_expr, &entry));
+        assert!(interpreter().matches(&or_expr, &entry));
 
         // Test NOT
         let not_expr = Expr::Not(Box::new(Expr::Test(Test::False)));
-        assert!(Interpreter::evaluate(&not_expr, &entry));
+        assert!(interpreter().matches(&not_expr, &entry));
+    }
+
+    #[test]
+    fn test_quit_stops_later_actions() {
+        let entry = stand_in("tests/inputs/a/a.txt");
+        let print = || Box::new(Expr::Action(Action::Print));
+        let quit = || Box::new(Expr::Action(Action::Quit));
+        let interpreter = interpreter();
+
+        // Actions before -quit happen, ones after it don't
+        let expr = Expr::And(Box::new(Expr::And(print(), quit())), print());
+        assert_eq!(interpreter.evaluate(&expr, &entry), EvalOutcome { matched: false, prune: false, quit: true });
+        assert_eq!(interpreter.take_output(), b"tests/inputs/a/a.txt\n");
+
+        // -quit itself is true, so the right side of an -o is skipped
+        assert_eq!(interpreter.evaluate(&Expr::Or(quit(), print()), &entry), EvalOutcome { matched: true, prune: false, quit: true });
+        assert!(interpreter.take_output().is_empty());
+
+        // Each evaluation starts afresh; stopping the walk is up to the caller
+        assert_eq!(interpreter.evaluate(&Expr::Action(Action::Print), &entry), EvalOutcome { matched: true, ..EvalOutcome::default() });
+        assert_eq!(interpreter.take_output(), b"tests/inputs/a/a.txt\n");
+    }
+
+    #[test]
+    fn test_prune_outcome() {
+        let entry = stand_in("tests/inputs/a");
+        let prune = || Box::new(Expr::Action(Action::Prune));
+        let name = |pattern: &str| Box::new(Expr::Test(Test::Name(pattern.to_string())));
+        let interpreter = interpreter();
+
+        // -name a -prune -o -print: the directory is pruned and the -o side not taken
+        let expr = Expr::Or(Box::new(Expr::And(name("a"), prune())), Box::new(Expr::Action(Action::Print)));
+        assert_eq!(interpreter.evaluate(&expr, &entry), EvalOutcome { matched: true, prune: true, quit: false });
+        assert!(interpreter.take_output().is_empty());
+
+        // Where the -prune isn't reached nothing is pruned
+        let expr = Expr::Or(Box::new(Expr::And(name("b"), prune())), Box::new(Expr::Action(Action::Print)));
+        assert_eq!(interpreter.evaluate(&expr, &entry), EvalOutcome { matched: true, prune: false, quit: false });
+        assert!(!interpreter.matches(&Expr::And(name("b"), prune()), &entry));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_ls_names_and_numeric_ids() {
+        use std::os::unix::fs::{symlink, MetadataExt};
+
+        let temp_dir = TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("file.txt");
+        fs::write(&file_path, "hello").unwrap();
+        let link_path = temp_dir.path().join("link");
+        symlink("file.txt", &link_path).unwrap();
+        let metadata = fs::metadata(&file_path).unwrap();
+        let entry = walkdir::WalkDir::new(&file_path).into_iter().next().unwrap().unwrap();
+
+        let ls = |interpreter: Interpreter, entry: &DirEntry| {
+            interpreter.matches(&Expr::Action(Action::Ls), entry);
+            String::from_utf8(interpreter.take_output()).unwrap()
+        };
+        let named = ls(interpreter(), &entry);
+        let numeric = ls(interpreter().with_numeric_ids(), &entry);
+
+        // Only the owner and group columns differ
+        let owner = file_owner::Owner::from_uid(metadata.uid()).name().unwrap().unwrap_or(metadata.uid().to_string());
+        let group = file_owner::Group::from_gid(metadata.gid()).name().unwrap().unwrap_or(metadata.gid().to_string());
+        let named: Vec<&str> = named.split_whitespace().collect();
+        let numeric: Vec<&str> = numeric.split_whitespace().collect();
+        assert_eq!(named.len(), 11);
+        assert_eq!(named[0], metadata.ino().to_string());
+        assert_eq!(named[2], crate::listing::mode_string(&FileStat::from(&metadata)));
+        assert_eq!((named[4], named[5]), (owner.as_str(), group.as_str()));
+        assert_eq!(named[6], "5");
+        assert_eq!(named[10], file_path.to_string_lossy());
+        assert_eq!((numeric[4], numeric[5]), (metadata.uid().to_string().as_str(), metadata.gid().to_string().as_str()));
+        assert_eq!([&named[..4], &named[6..]], [&numeric[..4], &numeric[6..]]);
+
+        // A symlink shows its target
+        let link = walkdir::WalkDir::new(&link_path).into_iter().next().unwrap().unwrap();
+        let line = ls(interpreter(), &link);
+        assert!(line.starts_with(&format!("{:>6} ", fs::symlink_metadata(&link_path).unwrap().ino())));
+        assert!(line.ends_with(&format!(" {} -> file.txt\n", link_path.display())), "{}", line);
+        assert_eq!(line.split_whitespace().nth(2).map(|mode| &mode[..1]), Some("l"));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_printf_inode_and_link_count() {
+        use std::os::unix::fs::MetadataExt;
+
+        let temp_dir = TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("test.txt");
+        fs::write(&file_path, "test content").unwrap();
+        fs::hard_link(&file_path, temp_dir.path().join("link.txt")).unwrap();
+
+        let entry = walkdir::WalkDir::new(&file_path)
+            .into_iter()
+            .next()
+            .unwrap()
+            .unwrap();
+        let metadata = fs::metadata(&file_path).unwrap();
+
+        let directives = vec![
+            FormatDirective::Inode,
+            FormatDirective::Literal(" ".to_string()),
+            FormatDirective::LinkCount,
+        ];
+        let rendered = render_text(&interpreter(), &directives, &entry);
+        assert_eq!(rendered, format!("{} 2", metadata.ino()));
+    }
+
+    #[test]
+    fn test_printf_path_directives() {
+        let temp_dir = TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("test.txt");
+        fs::write(&file_path, "test content").unwrap();
+
+        let entry = walkdir::WalkDir::new(&file_path)
+            .into_iter()
+            .next()
+            .unwrap()
+            .unwrap();
+
+        let directives = vec![
+            FormatDirective::Basename,
+            FormatDirective::Literal("|".to_string()),
+            FormatDirective::Dirname,
+            FormatDirective::Literal("|".to_string()),
+            FormatDirective::Size,
+            FormatDirective::Literal("|".to_string()),
+            FormatDirective::Depth,
+        ];
+        let rendered = render_text(&interpreter(), &directives, &entry);
+        assert_eq!(rendered, format!("test.txt|{}|12|0", temp_dir.path().display()));
+    }
+
+    #[test]
+    fn test_printf_utf8_output() {
+        let temp_dir = TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("naïve.txt");
+        fs::write(&file_path, "").unwrap();
+        let entry = walkdir::WalkDir::new(&file_path).into_iter().next().unwrap().unwrap();
+
+        let interpreter = interpreter();
+        let printf = Expr::Action(Action::Printf(vec![
+            FormatDirective::Literal("→ ".to_string()),
+            FormatDirective::Basename,
+            FormatDirective::Literal(" ✓ 100%".to_string()),
+        ]));
+        assert!(interpreter.matches(&printf, &entry));
+        assert_eq!(interpreter.take_output(), "→ naïve.txt ✓ 100%".as_bytes());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_newer_than_a_directory() {
+        // A directory reference is compared by its own timestamps, like any other file
+        let temp_dir = TempDir::new().unwrap();
+        let reference = temp_dir.path().join("reference");
+        fs::create_dir(&reference).unwrap();
+        let now = SystemTime::now();
+        let set_modified = |path: &Path, at| fs::File::open(path).unwrap().set_modified(at).unwrap();
+        for (name, hours_ago) in [("older.txt", 2), ("newer.txt", 0)] {
+            let path = temp_dir.path().join(name);
+            fs::write(&path, "").unwrap();
+            set_modified(&path, now - Duration::from_secs(hours_ago * 3600));
+        }
+        set_modified(&reference, now - Duration::from_secs(3600));
+
+        let interpreter = Interpreter::new(temp_dir.path(), now);
+        let entry = |name| walkdir::WalkDir::new(temp_dir.path().join(name)).into_iter().next().unwrap().unwrap();
+        for predicate in ["-newer", "-mnewer"] {
+            let input = format!("{} {}", predicate, reference.display());
+            let expr = crate::parser::parse_expression(&input).unwrap();
+            assert!(interpreter.matches(&expr, &entry("newer.txt")), "{}", input);
+            assert!(!interpreter.matches(&expr, &entry("older.txt")), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_printf_start_point_directives() {
+        let temp_dir = TempDir::new().unwrap();
+        let sub_dir = temp_dir.path().join("sub");
+        fs::create_dir(&sub_dir).unwrap();
+        fs::write(sub_dir.join("test.txt"), "test content").unwrap();
+
+        let interpreter = Interpreter::new(temp_dir.path(), SystemTime::now());
+        let directives = vec![
+            FormatDirective::StartPath,
+            FormatDirective::Literal("|".to_string()),
+            FormatDirective::RelativePath,
+        ];
+        let rendered: Vec<String> = walkdir::WalkDir::new(temp_dir.path())
+            .sort_by_file_name()
+            .into_iter()
+            .map(|entry| render_text(&interpreter, &directives, &entry.unwrap()))
+            .collect();
+
+        let start = temp_dir.path().display();
+        assert_eq!(
+            rendered,
+            vec![
+                format!("{}|", start),
+                format!("{}|sub", start),
+                format!("{}|{}", start, Path::new("sub").join("test.txt").display()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_time_tests_use_captured_now() {
+        let now = fixed_now();
+        let mock = MockFileSystem::new().with_file("src/lib.rs", mock_file(12, now));
+        let entry = stand_in("src/lib.rs");
+
+        // Ages are measured from the interpreter's reference time, not the clock, so
+        // pretending the run started three days from now makes the new file 3 days old
+        let later = now + Duration::from_secs(3 * 24 * 60 * 60 + 60);
+        let started_later = mock_interpreter(later, mock);
+        let mtime = |sign, value| Expr::Test(Test::Mtime(TimeSpec { sign, value }));
+
+        assert!(started_later.matches(&mtime(Sign::None, 3), &entry));
+        assert!(!started_later.matches(&mtime(Sign::None, 0), &entry));
+        assert!(started_later.matches(&mtime(Sign::Plus, 2), &entry));
+        // Against the time it was modified it is brand new
+        let started_now = mock_interpreter(now, MockFileSystem::new().with_file("src/lib.rs", mock_file(12, now)));
+        assert!(started_now.matches(&mtime(Sign::None, 0), &entry));
+    }
+
+    #[test]
+    fn test_humanize() {
+        assert_eq!(Interpreter::humanize(0), "0");
+        assert_eq!(Interpreter::humanize(1023), "1023");
+        assert_eq!(Interpreter::humanize(1024), "1.0K");
+        assert_eq!(Interpreter::humanize(1536), "1.5K");
+        assert_eq!(Interpreter::humanize(20 * 1024), "20K");
+        assert_eq!(Interpreter::humanize(1048575), "1.0M");
+        assert_eq!(Interpreter::humanize(1048576), "1.0M");
+        assert_eq!(Interpreter::humanize(3355443), "3.2M");
+        assert_eq!(Interpreter::humanize(1024 * 1024 * 1024), "1.0G");
+        assert_eq!(Interpreter::humanize(u64::MAX), "16E");
+    }
+
+    #[test]
+    fn test_printf_default_time_format() {
+        let at = |input| crate::datetime::parse_datetime(input).unwrap();
+        let file = FileStat {
+            changed: Some(at("2024-03-05T07:08:09")),
+            created: Some(at("2020-01-01T00:00:00")),
+            ..mock_file(3, at("2021-06-30T21:49:08"))
+        };
+        let mock = MockFileSystem::new()
+            .with_file("src/lib.rs", file)
+            .with_file("src/ast.rs", FileStat::default());
+        let interpreter = mock_interpreter(SystemTime::now(), mock);
+
+        let render = |field, path| render_text(&interpreter, &[FormatDirective::Time(field)], &stand_in(path));
+        assert_eq!(render(TimeField::Modify, "src/lib.rs"), "Wed Jun 30 21:49:08 2021");
+        assert_eq!(render(TimeField::Access, "src/lib.rs"), "Wed Jun 30 21:49:08 2021");
+        assert_eq!(render(TimeField::Change, "src/lib.rs"), "Tue Mar  5 07:08:09 2024");
+        // A timestamp that isn't recorded prints as ?
+        assert_eq!(render(TimeField::Modify, "src/ast.rs"), "?");
+    }
+
+    #[test]
+    fn test_printf_human_size() {
+        let mock = MockFileSystem::new()
+            .with_file("src/lib.rs", mock_file(1536, SystemTime::now()))
+            .with_file("src/ast.rs", mock_file(3 * 1024 * 1024 * 1024, SystemTime::now()));
+        let interpreter = mock_interpreter(SystemTime::now(), mock).with_human_sizes();
+
+        let render = |path| render_text(&interpreter, &[FormatDirective::Size], &stand_in(path));
+        assert_eq!(render("src/lib.rs"), "1.5K");
+        assert_eq!(render("src/ast.rs"), "3.0G");
+    }
+
+    #[test]
+    fn test_printf_sparseness() {
+        let sized = |len, blocks| FileStat { blocks, ..mock_file(len, SystemTime::now()) };
+        let mock = MockFileSystem::new()
+            .with_file("src/lib.rs", sized(4096, 8))
+            .with_file("src/ast.rs", sized(1 << 20, 8))
+            .with_file("src/main.rs", sized(10, 8))
+            .with_file("src/parser.rs", sized(3 * 512, 1))
+            .with_file("src/options.rs", sized(0, 0))
+            .with_file("src/prune.rs", sized(0, 8));
+        let interpreter = mock_interpreter(SystemTime::now(), mock);
+
+        // The same figures GNU find prints, which formats them with %g
+        let render = |path| render_text(&interpreter, &[FormatDirective::Sparseness], &stand_in(path));
+        assert_eq!(render("src/lib.rs"), "1");
+        assert_eq!(render("src/ast.rs"), "0.00390625");
+        assert_eq!(render("src/main.rs"), "409.6");
+        assert_eq!(render("src/parser.rs"), "0.333333");
+        assert_eq!(render("src/options.rs"), "1");
+        assert_eq!(render("src/prune.rs"), "inf");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_same_file() {
+        let inode = |dev, ino| FileStat { file_type: Some(FileType::RegularFile), dev, ino, ..FileStat::default() };
+        let mock = MockFileSystem::new()
+            .with_file("src/lib.rs", inode(1, 42))
+            .with_file("src/ast.rs", inode(2, 42));
+        let interpreter = mock_interpreter(SystemTime::now(), mock);
+        let same_file = Expr::Test(Test::SameFile(ReferenceFile { path: "hard-link".to_string(), metadata: inode(1, 42) }));
+
+        assert!(interpreter.matches(&same_file, &stand_in("src/lib.rs")));
+        // The same inode number on another device is a different file
+        assert!(!interpreter.matches(&same_file, &stand_in("src/ast.rs")));
+        assert!(!interpreter.matches(&same_file, &stand_in("src/parser.rs")));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_same_file_on_another_device() {
+        let inode = |file_type, dev| FileStat { file_type: Some(file_type), dev, ino: 42, ..FileStat::default() };
+        let mock = Arc::new(
+            MockFileSystem::new()
+                .with_file("src/lib.rs", inode(FileType::RegularFile, 1))
+                .with_file("src", inode(FileType::Directory, 1)),
+        );
+        let interpreter = Interpreter::new(Path::new("."), SystemTime::now())
+            .with_file_system(mock.clone())
+            .with_walk_device(2);
+        let same_file = |file_type| {
+            Expr::Test(Test::SameFile(ReferenceFile { path: "ref".to_string(), metadata: inode(file_type, 1) }))
+        };
+
+        // Files on the walk's device can't be the reference, so aren't stat'ed
+        assert!(!interpreter.matches(&same_file(FileType::RegularFile), &stand_in("src/lib.rs")));
+        assert_eq!(mock.lookups(), 0);
+        // But a directory may be a mount point of the reference's device
+        assert!(interpreter.matches(&same_file(FileType::Directory), &stand_in("src")));
+        assert_eq!(mock.lookups(), 1);
+    }
+
+    #[test]
+    fn test_each_time_field() {
+        // Access, status change and modification are an hour, two hours and three hours
+        // ago. The creation time, which none of the tests read, is older still
+        let now = fixed_now();
+        let hours_ago = |hours: u64| now - Duration::from_secs(hours * 3600);
+        let stat = FileStat {
+            file_type: Some(FileType::RegularFile),
+            accessed: Some(hours_ago(1)),
+            changed: Some(hours_ago(2)),
+            modified: Some(hours_ago(3)),
+            created: Some(hours_ago(5)),
+            ..FileStat::default()
+        };
+        let interpreter = mock_interpreter(now, MockFileSystem::new().with_file("src/lib.rs", stat));
+        let entry = stand_in("src/lib.rs");
+        let matches = |test| interpreter.matches(&Expr::Test(test), &entry);
+
+        // A reference whose every timestamp is 150 minutes ago is older than the access
+        // and change times, but newer than the modification time
+        let reference = ReferenceFile {
+            path: "reference".to_string(),
+            metadata: FileStat {
+                accessed: Some(now - Duration::from_secs(150 * 60)),
+                changed: Some(now - Duration::from_secs(150 * 60)),
+                modified: Some(now - Duration::from_secs(150 * 60)),
+                ..FileStat::default()
+            },
+        };
+        assert!(matches(Test::Anewer(reference.clone())));
+        assert!(matches(Test::Cnewer(reference.clone())));
+        assert!(!matches(Test::Mnewer(reference.clone())));
+        assert!(!matches(Test::Newer(reference)));
+
+        let at = now - Duration::from_secs(150 * 60);
+        assert!(matches(Test::NewerAt(at)));
+        assert!(matches(Test::NewerCt(at)));
+        assert!(!matches(Test::NewerMt(at)));
+
+        let exactly = |value| TimeSpec { sign: Sign::None, value };
+        assert!(matches(Test::Amin(exactly(60))));
+        assert!(matches(Test::Cmin(exactly(120))));
+        assert!(matches(Test::Mmin(exactly(180))));
+        assert!(!matches(Test::Mmin(exactly(60))));
+        for test in [Test::Atime(exactly(0)), Test::Ctime(exactly(0)), Test::Mtime(exactly(0))] {
+            assert!(matches(test));
+        }
+
+        // A timestamp the reference doesn't have can't be compared
+        let no_times = ReferenceFile { path: "reference".to_string(), metadata: FileStat::default() };
+        assert!(!matches(Test::Anewer(no_times)));
+    }
+
+    #[test]
+    fn test_future_times() {
+        // Clock skew or a file copied from elsewhere can leave times in the future
+        let now = fixed_now();
+        let mock = MockFileSystem::new().with_file("src/lib.rs", mock_file(1, now + Duration::from_secs(3600)));
+        let interpreter = mock_interpreter(now, mock);
+        let entry = stand_in("src/lib.rs");
+
+        // A file from the future has no age, so no age test matches it
+        for sign in [Sign::None, Sign::Plus, Sign::Minus] {
+            assert!(!interpreter.matches(&Expr::Test(Test::Mmin(TimeSpec { sign: sign.clone(), value: 1 })), &entry));
+            assert!(!interpreter.matches(&Expr::Test(Test::Atime(TimeSpec { sign, value: 1 })), &entry));
+        }
+        // But it is newer than now
+        assert!(interpreter.matches(&Expr::Test(Test::NewerMt(now)), &entry));
+    }
+
+    #[test]
+    fn test_unreadable_metadata() {
+        let mock = MockFileSystem::new()
+            .with_file("src", FileStat { file_type: Some(FileType::Directory), ..FileStat::default() })
+            .with_error("src/lib.rs", io::ErrorKind::PermissionDenied);
+        let interpreter = mock_interpreter(SystemTime::now(), mock);
+        let entry = stand_in("src/lib.rs");
+
+        // Tests that need metadata don't match, in either sense of a comparison
+        let size = |sign| Expr::Test(Test::Size(SizeSpec { sign, value: 1, suffix: None }));
+        for test in [size(Sign::Plus), size(Sign::Minus), Expr::Test(Test::Empty)] {
+            assert!(!interpreter.matches(&test, &entry));
+        }
+        assert!(!interpreter.matches(&Expr::Test(Test::Type(FileType::RegularFile)), &entry));
+        assert!(!interpreter.matches(&Expr::Test(Test::NewerMt(SystemTime::UNIX_EPOCH)), &entry));
+        assert_eq!(render_text(&interpreter, &[FormatDirective::Size], &entry), "?");
+
+        // A directory is still not empty when an entry in it can't be read
+        assert!(!interpreter.matches(&Expr::Test(Test::Empty), &stand_in("src")));
+    }
+
+    #[test]
+    fn test_broken_symlink() {
+        let mock = MockFileSystem::new().with_symlink("src/lib.rs", "missing.rs");
+        let interpreter = mock_interpreter(SystemTime::now(), mock);
+        let entry = stand_in("src/lib.rs");
+
+        // Tests describe the link itself, and its target's type is unknown
+        assert!(!interpreter.matches(&Expr::Test(Test::Type(FileType::RegularFile)), &entry));
+        assert!(!interpreter.matches(&Expr::Test(Test::Empty), &entry));
+        let directives = [FormatDirective::Size, FormatDirective::Literal(" ".to_string()), FormatDirective::TargetType];
+        assert_eq!(render_text(&interpreter, &directives, &entry), "10 ?");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_special_file_types() {
+        use std::os::unix::ffi::OsStrExt;
+        use std::os::unix::fs::{symlink, FileTypeExt};
+        use std::os::unix::net::UnixListener;
+
+        let temp_dir = TempDir::new().unwrap();
+        let fifo = temp_dir.path().join("fifo");
+        let fifo_c = std::ffi::CString::new(fifo.as_os_str().as_bytes()).unwrap();
+        // SAFETY: fifo_c is a valid NUL-terminated path
+        assert_eq!(unsafe { libc::mkfifo(fifo_c.as_ptr(), 0o600) }, 0);
+        let socket = temp_dir.path().join("socket");
+        let _listener = UnixListener::bind(&socket).unwrap();
+        let link = temp_dir.path().join("link");
+        symlink(&fifo, &link).unwrap();
+        let mut cases = vec![
+            (fifo, FileType::NamedPipe),
+            (socket, FileType::Socket),
+            (PathBuf::from("/dev/null"), FileType::CharFile),
+        ];
+        // Not every sandbox has a block device to look at
+        let block = fs::read_dir("/dev").into_iter().flatten()
+            .filter_map(|entry| entry.ok())
+            .find(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_block_device()));
+        if let Some(block) = block {
+            cases.push((block.path(), FileType::BlockFile));
+        }
+
+        // Every lookup fails in an empty mock, so these types come from the walk alone
+        let interpreter = mock_interpreter(SystemTime::now(), MockFileSystem::new());
+        let all = [
+            FileType::BlockFile, FileType::CharFile, FileType::NamedPipe, FileType::Socket,
+            FileType::RegularFile, FileType::Directory, FileType::SymbolicLink,
+        ];
+        let types_of = |entry: &DirEntry| -> Vec<FileType> {
+            all.iter()
+                .filter(|file_type| interpreter.matches(&Expr::Test(Test::Type((*file_type).clone())), entry))
+                .cloned()
+                .collect()
+        };
+        for (path, expected) in cases {
+            let entry = WalkDir::new(&path).into_iter().next().unwrap().unwrap();
+            assert_eq!(types_of(&entry), [expected], "{}", path.display());
+        }
+
+        // A link to a pipe is only a pipe too when the walk follows it
+        let entry = WalkDir::new(&link).into_iter().next().unwrap().unwrap();
+        assert_eq!(types_of(&entry), [FileType::SymbolicLink]);
+        let entry = WalkDir::new(&link).follow_links(true).into_iter().next().unwrap().unwrap();
+        assert_eq!(types_of(&entry), [FileType::NamedPipe, FileType::SymbolicLink]);
+    }
+
+    #[test]
+    fn test_empty_start_path() {
+        let temp_dir = TempDir::new().unwrap();
+        let empty_dir = temp_dir.path().join("empty");
+        let full_dir = temp_dir.path().join("full");
+        fs::create_dir(&empty_dir).unwrap();
+        fs::create_dir(&full_dir).unwrap();
+        fs::write(full_dir.join("empty.txt"), "").unwrap();
+
+        let empty_test = Expr::Test(Test::Empty);
+        let root_entry = |path: &std::path::Path| {
+            let entry = walkdir::WalkDir::new(path).into_iter().next().unwrap().unwrap();
+            assert_eq!(entry.depth(), 0);
+            entry
+        };
+
+        assert!(interpreter().matches(&empty_test, &root_entry(&empty_dir)));
+        assert!(!interpreter().matches(&empty_test, &root_entry(&full_dir)));
+        assert!(interpreter().matches(&empty_test, &root_entry(&full_dir.join("empty.txt"))));
+    }
+
+    #[test]
+    fn test_empty_reports_unlistable_dir() {
+        let now = SystemTime::now();
+        let locked = FileStat { file_type: Some(FileType::Directory), ..FileStat::default() };
+        let mock = MockFileSystem::new()
+            .with_file("src", locked)
+            .with_read_dir_error("src", io::ErrorKind::PermissionDenied);
+        let interpreter = mock_interpreter(now, mock);
+
+        assert!(!interpreter.matches(&Expr::Test(Test::Empty), &stand_in("src")));
+        assert_eq!(interpreter.take_errors(), ["src: permission denied"]);
+        assert!(interpreter.take_errors().is_empty());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_empty_unreadable_dir() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = TempDir::new().unwrap();
+        let locked = temp_dir.path().join("locked");
+        fs::create_dir(&locked).unwrap();
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+        // Root reads the directory regardless, so there is nothing to check
+        let readable = fs::read_dir(&locked).is_ok();
+        let interpreter = interpreter();
+        let matched = interpreter.matches(&Expr::Test(Test::Empty), &stand_in(locked.to_str().unwrap()));
+        let errors = interpreter.take_errors();
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+        if readable {
+            return;
+        }
+
+        assert!(!matched);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with(&format!("{}: Permission denied", locked.display())), "{:?}", errors);
+    }
+
+    #[test]
+    fn test_size_and_empty_agree() {
+        let temp_dir = TempDir::new().unwrap();
+        let empty_path = temp_dir.path().join("empty");
+        let one_byte_path = temp_dir.path().join("one_byte");
+        fs::write(&empty_path, "").unwrap();
+        fs::write(&one_byte_path, "x").unwrap();
+
+        let entry_for = |path: &std::path::Path| {
+            walkdir::WalkDir::new(path).into_iter().next().unwrap().unwrap()
+        };
+        let size = |sign, value, suffix| Expr::Test(Test::Size(SizeSpec { sign, value, suffix }));
+        let matches = |expr: &Expr, path: &std::path::Path| interpreter().matches(expr, &entry_for(path));
+
+        let empty = Expr::Test(Test::Empty);
+        let zero_bytes = size(Sign::None, 0, Some(SizeSuffix::Bytes));
+        assert!(matches(&empty, &empty_path));
+        assert!(matches(&zero_bytes, &empty_path));
+        assert!(!matches(&empty, &one_byte_path));
+        assert!(!matches(&zero_bytes, &one_byte_path));
+
+        assert!(matches(&size(Sign::None, 1, Some(SizeSuffix::Bytes)), &one_byte_path));
+        // One byte rounds up to one 512-byte block, and a kilobyte too
+        assert!(matches(&size(Sign::None, 1, None), &one_byte_path));
+        assert!(matches(&size(Sign::None, 1, Some(SizeSuffix::Kb)), &one_byte_path));
+        assert!(!matches(&size(Sign::None, 0, None), &one_byte_path));
+        // So only empty files are smaller than one block
+        assert!(matches(&size(Sign::Minus, 1, None), &empty_path));
+        assert!(!matches(&size(Sign::Minus, 1, None), &one_byte_path));
+    }
+
+    #[test]
+    fn test_links() {
+        let now = SystemTime::now();
+        let linked = |nlink| FileStat { nlink, ..mock_file(3, now) };
+        let mock = MockFileSystem::new()
+            .with_file("src/lib.rs", linked(1))
+            .with_file("src/ast.rs", linked(3));
+        let interpreter = mock_interpreter(now, mock);
+        let links = |sign, value| Expr::Test(Test::Links(CountSpec { sign, value }));
+        let matches = |expr: &Expr, path| interpreter.matches(expr, &stand_in(path));
+
+        assert!(matches(&links(Sign::None, 1), "src/lib.rs"));
+        assert!(!matches(&links(Sign::None, 1), "src/ast.rs"));
+        assert!(matches(&links(Sign::Plus, 1), "src/ast.rs"));
+        assert!(!matches(&links(Sign::Plus, 3), "src/ast.rs"));
+        assert!(matches(&links(Sign::Minus, 3), "src/lib.rs"));
+        assert!(!matches(&links(Sign::Minus, 1), "src/lib.rs"));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_links_counts_subdirectories() {
+        let temp_dir = TempDir::new().unwrap();
+        let parent = temp_dir.path().join("parent");
+        for child in ["a", "b", "c"] {
+            fs::create_dir_all(parent.join(child)).unwrap();
+        }
+        fs::write(parent.join("file.txt"), "").unwrap();
+        // btrfs and some others give every directory one link, so there's nothing to check
+        if RealFileSystem.metadata(&parent).unwrap().nlink == 1 {
+            return;
+        }
+
+        // Its entry in the parent, its own ".", and the ".." of each subdirectory;
+        // files don't count
+        let links = |value| Expr::Test(Test::Links(CountSpec { sign: Sign::None, value }));
+        let entry = stand_in(parent.to_str().unwrap());
+        assert!(interpreter().matches(&links(5), &entry));
+        // So a leaf directory has two
+        let leaf = stand_in(parent.join("a").to_str().unwrap());
+        assert!(interpreter().matches(&links(2), &leaf));
+        assert!(!interpreter().matches(&links(2), &entry));
+    }
+
+    #[test]
+    fn test_size_sign_edge_cases() {
+        let matching = |sign: Sign, value, suffix: Option<SizeSuffix>| -> Vec<u64> {
+            let spec = SizeSpec { sign, value, suffix };
+            [0, 1, 511, 512, 513, 1024 * 1024]
+                .into_iter()
+                .filter(|size| Interpreter::compare_size(*size, &spec))
+                .collect()
+        };
+
+        // Every non-empty file rounds up to at least one unit, so "less than one" is
+        // only the empty files, however big the unit
+        assert_eq!(matching(Sign::Minus, 1, None), [0]);
+        assert_eq!(matching(Sign::Minus, 1, Some(SizeSuffix::Kb)), [0]);
+        assert_eq!(matching(Sign::Minus, 1, Some(SizeSuffix::Gb)), [0]);
+        assert_eq!(matching(Sign::None, 0, None), [0]);
+        assert_eq!(matching(Sign::Plus, 0, None), [1, 511, 512, 513, 1024 * 1024]);
+        assert_eq!(matching(Sign::Plus, 0, Some(SizeSuffix::Bytes)), [1, 511, 512, 513, 1024 * 1024]);
+        // Nothing is smaller than zero units
+        assert!(matching(Sign::Minus, 0, None).is_empty());
+        // A 513-byte file is two blocks
+        assert_eq!(matching(Sign::None, 1, None), [1, 511, 512]);
+        assert_eq!(matching(Sign::Minus, 2, None), [0, 1, 511, 512]);
+    }
+
+    #[test]
+    fn test_size_in_bytes_is_exact() {
+        let temp_dir = TempDir::new().unwrap();
+        let size = |value, suffix| Expr::Test(Test::Size(SizeSpec { sign: Sign::None, value, suffix }));
+        let matches_len = |expr: &Expr, len: usize| {
+            let path = temp_dir.path().join(len.to_string());
+            fs::write(&path, vec![b'x'; len]).unwrap();
+            let entry = walkdir::WalkDir::new(&path).into_iter().next().unwrap().unwrap();
+            interpreter().matches(expr, &entry)
+        };
+
+        let hundred_bytes = size(100, Some(SizeSuffix::Bytes));
+        assert!(matches_len(&hundred_bytes, 100));
+        assert!(!matches_len(&hundred_bytes, 99));
+        assert!(!matches_len(&hundred_bytes, 101));
+
+        // Every other unit rounds up, so 1k is anything from 1 to 1024 bytes
+        let one_kb = size(1, Some(SizeSuffix::Kb));
+        for len in [1, 512, 1023, 1024] {
+            assert!(matches_len(&one_kb, len), "{}", len);
+        }
+        assert!(!matches_len(&one_kb, 0));
+        assert!(!matches_len(&one_kb, 1025));
+    }
+
+    #[test]
+    fn test_tree_size() {
+        let temp_dir = TempDir::new().unwrap();
+        let outer = temp_dir.path().join("outer");
+        let inner = outer.join("inner");
+        fs::create_dir_all(&inner).unwrap();
+        fs::write(outer.join("small.txt"), vec![b'x'; 1000]).unwrap();
+        fs::write(inner.join("big.txt"), vec![b'x'; 3000]).unwrap();
+        // A link back up the tree must not be followed while summing
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&outer, inner.join("loop")).unwrap();
+
+        let spec = SizeSpec { sign: Sign::Plus, value: 3, suffix: Some(SizeSuffix::Kb) };
+        let expr = Expr::Test(Test::TreeSize(spec));
+        let entries: Vec<_> = walkdir::WalkDir::new(&outer)
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .collect();
+        let entry_for = |path: &std::path::Path| entries.iter().find(|e| e.path() == path).unwrap();
+
+        // 4000 bytes in total is over 3k, the inner 3000 bytes isn't
+        let interpreter = interpreter();
+        assert!(interpreter.matches(&expr, entry_for(&outer)));
+        // Summing the outer directory cached the inner one too
+        assert_eq!(interpreter.tree_totals.borrow().get(&inner), Some(&3000));
+        assert_eq!(interpreter.tree_totals.borrow().len(), 2);
+        assert!(!interpreter.matches(&expr, entry_for(&inner)));
+        // Files never match, however big
+        assert!(!interpreter.matches(&expr, entry_for(&inner.join("big.txt"))));
+    }
+
+    #[test]
+    fn test_magic() {
+        let temp_dir = TempDir::new().unwrap();
+        let png_path = temp_dir.path().join("image.dat");
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR".to_vec();
+        png.resize(64, 0);
+        fs::write(&png_path, png).unwrap();
+        let gzip_path = temp_dir.path().join("archive.png");
+        fs::write(&gzip_path, b"\x1f\x8b\x08\0\0\0\0\0\0\x03").unwrap();
+        let text_path = temp_dir.path().join("notes.png");
+        fs::write(&text_path, "just some text").unwrap();
+
+        let entry_for = |path: &std::path::Path| {
+            walkdir::WalkDir::new(path).into_iter().next().unwrap().unwrap()
+        };
+
+        // The content decides, not the extension
+        let png_test = Expr::Test(Test::Magic("image/png".to_string()));
+        assert!(interpreter().matches(&png_test, &entry_for(&png_path)));
+        assert!(!interpreter().matches(&png_test, &entry_for(&gzip_path)));
+        assert!(!interpreter().matches(&png_test, &entry_for(&text_path)));
+
+        let gzip_test = Expr::Test(Test::Magic("application/GZIP".to_string()));
+        assert!(interpreter().matches(&gzip_test, &entry_for(&gzip_path)));
+
+        // Directories are never read
+        assert!(!interpreter().matches(&png_test, &entry_for(temp_dir.path())));
+    }
+
+    #[test]
+    fn test_grep() {
+        let temp_dir = TempDir::new().unwrap();
+        let write = |name: &str, content: &[u8]| {
+            let path = temp_dir.path().join(name);
+            fs::write(&path, content).unwrap();
+            walkdir::WalkDir::new(path).into_iter().next().unwrap().unwrap()
+        };
+        let matching = write("matching.rs", b"use std::fs;\r\nfn main() {\r\n}\r\n");
+        let other = write("other.rs", b"fn helper() {}\nmain\n");
+        let binary = write("binary.dat", b"\x7fELF\0\0fn main()\n");
+        // The line matches, but the NUL after it still makes the file binary
+        let binary_after_match = write("late.dat", b"fn main()\n\x7fELF\0\0");
+        let mut long_line = b"fn main() ".repeat(200_000);
+        long_line.push(b'\n');
+        let long_line = write("minified.js", &long_line);
+        let grep = |re: &str| {
+            let regex = regex::Regex::new(re).unwrap();
+            Expr::Test(Test::Grep(RegexPattern { source: re.to_string(), regex }))
+        };
+
+        assert!(interpreter().matches(&grep("fn main"), &matching));
+        assert!(!interpreter().matches(&grep("fn main"), &other));
+        // Each line is matched on its own, without its line ending
+        assert!(interpreter().matches(&grep("^main$"), &other));
+        assert!(interpreter().matches(&grep("\\{$"), &matching));
+        assert!(!interpreter().matches(&grep("helper.*main"), &other));
+        // Binary files and directories are never searched
+        assert!(!interpreter().matches(&grep("fn main"), &binary));
+        assert!(!interpreter().matches(&grep("fn main"), &binary_after_match));
+        // As are files with lines too long to read whole
+        assert!(!interpreter().matches(&grep("fn main"), &long_line));
+        let dir = walkdir::WalkDir::new(temp_dir.path()).into_iter().next().unwrap().unwrap();
+        assert!(!interpreter().matches(&grep(".*"), &dir));
+        // Nor are files over the content size limit
+        assert!(!interpreter().with_max_content_size(8).matches(&grep("fn main"), &matching));
+    }
+
+    #[test]
+    fn test_content_tests_read_through_the_file_system() {
+        let dir = FileStat { file_type: Some(FileType::Directory), ..FileStat::default() };
+        let mock = MockFileSystem::new()
+            .with_file("src", dir.clone())
+            .with_contents("src/lib.rs", "pub mod ast;\nfn main() {}\n")
+            .with_contents("src/ast.rs", b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR".to_vec())
+            .with_file("src/sub", dir)
+            .with_contents("src/sub/data.bin", [0; 10])
+            .with_symlink("src/sub/lib.rs", "../lib.rs");
+        let interpreter = mock_interpreter(fixed_now(), mock);
+
+        let regex = regex::Regex::new("^fn main").unwrap();
+        let grep = Expr::Test(Test::Grep(RegexPattern { source: "^fn main".to_string(), regex }));
+        assert!(interpreter.matches(&grep, &stand_in("src/lib.rs")));
+        assert!(!interpreter.matches(&grep, &stand_in("src/ast.rs")));
+        let magic = Expr::Test(Test::Magic("image/png".to_string()));
+        assert!(interpreter.matches(&magic, &stand_in("src/ast.rs")));
+        assert!(!interpreter.matches(&magic, &stand_in("src/lib.rs")));
+
+        // The 26 bytes of lib.rs, 16 of ast.rs and 10 of data.bin, without following the link
+        let spec = SizeSpec { sign: Sign::None, value: 52, suffix: Some(SizeSuffix::Bytes) };
+        assert!(interpreter.matches(&Expr::Test(Test::TreeSize(spec)), &stand_in("src")));
+        assert_eq!(interpreter.tree_totals.borrow().get(Path::new("src/sub")), Some(&10));
+    }
+
+    #[test]
+    fn test_magic_skips_files_over_the_limit() {
+        let temp_dir = TempDir::new().unwrap();
+        let png_path = temp_dir.path().join("large.png");
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR".to_vec();
+        png.resize(10_000, 0);
+        fs::write(&png_path, png).unwrap();
+        let entry = walkdir::WalkDir::new(&png_path).into_iter().next().unwrap().unwrap();
+        let png_test = Expr::Test(Test::Magic("image/png".to_string()));
+
+        assert!(interpreter().matches(&png_test, &entry));
+        assert!(interpreter().with_max_content_size(10_000).matches(&png_test, &entry));
+        assert!(!interpreter().with_max_content_size(4096).matches(&png_test, &entry));
+        // Tests that don't read content ignore the limit
+        let size_test = Expr::Test(Test::Size(SizeSpec { sign: Sign::Plus, value: 1, suffix: Some(SizeSuffix::Kb) }));
+        assert!(interpreter().with_max_content_size(4096).matches(&size_test, &entry));
+    }
+
+    #[test]
+    fn test_printf_type_directives() {
+        let temp_dir = TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("test.txt");
+        fs::write(&file_path, "test content").unwrap();
+
+        // Find entries by walking the parent, since a broken link can't be a walk root
+        let directives = vec![FormatDirective::Type, FormatDirective::TargetType];
+        let render = |path: &std::path::Path| {
+            let entry = walkdir::WalkDir::new(temp_dir.path())
+                .into_iter()
+                .filter_map(|e| e.ok())
+                .find(|e| e.path() == path)
+                .unwrap();
+            render_text(&interpreter(), &directives, &entry)
+        };
+
+        assert_eq!(render(&file_path), "ff");
+        assert_eq!(render(temp_dir.path()), "dd");
+
+        #[cfg(unix)]
+        {
+            let link_path = temp_dir.path().join("link.txt");
+            std::os::unix::fs::symlink(&file_path, &link_path).unwrap();
+            assert_eq!(render(&link_path), "lf");
+
+            let broken_path = temp_dir.path().join("broken.txt");
+            std::os::unix::fs::symlink(temp_dir.path().join("missing"), &broken_path).unwrap();
+            assert_eq!(render(&broken_path), "l?");
+        }
     }
 
     #[test]
@@ -689,21 +3022,43 @@ mod tests {
             prefix: None,
             term: PermTerm::Numeric(0o644),
         });
-        assert!(Interpreter::evaluate(&Expr::Test(perm_test), &entry));
+        assert!(interpreter().matches(&Expr::Test(perm_test), &entry));
 
         // Test all mode (file has 644, checking for 044 should pass)
         let perm_test_all = Test::Perm(PermSpec {
             prefix: Some(PermPrefix::AllMode),
             term: PermTerm::Numeric(0o044),
         });
-        assert!(Interpreter::evaluate(&Expr::Test(perm_test_all), &entry));
+        assert!(interpreter().matches(&Expr::Test(perm_test_all), &entry));
 
         // Test any mode (file has 644, checking for 200 should pass)
         let perm_test_any = Test::Perm(PermSpec {
             prefix: Some(PermPrefix::AnyMode),
             term: PermTerm::Numeric(0o200),
         });
-        assert!(Interpreter::evaluate(&Expr::Test(perm_test_any), &entry));
+        assert!(interpreter().matches(&Expr::Test(perm_test_any), &entry));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_zero_permission_masks() {
+        let now = SystemTime::now();
+        let mode = |mode: u32| FileStat { file_type: Some(FileType::RegularFile), mode: 0o100000 | mode, ..FileStat::default() };
+        let mock = MockFileSystem::new()
+            .with_file("src/ast.rs", mode(0o000))
+            .with_file("src/lib.rs", mode(0o644));
+        let interpreter = mock_interpreter(now, mock);
+        let perm = |prefix| Expr::Test(Test::Perm(PermSpec { prefix, term: PermTerm::Numeric(0) }));
+        let matches = |expr: &Expr, path| interpreter.matches(expr, &stand_in(path));
+
+        // -perm 000 wants no permission bits at all
+        assert!(matches(&perm(None), "src/ast.rs"));
+        assert!(!matches(&perm(None), "src/lib.rs"));
+        // Having all of no bits, or any of them, is true of every file
+        for prefix in [Some(PermPrefix::AllMode), Some(PermPrefix::AnyMode)] {
+            assert!(matches(&perm(prefix.clone()), "src/ast.rs"), "{:?}", prefix);
+            assert!(matches(&perm(prefix.clone()), "src/lib.rs"), "{:?}", prefix);
+        }
     }
 
     #[test]
@@ -732,6 +3087,358 @@ mod tests {
         });
         
         // This should typically pass since most files have user read permission
-        assert!(Interpreter::evaluate(&Expr::Test(perm_test), &entry));
+        assert!(interpreter().matches(&Expr::Test(perm_test), &entry));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_symbolic_modes_start_from_nothing() {
+        let parse_mode = |mode: &str| match crate::parser::parse_expression(&format!("-perm {}", mode)).unwrap() {
+            Expr::Test(Test::Perm(PermSpec { term: PermTerm::Symbolic(statements), .. })) => {
+                Interpreter::symbolic_to_numeric(&statements)
+            }
+            other => panic!("Unexpected AST: {:?}", other),
+        };
+        assert_eq!(parse_mode("u="), 0);
+        assert_eq!(parse_mode("u+"), 0);
+        assert_eq!(parse_mode("a=rwx"), 0o777);
+        assert_eq!(parse_mode("u+r"), 0o400);
+        assert_eq!(parse_mode("u=rw,g=r"), 0o640);
+        assert_eq!(parse_mode("a=rwx,g=,o-w"), 0o705);
+        assert_eq!(parse_mode("a=rwx,u="), 0o077);
+
+        // An exact symbolic mode matches only files with exactly those bits
+        let mode = |mode| FileStat { file_type: Some(FileType::RegularFile), mode, ..FileStat::default() };
+        let mock = MockFileSystem::new()
+            .with_file("src/lib.rs", mode(0o100644))
+            .with_file("src/ast.rs", mode(0o100000));
+        let interpreter = mock_interpreter(SystemTime::now(), mock);
+        let perm = |spec: &str| crate::parser::parse_expression(&format!("-perm {}", spec)).unwrap();
+        let matches = |spec: &str, path| interpreter.matches(&perm(spec), &stand_in(path));
+        assert!(matches("u=rw,g=r,o=r", "src/lib.rs"));
+        assert!(!matches("u=rw", "src/lib.rs"));
+        assert!(matches("-u=rw", "src/lib.rs"));
+        assert!(matches("/g+r", "src/lib.rs"));
+        assert!(!matches("/g+w", "src/lib.rs"));
+        assert!(matches("u=", "src/ast.rs"));
+        assert!(!matches("u=", "src/lib.rs"));
+        assert!(matches("a=rwx,a=", "src/ast.rs"));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_symbolic_statements_apply_in_order() {
+        let parse_mode = |mode: &str| match crate::parser::parse_expression(&format!("-perm {}", mode)).unwrap() {
+            Expr::Test(Test::Perm(PermSpec { term: PermTerm::Symbolic(statements), .. })) => {
+                Interpreter::symbolic_to_numeric(&statements)
+            }
+            other => panic!("Unexpected AST: {:?}", other),
+        };
+        // Each statement starts from what the ones before it left, as with GNU find and
+        // chmod, so a later + adds back bits an earlier = cleared and the other way round
+        assert_eq!(parse_mode("u=rw,go=r,u+x"), 0o744);
+        assert_eq!(parse_mode("u+x,u=r"), 0o400);
+        assert_eq!(parse_mode("a=rwx,go-w,g+w"), 0o775);
+        assert_eq!(parse_mode("a+rwx,a-x,o="), 0o660);
+        assert_eq!(parse_mode("a=r,u+w,a-r"), 0o200);
+        assert_eq!(parse_mode("u=rwx,u-w,u+w,u="), 0);
+        assert_eq!(parse_mode("ug=rw,o+r"), 0o664);
+        // = only clears the bits of the principals it names
+        assert_eq!(parse_mode("a=rwx,g=x"), 0o717);
+        assert_eq!(parse_mode("go=,a+x"), 0o111);
+        // Repeating a principal is the same as naming it once
+        assert_eq!(parse_mode("uu=r,uo+w"), 0o602);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_name_expressions_make_no_lookups() {
+        let mock = Arc::new(MockFileSystem::new().with_file("src/lib.rs", mock_file(10, SystemTime::now())));
+        let interpreter = Interpreter::new(Path::new("."), SystemTime::now()).with_file_system(mock.clone());
+        let parse = |input| crate::parser::parse_expression(input).unwrap();
+
+        for input in ["-name *.rs -o -path ./x -print", "-regex .*lib.* -printf %p:%f:%d", "-type l -o -false"] {
+            let expr = parse(input);
+            assert!(!expr.needs_metadata(), "{}", input);
+            interpreter.matches(&expr, &stand_in("src/lib.rs"));
+            let _ = interpreter.take_output();
+            assert_eq!(mock.lookups(), 0, "{}", input);
+        }
+
+        let expr = parse("-name *.rs -printf %s");
+        assert!(expr.needs_metadata());
+        interpreter.matches(&expr, &stand_in("src/lib.rs"));
+        assert_eq!(interpreter.take_output(), b"10");
+        assert!(mock.lookups() > 0);
+    }
+
+    #[test]
+    fn test_used() {
+        // -used compares the last access with the last status change, so the result
+        // depends only on the two timestamps and not on the reference time
+        let now = fixed_now();
+        let changed = now - Duration::from_secs(10 * DAY);
+        let accessed_after = |secs| FileStat {
+            file_type: Some(FileType::RegularFile),
+            accessed: Some(changed + Duration::from_secs(secs)),
+            changed: Some(changed),
+            modified: Some(changed),
+            ..FileStat::default()
+        };
+        let mock = MockFileSystem::new()
+            .with_file("src/lib.rs", accessed_after(3 * DAY + 60))
+            .with_file("src/ast.rs", accessed_after(0))
+            .with_file("src/parser.rs", FileStat { accessed: Some(changed - Duration::from_secs(60)), ..accessed_after(0) })
+            .with_file("src/main.rs", FileStat { changed: None, ..accessed_after(DAY) });
+        let interpreter = mock_interpreter(now, mock);
+        let used = |sign, value| Expr::Test(Test::Used(TimeSpec { sign, value }));
+
+        // Accessed three days and a minute after its last change
+        let lib = stand_in("src/lib.rs");
+        assert!(interpreter.matches(&used(Sign::None, 3), &lib));
+        assert!(!interpreter.matches(&used(Sign::None, 4), &lib));
+        assert!(interpreter.matches(&used(Sign::Plus, 2), &lib));
+        assert!(!interpreter.matches(&used(Sign::Plus, 3), &lib));
+        assert!(interpreter.matches(&used(Sign::Minus, 4), &lib));
+        assert!(!interpreter.matches(&used(Sign::Minus, 3), &lib));
+
+        // Not accessed since the change
+        let ast = stand_in("src/ast.rs");
+        assert!(interpreter.matches(&used(Sign::None, 0), &ast));
+        assert!(interpreter.matches(&used(Sign::Minus, 1), &ast));
+
+        // An access before the change, or a missing timestamp, matches nothing
+        for path in ["src/parser.rs", "src/main.rs"] {
+            for sign in [Sign::None, Sign::Plus, Sign::Minus] {
+                assert!(!interpreter.matches(&used(sign, 0), &stand_in(path)), "{}", path);
+            }
+        }
+    }
+
+    #[test]
+    fn test_time_boundaries_against_fixed_clock() {
+        // Ages are truncated to whole units: 59 seconds is 0 minutes old, and a day
+        // less a second is 0 days old
+        let now = fixed_now();
+        let mock = MockFileSystem::new()
+            .with_file("src/lib.rs", mock_file(1, now - Duration::from_secs(59)))
+            .with_file("src/ast.rs", mock_file(1, now - Duration::from_secs(DAY - 1)))
+            .with_file("src/parser.rs", mock_file(1, now - Duration::from_secs(DAY)));
+        let interpreter = mock_interpreter(now, mock);
+        let spec = |sign, value| TimeSpec { sign, value };
+        let matches = |test, path| interpreter.matches(&Expr::Test(test), &stand_in(path));
+
+        assert!(matches(Test::Mmin(spec(Sign::None, 0)), "src/lib.rs"));
+        assert!(matches(Test::Amin(spec(Sign::Minus, 1)), "src/lib.rs"));
+        assert!(!matches(Test::Amin(spec(Sign::Plus, 0)), "src/lib.rs"));
+        assert!(matches(Test::Mmin(spec(Sign::Plus, 1000)), "src/ast.rs"));
+        assert!(matches(Test::Mtime(spec(Sign::None, 0)), "src/ast.rs"));
+        assert!(!matches(Test::Atime(spec(Sign::Plus, 0)), "src/ast.rs"));
+        assert!(matches(Test::Mtime(spec(Sign::None, 1)), "src/parser.rs"));
+        assert!(matches(Test::Atime(spec(Sign::Plus, 0)), "src/parser.rs"));
+        assert!(!matches(Test::Atime(spec(Sign::Minus, 1)), "src/parser.rs"));
+
+        // The -newer family compares against the reference's timestamps, so a file
+        // modified a second after the reference is newer and one modified at the same
+        // instant isn't
+        let reference = |at| ReferenceFile { path: "reference".to_string(), metadata: mock_file(1, at) };
+        let changed = |at| FileStat { changed: Some(at), ..mock_file(1, at) };
+        let mock = MockFileSystem::new().with_file("src/lib.rs", changed(now - Duration::from_secs(59)));
+        let interpreter = mock_interpreter(now, mock);
+        let matches = |test| interpreter.matches(&Expr::Test(test), &stand_in("src/lib.rs"));
+        let reference_time = now - Duration::from_secs(60);
+        assert!(matches(Test::Anewer(reference(reference_time))));
+        assert!(matches(Test::Newer(reference(reference_time))));
+        assert!(matches(Test::Cnewer(ReferenceFile { metadata: changed(reference_time), ..reference(reference_time) })));
+        assert!(!matches(Test::Anewer(reference(now - Duration::from_secs(59)))));
+        assert!(!matches(Test::Cnewer(ReferenceFile { metadata: changed(now), ..reference(now) })));
+    }
+
+    #[test]
+    fn test_render_csv() {
+        let now = fixed_now();
+        let stat = FileStat { mode: 0o100644, uid: 1000, gid: 100, ..mock_file(1536, now) };
+        let mock = MockFileSystem::new()
+            .with_file("src/lib.rs", stat)
+            .with_error("src/ast.rs", io::ErrorKind::PermissionDenied);
+        let interpreter = mock_interpreter(now, mock);
+
+        let modified = format_datetime(now).unwrap();
+        let render = |path: &str, printed: &str| {
+            String::from_utf8(interpreter.render_csv(&stand_in(path), OsStr::new(printed))).unwrap()
+        };
+        assert_eq!(
+            render("src/lib.rs", "src/lib.rs"),
+            format!("src/lib.rs,1536,f,{},1000,100,-rw-r--r--", modified)
+        );
+        // The path is quoted as needed, and is whatever the caller asks for
+        assert_eq!(
+            render("src/lib.rs", "a,\"b\""),
+            format!("\"a,\"\"b\"\"\",1536,f,{},1000,100,-rw-r--r--", modified)
+        );
+        assert_eq!(render("src/ast.rs", "src/ast.rs"), "src/ast.rs,,f,,,,");
+    }
+
+    #[test]
+    fn test_newer_family_compares_with_reference_mtime() {
+        // The reference was modified three hours ago but accessed and changed a minute ago
+        let now = fixed_now();
+        let ago = |minutes: u64| Some(now - Duration::from_secs(minutes * 60));
+        let reference = ReferenceFile {
+            path: "reference".to_string(),
+            metadata: FileStat { accessed: ago(1), changed: ago(1), modified: ago(180), ..FileStat::default() },
+        };
+        // Every timestamp of the entry is an hour ago: after the reference's modification,
+        // but before its access and change
+        let stat = FileStat { accessed: ago(60), changed: ago(60), modified: ago(60), ..mock_file(1, now) };
+        let interpreter = mock_interpreter(now, MockFileSystem::new().with_file("src/lib.rs", stat));
+        let matches = |test| interpreter.matches(&Expr::Test(test), &stand_in("src/lib.rs"));
+
+        for test in [Test::Anewer, Test::Cnewer, Test::Mnewer, Test::Newer] {
+            assert!(matches(test(reference.clone())));
+        }
+        // Only the reference's modification time counts, so without one nothing matches
+        let unmodified = ReferenceFile { metadata: FileStat { modified: None, ..reference.metadata.clone() }, ..reference };
+        for test in [Test::Anewer, Test::Cnewer, Test::Mnewer] {
+            assert!(!matches(test(unmodified.clone())));
+        }
+    }
+
+    #[test]
+    fn test_metadata_is_read_once_per_evaluation() {
+        let stat = FileStat { mode: 0o100644, uid: 1000, ..mock_file(10, fixed_now()) };
+        let mock = Arc::new(MockFileSystem::new().with_file("src/lib.rs", stat));
+        let interpreter = Interpreter::new(Path::new("."), fixed_now()).with_file_system(mock.clone());
+        let parse = |input| crate::parser::parse_expression(input).unwrap();
+
+        // Every test here is true, so each of them is evaluated
+        let expr = parse("-size -2k -a ! -empty -a -mtime -1 -a -perm -u=r -a -uid 1000 -a -printf %s:%n -a -ls");
+        assert!(interpreter.matches(&expr, &stand_in("src/lib.rs")));
+        assert!(interpreter.take_output().starts_with(b"10:0"));
+        assert_eq!(mock.lookups(), 1);
+        // Each evaluation stats the entry afresh, even one that failed last time
+        assert!(interpreter.matches(&expr, &stand_in("src/lib.rs")));
+        assert_eq!(mock.lookups(), 2);
+        // What the caller asks for afterwards, as --unique-inodes does, comes from that stat
+        assert_eq!(interpreter.metadata(&stand_in("src/lib.rs")).map(|stat| stat.uid), Some(1000));
+        assert_eq!(mock.lookups(), 2);
+        assert!(!interpreter.matches(&expr, &stand_in("src/ast.rs")));
+        assert!(!interpreter.matches(&expr, &stand_in("src/ast.rs")));
+        assert_eq!(mock.lookups(), 4);
+    }
+
+    #[test]
+    fn test_access_checks_are_made_once_per_evaluation() {
+        let mode = |mode| FileStat { mode, ..mock_file(1, fixed_now()) };
+        let mock = Arc::new(MockFileSystem::new()
+            .with_file("src/lib.rs", mode(0o100640))
+            .with_file("src/ast.rs", mode(0o100750)));
+        let interpreter = Interpreter::new(Path::new("."), fixed_now()).with_file_system(mock.clone());
+        let parse = |input| crate::parser::parse_expression(input).unwrap();
+
+        let readable = parse("-readable");
+        assert!(interpreter.matches(&readable, &stand_in("src/lib.rs")));
+        // The lookups one access check makes
+        let one_check = mock.lookups();
+        assert!(one_check > 0);
+        assert!(!interpreter.matches(&parse("-executable"), &stand_in("src/lib.rs")));
+        assert!(interpreter.matches(&parse("-executable"), &stand_in("src/ast.rs")));
+        assert!(!interpreter.matches(&parse("-not -writable"), &stand_in("src/ast.rs")));
+        let before = mock.lookups();
+
+        // Asking the same question again in one expression is answered from the cache
+        let expr = parse("-readable -a -writable -a -readable -a ! -executable -a -writable");
+        assert!(interpreter.matches(&expr, &stand_in("src/lib.rs")));
+        assert_eq!(mock.lookups() - before, 3 * one_check);
+        // But each evaluation starts afresh, so a changed file isn't judged by old answers
+        assert!(interpreter.matches(&readable, &stand_in("src/lib.rs")));
+        assert_eq!(mock.lookups() - before, 4 * one_check);
+        let (outcome, _) = interpreter.explain(&expr, &stand_in("src/ast.rs"));
+        assert!(!outcome.matched);
+        assert_eq!(mock.lookups() - before, 7 * one_check);
+    }
+
+    #[test]
+    fn test_substitute_path() {
+        let path = OsStr::new("dir/a.txt");
+        assert_eq!(substitute_path("{}", path), "dir/a.txt");
+        assert_eq!(substitute_path("prefix-{}", path), "prefix-dir/a.txt");
+        assert_eq!(substitute_path("{}.bak", path), "dir/a.txt.bak");
+        assert_eq!(substitute_path("{}:{}", path), "dir/a.txt:dir/a.txt");
+        assert_eq!(substitute_path("echo", path), "echo");
+        assert_eq!(substitute_path("{", path), "{");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_exec_output_and_status() {
+        let exec = |words: &[&str]| Expr::Action(Action::Exec(words.iter().map(|word| word.to_string()).collect()));
+        let interpreter = interpreter();
+        let entry = stand_in("src/lib.rs");
+
+        assert!(interpreter.matches(&exec(&["echo", "prefix-{}", "{}.bak"]), &entry));
+        assert_eq!(interpreter.take_output(), b"prefix-src/lib.rs src/lib.rs.bak\n");
+        assert!(!interpreter.matches(&exec(&["false"]), &entry));
+        assert!(interpreter.take_errors().is_empty());
+
+        // A command that can't be run is false, and reported
+        assert!(!interpreter.matches(&exec(&["no-such-command-for-findr"]), &entry));
+        let errors = interpreter.take_errors();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with("no-such-command-for-findr: "), "{:?}", errors);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_exec_stdout_gets_pending_output() {
+        let pending = Rc::new(RefCell::new(Vec::new()));
+        let exec_stdout = {
+            let pending = Rc::clone(&pending);
+            Rc::new(move |output: &[u8]| {
+                pending.borrow_mut().push(output.to_vec());
+                Ok(Stdio::null())
+            })
+        };
+        let interpreter = interpreter().with_exec_stdout(exec_stdout);
+        let expr = Expr::And(
+            Box::new(Expr::Action(Action::Print)),
+            Box::new(Expr::Action(Action::Exec(vec!["echo".to_string(), "{}".to_string()]))),
+        );
+
+        assert!(interpreter.matches(&expr, &stand_in("src/lib.rs")));
+        assert_eq!(*pending.borrow(), vec![b"src/lib.rs\n".to_vec()]);
+        assert!(interpreter.take_output().is_empty());
+    }
+
+    #[test]
+    fn test_delete_counts() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().join("a");
+        fs::create_dir_all(root.join("sub")).unwrap();
+        for file in ["x.txt", "y.txt", "keep.rs", "sub/z.txt"] {
+            fs::write(root.join(file), "").unwrap();
+        }
+        let delete_where = |test: Expr| Expr::And(Box::new(test), Box::new(Expr::Action(Action::Delete)));
+        let run = |expr: &Expr| {
+            let interpreter = Interpreter::new(&root, SystemTime::now());
+            for entry in WalkDir::new(&root).contents_first(true) {
+                interpreter.evaluate(expr, &entry.unwrap());
+            }
+            (interpreter.take_deletions(), interpreter.take_errors())
+        };
+
+        let (summary, errors) = run(&delete_where(Expr::Test(Test::Name("*.txt".to_string()))));
+        assert_eq!(summary, DeleteSummary { files: 3, directories: 0, failed: 0 });
+        assert!(errors.is_empty(), "{:?}", errors);
+
+        // sub is empty by the time it is reached, but a still holds keep.rs
+        let (summary, errors) = run(&delete_where(Expr::Not(Box::new(Expr::Test(Test::Name("*.rs".to_string()))))));
+        assert_eq!(summary, DeleteSummary { files: 0, directories: 1, failed: 1 });
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with(&format!("cannot delete {}: ", root.display())), "{:?}", errors);
+        assert!(root.join("keep.rs").exists() && !root.join("sub").exists());
+
+        let mut total = summary;
+        total.merge(DeleteSummary { files: 3, directories: 0, failed: 0 });
+        assert_eq!(total.to_string(), "deleted 3 files and 1 directory, 1 failed");
+    }
+}
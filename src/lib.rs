@@ -0,0 +1,26 @@
+//! A find(1) work-alike: a pest grammar for the expression language, an AST, and an
+//! interpreter that evaluates expressions against `walkdir` entries.
+
+use pest_derive::Parser;
+
+pub mod ast;
+pub mod catalog;
+pub mod completions;
+pub mod csv;
+pub mod datetime;
+pub mod filesystem;
+pub mod interpreter;
+pub mod listing;
+pub mod options;
+pub mod output;
+pub mod parser;
+pub mod predicates;
+pub mod profile;
+pub mod regextype;
+pub mod prune;
+pub mod sort;
+pub mod walk;
+
+#[derive(Parser)]
+#[grammar = "grammar.pest"]
+pub struct FindCommandParser;
@@ -0,0 +1,210 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+use crate::ast::FileType;
+use crate::datetime::{to_local_time, MONTHS};
+use crate::filesystem::FileStat;
+
+// Files modified longer ago than this, or in the future, show a year instead of a time
+const SIX_MONTHS: Duration = Duration::from_secs(182 * 24 * 60 * 60);
+
+/// Formats the lines of the -ls action, like `ls -dils`: inode, size in 1K blocks,
+/// permissions, link count, owner, group, size in bytes, modification time and path.
+///
+/// The number and name columns are as wide as the widest value seen so far, as in GNU
+/// find, so once a large value has turned up the lines after it line up with it.
+/// Owner and group names are looked up once per id.
+#[derive(Debug)]
+pub struct Listing {
+    /// Print uid and gid as numbers, like `ls -n`, rather than looking up their names
+    numeric_ids: bool,
+    inode: Cell<usize>,
+    blocks: Cell<usize>,
+    nlink: Cell<usize>,
+    owner: Cell<usize>,
+    group: Cell<usize>,
+    size: Cell<usize>,
+    owner_names: RefCell<HashMap<u32, String>>,
+    group_names: RefCell<HashMap<u32, String>>,
+}
+
+impl Default for Listing {
+    fn default() -> Self {
+        Listing::new(false)
+    }
+}
+
+impl Listing {
+    pub fn new(numeric_ids: bool) -> Self {
+        Listing {
+            numeric_ids,
+            inode: Cell::new(6),
+            blocks: Cell::new(4),
+            nlink: Cell::new(3),
+            owner: Cell::new(8),
+            group: Cell::new(8),
+            size: Cell::new(8),
+            owner_names: RefCell::new(HashMap::new()),
+            group_names: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Lays out one line, without a newline. `path` is printed as given, so a symlink's
+    /// ` -> target` is up to the caller
+    pub fn line(&self, stat: &FileStat, path: &str, now: SystemTime) -> String {
+        let owner = self.owner_name(stat.uid);
+        let group = self.group_name(stat.gid);
+        format!(
+            "{} {} {} {} {} {} {} {} {}",
+            right(&self.inode, &stat.ino.to_string()),
+            right(&self.blocks, &stat.blocks.div_ceil(2).to_string()),
+            mode_string(stat),
+            right(&self.nlink, &stat.nlink.to_string()),
+            left(&self.owner, &owner),
+            left(&self.group, &group),
+            right(&self.size, &stat.len.to_string()),
+            format_time(stat.modified, now),
+            path,
+        )
+    }
+
+    fn owner_name(&self, uid: u32) -> String {
+        if self.numeric_ids {
+            return uid.to_string();
+        }
+        self.owner_names.borrow_mut().entry(uid).or_insert_with(|| {
+            #[cfg(unix)]
+            if let Ok(Some(name)) = file_owner::Owner::from_uid(uid).name() {
+                return name;
+            }
+            uid.to_string()
+        }).clone()
+    }
+
+    fn group_name(&self, gid: u32) -> String {
+        if self.numeric_ids {
+            return gid.to_string();
+        }
+        self.group_names.borrow_mut().entry(gid).or_insert_with(|| {
+            #[cfg(unix)]
+            if let Ok(Some(name)) = file_owner::Group::from_gid(gid).name() {
+                return name;
+            }
+            gid.to_string()
+        }).clone()
+    }
+}
+
+// Pads a value to the column width, first widening the column if the value needs it
+fn right(width: &Cell<usize>, value: &str) -> String {
+    width.set(width.get().max(value.chars().count()));
+    format!("{:>width$}", value, width = width.get())
+}
+
+fn left(width: &Cell<usize>, value: &str) -> String {
+    width.set(width.get().max(value.chars().count()));
+    format!("{:<width$}", value, width = width.get())
+}
+
+/// The file type and permissions as `ls -l` shows them, e.g. `drwxr-sr-x`
+pub fn mode_string(stat: &FileStat) -> String {
+    let mode = stat.mode;
+    let mut out = String::with_capacity(10);
+    out.push(match stat.file_type {
+        Some(FileType::Directory) => 'd',
+        Some(FileType::SymbolicLink) => 'l',
+        Some(FileType::NamedPipe) => 'p',
+        Some(FileType::Socket) => 's',
+        Some(FileType::CharFile) => 'c',
+        Some(FileType::BlockFile) => 'b',
+        Some(FileType::RegularFile) | None => '-',
+    });
+    // Each class's execute letter also shows its setuid, setgid or sticky bit
+    for (shift, special, set) in [(6, 0o4000, 's'), (3, 0o2000, 's'), (0, 0o1000, 't')] {
+        let bits = mode >> shift;
+        out.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+        out.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+        out.push(match (bits & 0o1 != 0, mode & special != 0) {
+            (true, true) => set,
+            (false, true) => set.to_ascii_uppercase(),
+            (true, false) => 'x',
+            (false, false) => '-',
+        });
+    }
+    out
+}
+
+// "Oct 16 09:30" within the last six months, "Oct 16  2023" otherwise
+fn format_time(time: Option<SystemTime>, now: SystemTime) -> String {
+    let Some((time, local)) = time.and_then(|time| to_local_time(time).map(|local| (time, local))) else {
+        return format!("{:>12}", "?");
+    };
+    let month = MONTHS[local.month as usize - 1];
+    let recent = now.duration_since(time).is_ok_and(|age| age < SIX_MONTHS);
+    if recent {
+        format!("{} {:>2} {:02}:{:02}", month, local.day, local.hour, local.minute)
+    } else {
+        format!("{} {:>2} {:>5}", month, local.day, local.year)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stat(file_type: FileType, mode: u32) -> FileStat {
+        FileStat { file_type: Some(file_type), mode, ..FileStat::default() }
+    }
+
+    #[test]
+    fn test_mode_string() {
+        assert_eq!(mode_string(&stat(FileType::RegularFile, 0o644)), "-rw-r--r--");
+        assert_eq!(mode_string(&stat(FileType::Directory, 0o2755)), "drwxr-sr-x");
+        assert_eq!(mode_string(&stat(FileType::Directory, 0o1777)), "drwxrwxrwt");
+        assert_eq!(mode_string(&stat(FileType::RegularFile, 0o4644)), "-rwSr--r--");
+        assert_eq!(mode_string(&stat(FileType::SymbolicLink, 0o777)), "lrwxrwxrwx");
+        assert_eq!(mode_string(&stat(FileType::NamedPipe, 0o600)), "prw-------");
+        assert_eq!(mode_string(&FileStat::default()), "----------");
+    }
+
+    #[test]
+    fn test_format_time() {
+        let now = SystemTime::now();
+        let recent = format_time(Some(now - Duration::from_secs(3600)), now);
+        assert_eq!(recent.len(), 12);
+        assert_eq!(recent.as_bytes()[9], b':');
+
+        // Old and future times show the year
+        let old_time = now - SIX_MONTHS - Duration::from_secs(3600);
+        let old = format_time(Some(old_time), now);
+        assert!(old.ends_with(&format!(" {}", to_local_time(old_time).unwrap().year)));
+        let future = format_time(Some(now + Duration::from_secs(3600)), now);
+        assert!(!future.contains(':'));
+        assert_eq!(format_time(None, now).trim(), "?");
+    }
+
+    #[test]
+    fn test_columns_widen_for_large_values() {
+        let listing = Listing::new(true);
+        let now = SystemTime::now();
+        let small = FileStat { ino: 1, nlink: 1, len: 10, blocks: 8, ..stat(FileType::RegularFile, 0o644) };
+        let large = FileStat {
+            ino: 123_456_789_012,
+            nlink: 12_345,
+            uid: 4_294_967_294,
+            len: 98_765_432_109,
+            blocks: 3,
+            ..small.clone()
+        };
+
+        let first = listing.line(&small, "a", now);
+        assert!(first.starts_with("     1    4 -rw-r--r--   1 0        0              10 "), "{}", first);
+        let second = listing.line(&large, "b", now);
+        assert!(second.starts_with("123456789012    2 -rw-r--r-- 12345 4294967294 0        98765432109 "), "{}", second);
+
+        // Later lines keep the wider columns, so they line up with the large one
+        let third = listing.line(&small, "c", now);
+        assert_eq!(third.len(), second.len());
+        assert!(third.starts_with("           1    4 -rw-r--r--     1 0          0                 10 "), "{}", third);
+    }
+}
@@ -1,95 +1,524 @@
+use std::cell::RefCell;
+use std::collections::{HashSet, VecDeque};
 use std::env;
-use std::process;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::SystemTime;
+use std::process::{self, Stdio};
 use regex::Regex;
-use pest::Parser;
-use pest_derive::Parser;
-use walkdir::WalkDir;
 
-mod ast;
-mod parser;
-mod interpreter;
-
-use parser::*;
-use crate::interpreter::Interpreter;
-
-#[derive(Parser)]
-#[grammar = "grammar.pest"]
-struct FindCommandParser;
+use findr::ast::{Expr, Test};
+use findr::options::{self, Options, SymlinkMode};
+use findr::parser::*;
+use findr::completions::{completion_script, SHELLS};
+use findr::filesystem::RealFileSystem;
+use findr::interpreter::{DeleteSummary, EvalOutcome, Interpreter};
+use findr::output::{os_str_bytes, Output};
+use findr::predicates::PredicateRegistry;
+use findr::profile::Profile;
+use findr::prune::{Excludes, PathPruner};
+use findr::sort::ExternalSorter;
+use findr::walk::{walk_dir, DeviceBoundary};
+use walkdir::DirEntry;
 
 fn main() {
     let args: Vec<String> = env::args().skip(1).collect();
+    let (options, args) = match Options::extract(args) {
+        Ok(extracted) => extracted,
+        Err(e) => {
+            eprintln!("findr: {}", e);
+            process::exit(1);
+        }
+    };
+    // Hidden from --help: prints a script for the shell's completion setup, e.g.
+    // `findr --completions bash > /etc/bash_completion.d/findr`
+    if let Some(shell) = &options.completions {
+        match completion_script(shell) {
+            Some(script) => print!("{}", script),
+            None => {
+                eprintln!("findr: unsupported shell for --completions: {} (expected {})", shell, SHELLS.join(", "));
+                process::exit(1);
+            }
+        }
+        return;
+    }
     let cmdline = args.join(" ");
     let help_re = Regex::new(r"(?x)(?:^|\s)(-h|--help)(?:\s|$)").expect("Failed to compile help regex");
     if help_re.is_match(&cmdline) {
-        println!("Usage: findr [paths] -expression\nAvailable expressions:");
+        println!("Usage: findr [options] [paths] -expression\nAvailable options:");
+        println!("  -P                Never follow symbolic links (default)");
+        println!("  -L                Follow symbolic links; loops are reported and skipped");
+        println!("  --sort            Sort the output paths");
+        println!("  --sort-buffer <n> Lines --sort holds in memory before spilling to temp files (default {})", options::DEFAULT_SORT_BUFFER);
+        println!("  --human-sizes     Print -printf's %s in 1024-based units like ls -h, e.g. 1.5K");
+        println!("  --numeric-ids     Show owners and groups as numbers in -ls output, without looking up names");
+        println!("  --daystart        Measure -mtime, -mmin and the like from the end of today (also -daystart)");
+        println!("  --regex-relative  Match -regex and -iregex against the path below the start path, e.g. 'src/.*' from .");
+        println!("  --strip-dot-slash  Let -path, -ipath and --exclude also match paths without their leading ./, e.g. 'src/*' from .");
+        println!("  --regextype <t>   Dialect of -regex patterns: native (default), emacs, posix-basic, posix-extended,");
+        println!("                    awk or another GNU find name; applies to every -regex (also -regextype)");
+        println!("  --regex-size-limit <n> Largest compiled -regex in bytes (default {})", DEFAULT_REGEX_SIZE_LIMIT);
+        println!("  --max-filesize <n> Don't read files over n bytes for -magic or -grep; takes -size suffixes, e.g. 10M");
+        println!("  --exclude <glob>  Skip paths matching glob and everything below them; may be repeated");
+        println!("  --order <order>   Visit each directory's entries in this order: name, or none (default)");
+        println!("  --output-file <f> Write matches and -printf output to file f instead of stdout, replacing it");
+        println!("  --absolute-paths  Print paths, with -print and %p too, as absolute paths; symlinks are only resolved with -L");
+        println!("  --csv             Print matches as CSV rows of {} after a header row", findr::csv::HEADER);
+        println!("  --one-file-system Don't descend into directories on other devices than their start path (also -xdev, -mount)");
+        println!("  -D search         Report on stderr each mount point --one-file-system doesn't descend into (before the paths)");
+        println!("  --from-stdin0     Test the NUL-separated paths read from stdin instead of walking start paths");
+        println!("  --unique          Find each path only once, even if start paths overlap; actions run once too");
+        println!("  --unique-inodes   Print only the first path found for each file, skipping its other hard links");
+        println!("  --interleave      Take one entry from each start path in turn instead of one path at a time");
+        println!("  --lenient-types   Also accept -type F, -type dir, file, link and symlink");
+        println!("  --explain         Report on stderr which tests each matched path satisfied");
+        println!("  --profile         Report on stderr how often each predicate ran and the time it took");
+        println!("  --optimize        Run cheap tests such as -name before costly ones such as -grep in each -and");
+        println!("  --line-buffered   Flush after every match, even when output is a pipe (slower)");
+        println!("  --verbose         Report on stderr at the end how many files and directories -delete removed");
+        println!("  --nowarn          Don't warn about likely mistakes such as -size 10 (also -nowarn; -warn undoes it)");
+        println!("Available expressions:");
         println!("  -true             always true");
         println!("  -false            always false");
         println!("  -expr -and -expr  boolean and");
         println!("  -expr -or -expr   boolean or");
         println!("  -not -expr        boolean not");
-        println!("  -path <glob>      Match whole path");
+        println!("  -path <glob>      Match whole path; use wildcards to match part of it, e.g. '*/src/*'");
         println!("  -ipath <glob>     Match whole path, case insensitive");
         println!("  -name <glob>      Match filename");
         println!("  -iname <glob>     Match filename, case insensitive");
-        println!("  -regex <re>       Regex match filename");
-        println!("  -iregex <re>      Regex match filename, case insensitive");
+        println!("  -regex <re>       Regex match whole path");
+        println!("  -iregex <re>      Regex match whole path, case insensitive");
         println!("  -type <type>      Match type - f for file, d for dir, etc");
         println!("  -user <user>      Match files owned by username");
         println!("  -group <group>    Match files with groupname");
         println!("  -uid <uid>        Match files owned by uid");
         println!("  -gid <gid>        Match files with group id gid");
+        println!("  -links <n>        Match files with n hard links; -links 2 finds directories without subdirectories,");
+        println!("                    except on file systems such as btrfs that give every directory 1 link");
         println!("  -perm <perm>      Match files with specified permissions");
+        println!("  -readable         Match files you may read; also -writable and -executable (search, for directories)");
+        println!("  -maxdepth <n>     Descend at most n levels below the start paths");
+        println!("  -atdepth <n>      Match entries exactly n levels below their start path");
+        println!("  -treesize <size>  Match directories by the total size of the files below them");
+        println!("  -grep <re>        Match regular text files with a line matching re (reads each file)");
+        println!("  -magic <mime>     Match regular files by content type, e.g. image/png (reads each file)");
         println!("  -atime <time>     Match files by access time");
         println!("  -amin <time>      Match files by access time in minutes");
-        println!("  -anewer <other>   Match files accessed more recently than other file");
-        println!("  -ctime <time>     Match files by create time");
-        println!("  -cmin <time>      Match files by create time in minutes");
-        println!("  -cnewer <other>   Match files created more recently than other file");
+        println!("  -anewer <other>   Match files accessed more recently than other file was modified");
+        println!("  -ctime <time>     Match files by status change time");
+        println!("  -cmin <time>      Match files by status change time in minutes");
+        println!("  -cnewer <other>   Match files whose status changed more recently than other file was modified");
         println!("  -mtime <time>     Match files by modification time");
         println!("  -mmin <time>      Match files by modification time in minutes");
+        println!("  -used <days>      Match files last accessed this many days after their status changed");
         println!("  -mnewer <other>   Match files modified more recently than other file");
+        println!("  -newermt <time>   Match files modified after time: YYYY-MM-DD[THH:MM[:SS]][Z|+HH:MM] or @seconds");
+        println!("  -newerat <time>   Match files accessed after time");
+        println!("  -newerct <time>   Match files whose status changed after time");
+        println!("  -samefile <file>  Match hard links to file; with -L, a symlink matches what it points to");
+        println!("  -newer <other>    Same as -mnewer; reference files are relative to the current directory");
+        println!("  -print            Print the path followed by a newline");
+        println!("  -ls               List the entry like ls -dils: inode, blocks, mode, links, owner, group, size, time");
+        println!("  -exec <cmd> ;     Run cmd with every {{}} in its arguments replaced by the path; true if it succeeds");
+        println!("  -delete           Remove the entry, a directory once it is empty; walks contents before their directory, so use --exclude rather than -prune");
+        println!("  -quit             Stop at once, searching no more entries or start paths; -print -quit prints the first match");
+        println!("  -prune            Don't descend into this directory; -path x -prune -o -print skips x");
+        println!("  -printf <format>  Print using format directives %p %P %H %f %h %s %S %d %i %n %y %Y %a %c %t (no implicit newline)");
+        println!("Without an action, every match is printed, as if the expression were \\( expr \\) -print");
+        println!("Numbers given to -size, -mtime, -uid and the like may group digits with underscores, e.g. +1_000_000c");
+        println!("As in GNU find, a -size without a suffix counts 512-byte blocks, so -size 10 is 5K; use -size 10c for bytes");
+        println!("Size suffixes are c (bytes), w (2-byte words), b (blocks), k, M and G; K, m and g are taken with a warning");
         println!("Supported expressions should work just like they do in GNU find, consult their documentation for more details (man find)");
         process::exit(0);
     }
     
-    // Find the first occurrence of either '-', '(', or '!'
-    let split_pos = cmdline.find('-').unwrap_or(cmdline.len())
-        .min(cmdline.find('(').unwrap_or(cmdline.len()))
-        .min(cmdline.find('!').unwrap_or(cmdline.len()));
-    
-    let (dirstr, expr) = if split_pos < cmdline.len() {
-        cmdline.split_at(split_pos)
-    } else {
-        (cmdline.as_str(),"")
-    };
-    
-    //println!("Parsing command: {cmdline}");
-    let mut dirs: Vec<String> = dirstr.split(" ").into_iter()
-        .map(|d| d.to_string())
+    // Like GNU find, the start paths are the arguments before the first one that begins
+    // the expression: one starting with '-', an opening parenthesis or '!'. A path with
+    // a '-' in the middle is still a path.
+    let split_pos = args.iter()
+        .position(|arg| arg.starts_with('-') || ["(", "\\(", "!"].contains(&arg.as_str()))
+        .unwrap_or(args.len());
+    let (dir_args, expr_args) = args.split_at(split_pos);
+
+    let mut dirs: Vec<String> = dir_args.iter()
         .filter(|d| !d.is_empty())
+        .cloned()
         .collect();
-    if dirs.len() == 0 {
+    if options.from_stdin0 && !dirs.is_empty() {
+        eprintln!("findr: --from-stdin0 reads the paths to test from stdin, so none can be given as well");
+        process::exit(1);
+    }
+    if dirs.is_empty() && !options.from_stdin0 {
         dirs.push(".".to_string());
     }
     
-    let mut expr = expr.trim().to_string();
-    if expr.len() == 0 {expr = "-true".to_string();}
-
-    let parsed = FindCommandParser::parse(Rule::Program, &expr)
-        .expect("Failed to parse command line");
+    // Each argument is passed on whole, so a pattern such as 'b c.txt' stays one word
+    let expr_args = if expr_args.is_empty() { &["-true".to_string()][..] } else { expr_args };
 
-    match parse_to_ast(parsed) {
-        Ok(ast) => {
-            for dir in dirs {
-                for entry in WalkDir::new(dir).into_iter().filter_map(|e| e.ok()) {
-                    if Interpreter::evaluate(&ast, &entry) {
-                        println!("{}", entry.path().display());
+    let parse_options = ParseOptions {
+        lenient_types: options.lenient_types,
+        regex_size_limit: options.regex_size_limit,
+        regex_type: options.regex_type,
+        follow_symlinks: options.symlinks == SymlinkMode::Always,
+        warnings: options.warnings,
+    };
+    match parse_arguments_with_warnings(expr_args, &parse_options) {
+        Ok((ast, warnings)) => {
+            for warning in warnings {
+                eprintln!("findr: warning: {}", warning);
+            }
+            let ast = ast.simplify();
+            let ast = if options.optimize { ast.optimize() } else { ast };
+            // Custom predicates can only be registered by programs using findr as a library
+            if let Some(name) = PredicateRegistry::new().first_unregistered(&ast) {
+                eprintln!("findr: unknown custom predicate '{}'", name);
+                process::exit(1);
+            }
+            // Like GNU find, the implicit -print applies to the whole expression, as if it
+            // were `\( expr \) -print`, so it prints whatever the expression matches.
+            // `-name x -o -name y` prints both, but `-name x -o -name y -print` only y.
+            let print = !ast.has_action();
+            let mut sorter = options.sort.then(|| ExternalSorter::new(options.sort_buffer));
+            // --unique remembers every path that matched or printed something, so memory
+            // grows with the number of matches
+            let mut seen = options.unique.then(HashSet::new);
+            // --unique-inodes likewise remembers a (device, inode) pair per printed file
+            // with more than one link, 16 bytes plus set overhead each
+            let mut seen_inodes = options.unique_inodes.then(HashSet::new);
+            let pruner = PathPruner::new(&ast, options.strip_dot_slash);
+            let excludes = Excludes::new(&options.exclude, options.strip_dot_slash);
+            // Paths read by --from-stdin0 are tested themselves, without walking into them
+            let max_depth = if options.from_stdin0 { Some(0) } else { ast.max_depth() };
+            // -delete walks each directory's contents before the directory, so it is
+            // empty by the time it is deleted
+            let depth_first = ast.deletes();
+            // Like GNU find, refuse -prune there rather than delete what it was to keep
+            if depth_first && ast.prunes() {
+                eprintln!("findr: -delete visits each directory's contents first, so -prune can't skip them; use --exclude to keep directories from being deleted");
+                process::exit(1);
+            }
+            // Time tests all measure age from this one moment, like GNU find
+            let now = SystemTime::now();
+            // Block-buffer output to files and pipes unless asked to flush every line
+            let stdout = io::stdout();
+            let (sink, line_buffered, output_file): (Box<dyn Write>, bool, _) = match &options.output_file {
+                Some(path) => match File::create(path).and_then(|file| Ok((file.try_clone()?, file))) {
+                    Ok((file, for_commands)) => (Box::new(file), options.line_buffered, Some(for_commands)),
+                    Err(e) => {
+                        eprintln!("findr: {}: {}", path, e);
+                        process::exit(1);
+                    }
+                },
+                None => (Box::new(stdout.lock()), options.line_buffered || stdout.is_terminal(), None),
+            };
+            let out = Rc::new(RefCell::new(Output::new(sink, line_buffered)));
+            // -exec commands write straight to the same place, so what they print comes
+            // out as they print it. Whatever findr has buffered goes out first, to keep
+            // everything in order
+            let exec_stdout = {
+                let out = Rc::clone(&out);
+                Rc::new(move |pending: &[u8]| {
+                    let mut out = out.borrow_mut();
+                    if let Err(e) = out.write_bytes(pending).and_then(|_| out.flush()) {
+                        write_failed(e);
+                    }
+                    match &output_file {
+                        Some(file) => file.try_clone().map(Stdio::from),
+                        None => Ok(Stdio::inherit()),
                     }
+                })
+            };
+            // --csv only changes how matches are printed implicitly, so the header only
+            // goes out when they are
+            if options.csv && print
+                && let Err(e) = out.borrow_mut().write_line(findr::csv::HEADER) {
+                write_failed(e);
+            }
+            let mut profile = options.profile.then(Profile::new);
+            let mut deletions = DeleteSummary::default();
+            // Set by any error that should make the exit status 1
+            let mut failed = false;
+            let mut quit = false;
+            // Each start path is searched to the end before the next, so the results for
+            // a start path are always together. --interleave instead takes one entry from
+            // each start path in turn, so no tree has to be finished before another starts.
+            let per_turn = if options.interleave { 1 } else { usize::MAX };
+            // As in POSIX, a start path with a trailing slash names a directory, so
+            // `file.txt/` is an error rather than a search of the file. Most systems
+            // refuse to stat such a path anyway, but not all of them
+            let dirs: Vec<String> = dirs.into_iter().filter(|dir| {
+                let must_be_dir = dir.len() > 1 && dir.ends_with(std::path::is_separator);
+                if must_be_dir && Path::new(dir).metadata().is_ok_and(|metadata| !metadata.is_dir()) {
+                    eprintln!("findr: {}: Not a directory", dir);
+                    failed = true;
+                    return false;
+                }
+                true
+            }).collect();
+            // An expression that simplifies to -false can never print or do anything, so
+            // the start paths are only checked to exist rather than walked
+            let never_matches = ast == Expr::Test(Test::False);
+            let dirs = if never_matches {
+                for dir in &dirs {
+                    let stat = if options.symlinks == SymlinkMode::Always { fs::metadata(dir) } else { fs::symlink_metadata(dir) };
+                    if let Err(e) = stat {
+                        eprintln!("findr: {}: {}", dir, e);
+                        failed = true;
+                    }
+                }
+                Vec::new()
+            } else {
+                dirs
+            };
+            let start = |dir: PathBuf| {
+                let mut interpreter = Interpreter::new(&dir, now).with_exec_stdout(exec_stdout.clone());
+                if let Some(limit) = options.max_filesize {
+                    interpreter = interpreter.with_max_content_size(limit);
+                }
+                if options.profile {
+                    interpreter = interpreter.with_profiling();
+                }
+                if options.regex_relative {
+                    interpreter = interpreter.with_relative_regex();
+                }
+                if options.strip_dot_slash {
+                    interpreter = interpreter.with_dot_slash_stripped();
+                }
+                if options.human_sizes {
+                    interpreter = interpreter.with_human_sizes();
+                }
+                if options.numeric_ids {
+                    interpreter = interpreter.with_numeric_ids();
+                }
+                if options.daystart {
+                    interpreter = interpreter.with_day_start();
+                }
+                // With -L the walk follows links anyway, so the paths are canonicalized
+                if options.absolute_paths {
+                    interpreter = interpreter.with_absolute_paths(options.symlinks == SymlinkMode::Always);
                 }
+                // -xdev keeps each walk on the device of its own start path
+                let boundary = options.one_file_system
+                    .then(|| DeviceBoundary::new(&dir, Arc::new(RealFileSystem)))
+                    .flatten()
+                    .map(|boundary| Rc::new(if options.debug_search { boundary.with_reporting() } else { boundary }));
+                if let Some(boundary) = &boundary {
+                    interpreter = interpreter.with_walk_device(boundary.device());
+                }
+                // Walking depth first, a directory comes after its contents, too late to
+                // skip them, so directories on another device are filtered out up front
+                // and aren't tested themselves
+                let (excludes, pruner, walk_boundary) = (&excludes, &pruner, boundary.clone().filter(|_| depth_first));
+                let walker = walk_dir(&dir, options.symlinks, max_depth, options.order, depth_first)
+                    .into_iter()
+                    .filter_entry(move |e| {
+                        excludes.as_ref().is_none_or(|x| if depth_first { x.allows_within(e) } else { x.allows(e) })
+                            && pruner.as_ref().is_none_or(|p| p.may_contain_matches(e))
+                            && walk_boundary.as_ref().is_none_or(|b| !b.excludes(e))
+                    });
+                (dir, interpreter, walker, boundary)
+            };
+            let mut roots: VecDeque<_> = dirs.into_iter().map(PathBuf::from).map(start).collect();
+            // --from-stdin0 reads each path only once the one before it is done, so
+            // findr can filter a list as it is produced
+            let mut stdin_paths = (options.from_stdin0 && !never_matches).then(|| io::stdin().lock().split(b'\0'));
+            loop {
+                let (dir, interpreter, mut walker, boundary) = match roots.pop_front() {
+                    Some(root) => root,
+                    None => match stdin_paths.as_mut().and_then(Iterator::next) {
+                        Some(Ok(path)) if path.is_empty() => continue,
+                        Some(Ok(path)) => match path_from_bytes(path) {
+                            Ok(dir) => start(dir),
+                            Err(path) => {
+                                eprintln!("findr: {}: path on stdin is not valid UTF-8", String::from_utf8_lossy(&path));
+                                failed = true;
+                                continue;
+                            }
+                        },
+                        Some(Err(e)) => {
+                            eprintln!("findr: failed to read paths from stdin: {}", e);
+                            failed = true;
+                            break;
+                        }
+                        None => break,
+                    },
+                };
+                let mut taken = 0;
+                while taken < per_turn {
+                    let Some(entry) = walker.next() else { break };
+                    taken += 1;
+                    // WalkDir checks each followed directory against its ancestors and
+                    // reports a cycle as an error instead of descending into it
+                    let entry = match entry {
+                        Ok(entry) => entry,
+                        Err(e) => {
+                            if let (Some(path), Some(ancestor)) = (e.path(), e.loop_ancestor()) {
+                                eprintln!(
+                                    "findr: File system loop detected; '{}' is part of the same file system loop as '{}'.",
+                                    path.display(),
+                                    ancestor.display()
+                                );
+                            } else if e.depth() == 0
+                                && let Some(io_error) = e.io_error() {
+                                // A start path that is missing or unreadable is reported,
+                                // and the other start paths are still searched
+                                eprintln!("findr: {}: {}", dir.display(), io_error);
+                                failed = true;
+                            }
+                            continue;
+                        }
+                    };
+                    // --unique skips a path already found from an overlapping start
+                    // path before evaluating it, so its actions, -exec included, run and
+                    // print only once. Below the start, a directory found again has had
+                    // its contents walked already too
+                    if seen.as_ref().is_some_and(|seen| seen.contains(entry.path())) {
+                        if entry.file_type().is_dir() && !depth_first {
+                            walker.skip_current_dir();
+                        }
+                        continue;
+                    }
+                    let outcome = if options.explain {
+                        explain(&interpreter, &ast, &entry)
+                    } else {
+                        interpreter.evaluate(&ast, &entry)
+                    };
+                    for error in interpreter.take_errors() {
+                        eprintln!("findr: {}", error);
+                        failed = true;
+                    }
+                    let printed = interpreter.take_output();
+                    // --unique-inodes drops what -print and the like print for a file
+                    // another hard link has already printed, as it does the implicit
+                    // print, though every other action still runs
+                    let prints = !printed.is_empty() || (outcome.matched && print);
+                    if let Some(seen) = seen.as_mut()
+                        && (outcome.matched || prints) {
+                        seen.insert(entry.path().to_path_buf());
+                    }
+                    let repeated_inode = prints && seen_inodes.as_mut()
+                        .and_then(|seen_inodes| Some((seen_inodes, inode_of(&interpreter, &entry)?)))
+                        .is_some_and(|(seen_inodes, inode)| !seen_inodes.insert(inode));
+                    if !printed.is_empty() && !repeated_inode
+                        && let Err(e) = out.borrow_mut().write_bytes(&printed) {
+                        write_failed(e);
+                    }
+                    // -quit ends the walk before this entry is printed implicitly
+                    if outcome.quit {
+                        quit = true;
+                        break;
+                    }
+                    let leaves_device = !depth_first && boundary.as_ref().is_some_and(|boundary| boundary.excludes(&entry));
+                    for note in boundary.iter().flat_map(|boundary| boundary.take_notes()) {
+                        eprintln!("findr: {}", note);
+                    }
+                    // Depth first, the directory's contents have already been walked
+                    if (outcome.prune || leaves_device) && entry.file_type().is_dir() && !depth_first {
+                        walker.skip_current_dir();
+                    }
+                    if outcome.matched && print {
+                        if repeated_inode {
+                            continue;
+                        }
+                        let path = interpreter.printed_path(&entry);
+                        let line = if options.csv {
+                            interpreter.render_csv(&entry, path.as_os_str())
+                        } else {
+                            os_str_bytes(path.as_os_str()).into_owned()
+                        };
+                        match sorter.as_mut() {
+                            Some(sorter) => {
+                                if let Err(e) = sorter.push(line) {
+                                    eprintln!("findr: failed to buffer sorted output: {}", e);
+                                    process::exit(1);
+                                }
+                            }
+                            None => {
+                                if let Err(e) = out.borrow_mut().write_line(&line) {
+                                    write_failed(e);
+                                }
+                            }
+                        }
+                    }
+                }
+                if let (Some(profile), Some(taken)) = (profile.as_mut(), interpreter.take_profile()) {
+                    profile.merge(taken);
+                }
+                deletions.merge(interpreter.take_deletions());
+                // -quit also ends the walks of the start paths still to come
+                if quit {
+                    break;
+                }
+                // A start path that filled its turn may have more entries to come
+                if taken == per_turn {
+                    roots.push_back((dir, interpreter, walker, boundary));
+                }
+            }
+            if let Some(sorter) = sorter
+                && let Err(e) = sorter.finish(&mut *out.borrow_mut()) {
+                write_failed(e);
+            }
+            if let Err(e) = out.borrow_mut().flush() {
+                write_failed(e);
+            }
+            if let Some(profile) = profile {
+                eprint!("{}", profile);
+            }
+            if options.verbose && depth_first {
+                eprintln!("findr: {}", deletions);
+            }
+            if failed {
+                process::exit(1);
             }
         }
         Err(e) => {
-            eprintln!("Failed to build AST: {}", e);
+            eprintln!("findr: {}", e);
+            process::exit(1);
         }
     }
 }
+
+// Evaluates an entry, and if it matches says which tests it satisfied
+fn explain(interpreter: &Interpreter, ast: &Expr, entry: &DirEntry) -> EvalOutcome {
+    let (outcome, tests) = interpreter.explain(ast, entry);
+    if outcome.matched {
+        let tests: Vec<String> = tests.iter().map(|test| test.to_string()).collect();
+        eprintln!("findr: {} matched: {}", entry.path().display(), tests.join(", "));
+    }
+    outcome
+}
+
+// A path read by --from-stdin0. On Unix any bytes but NUL make a path, so a name that
+// isn't UTF-8 is searched like any other; elsewhere paths are Unicode, so one that
+// isn't UTF-8 can't name a file
+#[cfg(unix)]
+fn path_from_bytes(bytes: Vec<u8>) -> Result<PathBuf, Vec<u8>> {
+    use std::os::unix::ffi::OsStringExt;
+    Ok(PathBuf::from(OsString::from_vec(bytes)))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: Vec<u8>) -> Result<PathBuf, Vec<u8>> {
+    String::from_utf8(bytes).map(PathBuf::from).map_err(|e| e.into_bytes())
+}
+
+// The device and inode number of an entry, for --unique-inodes, reusing the stat its
+// evaluation made. None for a file with a single link, which no other path can reach,
+// and when they can't be read or FileStat leaves them 0, so the entry is printed anyway
+fn inode_of(interpreter: &Interpreter, entry: &DirEntry) -> Option<(u64, u64)> {
+    let stat = interpreter.metadata(entry)?;
+    (stat.ino != 0 && stat.nlink > 1).then_some((stat.dev, stat.ino))
+}
+
+// A closed pipe (e.g. output piped into head) just means nobody wants more results
+fn write_failed(e: io::Error) -> ! {
+    if e.kind() != io::ErrorKind::BrokenPipe {
+        eprintln!("findr: failed to write output: {}", e);
+    }
+    process::exit(1);
+}
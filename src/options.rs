@@ -0,0 +1,571 @@
+use anyhow::{anyhow, Result};
+use glob::Pattern;
+use crate::catalog::{find_predicate, ArgumentKind};
+use crate::parser::{parse_byte_count, DEFAULT_REGEX_SIZE_LIMIT};
+use crate::regextype::{RegexType, REGEX_TYPES};
+
+/// Default number of output lines held in memory by `--sort` before spilling to disk
+pub const DEFAULT_SORT_BUFFER: usize = 100_000;
+
+/// How symbolic links met during the walk are treated
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum SymlinkMode {
+    /// Never follow symlinks, examining the links themselves (-P, the default)
+    #[default]
+    Never,
+    /// Follow every symlink, examining what it points to (-L)
+    Always,
+}
+
+/// Order in which the entries of each directory are visited
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum WalkOrder {
+    /// Whatever order the file system returns them in (the default)
+    #[default]
+    None,
+    /// Sorted by file name, so output is ordered within each directory
+    Name,
+}
+
+/// The flags `Options::extract` recognizes, for listing in shell completions
+pub const FLAGS: &[&str] = &[
+    "-P", "-L", "-D", "--sort", "--sort-buffer", "--unique", "--unique-inodes", "--line-buffered",
+    "--lenient-types", "--explain", "--profile", "--optimize", "--regex-relative",
+    "--strip-dot-slash", "--human-sizes", "--numeric-ids", "--daystart", "--absolute-paths", "--interleave", "--regex-size-limit", "--max-filesize",
+    "--exclude", "--output-file", "--order", "--regextype", "--from-stdin0",
+    "--one-file-system", "--warn", "--nowarn", "--csv", "--verbose",
+];
+
+/// The flags in `FLAGS` that take the next argument as their value
+pub const VALUE_FLAGS: &[&str] = &[
+    "-D", "--sort-buffer", "--regex-size-limit", "--max-filesize", "--exclude", "--output-file", "--order",
+    "--regextype",
+];
+
+/// Global options given as `--long` flags anywhere on the command line, or as GNU
+/// style `-L`/`-P` flags before the first path
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// Sort the output paths before printing
+    pub sort: bool,
+    /// Number of lines `--sort` keeps in memory before spilling sorted runs to temp files
+    pub sort_buffer: usize,
+    /// Suppress duplicate output paths, e.g. when the same start path is given twice
+    pub unique: bool,
+    /// Print only the first path found for each (device, inode), skipping other hard links
+    pub unique_inodes: bool,
+    /// Flush output after every match even when stdout is not a terminal
+    pub line_buffered: bool,
+    /// Whether the walk follows symlinks
+    pub symlinks: SymlinkMode,
+    /// Order of the entries within each directory
+    pub order: WalkOrder,
+    /// Accept forgiving `-type` spellings such as `F` or `dir`
+    pub lenient_types: bool,
+    /// Report on stderr which tests made each entry match
+    pub explain: bool,
+    /// Report on stderr how often each predicate ran and how long it took
+    pub profile: bool,
+    /// Run cheaper tests first within each `-a` chain
+    pub optimize: bool,
+    /// Match `-regex`/`-iregex` against the path below the start path
+    pub regex_relative: bool,
+    /// Let `-path`, `-ipath` and `--exclude` also match without a leading "./"
+    pub strip_dot_slash: bool,
+    /// Print `-printf`'s `%s` in 1024-based units such as 1.5K
+    pub human_sizes: bool,
+    /// Show owners and groups in `-ls` output as numeric ids
+    pub numeric_ids: bool,
+    /// Measure the ages of `-mtime` and the like from the end of today
+    pub daystart: bool,
+    /// Print matched paths, and those -print and %p print, as absolute paths
+    pub absolute_paths: bool,
+    /// Print each match as a CSV row of its path and metadata, after a header row
+    pub csv: bool,
+    /// Test the NUL-separated paths read from stdin rather than walking start paths
+    pub from_stdin0: bool,
+    /// Don't descend into directories on another device than their start path
+    pub one_file_system: bool,
+    /// Report on stderr the directories the search skips, from `-D search`
+    pub debug_search: bool,
+    /// Warn about arguments that are allowed but probably not what was meant
+    pub warnings: bool,
+    /// Report on stderr at the end how many entries `-delete` removed and failed to
+    pub verbose: bool,
+    /// Alternate between start paths an entry at a time rather than walking them in turn
+    pub interleave: bool,
+    /// Largest compiled size, in bytes, allowed for a `-regex`/`-iregex` pattern
+    pub regex_size_limit: usize,
+    /// Dialect `-regex`/`-iregex` patterns are written in
+    pub regex_type: RegexType,
+    /// Files larger than this many bytes are skipped by tests that read content
+    pub max_filesize: Option<u64>,
+    /// Globs for paths that are skipped, with everything below them, before the expression
+    pub exclude: Vec<String>,
+    /// File that matches are written to instead of stdout, truncated first
+    pub output_file: Option<String>,
+    /// Shell to print a completion script for instead of searching, from the hidden
+    /// `--completions` flag
+    pub completions: Option<String>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            sort: false,
+            sort_buffer: DEFAULT_SORT_BUFFER,
+            unique: false,
+            unique_inodes: false,
+            line_buffered: false,
+            symlinks: SymlinkMode::Never,
+            order: WalkOrder::None,
+            lenient_types: false,
+            explain: false,
+            profile: false,
+            optimize: false,
+            regex_relative: false,
+            strip_dot_slash: false,
+            human_sizes: false,
+            numeric_ids: false,
+            daystart: false,
+            absolute_paths: false,
+            csv: false,
+            from_stdin0: false,
+            one_file_system: false,
+            debug_search: false,
+            warnings: true,
+            verbose: false,
+            interleave: false,
+            regex_size_limit: DEFAULT_REGEX_SIZE_LIMIT,
+            regex_type: RegexType::Native,
+            max_filesize: None,
+            exclude: Vec::new(),
+            output_file: None,
+            completions: None,
+        }
+    }
+}
+
+impl Options {
+    /// Pulls the global options out of the argument list, returning the options and the
+    /// remaining arguments (paths and expression) in their original order. Options are
+    /// only recognized where a predicate could start, so the argument of one, as in
+    /// `-name -mount`, or a word of an -exec command is left in the expression.
+    pub fn extract(args: Vec<String>) -> Result<(Options, Vec<String>)> {
+        let mut options = Options::default();
+        let mut rest = Vec::new();
+        let mut args = args.into_iter();
+        // Whether the last word kept is a predicate still waiting for its argument
+        let mut takes_argument = false;
+        // Whether the words kept are part of an -exec command not yet ended by ;
+        let mut in_command = false;
+
+        while let Some(arg) = args.next() {
+            if in_command || takes_argument {
+                in_command = in_command && arg != ";" && arg != "\\;";
+                takes_argument = false;
+                rest.push(arg);
+                continue;
+            }
+            match arg.as_str() {
+                "--sort" => options.sort = true,
+                "--unique" => options.unique = true,
+                "--unique-inodes" => options.unique_inodes = true,
+                "--line-buffered" => options.line_buffered = true,
+                "--lenient-types" => options.lenient_types = true,
+                "--explain" => options.explain = true,
+                "--profile" => options.profile = true,
+                "--optimize" => options.optimize = true,
+                "--regex-relative" => options.regex_relative = true,
+                "--strip-dot-slash" => options.strip_dot_slash = true,
+                "--human-sizes" => options.human_sizes = true,
+                "--numeric-ids" => options.numeric_ids = true,
+                // GNU find's spelling is accepted too, but here it applies to every time
+                // test, not just those after it
+                "--daystart" | "-daystart" => options.daystart = true,
+                "--absolute-paths" => options.absolute_paths = true,
+                "--csv" => options.csv = true,
+                "--from-stdin0" => options.from_stdin0 = true,
+                // GNU find's -xdev and -mount also apply wherever they appear
+                "--one-file-system" | "-xdev" | "-mount" => options.one_file_system = true,
+                // As in GNU find, the last of -warn and -nowarn wins
+                "--warn" | "-warn" => options.warnings = true,
+                "--nowarn" | "-nowarn" => options.warnings = false,
+                "--interleave" => options.interleave = true,
+                "--verbose" => options.verbose = true,
+                // Like GNU find these only count before the paths, so they can't be
+                // confused with an argument later in the expression
+                "-P" if rest.is_empty() => options.symlinks = SymlinkMode::Never,
+                "-L" if rest.is_empty() => options.symlinks = SymlinkMode::Always,
+                // GNU find's debug options are a comma-separated list, of which only
+                // search is supported
+                "-D" if rest.is_empty() => {
+                    let value = args.next()
+                        .ok_or_else(|| anyhow!("missing argument to -D"))?;
+                    for name in value.split(',') {
+                        match name {
+                            "search" => options.debug_search = true,
+                            _ => return Err(anyhow!("invalid argument to -D: {} (expected search)", name)),
+                        }
+                    }
+                }
+                "--sort-buffer" => {
+                    let value = args.next()
+                        .ok_or_else(|| anyhow!("missing argument to --sort-buffer"))?;
+                    options.sort_buffer = value.parse::<usize>()
+                        .ok()
+                        .filter(|n| *n > 0)
+                        .ok_or_else(|| anyhow!("invalid argument to --sort-buffer: {}", value))?;
+                }
+                "--max-filesize" => {
+                    let value = args.next()
+                        .ok_or_else(|| anyhow!("missing argument to --max-filesize"))?;
+                    options.max_filesize = Some(parse_byte_count(&value)
+                        .map_err(|_| anyhow!("invalid argument to --max-filesize: {}", value))?);
+                }
+                "--exclude" => {
+                    let value = args.next()
+                        .ok_or_else(|| anyhow!("missing argument to --exclude"))?;
+                    Pattern::new(&value)
+                        .map_err(|e| anyhow!("invalid argument to --exclude: {}: {}", value, e.msg))?;
+                    options.exclude.push(value);
+                }
+                "--output-file" => {
+                    let value = args.next()
+                        .ok_or_else(|| anyhow!("missing argument to --output-file"))?;
+                    options.output_file = Some(value);
+                }
+                "--completions" => {
+                    let value = args.next()
+                        .ok_or_else(|| anyhow!("missing argument to --completions"))?;
+                    options.completions = Some(value);
+                }
+                "--order" => {
+                    let value = args.next()
+                        .ok_or_else(|| anyhow!("missing argument to --order"))?;
+                    options.order = match value.as_str() {
+                        "name" => WalkOrder::Name,
+                        "none" => WalkOrder::None,
+                        _ => return Err(anyhow!("invalid argument to --order: {} (expected name or none)", value)),
+                    };
+                }
+                // Unlike GNU find's, this applies to every -regex, wherever it appears
+                "--regextype" | "-regextype" => {
+                    let value = args.next()
+                        .ok_or_else(|| anyhow!("missing argument to -regextype"))?;
+                    options.regex_type = RegexType::from_name(&value).ok_or_else(|| {
+                        anyhow!("invalid argument to -regextype: {} (expected one of {})", value, REGEX_TYPES.join(", "))
+                    })?;
+                }
+                "--regex-size-limit" => {
+                    let value = args.next()
+                        .ok_or_else(|| anyhow!("missing argument to --regex-size-limit"))?;
+                    options.regex_size_limit = value.parse::<usize>()
+                        .ok()
+                        .filter(|n| *n > 0)
+                        .ok_or_else(|| anyhow!("invalid argument to --regex-size-limit: {}", value))?;
+                }
+                _ => {
+                    match find_predicate(&arg).map(|info| info.argument) {
+                        Some(ArgumentKind::Command) => in_command = true,
+                        Some(ArgumentKind::None) | None => {}
+                        Some(_) => takes_argument = true,
+                    }
+                    rest.push(arg);
+                }
+            }
+        }
+
+        Ok((options, rest))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_extract_leaves_expression_alone() {
+        let (options, rest) = Options::extract(args(&["dir", "--sort", "--name", "x"])).unwrap();
+        assert!(options.sort);
+        assert!(!options.unique);
+        assert!(!options.line_buffered);
+        assert_eq!(options.sort_buffer, DEFAULT_SORT_BUFFER);
+        assert_eq!(rest, args(&["dir", "--name", "x"]));
+    }
+
+    #[test]
+    fn test_extract_sort_buffer() {
+        let (options, rest) = Options::extract(args(&["--sort-buffer", "10", "-true"])).unwrap();
+        assert_eq!(options.sort_buffer, 10);
+        assert_eq!(rest, args(&["-true"]));
+
+        assert!(Options::extract(args(&["--sort-buffer"])).is_err());
+        assert!(Options::extract(args(&["--sort-buffer", "0"])).is_err());
+        assert!(Options::extract(args(&["--sort-buffer", "lots"])).is_err());
+    }
+
+    #[test]
+    fn test_extract_regex_size_limit() {
+        let (options, _) = Options::extract(args(&["-true"])).unwrap();
+        assert_eq!(options.regex_size_limit, DEFAULT_REGEX_SIZE_LIMIT);
+
+        let (options, rest) = Options::extract(args(&["--regex-size-limit", "4096", "-true"])).unwrap();
+        assert_eq!(options.regex_size_limit, 4096);
+        assert_eq!(rest, args(&["-true"]));
+
+        assert!(Options::extract(args(&["--regex-size-limit"])).is_err());
+        assert!(Options::extract(args(&["--regex-size-limit", "0"])).is_err());
+    }
+
+    #[test]
+    fn test_extract_max_filesize() {
+        let (options, _) = Options::extract(args(&["."])).unwrap();
+        assert_eq!(options.max_filesize, None);
+
+        let (options, rest) = Options::extract(args(&["--max-filesize", "2M", "-magic", "x"])).unwrap();
+        assert_eq!(options.max_filesize, Some(2 * 1024 * 1024));
+        assert_eq!(rest, args(&["-magic", "x"]));
+
+        assert!(Options::extract(args(&["--max-filesize"])).is_err());
+        assert!(Options::extract(args(&["--max-filesize", "-2M"])).is_err());
+    }
+
+    #[test]
+    fn test_extract_order() {
+        let (options, _) = Options::extract(args(&["."])).unwrap();
+        assert_eq!(options.order, WalkOrder::None);
+
+        let (options, rest) = Options::extract(args(&["--order", "name", "."])).unwrap();
+        assert_eq!(options.order, WalkOrder::Name);
+        assert_eq!(rest, args(&["."]));
+
+        assert!(Options::extract(args(&["--order"])).is_err());
+        assert!(Options::extract(args(&["--order", "size"])).is_err());
+    }
+
+    #[test]
+    fn test_extract_exclude() {
+        let (options, rest) = Options::extract(args(&[".", "--exclude", "*/target/*", "-name", "x", "--exclude", "*.o"])).unwrap();
+        assert_eq!(options.exclude, args(&["*/target/*", "*.o"]));
+        assert_eq!(rest, args(&[".", "-name", "x"]));
+
+        assert!(Options::extract(args(&["--exclude"])).is_err());
+        assert!(Options::extract(args(&["--exclude", "[a"])).is_err());
+    }
+
+    #[test]
+    fn test_extract_output_file() {
+        let (options, _) = Options::extract(args(&["."])).unwrap();
+        assert_eq!(options.output_file, None);
+
+        let (options, rest) = Options::extract(args(&[".", "--output-file", "out.txt", "-type", "f"])).unwrap();
+        assert_eq!(options.output_file.as_deref(), Some("out.txt"));
+        assert_eq!(rest, args(&[".", "-type", "f"]));
+
+        assert!(Options::extract(args(&["--output-file"])).is_err());
+    }
+
+    #[test]
+    fn test_extract_symlink_mode() {
+        let (options, rest) = Options::extract(args(&["-L", "dir", "-name", "x"])).unwrap();
+        assert_eq!(options.symlinks, SymlinkMode::Always);
+        assert_eq!(rest, args(&["dir", "-name", "x"]));
+
+        let (options, _) = Options::extract(args(&["-L", "-P", "dir"])).unwrap();
+        assert_eq!(options.symlinks, SymlinkMode::Never);
+
+        let (options, rest) = Options::extract(args(&["dir", "-name", "-L"])).unwrap();
+        assert_eq!(options.symlinks, SymlinkMode::Never);
+        assert_eq!(rest, args(&["dir", "-name", "-L"]));
+    }
+
+    #[test]
+    fn test_extract_line_buffered() {
+        let (options, rest) = Options::extract(args(&[".", "--line-buffered", "-true"])).unwrap();
+        assert!(options.line_buffered);
+        assert_eq!(rest, args(&[".", "-true"]));
+    }
+
+    #[test]
+    fn test_extract_lenient_types() {
+        let (options, rest) = Options::extract(args(&[".", "-type", "dir", "--lenient-types"])).unwrap();
+        assert!(options.lenient_types);
+        assert_eq!(rest, args(&[".", "-type", "dir"]));
+    }
+
+    #[test]
+    fn test_extract_profile() {
+        let (options, rest) = Options::extract(args(&["--profile", ".", "-name", "*.rs"])).unwrap();
+        assert!(options.profile);
+        assert_eq!(rest, args(&[".", "-name", "*.rs"]));
+        assert!(!Options::extract(args(&["."])).unwrap().0.profile);
+    }
+
+    #[test]
+    fn test_extract_optimize() {
+        let (options, rest) = Options::extract(args(&[".", "-grep", "x", "--optimize", "-name", "y"])).unwrap();
+        assert!(options.optimize);
+        assert_eq!(rest, args(&[".", "-grep", "x", "-name", "y"]));
+    }
+
+    #[test]
+    fn test_extract_regex_relative() {
+        let (options, rest) = Options::extract(args(&[".", "--regex-relative", "-regex", "src/.*"])).unwrap();
+        assert!(options.regex_relative);
+        assert_eq!(rest, args(&[".", "-regex", "src/.*"]));
+    }
+
+    #[test]
+    fn test_extract_strip_dot_slash() {
+        let (options, rest) = Options::extract(args(&[".", "-path", "src/*", "--strip-dot-slash"])).unwrap();
+        assert!(options.strip_dot_slash);
+        assert_eq!(rest, args(&[".", "-path", "src/*"]));
+    }
+
+    #[test]
+    fn test_extract_human_sizes() {
+        let (options, rest) = Options::extract(args(&[".", "-printf", "%s\\n", "--human-sizes"])).unwrap();
+        assert!(options.human_sizes);
+        assert_eq!(rest, args(&[".", "-printf", "%s\\n"]));
+    }
+
+    #[test]
+    fn test_extract_numeric_ids() {
+        let (options, rest) = Options::extract(args(&[".", "-ls", "--numeric-ids"])).unwrap();
+        assert!(options.numeric_ids);
+        assert_eq!(rest, args(&[".", "-ls"]));
+    }
+
+    #[test]
+    fn test_extract_daystart() {
+        let (options, rest) = Options::extract(args(&[".", "-daystart", "-mtime", "0"])).unwrap();
+        assert!(options.daystart);
+        assert_eq!(rest, args(&[".", "-mtime", "0"]));
+        let (options, _) = Options::extract(args(&["--daystart", "."])).unwrap();
+        assert!(options.daystart);
+    }
+
+    #[test]
+    fn test_extract_unique_inodes() {
+        let (options, rest) = Options::extract(args(&[".", "--unique-inodes", "-type", "f"])).unwrap();
+        assert!(options.unique_inodes);
+        assert!(!options.unique);
+        assert_eq!(rest, args(&[".", "-type", "f"]));
+    }
+
+    #[test]
+    fn test_flags_are_recognized() {
+        for flag in FLAGS {
+            let mut list = vec![flag.to_string()];
+            if VALUE_FLAGS.contains(flag) {
+                list.push(match *flag {
+                    "--order" => "name",
+                    "--regextype" => "emacs",
+                    "-D" => "search",
+                    _ => "1",
+                }.to_string());
+            }
+            let (_, rest) = Options::extract(list).unwrap();
+            assert!(rest.is_empty(), "{}", flag);
+        }
+    }
+
+    #[test]
+    fn test_extract_completions() {
+        let (options, rest) = Options::extract(args(&["--completions", "zsh"])).unwrap();
+        assert_eq!(options.completions.as_deref(), Some("zsh"));
+        assert!(rest.is_empty());
+        assert!(Options::extract(args(&["--completions"])).is_err());
+    }
+
+    #[test]
+    fn test_extract_absolute_paths() {
+        let (options, rest) = Options::extract(args(&[".", "--absolute-paths", "-name", "x"])).unwrap();
+        assert!(options.absolute_paths);
+        assert_eq!(rest, args(&[".", "-name", "x"]));
+    }
+
+    #[test]
+    fn test_extract_regextype() {
+        assert_eq!(Options::extract(args(&["."])).unwrap().0.regex_type, RegexType::Native);
+        let (options, rest) = Options::extract(args(&[".", "-regextype", "posix-basic", "-regex", "x"])).unwrap();
+        assert_eq!(options.regex_type, RegexType::Basic);
+        assert_eq!(rest, args(&[".", "-regex", "x"]));
+        let (options, _) = Options::extract(args(&["--regextype", "emacs"])).unwrap();
+        assert_eq!(options.regex_type, RegexType::Emacs);
+        assert!(Options::extract(args(&["-regextype"])).is_err());
+        assert!(Options::extract(args(&["-regextype", "perl"])).is_err());
+    }
+
+    #[test]
+    fn test_extract_from_stdin0() {
+        let (options, rest) = Options::extract(args(&["--from-stdin0", "-type", "f"])).unwrap();
+        assert!(options.from_stdin0);
+        assert_eq!(rest, args(&["-type", "f"]));
+    }
+
+    #[test]
+    fn test_extract_one_file_system() {
+        for flag in ["--one-file-system", "-xdev", "-mount"] {
+            let (options, rest) = Options::extract(args(&["/", flag, "-name", "x"])).unwrap();
+            assert!(options.one_file_system, "{}", flag);
+            assert_eq!(rest, args(&["/", "-name", "x"]));
+        }
+    }
+
+    #[test]
+    fn test_extract_warnings() {
+        let (options, rest) = Options::extract(args(&[".", "-nowarn", "-size", "10"])).unwrap();
+        assert!(!options.warnings);
+        assert_eq!(rest, args(&[".", "-size", "10"]));
+        assert!(Options::extract(args(&[])).unwrap().0.warnings);
+        assert!(Options::extract(args(&["--nowarn", "-warn"])).unwrap().0.warnings);
+        assert!(!Options::extract(args(&["-warn", "--nowarn"])).unwrap().0.warnings);
+    }
+
+    #[test]
+    fn test_extract_csv() {
+        let (options, rest) = Options::extract(args(&[".", "--csv", "-type", "f"])).unwrap();
+        assert!(options.csv);
+        assert_eq!(rest, args(&[".", "-type", "f"]));
+    }
+
+    #[test]
+    fn test_extract_debug_options() {
+        let (options, rest) = Options::extract(args(&["-D", "search", ".", "-xdev"])).unwrap();
+        assert!(options.debug_search);
+        assert_eq!(rest, args(&["."]));
+        assert!(Options::extract(args(&["-D", "search,search"])).unwrap().0.debug_search);
+        assert!(!Options::extract(args(&[])).unwrap().0.debug_search);
+        assert!(Options::extract(args(&["-D"])).is_err());
+        assert!(Options::extract(args(&["-D", "search,rates"])).is_err());
+    }
+
+    #[test]
+    fn test_extract_leaves_predicate_arguments_alone() {
+        for flag in ["-mount", "-xdev", "-daystart", "-nowarn", "-warn", "-regextype", "--sort"] {
+            let (options, rest) = Options::extract(args(&[".", "-name", flag, "-xdev"])).unwrap();
+            assert!(options.one_file_system, "{}", flag);
+            assert_eq!(rest, args(&[".", "-name", flag]));
+        }
+        let (options, rest) = Options::extract(args(&[".", "-exec", "echo", "-daystart", "{}", ";", "-daystart"])).unwrap();
+        assert!(options.daystart);
+        assert_eq!(rest, args(&[".", "-exec", "echo", "-daystart", "{}", ";"]));
+        let (options, rest) = Options::extract(args(&[".", "-print", "-mount", "-type", "-warn"])).unwrap();
+        assert!(options.one_file_system);
+        assert_eq!(rest, args(&[".", "-print", "-type", "-warn"]));
+    }
+
+    #[test]
+    fn test_extract_verbose() {
+        let (options, rest) = Options::extract(args(&["tmp", "-name", "*.o", "-delete", "--verbose"])).unwrap();
+        assert!(options.verbose);
+        assert_eq!(rest, args(&["tmp", "-name", "*.o", "-delete"]));
+        assert!(!Options::extract(args(&["tmp"])).unwrap().0.verbose);
+    }
+}
@@ -0,0 +1,125 @@
+use std::borrow::Cow;
+use std::ffi::OsStr;
+use std::io::{self, BufWriter, Write};
+
+/// Returns the bytes to print for a path or file name. On Unix these are the bytes the
+/// file system gave, so a name that isn't UTF-8 comes out as it is rather than with
+/// U+FFFD in it; elsewhere names are Unicode and are printed as UTF-8
+pub fn os_str_bytes(text: &OsStr) -> Cow<'_, [u8]> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        Cow::Borrowed(text.as_bytes())
+    }
+
+    #[cfg(not(unix))]
+    {
+        match text.to_string_lossy() {
+            Cow::Borrowed(text) => Cow::Borrowed(text.as_bytes()),
+            Cow::Owned(text) => Cow::Owned(text.into_bytes()),
+        }
+    }
+}
+
+/// Buffered writer for matched paths.
+///
+/// Output is block-buffered, which is much cheaper than a write per match when stdout
+/// is a file or pipe, but means a slow consumer only sees results in chunks. With
+/// `flush_each_line` every line is flushed as soon as it is written, which is the
+/// default for terminals and what `--line-buffered` forces for interactive pipelines.
+pub struct Output<W: Write> {
+    writer: BufWriter<W>,
+    flush_each_line: bool,
+}
+
+impl<W: Write> Output<W> {
+    pub fn new(writer: W, flush_each_line: bool) -> Self {
+        Output {
+            writer: BufWriter::new(writer),
+            flush_each_line,
+        }
+    }
+
+    /// Writes a line, flushing it straight away if line buffering is on
+    pub fn write_line(&mut self, line: impl AsRef<[u8]>) -> io::Result<()> {
+        self.writer.write_all(line.as_ref())?;
+        self.writer.write_all(b"\n")?;
+        if self.flush_each_line {
+            self.writer.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Writes raw bytes, e.g. from -printf, flushing them straight away if line
+    /// buffering is on
+    pub fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.writer.write_all(bytes)?;
+        if self.flush_each_line {
+            self.writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for Output<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Records what has actually reached the underlying writer
+    #[derive(Default)]
+    struct Sink {
+        written: Vec<u8>,
+    }
+
+    impl Write for &mut Sink {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.written.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_block_buffered_output_waits_for_flush() {
+        let mut sink = Sink::default();
+        {
+            let mut output = Output::new(&mut sink, false);
+            output.write_line("a").unwrap();
+            output.write_line("b").unwrap();
+            assert!(output.writer.get_ref().written.is_empty());
+            output.flush().unwrap();
+        }
+        assert_eq!(sink.written, b"a\nb\n");
+    }
+
+    #[test]
+    fn test_line_buffered_output_flushes_each_line() {
+        let mut sink = Sink::default();
+        let mut output = Output::new(&mut sink, true);
+        output.write_line("a").unwrap();
+        assert_eq!(output.writer.get_ref().written, b"a\n");
+        output.write_line("b").unwrap();
+        assert_eq!(output.writer.get_ref().written, b"a\nb\n");
+    }
+
+    #[test]
+    fn test_write_bytes_keeps_nuls() {
+        let mut sink = Sink::default();
+        let mut output = Output::new(&mut sink, true);
+        output.write_bytes(b"a\0b\0").unwrap();
+        assert_eq!(output.writer.get_ref().written, b"a\0b\0");
+    }
+}
@@ -1,58 +1,453 @@
+use pest::error::InputLocation;
 use pest::iterators::{Pair, Pairs};
+use pest::Parser;
+use regex::RegexBuilder;
+use std::cell::RefCell;
+use std::fmt;
+use std::fs;
+use std::ops::Range;
+use std::str::FromStr;
+use std::time::SystemTime;
 use crate::ast::*;
-use crate::Rule;
+use crate::datetime::parse_datetime;
+use crate::filesystem::FileStat;
+use crate::regextype::RegexType;
+use crate::{FindCommandParser, Rule};
 
 /// Parser error type
 #[derive(Debug)]
 pub enum ParseError {
+    Syntax(String),
     UnexpectedRule { expected: String, found: String },
     InvalidNumber(String),
+    InvalidFormat(String),
+    InvalidRegex { pattern: String, message: String },
+    RegexTooLarge(usize),
+    InvalidReference { predicate: String, path: String, message: String },
+    OperatorWithNothingBefore(String),
+    OperatorWithNothingAfter(String),
+    MissingArgument(String),
+    InvalidArgument { predicate: String, argument: String },
+    InvalidMode(String),
+    /// A word in the expression that is neither a predicate nor an argument to one
+    StrayArgument(String),
+    UnknownPredicate(String),
+    /// Parentheses and -not nested deeper than the limit
+    NestedTooDeeply(usize),
+    /// An expression tree deeper than the limit, e.g. from a very long -o chain
+    ExpressionTooDeep(usize),
 }
 
 impl std::fmt::Display for ParseError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
+            ParseError::Syntax(message) => write!(f, "invalid expression:\n{}", message),
             ParseError::UnexpectedRule { expected, found } => {
                 write!(f, "Expected {}, found {}", expected, found)
             }
             ParseError::InvalidNumber(s) => write!(f, "Invalid number: {}", s),
+            ParseError::InvalidFormat(s) => write!(f, "Invalid format: {}", s),
+            ParseError::InvalidRegex { pattern, message } => {
+                write!(f, "invalid regex: {}: {}", pattern, message)
+            }
+            ParseError::RegexTooLarge(limit) => {
+                write!(f, "regex too large: compiled size exceeds the limit of {} bytes", limit)
+            }
+            ParseError::NestedTooDeeply(limit) => {
+                write!(f, "invalid expression; parentheses and -not are nested more than {} deep", limit)
+            }
+            ParseError::ExpressionTooDeep(limit) => write!(
+                f,
+                "invalid expression; it is more than {} operators deep, counting each -a and -o of a chain",
+                limit
+            ),
+            ParseError::InvalidReference { predicate, path, message } => {
+                write!(f, "{} reference {}: {}", predicate, path, message)
+            }
+            ParseError::OperatorWithNothingBefore(operator) => write!(
+                f,
+                "invalid expression; you have used a binary operator '{}' with nothing before it",
+                operator
+            ),
+            ParseError::OperatorWithNothingAfter(operator) => write!(
+                f,
+                "invalid expression; you have used a binary operator '{}' with nothing after it",
+                operator
+            ),
+            ParseError::MissingArgument(predicate) => {
+                write!(f, "missing argument to `{}'", predicate)
+            }
+            ParseError::InvalidArgument { predicate, argument } => {
+                write!(f, "invalid argument `{}' to `{}'", argument, predicate)
+            }
+            ParseError::InvalidMode(mode) => write!(
+                f,
+                "invalid mode `{}' to `-perm': a numeric mode is 3 or 4 octal digits (0-7); \
+                 only symbolic modes such as u+w,g-w are joined with commas",
+                mode
+            ),
+            ParseError::StrayArgument(word) => write!(f, "paths must precede expression: `{}'", word),
+            ParseError::UnknownPredicate(predicate) => write!(f, "unknown predicate `{}'", predicate),
         }
     }
 }
 
 impl std::error::Error for ParseError {}
 
+/// What the grammar sees between two arguments. No command line argument can contain
+/// a NUL, so each one is read as a single word, spaces and all, and an empty one is
+/// just two of them in a row
+const ARGUMENT_END: char = '\0';
+
+/// How deep parentheses and -not may nest. The grammar and the parser recurse for each
+/// level, so without a limit a deep enough expression would overflow the stack
+pub const MAX_NESTING_DEPTH: usize = 256;
+
+/// How deep the expression tree may be, as evaluating, simplifying and even dropping the
+/// tree recurse down it. Chains of -a and -o are balanced trees, so it is nesting that
+/// makes a tree deep, not length
+pub const MAX_EXPRESSION_DEPTH: usize = 1000;
+
+/// Default cap on the compiled size of a -regex/-iregex pattern, in bytes
+pub const DEFAULT_REGEX_SIZE_LIMIT: usize = 1 << 20;
+
+/// Settings that change what the parser accepts
+#[derive(Debug, Clone)]
+pub struct ParseOptions {
+    /// Accept `-type` arguments in any case and the aliases file, dir, link and symlink,
+    /// which GNU find rejects
+    pub lenient_types: bool,
+    /// Largest compiled regex accepted, so an enormous untrusted pattern is rejected
+    /// up front rather than eating memory
+    pub regex_size_limit: usize,
+    /// Whether the walk follows symlinks (-L), which decides whether a symlink given as
+    /// the reference of -samefile or the -newer family stands for itself or its target
+    pub follow_symlinks: bool,
+    /// Dialect -regex and -iregex patterns are written in, translated before compiling
+    pub regex_type: RegexType,
+    /// Warn about arguments that are allowed but probably not what was meant, such as
+    /// an empty -name pattern. The warnings are returned by
+    /// parse_arguments_with_warnings, and never printed. Cleared by -nowarn.
+    pub warnings: bool,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions {
+            lenient_types: false,
+            regex_size_limit: DEFAULT_REGEX_SIZE_LIMIT,
+            follow_symlinks: false,
+            regex_type: RegexType::Native,
+            warnings: true,
+        }
+    }
+}
+
+/// Parses the expression's command line arguments into our AST. Each argument is one
+/// word of the expression, so a pattern or format with spaces in it needs no quoting
+pub fn parse_arguments<S: AsRef<str>>(args: &[S]) -> Result<Expr, ParseError> {
+    parse_arguments_with(args, &ParseOptions::default())
+}
+
+/// Parses the expression's command line arguments into our AST, with non-default
+/// parser settings
+pub fn parse_arguments_with<S: AsRef<str>>(args: &[S], options: &ParseOptions) -> Result<Expr, ParseError> {
+    parse_arguments_with_warnings(args, options).map(|(expr, _)| expr)
+}
+
+/// Like parse_arguments_with, also returning the warnings about the arguments, for the
+/// caller to show as it sees fit
+pub fn parse_arguments_with_warnings<S: AsRef<str>>(
+    args: &[S],
+    options: &ParseOptions,
+) -> Result<(Expr, Vec<String>), ParseError> {
+    // Only a predicate's argument may be empty, which the first argument can't be
+    if args.first().is_some_and(|arg| arg.as_ref().is_empty()) {
+        return Err(ParseError::StrayArgument(String::new()));
+    }
+    let input = join_arguments(args.iter().map(AsRef::as_ref));
+    check_nesting(&input).map_err(|e| e.error)?;
+    let pairs = FindCommandParser::parse(Rule::Program, &input)
+        .map_err(|e| syntax_error(&input, e))?;
+    let cx = ParseContext::new(options);
+    let expr = parse_to_ast_with(pairs, &cx)?;
+    Ok((expr, cx.warnings.take()))
+}
+
+// What the parse functions share: the settings, and the warnings collected so far
+#[derive(Default)]
+struct ParseContext {
+    options: ParseOptions,
+    warnings: RefCell<Vec<String>>,
+}
+
+impl ParseContext {
+    fn new(options: &ParseOptions) -> Self {
+        ParseContext { options: options.clone(), warnings: RefCell::new(Vec::new()) }
+    }
+
+    // Notes a warning, unless -nowarn turned them off
+    fn warn(&self, message: String) {
+        if self.options.warnings {
+            self.warnings.borrow_mut().push(message);
+        }
+    }
+}
+
+/// Parses an expression string into our AST. The string is split into arguments at
+/// whitespace, so none of them can contain any
+pub fn parse_expression(input: &str) -> Result<Expr, ParseError> {
+    parse_expression_with(input, &ParseOptions::default())
+}
+
+/// Parses an expression string into our AST, with non-default parser settings
+pub fn parse_expression_with(input: &str, options: &ParseOptions) -> Result<Expr, ParseError> {
+    parse_arguments_with(&input.split_whitespace().collect::<Vec<_>>(), options)
+}
+
+// The arguments as the grammar reads them, each followed by ARGUMENT_END but the last
+fn join_arguments<'a>(args: impl Iterator<Item = &'a str>) -> String {
+    let mut input = String::new();
+    for (i, arg) in args.enumerate() {
+        if i > 0 {
+            input.push(ARGUMENT_END);
+        }
+        input.push_str(arg);
+    }
+    input
+}
+
+/// A parse error along with the byte range of the input it is about
+#[derive(Debug)]
+pub struct SpannedError {
+    pub error: ParseError,
+    pub span: Range<usize>,
+}
+
+impl fmt::Display for SpannedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (at {}..{})", self.error, self.span.start, self.span.end)
+    }
+}
+
+impl std::error::Error for SpannedError {}
+
+/// Parses as much of the start of the input as forms a whole expression, and returns it
+/// along with the rest of the input, less leading whitespace. This suits tools such as
+/// editor completion, which see expressions while they are being typed: an unfinished
+/// or unparseable tail is returned rather than rejected. It is still an error if no
+/// expression can be read at all, or if one of the predicates read is invalid, and the
+/// error carries the span of the input at fault.
+pub fn parse_expr_str(input: &str) -> Result<(Expr, &str), SpannedError> {
+    parse_expr_str_with(input, &ParseOptions::default())
+}
+
+/// Like parse_expr_str, with non-default parser settings. Warnings are dropped
+pub fn parse_expr_str_with<'a>(input: &'a str, options: &ParseOptions) -> Result<(Expr, &'a str), SpannedError> {
+    let cx = ParseContext::new(options);
+    let words = Words::new(input);
+    let joined = join_arguments(words.words.iter().map(|&(start, len)| &input[start..start + len]));
+    let spanned = |error, span: Range<usize>| SpannedError {
+        error,
+        span: words.original(span.start)..words.original(span.end),
+    };
+    check_nesting(&joined).map_err(|e| spanned(e.error, e.span))?;
+    let pairs = FindCommandParser::parse(Rule::Prefix, &joined).map_err(|e| {
+        let start = match e.location {
+            InputLocation::Pos(pos) | InputLocation::Span((pos, _)) => pos,
+        };
+        // Point at the word the parse stopped at
+        let end = joined[start..].find(ARGUMENT_END).map_or(joined.len(), |len| start + len);
+        spanned(syntax_error(&joined, e), start..end)
+    })?;
+
+    for pair in pairs {
+        let span = pair.as_span().start()..pair.as_span().end();
+        match pair.as_rule() {
+            Rule::LeadingOperator => {
+                return Err(spanned(dangling_operator_error(pair), span));
+            }
+            Rule::Expr => {
+                let expr = parse_expr(pair.clone(), &cx, 1).map_err(|error| {
+                    spanned(error, failing_span(pair, &cx).unwrap_or(span.clone()))
+                })?;
+                return Ok((expr, input[words.original(span.end)..].trim_start()));
+            }
+            _ => continue,
+        }
+    }
+    unreachable!("a successful parse of Prefix always contains an Expr")
+}
+
+// The whitespace-separated words of an expression string, as the byte offset and length
+// of each, for mapping offsets in the joined arguments back to the string
+struct Words {
+    words: Vec<(usize, usize)>,
+    len: usize,
+}
+
+impl Words {
+    fn new(input: &str) -> Self {
+        let words = input.split_whitespace()
+            .map(|word| (word.as_ptr() as usize - input.as_ptr() as usize, word.len()))
+            .collect();
+        Words { words, len: input.len() }
+    }
+
+    // The offset in the string of an offset in the joined arguments. The end of a word,
+    // where its ARGUMENT_END is, maps to the end of the word in the string too
+    fn original(&self, mut offset: usize) -> usize {
+        for &(start, len) in &self.words {
+            if offset <= len {
+                return start + offset;
+            }
+            offset -= len + ARGUMENT_END.len_utf8();
+        }
+        self.len
+    }
+}
+
+// Rejects parentheses and -not nested deeper than MAX_NESTING_DEPTH before pest, which
+// recurses for each level, sees them. Words are only looked at for parentheses at their
+// ends, so a group written without spaces such as `(-true)` counts too, while a regex
+// such as `\(a\)` opens and closes one and changes nothing. The span is of the word
+// that goes over the limit
+fn check_nesting(input: &str) -> Result<(), SpannedError> {
+    // The -not operators in a row not yet applied to an operand, for each open group
+    // and then for the current one
+    let mut pending_nots = vec![0];
+    for word in input.split(ARGUMENT_END) {
+        let start = word.as_ptr() as usize - input.as_ptr() as usize;
+        let mut rest = word;
+        if rest == "!" || rest == "-not" {
+            *pending_nots.last_mut().unwrap() += 1;
+            rest = "";
+        }
+        while let Some(inner) = rest.strip_prefix('(').or_else(|| rest.strip_prefix("\\(")) {
+            pending_nots.push(0);
+            rest = inner;
+        }
+        if pending_nots.len() - 1 + pending_nots.iter().sum::<usize>() > MAX_NESTING_DEPTH {
+            return Err(SpannedError {
+                error: ParseError::NestedTooDeeply(MAX_NESTING_DEPTH),
+                span: start..start + word.len(),
+            });
+        }
+        // An operand takes the -not operators before it
+        if !rest.is_empty() {
+            *pending_nots.last_mut().unwrap() = 0;
+        }
+        while let Some(inner) = rest.strip_suffix(')').or_else(|| rest.strip_suffix("\\)")) {
+            if pending_nots.len() > 1 {
+                pending_nots.pop();
+            }
+            *pending_nots.last_mut().unwrap() = 0;
+            rest = inner;
+        }
+    }
+    Ok(())
+}
+
+// The span of the first predicate or dangling operator in the expression that doesn't
+// convert to the AST
+fn failing_span(pair: Pair<Rule>, cx: &ParseContext) -> Option<Range<usize>> {
+    pair.into_inner().flatten()
+        .find(|inner| match inner.as_rule() {
+            Rule::Test => parse_test(inner.clone(), cx).is_err(),
+            Rule::Action => parse_action(inner.clone(), cx).is_err(),
+            Rule::LeadingOperator | Rule::TrailingOperator => true,
+            _ => false,
+        })
+        .map(|inner| inner.as_span().start()..inner.as_span().end())
+}
+
+// Names the offending word when the parse stopped at the start of one that can't begin
+// anything: a bare word, which GNU find would have taken for a misplaced path, or an
+// option that isn't a predicate. So is an argument that starts with a predicate's name
+// but goes on, such as "-name a" passed as one. Anything else gets pest's description.
+fn syntax_error(input: &str, error: pest::error::Error<Rule>) -> ParseError {
+    let pos = match error.location {
+        InputLocation::Pos(pos) | InputLocation::Span((pos, _)) => pos,
+    };
+    let word_start = input[..pos].rfind(ARGUMENT_END).map_or(0, |end| end + ARGUMENT_END.len_utf8());
+    let word = input[word_start..].split(ARGUMENT_END).next().unwrap_or("");
+    // An empty argument where a predicate was expected
+    if input[pos..].starts_with(ARGUMENT_END) {
+        return ParseError::StrayArgument(String::new());
+    }
+    let run_on = pos > word_start
+        && [Rule::Test, Rule::Action, Rule::Not].into_iter().any(|rule| parses_whole(rule, &input[word_start..pos]));
+    if (pos == word_start || run_on) && !word.is_empty() && word != "!" && !word.contains(['(', ')']) {
+        if !word.starts_with('-') {
+            return ParseError::StrayArgument(word.to_string());
+        }
+        let known = [Rule::Test, Rule::Action, Rule::Not, Rule::AndOperator, Rule::OrOperator]
+            .into_iter()
+            .any(|rule| parses_whole(rule, word));
+        if !known {
+            return ParseError::UnknownPredicate(word.to_string());
+        }
+    }
+    // pest quotes the input, which is easier to read with spaces between the arguments
+    ParseError::Syntax(error.to_string().replace(ARGUMENT_END, " "))
+}
+
+// Whether the rule matches all of the text
+fn parses_whole(rule: Rule, text: &str) -> bool {
+    FindCommandParser::parse(rule, text)
+        .is_ok_and(|mut pairs| pairs.next().is_some_and(|pair| pair.as_span().end() == text.len()))
+}
+
 /// Converts a pest parse tree to our AST
 pub fn parse_to_ast(pairs: Pairs<Rule>) -> Result<Expr, ParseError> {
+    parse_to_ast_with(pairs, &ParseContext::default())
+}
+
+fn parse_to_ast_with(pairs: Pairs<Rule>, cx: &ParseContext) -> Result<Expr, ParseError> {
+    let mut expr = None;
     for pair in pairs {
         match pair.as_rule() {
             Rule::Program => {
                 let mut inner = pair.into_inner();
                 if let Some(expr_pair) = inner.next() {
-                    return parse_expr(expr_pair);
+                    return parse_expr(expr_pair, cx, 1);
                 }
             }
             Rule::Expr => {
-                return parse_expr(pair);
+                expr = Some(parse_expr(pair, cx, 1)?);
+            }
+            Rule::LeadingOperator | Rule::TrailingOperator => {
+                return Err(dangling_operator_error(pair));
             }
             _ => continue,
         }
     }
-    Err(ParseError::UnexpectedRule {
+    expr.ok_or(ParseError::UnexpectedRule {
         expected: "Program".to_string(),
         found: "None".to_string(),
     })
 }
 
-fn parse_expr(pair: Pair<Rule>) -> Result<Expr, ParseError> {
+fn dangling_operator_error(pair: Pair<Rule>) -> ParseError {
+    let operator = pair.as_str().trim().to_string();
+    if pair.as_rule() == Rule::LeadingOperator {
+        ParseError::OperatorWithNothingBefore(operator)
+    } else {
+        ParseError::OperatorWithNothingAfter(operator)
+    }
+}
+
+// Each of these takes the depth in the tree the expression will be at, the root being
+// at 1, so a tree deeper than MAX_EXPRESSION_DEPTH is rejected before it is built
+fn parse_expr(pair: Pair<Rule>, cx: &ParseContext, depth: usize) -> Result<Expr, ParseError> {
     match pair.as_rule() {
         Rule::Expr => {
             let inner = pair.into_inner().next().unwrap();
-            parse_expr(inner)
+            parse_expr(inner, cx, depth)
         }
-        Rule::UnaryExpr => parse_unary_expr(pair),
-        Rule::BinaryExpr => parse_binary_expr(pair),
-        Rule::Term => parse_term(pair),
+        Rule::OrExpr => parse_or_expr(pair, cx, depth),
+        Rule::AndExpr => parse_and_expr(pair, cx, depth),
+        Rule::UnaryExpr => parse_unary_expr(pair, cx, depth),
         _ => Err(ParseError::UnexpectedRule {
             expected: "Expr".to_string(),
             found: format!("{:?}", pair.as_rule()),
@@ -60,88 +455,73 @@ fn parse_expr(pair: Pair<Rule>) -> Result<Expr, ParseError> {
     }
 }
 
-fn parse_unary_expr(pair: Pair<Rule>) -> Result<Expr, ParseError> {
+fn parse_unary_expr(pair: Pair<Rule>, cx: &ParseContext, depth: usize) -> Result<Expr, ParseError> {
+    if depth > MAX_EXPRESSION_DEPTH {
+        return Err(ParseError::ExpressionTooDeep(MAX_EXPRESSION_DEPTH));
+    }
     let mut inner = pair.into_inner();
-    let _not = inner.next().unwrap(); // Skip the Not token
-    let term = inner.next().unwrap();
-    let expr = parse_term(term)?;
-    Ok(Expr::Not(Box::new(expr)))
+    let first = inner.next().unwrap();
+    if first.as_rule() == Rule::Not {
+        let operand = inner.next().unwrap();
+        return Ok(Expr::Not(Box::new(parse_unary_expr(operand, cx, depth + 1)?)));
+    }
+    parse_term(first, cx, depth)
 }
 
-fn parse_binary_expr(pair: Pair<Rule>) -> Result<Expr, ParseError> {
-    let inner = pair.into_inner().next().unwrap();
-    match inner.as_rule() {
-        Rule::AndExpr => parse_and_expr(inner),
-        Rule::OrExpr => parse_or_expr(inner),
-        _ => Err(ParseError::UnexpectedRule {
-            expected: "AndExpr or OrExpr".to_string(),
-            found: format!("{:?}", inner.as_rule()),
-        }),
-    }
+fn parse_and_expr(pair: Pair<Rule>, cx: &ParseContext, depth: usize) -> Result<Expr, ParseError> {
+    let operands: Vec<_> = pair.into_inner().filter(|p| p.as_rule() != Rule::AndOperator).collect();
+    // Explicit -a and an implicit AND (no operator) mean the same thing
+    fold_chain(operands, depth, Expr::And, |operand, depth| parse_unary_expr(operand, cx, depth))
 }
 
-fn parse_and_expr(pair: Pair<Rule>) -> Result<Expr, ParseError> {
-    let mut inner = pair.into_inner();
-    let first_term = inner.next().unwrap();
-    let mut left = parse_term(first_term)?;
-    
-    while let Some(next_pair) = inner.next() {
-        match next_pair.as_rule() {
-            Rule::AndOperator => {
-                // Skip the operator, get the next expression
-                if let Some(expr_pair) = inner.next() {
-                    let right = parse_expr(expr_pair)?;
-                    left = Expr::And(Box::new(left), Box::new(right));
-                }
-            }
-            Rule::Expr => {
-                // Implicit AND (no operator)
-                let right = parse_expr(next_pair)?;
-                left = Expr::And(Box::new(left), Box::new(right));
-            }
-            _ => {}
-        }
-    }
-    
-    Ok(left)
+fn parse_or_expr(pair: Pair<Rule>, cx: &ParseContext, depth: usize) -> Result<Expr, ParseError> {
+    let operands: Vec<_> = pair.into_inner().filter(|p| p.as_rule() != Rule::OrOperator).collect();
+    fold_chain(operands, depth, Expr::Or, |operand, depth| parse_and_expr(operand, cx, depth))
 }
 
-fn parse_or_expr(pair: Pair<Rule>) -> Result<Expr, ParseError> {
-    let mut inner = pair.into_inner();
-    let first_term = inner.next().unwrap();
-    let mut left = parse_term(first_term)?;
-    
-    while let Some(next_pair) = inner.next() {
-        match next_pair.as_rule() {
-            Rule::OrOperator => {
-                // Skip the operator, get the next expression
-                if let Some(expr_pair) = inner.next() {
-                    let right = parse_expr(expr_pair)?;
-                    left = Expr::Or(Box::new(left), Box::new(right));
-                }
-            }
-            _ => {}
-        }
-    }
-    
-    Ok(left)
+// Joins the operands of a chain into a balanced tree with Expr::chain, so "a b c"
+// becomes And(And(a, b), c). In a chain of n no operand ends up more than log2(n)
+// levels, rounded up, below the top
+fn fold_chain(
+    operands: Vec<Pair<Rule>>,
+    depth: usize,
+    operator: fn(Box<Expr>, Box<Expr>) -> Expr,
+    parse_operand: impl Fn(Pair<Rule>, usize) -> Result<Expr, ParseError>,
+) -> Result<Expr, ParseError> {
+    let below = operands.len().next_power_of_two().trailing_zeros() as usize;
+    let operands = operands.into_iter()
+        .map(|operand| parse_operand(operand, depth + below))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Expr::chain(operands, operator))
 }
 
-fn parse_term(pair: Pair<Rule>) -> Result<Expr, ParseError> {
+fn parse_term(pair: Pair<Rule>, cx: &ParseContext, depth: usize) -> Result<Expr, ParseError> {
     match pair.as_rule() {
         Rule::Term => {
-            let inner = pair.into_inner().next().unwrap();
+            let mut inner_pairs = pair.into_inner();
+            let inner = inner_pairs.next().unwrap();
+            if inner.as_rule() == Rule::LeadingOperator {
+                return Err(dangling_operator_error(inner));
+            }
+            // A parenthesized expression may be followed by a dangling operator
+            if let Some(trailing) = inner_pairs.next() {
+                return Err(dangling_operator_error(trailing));
+            }
             match inner.as_rule() {
                 Rule::Test => {
-                    let test = parse_test(inner)?;
+                    let test = parse_test(inner, cx)?;
                     Ok(Expr::Test(test))
                 }
+                Rule::Action => {
+                    let action = parse_action(inner, cx)?;
+                    Ok(Expr::Action(action))
+                }
                 Rule::Expr => {
                     // Parenthesized expression
-                    parse_expr(inner)
+                    parse_expr(inner, cx, depth)
                 }
                 _ => Err(ParseError::UnexpectedRule {
-                    expected: "Test or Expr".to_string(),
+                    expected: "Test, Action or Expr".to_string(),
                     found: format!("{:?}", inner.as_rule()),
                 }),
             }
@@ -153,136 +533,153 @@ fn parse_term(pair: Pair<Rule>) -> Result<Expr, ParseError> {
     }
 }
 
-fn parse_test(pair: Pair<Rule>) -> Result<Test, ParseError> {
+fn parse_test(pair: Pair<Rule>, cx: &ParseContext) -> Result<Test, ParseError> {
     let inner = pair.into_inner().next().unwrap();
     match inner.as_rule() {
-        Rule::Path => {
-            let mut inner = inner.into_inner();
-            let glob = inner.next().unwrap();
-            Ok(Test::Path(glob.as_str().to_string()))
-        }
-        Rule::Ipath => {
-            let mut inner = inner.into_inner();
-            let glob = inner.next().unwrap();
-            Ok(Test::Ipath(glob.as_str().to_string()))
-        }
-        Rule::Name => {
-            let mut inner = inner.into_inner();
-            let glob = inner.next().unwrap();
-            Ok(Test::Name(glob.as_str().to_string()))
-        }
-        Rule::Iname => {
-            let mut inner = inner.into_inner();
-            let glob = inner.next().unwrap();
-            Ok(Test::Iname(glob.as_str().to_string()))
-        }
+        Rule::Path => Ok(Test::Path(pattern_argument(inner, cx)?)),
+        Rule::Ipath => Ok(Test::Ipath(pattern_argument(inner, cx)?)),
+        Rule::Name => Ok(Test::Name(pattern_argument(inner, cx)?)),
+        Rule::Iname => Ok(Test::Iname(pattern_argument(inner, cx)?)),
         Rule::Regex => {
-            let mut inner = inner.into_inner();
-            let pattern = inner.next().unwrap();
-            Ok(Test::Regex(pattern.as_str().to_string()))
+            let pattern = pattern_argument(inner, cx)?;
+            Ok(Test::Regex(compile_regex(&pattern, false, &cx.options)?))
         }
         Rule::Iregex => {
-            let mut inner = inner.into_inner();
-            let pattern = inner.next().unwrap();
-            Ok(Test::Iregex(pattern.as_str().to_string()))
+            let pattern = pattern_argument(inner, cx)?;
+            Ok(Test::Iregex(compile_regex(&pattern, true, &cx.options)?))
+        }
+        Rule::Grep => {
+            // Unlike -regex this looks for a match anywhere in a line, like grep
+            let pattern = predicate_argument(inner)?;
+            Ok(Test::Grep(build_regex(pattern.as_str(), pattern.as_str(), &cx.options)?))
         }
         Rule::True => Ok(Test::True),
         Rule::False => Ok(Test::False),
         Rule::Type => {
-            let mut inner = inner.into_inner();
-            let filetype = inner.next().unwrap();
-            let file_type = parse_filetype(filetype)?;
+            let predicate = predicate_name(&inner);
+            let filetype = predicate_argument(inner)?;
+            let file_type = match filetype.as_rule() {
+                Rule::Typename => cx.options.lenient_types
+                    .then(|| filetype_alias(filetype.as_str()))
+                    .flatten()
+                    .ok_or_else(|| ParseError::InvalidArgument {
+                        predicate,
+                        argument: filetype.as_str().to_string(),
+                    })?,
+                _ => parse_filetype(filetype)?,
+            };
             Ok(Test::Type(file_type))
         }
-        Rule::Size => {
-            let mut inner = inner.into_inner();
-            let sizespec = inner.next().unwrap();
-            let size_spec = parse_sizespec(sizespec)?;
-            Ok(Test::Size(size_spec))
-        }
+        Rule::Size => Ok(Test::Size(size_argument(inner, cx)?)),
+        Rule::TreeSize => Ok(Test::TreeSize(size_argument(inner, cx)?)),
         Rule::Empty => {
             Ok(Test::Empty)
         }
+        Rule::Readable => Ok(Test::Readable),
+        Rule::Writable => Ok(Test::Writable),
+        Rule::Executable => Ok(Test::Executable),
         Rule::Amin => {
-            let mut inner = inner.into_inner();
-            let timespec = inner.next().unwrap();
+            let timespec = predicate_argument(inner)?;
             let time_spec = parse_timespec(timespec)?;
             Ok(Test::Amin(time_spec))
         }
         Rule::Atime => {
-            let mut inner = inner.into_inner();
-            let timespec = inner.next().unwrap();
+            let timespec = predicate_argument(inner)?;
             let time_spec = parse_timespec(timespec)?;
             Ok(Test::Atime(time_spec))
         }
+        Rule::Used => {
+            let timespec = predicate_argument(inner)?;
+            let time_spec = parse_timespec(timespec)?;
+            Ok(Test::Used(time_spec))
+        }
         Rule::Ctime => {
-            let mut inner = inner.into_inner();
-            let timespec = inner.next().unwrap();
+            let timespec = predicate_argument(inner)?;
             let time_spec = parse_timespec(timespec)?;
             Ok(Test::Ctime(time_spec))
         }
         Rule::Cmin => {
-            let mut inner = inner.into_inner();
-            let timespec = inner.next().unwrap();
+            let timespec = predicate_argument(inner)?;
             let time_spec = parse_timespec(timespec)?;
             Ok(Test::Cmin(time_spec))
         }
         Rule::Mmin => {
-            let mut inner = inner.into_inner();
-            let timespec = inner.next().unwrap();
+            let timespec = predicate_argument(inner)?;
             let time_spec = parse_timespec(timespec)?;
             Ok(Test::Mmin(time_spec))
         }
         Rule::Mtime => {
-            let mut inner = inner.into_inner();
-            let timespec = inner.next().unwrap();
+            let timespec = predicate_argument(inner)?;
             let time_spec = parse_timespec(timespec)?;
             Ok(Test::Mtime(time_spec))
         }
+        // Any kind of file can be the reference. As in GNU find, a symlink stands for
+        // its target only under -L.
         Rule::Anewer => {
-            let mut inner = inner.into_inner();
-            let filepath = inner.next().unwrap();
-            Ok(Test::Anewer(filepath.as_str().to_string()))
+            let filepath = predicate_argument(inner)?;
+            Ok(Test::Anewer(stat_reference("-anewer", filepath.as_str(), cx.options.follow_symlinks)?))
         }
         Rule::Cnewer => {
-            let mut inner = inner.into_inner();
-            let filepath = inner.next().unwrap();
-            Ok(Test::Cnewer(filepath.as_str().to_string()))
+            let filepath = predicate_argument(inner)?;
+            Ok(Test::Cnewer(stat_reference("-cnewer", filepath.as_str(), cx.options.follow_symlinks)?))
         }
         Rule::Mnewer => {
-            let mut inner = inner.into_inner();
-            let filepath = inner.next().unwrap();
-            Ok(Test::Mnewer(filepath.as_str().to_string()))
+            let filepath = predicate_argument(inner)?;
+            Ok(Test::Mnewer(stat_reference("-mnewer", filepath.as_str(), cx.options.follow_symlinks)?))
         }
         Rule::Newer => {
-            let mut inner = inner.into_inner();
-            let filepath = inner.next().unwrap();
-            Ok(Test::Newer(filepath.as_str().to_string()))
+            let filepath = predicate_argument(inner)?;
+            Ok(Test::Newer(stat_reference("-newer", filepath.as_str(), cx.options.follow_symlinks)?))
+        }
+        Rule::SameFile => {
+            let filepath = predicate_argument(inner)?;
+            // Entries are only resolved through symlinks under -L, so a symlink reference
+            // is too, or it could never match the link itself under -P
+            Ok(Test::SameFile(stat_reference("-samefile", filepath.as_str(), cx.options.follow_symlinks)?))
         }
+        Rule::NewerAt => Ok(Test::NewerAt(parse_time_argument(inner)?)),
+        Rule::NewerCt => Ok(Test::NewerCt(parse_time_argument(inner)?)),
+        Rule::NewerMt => Ok(Test::NewerMt(parse_time_argument(inner)?)),
         Rule::User => {
-            let mut inner = inner.into_inner();
-            let username = inner.next().unwrap();
+            let username = predicate_argument(inner)?;
             Ok(Test::User(username.as_str().to_string()))
         }
         Rule::Group => {
-            let mut inner = inner.into_inner();
-            let groupname = inner.next().unwrap();
+            let groupname = predicate_argument(inner)?;
             Ok(Test::Group(groupname.as_str().to_string()))
         }
         Rule::Uid => {
-            let mut inner = inner.into_inner();
-            let uid_str = inner.next().unwrap();
-            let uid = uid_str.as_str().parse::<u32>()
-                .map_err(|_| ParseError::InvalidNumber(uid_str.as_str().to_string()))?;
+            let uid_str = predicate_argument(inner)?;
+            let uid = parse_number(uid_str.as_str())?;
             Ok(Test::Uid(uid))
         }
         Rule::Gid => {
-            let mut inner = inner.into_inner();
-            let gid_str = inner.next().unwrap();
-            let gid = gid_str.as_str().parse::<u32>()
-                .map_err(|_| ParseError::InvalidNumber(gid_str.as_str().to_string()))?;
+            let gid_str = predicate_argument(inner)?;
+            let gid = parse_number(gid_str.as_str())?;
             Ok(Test::Gid(gid))
         }
+        Rule::Links => {
+            let spec = predicate_argument(inner)?;
+            Ok(Test::Links(parse_countspec(spec)?))
+        }
+        Rule::MaxDepth => {
+            let depth_str = predicate_argument(inner)?;
+            let depth = parse_number(depth_str.as_str())?;
+            Ok(Test::MaxDepth(depth))
+        }
+        Rule::AtDepth => {
+            let depth_str = predicate_argument(inner)?;
+            let depth = parse_number(depth_str.as_str())?;
+            Ok(Test::AtDepth(depth))
+        }
+        Rule::Custom => {
+            let name = predicate_argument(inner)?;
+            Ok(Test::Custom(name.as_str().to_string()))
+        }
+        Rule::Magic => {
+            let mimetype = predicate_argument(inner)?;
+            Ok(Test::Magic(mimetype.as_str().to_string()))
+        }
         Rule::Perm => {
             let inner = inner.into_inner();
             let perm_spec = parse_perm_rule(inner)?;
@@ -295,6 +692,255 @@ fn parse_test(pair: Pair<Rule>) -> Result<Test, ParseError> {
     }
 }
 
+fn parse_action(pair: Pair<Rule>, cx: &ParseContext) -> Result<Action, ParseError> {
+    let inner = pair.into_inner().next().unwrap();
+    match inner.as_rule() {
+        Rule::Printf => {
+            let format = possibly_empty_argument(inner)?;
+            let directives = parse_format(format.as_str(), cx)?;
+            Ok(Action::Printf(directives))
+        }
+        Rule::Print => Ok(Action::Print),
+        Rule::Prune => Ok(Action::Prune),
+        Rule::Ls => Ok(Action::Ls),
+        Rule::Quit => Ok(Action::Quit),
+        Rule::Delete => Ok(Action::Delete),
+        Rule::Exec => {
+            let command = inner.into_inner().next()
+                .filter(|command| command.as_rule() == Rule::ExecCommand)
+                .ok_or_else(|| ParseError::MissingArgument("-exec".to_string()))?;
+            let words = command.into_inner()
+                .filter(|word| word.as_rule() == Rule::ExecWord)
+                .map(|word| word.as_str().to_string())
+                .collect();
+            Ok(Action::Exec(words))
+        }
+        _ => Err(ParseError::UnexpectedRule {
+            expected: "Action variant".to_string(),
+            found: format!("{:?}", inner.as_rule()),
+        }),
+    }
+}
+
+/// Returns the argument of a predicate such as `-name x`, or a MissingArgument or
+/// InvalidArgument error naming the predicate as the user typed it. An empty argument
+/// is invalid
+fn predicate_argument(pair: Pair<Rule>) -> Result<Pair<Rule>, ParseError> {
+    let predicate = predicate_name(&pair);
+    let argument = possibly_empty_argument(pair)?;
+    if argument.as_rule() == Rule::BadSpec || argument.as_str().is_empty() {
+        return Err(ParseError::InvalidArgument {
+            predicate,
+            argument: argument.as_str().to_string(),
+        });
+    }
+    Ok(argument)
+}
+
+// Like predicate_argument, for the predicates whose argument may be empty
+fn possibly_empty_argument(pair: Pair<Rule>) -> Result<Pair<Rule>, ParseError> {
+    let predicate = predicate_name(&pair);
+    pair.into_inner().next().ok_or(ParseError::MissingArgument(predicate))
+}
+
+// The glob or regex of -name, -iname, -path, -ipath, -regex and -iregex. These have to
+// match the whole name or path, and no entry has an empty one, so an empty pattern
+// matches nothing. Like GNU find that is allowed, but warned about.
+fn pattern_argument(pair: Pair<Rule>, cx: &ParseContext) -> Result<String, ParseError> {
+    let predicate = predicate_name(&pair);
+    let argument = possibly_empty_argument(pair)?;
+    if argument.as_str().is_empty() {
+        cx.warn(format!("`{}' with an empty pattern matches nothing", predicate));
+        return Ok(String::new());
+    }
+    Ok(argument.as_str().to_string())
+}
+
+// The size argument of -size and -treesize. Without a suffix these count 512-byte
+// blocks, which is easily mistaken for bytes, so a nonzero count of blocks is warned
+// about with the spelling that counts bytes. So is a suffix in a case GNU find
+// doesn't take, such as K for k.
+fn size_argument(pair: Pair<Rule>, cx: &ParseContext) -> Result<SizeSpec, ParseError> {
+    let predicate = predicate_name(&pair);
+    let argument = predicate_argument(pair)?;
+    let spec = argument.as_str().to_string();
+    let size_spec = parse_sizespec(argument)?;
+    if size_spec.value != 0 && matches!(size_spec.suffix, None | Some(SizeSuffix::Blocks)) {
+        cx.warn(format!(
+            "`{} {}' counts 512-byte blocks; use `{}c' for bytes",
+            predicate, spec, spec.trim_end_matches('b')
+        ));
+    }
+    if let Some(suffix) = &size_spec.suffix
+        && !spec.ends_with(&suffix.to_string()) {
+        cx.warn(format!(
+            "`{} {}' has a suffix GNU find doesn't accept; its spelling is `{}'",
+            predicate, spec, suffix
+        ));
+    }
+    Ok(size_spec)
+}
+
+// The date and time argument of -newerat, -newerct and -newermt
+fn parse_time_argument(pair: Pair<Rule>) -> Result<SystemTime, ParseError> {
+    let predicate = predicate_name(&pair);
+    let argument = predicate_argument(pair)?;
+    parse_datetime(argument.as_str()).ok_or_else(|| ParseError::InvalidArgument {
+        predicate,
+        argument: argument.as_str().to_string(),
+    })
+}
+
+// The predicate as the user spelled it, e.g. -t rather than -type
+fn predicate_name(pair: &Pair<Rule>) -> String {
+    pair.as_str().split(ARGUMENT_END).next().unwrap_or_default().to_string()
+}
+
+/// Parses a -printf format string into literal text and directives
+fn parse_format(format: &str, cx: &ParseContext) -> Result<Vec<FormatDirective>, ParseError> {
+    let mut directives = Vec::new();
+    let mut literal = String::new();
+    let mut chars = format.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n') => literal.push('\n'),
+                Some('t') => literal.push('\t'),
+                Some('r') => literal.push('\r'),
+                Some('0') => literal.push('\0'),
+                Some('a') => literal.push('\x07'),
+                Some('b') => literal.push('\x08'),
+                Some('f') => literal.push('\x0c'),
+                Some('v') => literal.push('\x0b'),
+                Some('\\') => literal.push('\\'),
+                Some(other) => {
+                    // Unknown escapes are kept as written
+                    literal.push('\\');
+                    literal.push(other);
+                }
+                None => literal.push('\\'),
+            },
+            '%' => {
+                let directive = match chars.next() {
+                    Some('%') => {
+                        literal.push('%');
+                        continue;
+                    }
+                    Some('p') => FormatDirective::Path,
+                    Some('P') => FormatDirective::RelativePath,
+                    Some('H') => FormatDirective::StartPath,
+                    Some('f') => FormatDirective::Basename,
+                    Some('h') => FormatDirective::Dirname,
+                    Some('s') => FormatDirective::Size,
+                    Some('S') => FormatDirective::Sparseness,
+                    Some('d') => FormatDirective::Depth,
+                    Some('i') => FormatDirective::Inode,
+                    Some('n') => FormatDirective::LinkCount,
+                    Some('y') => FormatDirective::Type,
+                    Some('Y') => FormatDirective::TargetType,
+                    Some('a') => FormatDirective::Time(TimeField::Access),
+                    Some('c') => FormatDirective::Time(TimeField::Change),
+                    Some('t') => FormatDirective::Time(TimeField::Modify),
+                    Some(other) => {
+                        return Err(ParseError::InvalidFormat(format!(
+                            "unrecognized directive %{} in {}", other, format
+                        )));
+                    }
+                    None => {
+                        // GNU find warns about a bare % at the end and prints it as is
+                        cx.warn("format directive `%' should be followed by another character".to_string());
+                        literal.push('%');
+                        continue;
+                    }
+                };
+                if !literal.is_empty() {
+                    directives.push(FormatDirective::Literal(std::mem::take(&mut literal)));
+                }
+                directives.push(directive);
+            }
+            _ => literal.push(c),
+        }
+    }
+
+    if !literal.is_empty() {
+        directives.push(FormatDirective::Literal(literal));
+    }
+    Ok(directives)
+}
+
+/// Stats a reference file once, resolving it like GNU find does: relative to the
+/// current working directory rather than to any of the start paths. A symlink is
+/// followed only if `follow_symlinks` is set, and a dangling one stands for itself.
+fn stat_reference(predicate: &str, path: &str, follow_symlinks: bool) -> Result<ReferenceFile, ParseError> {
+    let metadata = if follow_symlinks {
+        fs::metadata(path).or_else(|error| fs::symlink_metadata(path).map_err(|_| error))
+    } else {
+        fs::symlink_metadata(path)
+    };
+    let metadata = metadata.map_err(|e| ParseError::InvalidReference {
+        predicate: predicate.to_string(),
+        path: path.to_string(),
+        message: e.to_string(),
+    })?;
+    Ok(ReferenceFile {
+        path: path.to_string(),
+        metadata: FileStat::from(&metadata),
+    })
+}
+
+/// Compiles a -regex/-iregex pattern anchored to match the whole path, after
+/// translating it from the -regextype dialect
+fn compile_regex(
+    pattern: &str,
+    case_insensitive: bool,
+    options: &ParseOptions,
+) -> Result<RegexPattern, ParseError> {
+    let translated = options.regex_type.translate(pattern).map_err(|message| ParseError::InvalidRegex {
+        pattern: pattern.to_string(),
+        message,
+    })?;
+    let anchored = if case_insensitive {
+        format!("(?i)^(?:{})$", translated)
+    } else {
+        format!("^(?:{})$", translated)
+    };
+    build_regex(pattern, &anchored, options)
+}
+
+// Compiles `expanded`, the pattern as the regex engine should see it, keeping
+// `pattern` as the user wrote it for errors and display
+fn build_regex(pattern: &str, expanded: &str, options: &ParseOptions) -> Result<RegexPattern, ParseError> {
+    let regex = RegexBuilder::new(expanded)
+        .size_limit(options.regex_size_limit)
+        .build()
+        .map_err(|e| match e {
+            regex::Error::CompiledTooBig(limit) => ParseError::RegexTooLarge(limit),
+            e => ParseError::InvalidRegex {
+                pattern: pattern.to_string(),
+                message: e.to_string(),
+            },
+        })?;
+    Ok(RegexPattern {
+        source: pattern.to_string(),
+        regex,
+    })
+}
+
+// The forgiving -type spellings allowed by --lenient-types
+fn filetype_alias(name: &str) -> Option<FileType> {
+    match name.to_lowercase().as_str() {
+        "b" => Some(FileType::BlockFile),
+        "c" => Some(FileType::CharFile),
+        "d" | "dir" => Some(FileType::Directory),
+        "p" => Some(FileType::NamedPipe),
+        "f" | "file" => Some(FileType::RegularFile),
+        "l" | "link" | "symlink" => Some(FileType::SymbolicLink),
+        "s" => Some(FileType::Socket),
+        _ => None,
+    }
+}
+
 fn parse_filetype(pair: Pair<Rule>) -> Result<FileType, ParseError> {
     let inner = pair.into_inner().next().unwrap();
     match inner.as_rule() {
@@ -313,8 +959,9 @@ fn parse_filetype(pair: Pair<Rule>) -> Result<FileType, ParseError> {
 }
 
 fn parse_sizespec(pair: Pair<Rule>) -> Result<SizeSpec, ParseError> {
+    let spec = pair.as_str();
     let mut sign = Sign::None;
-    let mut value = 0u64;
+    let mut value = None;
     let mut suffix = None;
     
     for inner in pair.into_inner() {
@@ -328,8 +975,7 @@ fn parse_sizespec(pair: Pair<Rule>) -> Result<SizeSpec, ParseError> {
                 };
             }
             Rule::Number => {
-                value = inner.as_str().parse::<u64>()
-                    .map_err(|_| ParseError::InvalidNumber(inner.as_str().to_string()))?;
+                value = Some(parse_number(inner.as_str())?);
             }
             Rule::SizeSuffix => {
                 let suffix_inner = inner.into_inner().next().unwrap();
@@ -349,13 +995,45 @@ fn parse_sizespec(pair: Pair<Rule>) -> Result<SizeSpec, ParseError> {
             _ => {}
         }
     }
-    
+
+    // A sign on its own is not a size
+    let value = value.ok_or_else(|| ParseError::InvalidNumber(spec.to_string()))?;
     Ok(SizeSpec { sign, value, suffix })
 }
 
+/// Parses a byte count for an option such as `--max-filesize`, using the -size
+/// suffixes (`c`, `w`, `b`, `k`, `M`, `G`). Unlike -size a bare number is in bytes,
+/// and no sign is allowed.
+pub fn parse_byte_count(input: &str) -> Result<u64, ParseError> {
+    let invalid = || ParseError::InvalidNumber(input.to_string());
+    let pair = FindCommandParser::parse(Rule::Sizespec, input)
+        .ok()
+        .and_then(|mut pairs| pairs.next())
+        .filter(|pair| pair.as_str() == input)
+        .ok_or_else(invalid)?;
+    let spec = parse_sizespec(pair)?;
+    if spec.sign != Sign::None {
+        return Err(invalid());
+    }
+    let unit = if spec.suffix.is_some() { spec.unit() } else { 1 };
+    spec.value.checked_mul(unit).ok_or_else(invalid)
+}
+
+fn parse_countspec(pair: Pair<Rule>) -> Result<CountSpec, ParseError> {
+    // Counts are written just like times, so share the parsing
+    let TimeSpec { sign, value } = parse_timespec(pair)?;
+    Ok(CountSpec { sign, value })
+}
+
+// Parses a Number, dropping the underscores the grammar allows between digits
+fn parse_number<T: FromStr>(number: &str) -> Result<T, ParseError> {
+    number.replace('_', "").parse::<T>().map_err(|_| ParseError::InvalidNumber(number.to_string()))
+}
+
 fn parse_timespec(pair: Pair<Rule>) -> Result<TimeSpec, ParseError> {
+    let spec = pair.as_str();
     let mut sign = Sign::None;
-    let mut value = 0u64;
+    let mut value = None;
     
     for inner in pair.into_inner() {
         match inner.as_rule() {
@@ -368,13 +1046,13 @@ fn parse_timespec(pair: Pair<Rule>) -> Result<TimeSpec, ParseError> {
                 };
             }
             Rule::Number => {
-                value = inner.as_str().parse::<u64>()
-                    .map_err(|_| ParseError::InvalidNumber(inner.as_str().to_string()))?;
+                value = Some(parse_number(inner.as_str())?);
             }
             _ => {}
         }
     }
-    
+
+    let value = value.ok_or_else(|| ParseError::InvalidNumber(spec.to_string()))?;
     Ok(TimeSpec { sign, value })
 }
 
@@ -382,7 +1060,7 @@ fn parse_perm_rule(mut pairs: Pairs<Rule>) -> Result<PermSpec, ParseError> {
     let mut prefix = None;
     let mut term = None;
     
-    while let Some(pair) = pairs.next() {
+    for pair in pairs.by_ref() {
         match pair.as_rule() {
             Rule::PermPrefix => {
                 prefix = Some(parse_perm_prefix(pair)?);
@@ -390,14 +1068,12 @@ fn parse_perm_rule(mut pairs: Pairs<Rule>) -> Result<PermSpec, ParseError> {
             Rule::PermTerm => {
                 term = Some(parse_perm_term(pair)?);
             }
+            Rule::BadSpec => return Err(ParseError::InvalidMode(pair.as_str().to_string())),
             _ => {}
         }
     }
     
-    let term = term.ok_or(ParseError::UnexpectedRule {
-        expected: "PermTerm".to_string(),
-        found: "None".to_string(),
-    })?;
+    let term = term.ok_or_else(|| ParseError::MissingArgument("-perm".to_string()))?;
     
     Ok(PermSpec { prefix, term })
 }
@@ -439,46 +1115,38 @@ fn parse_sym_perm_term(pair: Pair<Rule>) -> Result<Vec<SymPermStatement>, ParseE
     let mut statements = Vec::new();
     
     for inner in pair.into_inner() {
-        match inner.as_rule() {
-            Rule::SymPermTermStmt => {
-                let statement = parse_sym_perm_term_stmt(inner)?;
-                statements.push(statement);
-            }
-            _ => {}
+        if inner.as_rule() == Rule::SymPermTermStmt {
+            statements.extend(parse_sym_perm_term_stmt(inner)?);
         }
     }
     
     Ok(statements)
 }
 
-fn parse_sym_perm_term_stmt(pair: Pair<Rule>) -> Result<SymPermStatement, ParseError> {
-    let mut inner = pair.into_inner();
-    
-    let principal_pair = inner.next().ok_or(ParseError::UnexpectedRule {
-        expected: "SymPrincipal".to_string(),
-        found: "None".to_string(),
-    })?;
-    let principal = parse_sym_principal(principal_pair)?;
-    
-    let operator_pair = inner.next().ok_or(ParseError::UnexpectedRule {
-        expected: "SymPermOperator".to_string(),
-        found: "None".to_string(),
-    })?;
-    let operator = parse_sym_perm_operator(operator_pair)?;
-    
+// A statement naming several principals, e.g. go=r, becomes one statement for each.
+// Each principal's bits are separate, so g=r,o=r has the same effect
+fn parse_sym_perm_term_stmt(pair: Pair<Rule>) -> Result<Vec<SymPermStatement>, ParseError> {
+    let mut principals = Vec::new();
+    let mut operator = None;
     let mut privileges = Vec::new();
-    while let Some(priv_pair) = inner.next() {
-        if priv_pair.as_rule() == Rule::SymPermPriv {
-            let privilege = parse_sym_perm_priv(priv_pair)?;
-            privileges.push(privilege);
+    for inner in pair.into_inner() {
+        match inner.as_rule() {
+            Rule::SymPrincipal => principals.push(parse_sym_principal(inner)?),
+            Rule::SymPermOperator => operator = Some(parse_sym_perm_operator(inner)?),
+            Rule::SymPermPriv => privileges.push(parse_sym_perm_priv(inner)?),
+            _ => {}
         }
     }
+    let operator = operator.ok_or(ParseError::UnexpectedRule {
+        expected: "SymPermOperator".to_string(),
+        found: "None".to_string(),
+    })?;
     
-    Ok(SymPermStatement {
+    Ok(principals.into_iter().map(|principal| SymPermStatement {
         principal,
-        operator,
-        privileges,
-    })
+        operator: operator.clone(),
+        privileges: privileges.clone(),
+    }).collect())
 }
 
 fn parse_sym_principal(pair: Pair<Rule>) -> Result<SymPrincipal, ParseError> {
@@ -519,4 +1187,920 @@ fn parse_sym_perm_priv(pair: Pair<Rule>) -> Result<SymPermPriv, ParseError> {
             found: format!("{:?}", inner.as_rule()),
         }),
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(input: &str) -> Expr {
+        try_parse(input).unwrap_or_else(|e| panic!("Failed to parse {:?}: {}", input, e))
+    }
+
+    fn try_parse(input: &str) -> Result<Expr, ParseError> {
+        parse_expression(input)
+    }
+
+    #[test]
+    fn test_parentheses_spacing() {
+        let expected = Expr::Test(Test::Name("x".to_string()));
+
+        assert_eq!(parse("( -name x )"), expected);
+        assert_eq!(parse("(-name x)"), expected);
+        assert_eq!(parse("(-name x )"), expected);
+        assert_eq!(parse("( -name x)"), expected);
+
+        // A regex's own groups stay in it, but a ) it doesn't open closes the expression's
+        let regex = |input| match parse(input) {
+            Expr::And(left, right) => match (*left, *right) {
+                (Expr::Test(Test::Type(FileType::RegularFile)), Expr::Test(Test::Regex(pattern))) => pattern.source,
+                other => panic!("{:?}", other),
+            },
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(regex("-type f -a (-regex .*a.txt)"), ".*a.txt");
+        assert_eq!(regex("-type f -a (-regex .*(a|b).txt)"), ".*(a|b).txt");
+        assert_eq!(regex("-type f -a (-regex .*(a|b))"), ".*(a|b)");
+        assert_eq!(regex("-type f -a (-regex .*[)]\\))"), ".*[)]\\)");
+        assert_eq!(regex("-type f -a ( -regex (a|b) )"), "(a|b)");
+    }
+
+    #[test]
+    fn test_escaped_parentheses() {
+        let expected = Expr::Test(Test::Name("x".to_string()));
+
+        assert_eq!(parse("\\( -name x \\)"), expected);
+        assert_eq!(parse("\\(-name x\\)"), expected);
+        assert_eq!(parse("\\( -name x )"), expected);
+    }
+
+    #[test]
+    fn test_parenthesized_or_spacing() {
+        let expected = Expr::And(
+            Box::new(Expr::Or(
+                Box::new(Expr::Test(Test::Name("a".to_string()))),
+                Box::new(Expr::Test(Test::Name("b".to_string()))),
+            )),
+            Box::new(Expr::Test(Test::Type(FileType::RegularFile))),
+        );
+
+        assert_eq!(parse("( -name a -o -name b ) -type f"), expected);
+        assert_eq!(parse("(-name a -o -name b) -type f"), expected);
+        assert_eq!(parse("\\( -name a -o -name b \\) -type f"), expected);
+        assert_eq!(parse("\\(-name a -o -name b\\) -type f"), expected);
+    }
+
+    #[test]
+    fn test_operator_precedence() {
+        let name = |n: &str| Box::new(Expr::Test(Test::Name(n.to_string())));
+        let file = || Box::new(Expr::Test(Test::Type(FileType::RegularFile)));
+
+        // AND binds tighter than OR, whether written or implied
+        let expected = Expr::Or(Box::new(Expr::And(name("a"), file())), name("b"));
+        assert_eq!(parse("-name a -type f -o -name b"), expected);
+        assert_eq!(parse("-name a -a -type f -o -name b"), expected);
+
+        let expected = Expr::Or(name("a"), Box::new(Expr::And(name("b"), file())));
+        assert_eq!(parse("-name a -o -name b -type f"), expected);
+
+        // Both operators group to the left
+        let expected = Expr::Or(Box::new(Expr::Or(name("a"), name("b"))), name("c"));
+        assert_eq!(parse("-name a -o -name b -o -name c"), expected);
+
+        // -not applies only to the test that follows it
+        let expected = Expr::And(Box::new(Expr::Not(name("a"))), file());
+        assert_eq!(parse("! -name a -type f"), expected);
+        assert_eq!(parse("-not -not -name a"), Expr::Not(Box::new(Expr::Not(name("a")))));
+    }
+
+    #[test]
+    fn test_implicit_and_precedence() {
+        let name = |n: &str| Box::new(Expr::Test(Test::Name(n.to_string())));
+        let file_type = |t| Box::new(Expr::Test(Test::Type(t)));
+        let and = |l, r| Box::new(Expr::And(l, r));
+
+        // Implicit AND chains bind tighter than -o on both sides of it
+        let expected = Expr::Or(
+            and(and(name("a"), file_type(FileType::RegularFile)), name("c")),
+            and(name("b"), file_type(FileType::Directory)),
+        );
+        assert_eq!(parse("-name a -type f -name c -o -name b -type d"), expected);
+        assert_eq!(parse("-name a -a -type f -name c -o -name b -and -type d"), expected);
+
+        // ... including when a side starts with -not or is parenthesized
+        let expected = Expr::Or(
+            and(Box::new(Expr::Not(name("a"))), file_type(FileType::RegularFile)),
+            and(Box::new(Expr::Or(name("b"), name("c"))), file_type(FileType::Directory)),
+        );
+        assert_eq!(parse("! -name a -type f -o ( -name b -o -name c ) -type d"), expected);
+
+        // An implicit AND after a parenthesized OR applies to the whole group
+        let expected = Expr::And(
+            Box::new(Expr::Or(name("a"), name("b"))),
+            file_type(FileType::RegularFile),
+        );
+        assert_eq!(parse("( -name a -o -name b ) -type f"), expected);
+    }
+
+    #[test]
+    fn test_prune_print_parsing() {
+        let expected = Expr::Or(
+            Box::new(Expr::And(
+                Box::new(Expr::Test(Test::Name("skip".to_string()))),
+                Box::new(Expr::Action(Action::Prune)),
+            )),
+            Box::new(Expr::Action(Action::Print)),
+        );
+        assert_eq!(parse("-name skip -prune -o -print"), expected);
+        assert!(!parse("-name skip -prune").has_action());
+    }
+
+    #[test]
+    fn test_ls_parsing() {
+        let expected = Expr::And(
+            Box::new(Expr::Test(Test::Type(FileType::RegularFile))),
+            Box::new(Expr::Action(Action::Ls)),
+        );
+        assert_eq!(parse("-type f -ls"), expected);
+        assert!(parse("-ls").has_action());
+    }
+
+    #[test]
+    fn test_quit_parsing() {
+        let expected = Expr::And(
+            Box::new(Expr::Action(Action::Print)),
+            Box::new(Expr::Action(Action::Quit)),
+        );
+        assert_eq!(parse("-print -quit"), expected);
+        assert!(!parse("-name x -quit").has_action());
+    }
+
+    #[test]
+    fn test_printf_parsing() {
+        let expected = Expr::Action(Action::Printf(vec![
+            FormatDirective::Path,
+            FormatDirective::Literal(":".to_string()),
+            FormatDirective::Inode,
+            FormatDirective::Literal(":".to_string()),
+            FormatDirective::LinkCount,
+            FormatDirective::Literal("%\n".to_string()),
+        ]));
+        assert_eq!(parse("-printf %p:%i:%n%%\\n"), expected);
+        assert_eq!(
+            parse_arguments(&["-printf", "%p (%s)!\\n", "-o", "-print"]).unwrap(),
+            Expr::Or(
+                Box::new(Expr::Action(Action::Printf(vec![
+                    FormatDirective::Path,
+                    FormatDirective::Literal(" (".to_string()),
+                    FormatDirective::Size,
+                    FormatDirective::Literal(")!\n".to_string()),
+                ]))),
+                Box::new(Expr::Action(Action::Print)),
+            ),
+        );
+
+        assert_eq!(
+            parse_format("\\0\\a\\b\\f\\r\\v\\t\\q", &ParseContext::default()).unwrap(),
+            vec![FormatDirective::Literal("\0\x07\x08\x0c\r\x0b\t\\q".to_string())]
+        );
+
+        assert_eq!(
+            parse_format("%H/%P", &ParseContext::default()).unwrap(),
+            vec![
+                FormatDirective::StartPath,
+                FormatDirective::Literal("/".to_string()),
+                FormatDirective::RelativePath,
+            ]
+        );
+        assert_eq!(
+            parse_format("%S %s", &ParseContext::default()).unwrap(),
+            vec![
+                FormatDirective::Sparseness,
+                FormatDirective::Literal(" ".to_string()),
+                FormatDirective::Size,
+            ]
+        );
+
+        assert_eq!(
+            parse_format("%a%c%t", &ParseContext::default()).unwrap(),
+            vec![
+                FormatDirective::Time(TimeField::Access),
+                FormatDirective::Time(TimeField::Change),
+                FormatDirective::Time(TimeField::Modify),
+            ]
+        );
+
+        assert!(parse_format("%q", &ParseContext::default()).is_err());
+        let cx = ParseContext::default();
+        assert_eq!(parse_format("100%", &cx).unwrap(), vec![FormatDirective::Literal("100%".to_string())]);
+        assert_eq!(cx.warnings.take(), vec!["format directive `%' should be followed by another character"]);
+    }
+
+    #[test]
+    fn test_printf_utf8_literals() {
+        assert_eq!(
+            parse("-printf «%f»→%s\\\\ü\\n"),
+            Expr::Action(Action::Printf(vec![
+                FormatDirective::Literal("«".to_string()),
+                FormatDirective::Basename,
+                FormatDirective::Literal("»→".to_string()),
+                FormatDirective::Size,
+                FormatDirective::Literal("\\ü\n".to_string()),
+            ]))
+        );
+        // An unknown escape or directive before a multibyte character keeps it whole
+        assert_eq!(parse_format("\\é✓%", &ParseContext::default()).unwrap(), vec![FormatDirective::Literal("\\é✓%".to_string())]);
+        let err = parse_format("%é", &ParseContext::default()).unwrap_err();
+        assert_eq!(err.to_string(), "Invalid format: unrecognized directive %é in %é");
+    }
+
+    #[test]
+    fn test_regex_compiled_at_parse_time() {
+        match parse("-regex .*[.]txt") {
+            Expr::Test(Test::Regex(pattern)) => {
+                assert_eq!(pattern.source, ".*[.]txt");
+                assert!(pattern.regex.is_match("./a.txt"));
+                assert!(!pattern.regex.is_match("./a.txt.bak"));
+            }
+            other => panic!("Unexpected AST: {:?}", other),
+        }
+
+        match parse("-iregex .*[.]TXT") {
+            Expr::Test(Test::Iregex(pattern)) => assert!(pattern.regex.is_match("./a.txt")),
+            other => panic!("Unexpected AST: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_empty_patterns() {
+        for (predicate, expected) in [
+            ("-name", Test::Name(String::new())),
+            ("-iname", Test::Iname(String::new())),
+            ("-path", Test::Path(String::new())),
+            ("-ipath", Test::Ipath(String::new())),
+        ] {
+            assert_eq!(parse_arguments(&[predicate, ""]).unwrap(), Expr::Test(expected.clone()), "{}", predicate);
+            // An empty argument in the middle of the expression is one too
+            assert_eq!(
+                parse_arguments(&[predicate, "", "-print"]).unwrap(),
+                Expr::And(Box::new(Expr::Test(expected)), Box::new(Expr::Action(Action::Print))),
+            );
+        }
+        for predicate in ["-regex", "-iregex"] {
+            match parse_arguments(&[predicate, ""]).unwrap() {
+                Expr::Test(Test::Regex(pattern) | Test::Iregex(pattern)) => {
+                    assert_eq!(pattern.source, "");
+                    assert!(!pattern.regex.is_match("./a"));
+                }
+                other => panic!("Unexpected AST: {:?}", other),
+            }
+        }
+
+        // Each is warned about, as the parse's result rather than on stderr
+        for predicate in ["-name", "-iname", "-path", "-ipath", "-regex", "-iregex"] {
+            let (_, warnings) = parse_arguments_with_warnings(&[predicate, ""], &ParseOptions::default()).unwrap();
+            assert_eq!(warnings, vec![format!("`{}' with an empty pattern matches nothing", predicate)]);
+        }
+
+        // Quotes are no marker: '' is a pattern like any other
+        assert_eq!(parse("-name ''"), Expr::Test(Test::Name("''".to_string())));
+        assert!(matches!(try_parse("-name"), Err(ParseError::MissingArgument(_))));
+        // Other arguments can't be empty, and nor can anything but an argument
+        for args in [&["-size", ""][..], &["-newer", ""], &["-type", ""], &["-user", ""]] {
+            assert!(matches!(
+                parse_arguments(args),
+                Err(ParseError::InvalidArgument { ref argument, .. }) if argument.is_empty()
+            ), "{:?}", args);
+        }
+        for args in [&["-print", ""][..], &["-print", "", "-print"], &["", "-print"]] {
+            assert!(matches!(
+                parse_arguments(args),
+                Err(ParseError::StrayArgument(ref word)) if word.is_empty()
+            ), "{:?}", args);
+        }
+        assert_eq!(parse_arguments(&["-printf", ""]).unwrap(), Expr::Action(Action::Printf(Vec::new())));
+    }
+
+    #[test]
+    fn test_invalid_regex_is_an_error() {
+        let err = try_parse("-regex a[b").unwrap_err();
+        assert!(matches!(err, ParseError::InvalidRegex { .. }));
+        assert!(err.to_string().starts_with("invalid regex: a[b"));
+
+        assert!(try_parse("-iregex *").is_err());
+    }
+
+    #[test]
+    fn test_regex_type_translates_patterns() {
+        let options = ParseOptions { regex_type: RegexType::Emacs, ..ParseOptions::default() };
+        let Expr::Test(Test::Regex(pattern)) = parse_expression_with(r"-regex .*\.\(rs\|toml\)", &options).unwrap() else {
+            panic!("not a -regex");
+        };
+        // The pattern is shown as written
+        assert_eq!(pattern.source, r".*\.\(rs\|toml\)");
+        assert!(pattern.regex.is_match("./src/lib.rs"));
+        assert!(!pattern.regex.is_match("./README.md"));
+
+        let err = parse_expression_with(r"-regex \(a\)\1", &options).unwrap_err();
+        assert!(matches!(err, ParseError::InvalidRegex { .. }));
+    }
+
+    #[test]
+    fn test_deep_nesting_is_an_error() {
+        let nested = |depth| format!("{}-true{}", "( ".repeat(depth), " )".repeat(depth));
+        assert!(try_parse(&nested(50)).is_ok());
+        assert!(matches!(try_parse(&nested(MAX_NESTING_DEPTH + 1)), Err(ParseError::NestedTooDeeply(_))));
+        // Groups written without spaces count too
+        let packed = format!("{}-true{}", "\\(".repeat(MAX_NESTING_DEPTH + 1), "\\)".repeat(MAX_NESTING_DEPTH + 1));
+        assert!(matches!(try_parse(&packed), Err(ParseError::NestedTooDeeply(_))));
+        // -not nests as well, alone or between groups
+        let nots = format!("{}-true", "-not ".repeat(MAX_NESTING_DEPTH + 1));
+        assert!(matches!(try_parse(&nots), Err(ParseError::NestedTooDeeply(_))));
+        let mixed = format!("{}-true{}", "! ( ".repeat(MAX_NESTING_DEPTH / 2 + 1), " )".repeat(MAX_NESTING_DEPTH / 2 + 1));
+        assert!(matches!(try_parse(&mixed), Err(ParseError::NestedTooDeeply(_))));
+        // Groups that close again don't add up
+        assert!(try_parse(&("( -true ) -o ".repeat(MAX_NESTING_DEPTH * 2) + "-false")).is_ok());
+
+        let error = parse_expr_str(&nested(MAX_NESTING_DEPTH + 1)).unwrap_err();
+        assert_eq!(error.span.start, 2 * MAX_NESTING_DEPTH);
+    }
+
+    #[test]
+    fn test_long_chains_are_balanced() {
+        fn depth(expr: &Expr) -> usize {
+            match expr {
+                Expr::And(left, right) | Expr::Or(left, right) => 1 + depth(left).max(depth(right)),
+                Expr::Not(inner) => 1 + depth(inner),
+                Expr::Test(_) | Expr::Action(_) => 1,
+            }
+        }
+        let chain = |length: usize, operator: &str| format!("-true{}", format!(" {} -false", operator).repeat(length - 1));
+        for operator in ["-o", "-a", ""] {
+            let expr = try_parse(&chain(10 * MAX_EXPRESSION_DEPTH, operator)).unwrap();
+            assert_eq!(depth(&expr), 15, "{:?}", operator);
+        }
+
+        // Operands keep their order
+        let name = |n: &str| Box::new(Expr::Test(Test::Name(n.to_string())));
+        assert_eq!(
+            parse("-name a -o -name b -o -name c -o -name d -o -name e"),
+            Expr::Or(
+                Box::new(Expr::Or(Box::new(Expr::Or(name("a"), name("b"))), name("c"))),
+                Box::new(Expr::Or(name("d"), name("e"))),
+            ),
+        );
+    }
+
+    #[test]
+    fn test_oversized_regex_is_an_error() {
+        let pattern = "[a-z]{1000}";
+        let options = ParseOptions { regex_size_limit: 10_000, ..ParseOptions::default() };
+        let err = parse_expression_with(&format!("-regex {}", pattern), &options).unwrap_err();
+        assert!(matches!(err, ParseError::RegexTooLarge(10_000)));
+        assert_eq!(
+            err.to_string(),
+            "regex too large: compiled size exceeds the limit of 10000 bytes"
+        );
+
+        // The same pattern fits under the default limit
+        assert!(try_parse(&format!("-iregex {}", pattern)).is_ok());
+        assert!(try_parse("-regex [a-z]{100}{100}{100}").is_err());
+    }
+
+    #[test]
+    fn test_newer_reference() {
+        match parse("-newer Cargo.toml") {
+            Expr::Test(Test::Newer(reference)) => {
+                assert_eq!(reference.path, "Cargo.toml");
+                assert_eq!(
+                    reference.metadata.modified,
+                    fs::metadata("Cargo.toml").unwrap().modified().ok()
+                );
+            }
+            other => panic!("Unexpected AST: {:?}", other),
+        }
+
+        for predicate in ["-anewer", "-cnewer", "-mnewer", "-newer", "-samefile"] {
+            let err = try_parse(&format!("{} no/such/reference", predicate)).unwrap_err();
+            assert!(matches!(err, ParseError::InvalidReference { .. }));
+            let expected = format!("{} reference no/such/reference: ", predicate);
+            assert!(err.to_string().starts_with(&expected), "{}", err);
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_newer_reference_follows_symlinks_only_under_follow() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let target = temp_dir.path().join("target.txt");
+        let link = temp_dir.path().join("link");
+        let dangling = temp_dir.path().join("dangling");
+        fs::write(&target, "").unwrap();
+        let an_hour_ago = SystemTime::now() - std::time::Duration::from_secs(3600);
+        fs::File::options().write(true).open(&target).unwrap().set_modified(an_hour_ago).unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+        std::os::unix::fs::symlink(temp_dir.path().join("missing"), &dangling).unwrap();
+
+        let reference = |path: &std::path::Path, follow_symlinks| {
+            let options = ParseOptions { follow_symlinks, ..ParseOptions::default() };
+            match parse_expression_with(&format!("-newer {}", path.display()), &options).unwrap() {
+                Expr::Test(Test::Newer(reference)) => reference.metadata,
+                other => panic!("Unexpected AST: {:?}", other),
+            }
+        };
+
+        let unfollowed = reference(&link, false);
+        assert_eq!(unfollowed.file_type, Some(FileType::SymbolicLink));
+        assert_eq!(unfollowed.modified, fs::symlink_metadata(&link).unwrap().modified().ok());
+        let followed = reference(&link, true);
+        assert_eq!(followed.file_type, Some(FileType::RegularFile));
+        assert_eq!(followed.modified, Some(an_hour_ago));
+        // With nothing to follow, a dangling link is its own reference
+        assert_eq!(reference(&dangling, true).file_type, Some(FileType::SymbolicLink));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_samefile_reference_follows_symlinks_only_under_follow() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let target = temp_dir.path().join("target.txt");
+        let link = temp_dir.path().join("link");
+        fs::write(&target, "").unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        let reference = |follow_symlinks| {
+            let options = ParseOptions { follow_symlinks, ..ParseOptions::default() };
+            match parse_expression_with(&format!("-samefile {}", link.display()), &options).unwrap() {
+                Expr::Test(Test::SameFile(reference)) => reference.metadata,
+                other => panic!("Unexpected AST: {:?}", other),
+            }
+        };
+        let target_stat = FileStat::from(&fs::metadata(&target).unwrap());
+
+        let unfollowed = reference(false);
+        assert_eq!(unfollowed.file_type, Some(FileType::SymbolicLink));
+        assert_ne!(unfollowed.ino, target_stat.ino);
+        let followed = reference(true);
+        assert_eq!(followed.file_type, Some(FileType::RegularFile));
+        assert_eq!((followed.dev, followed.ino), (target_stat.dev, target_stat.ino));
+    }
+
+    #[test]
+    fn test_newer_than_time_parsing() {
+        let time = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_709_210_096);
+        assert_eq!(parse("-newermt 2024-02-29T12:34:56Z"), Expr::Test(Test::NewerMt(time)));
+        assert_eq!(parse("-newerat @1709210096"), Expr::Test(Test::NewerAt(time)));
+        assert_eq!(parse("-newerct 2024-02-29T13:34:56+01:00"), Expr::Test(Test::NewerCt(time)));
+        // -newer itself still takes a reference file
+        assert!(matches!(parse("-newer Cargo.toml"), Expr::Test(Test::Newer(_))));
+
+        let err = try_parse("-newermt yesterday").unwrap_err();
+        assert_eq!(err.to_string(), "invalid argument `yesterday' to `-newermt'");
+        assert!(matches!(try_parse("-newerat"), Err(ParseError::MissingArgument(_))));
+    }
+
+    #[test]
+    fn test_trailing_operator_is_an_error() {
+        let err = try_parse("-name x -and").unwrap_err();
+        assert!(matches!(err, ParseError::OperatorWithNothingAfter(ref op) if op == "-and"));
+        assert!(err.to_string().contains("binary operator '-and' with nothing after it"));
+
+        let err = try_parse("-name x -or").unwrap_err();
+        assert!(matches!(err, ParseError::OperatorWithNothingAfter(ref op) if op == "-or"));
+
+        let err = try_parse("( -name x -a )").unwrap_err();
+        assert!(matches!(err, ParseError::OperatorWithNothingAfter(ref op) if op == "-a"));
+    }
+
+    #[test]
+    fn test_stray_words_are_named() {
+        for (input, word) in [("-type f foo", "foo"), ("-name a.txt -o foo", "foo"), ("( -true ) foo bar", "foo")] {
+            let err = try_parse(input).unwrap_err();
+            assert!(matches!(err, ParseError::StrayArgument(ref w) if w == word), "{}: {:?}", input, err);
+        }
+        assert_eq!(
+            try_parse("-type f foo").unwrap_err().to_string(),
+            "paths must precede expression: `foo'"
+        );
+
+        let err = try_parse("-name x -frobnicate").unwrap_err();
+        assert!(matches!(err, ParseError::UnknownPredicate(ref p) if p == "-frobnicate"));
+        assert_eq!(err.to_string(), "unknown predicate `-frobnicate'");
+
+        // Other mistakes still get the parser's own description
+        assert!(matches!(try_parse("( -true"), Err(ParseError::Syntax(_))));
+        assert!(matches!(try_parse("-name x )"), Err(ParseError::Syntax(_))));
+    }
+
+    #[test]
+    fn test_leading_operator_is_an_error() {
+        let err = try_parse("-o -name x").unwrap_err();
+        assert!(matches!(err, ParseError::OperatorWithNothingBefore(ref op) if op == "-o"));
+        assert!(err.to_string().contains("binary operator '-o' with nothing before it"));
+
+        let err = try_parse("-type f ( -or -name x )").unwrap_err();
+        assert!(matches!(err, ParseError::OperatorWithNothingBefore(ref op) if op == "-or"));
+    }
+
+    #[test]
+    fn test_operator_prefixed_tests_are_not_operators() {
+        assert_eq!(
+            parse("-amin -5"),
+            Expr::Test(Test::Amin(TimeSpec { sign: Sign::Minus, value: 5 }))
+        );
+        assert_eq!(
+            parse("-name x -atime +1"),
+            Expr::And(
+                Box::new(Expr::Test(Test::Name("x".to_string()))),
+                Box::new(Expr::Test(Test::Atime(TimeSpec { sign: Sign::Plus, value: 1 }))),
+            )
+        );
+    }
+
+    #[test]
+    fn test_syntax_error_is_reported() {
+        assert!(matches!(try_parse("-name x -o -o -name y"), Err(ParseError::Syntax(_))));
+        assert!(matches!(try_parse("-bogus"), Err(ParseError::UnknownPredicate(_))));
+    }
+
+    #[test]
+    fn test_negated_parentheses_spacing() {
+        let expected = Expr::Not(Box::new(Expr::Test(Test::Name("x".to_string()))));
+
+        assert_eq!(parse("! ( -name x )"), expected);
+        assert_eq!(parse("!(-name x)"), expected);
+        assert_eq!(parse("-not \\( -name x \\)"), expected);
+    }
+
+    #[test]
+    fn test_size_in_blocks_is_warned_about() {
+        let warnings = |input: &str, warnings| {
+            let options = ParseOptions { warnings, ..ParseOptions::default() };
+            let args: Vec<&str> = input.split_whitespace().collect();
+            parse_arguments_with_warnings(&args, &options).unwrap().1
+        };
+        assert_eq!(warnings("-size -10", true), vec!["`-size -10' counts 512-byte blocks; use `-10c' for bytes"]);
+        assert_eq!(warnings("-treesize 3b", true), vec!["`-treesize 3b' counts 512-byte blocks; use `3c' for bytes"]);
+        assert!(warnings("-size 0", true).is_empty());
+        assert!(warnings("-size 10c", true).is_empty());
+        // -nowarn turns them off
+        assert!(warnings("-size -10", false).is_empty());
+    }
+
+    #[test]
+    fn test_size_suffix_casing() {
+        let size = |suffix| Expr::Test(Test::Size(SizeSpec { sign: Sign::None, value: 2, suffix }));
+        assert_eq!(parse("-size 2"), size(None));
+        for (input, suffix) in [
+            ("-size 2b", SizeSuffix::Blocks),
+            ("-size 2c", SizeSuffix::Bytes),
+            ("-size 2w", SizeSuffix::Words),
+            ("-size 2k", SizeSuffix::Kb),
+            ("-size 2M", SizeSuffix::Mb),
+            ("-size 2G", SizeSuffix::Gb),
+            // The other case of k, M and G is taken to mean the same
+            ("-size 2K", SizeSuffix::Kb),
+            ("-size 2m", SizeSuffix::Mb),
+            ("-size 2g", SizeSuffix::Gb),
+        ] {
+            assert_eq!(parse(input), size(Some(suffix)), "{}", input);
+        }
+        // The other case is warned about, with GNU find's spelling
+        let (_, warnings) = parse_arguments_with_warnings(&["-size", "2K"], &ParseOptions::default()).unwrap();
+        assert_eq!(warnings, vec!["`-size 2K' has a suffix GNU find doesn't accept; its spelling is `k'"]);
+        let (_, warnings) = parse_arguments_with_warnings(&["-size", "2M"], &ParseOptions::default()).unwrap();
+        assert!(warnings.is_empty());
+        // The others have no other case, as B and C are no unit
+        for input in ["-size 2B", "-size 2C", "-size 2W"] {
+            assert!(matches!(try_parse(input), Err(ParseError::InvalidArgument { .. })), "{}", input);
+        }
+        // Displayed as GNU find spells them
+        let spelled: String = [SizeSuffix::Blocks, SizeSuffix::Bytes, SizeSuffix::Words, SizeSuffix::Kb, SizeSuffix::Mb, SizeSuffix::Gb]
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(spelled, "bcwkMG");
+    }
+
+    #[test]
+    fn test_tree_size_parsing() {
+        let spec = SizeSpec { sign: Sign::Plus, value: 3, suffix: Some(SizeSuffix::Mb) };
+        assert_eq!(parse("-treesize +3M"), Expr::Test(Test::TreeSize(spec)));
+        // The -t alias of -type is a prefix of -treesize
+        assert_eq!(parse("-t d"), Expr::Test(Test::Type(FileType::Directory)));
+    }
+
+    #[test]
+    fn test_max_depth_parsing() {
+        let expr = parse("-maxdepth 1 -name x -maxdepth 3");
+        assert_eq!(expr.max_depth(), Some(3));
+        assert_eq!(parse("-name x").max_depth(), None);
+        assert!(matches!(try_parse("-maxdepth"), Err(ParseError::MissingArgument(_))));
+
+        // -atdepth selects a level without limiting the walk
+        let expr = parse("-atdepth 2 -o -name x");
+        assert!(matches!(expr, Expr::Or(ref left, _) if **left == Expr::Test(Test::AtDepth(2))));
+        assert_eq!(expr.max_depth(), None);
+        assert!(matches!(try_parse("-atdepth"), Err(ParseError::MissingArgument(_))));
+        assert!(try_parse("-atdepth -1").is_err());
+
+        // A number followed by more of the expression doesn't swallow the space after it
+        assert_eq!(
+            parse("-uid 0 -true"),
+            Expr::And(Box::new(Expr::Test(Test::Uid(0))), Box::new(Expr::Test(Test::True)))
+        );
+    }
+
+    #[test]
+    fn test_links_parsing() {
+        let links = |sign, value| Expr::Test(Test::Links(CountSpec { sign, value }));
+        assert_eq!(parse("-links 2"), links(Sign::None, 2));
+        assert_eq!(parse("-links +1"), links(Sign::Plus, 1));
+        assert_eq!(parse("-links -3"), links(Sign::Minus, 3));
+        assert!(matches!(try_parse("-links"), Err(ParseError::MissingArgument(_))));
+        assert!(matches!(try_parse("-links two"), Err(ParseError::InvalidArgument { .. })));
+    }
+
+    #[test]
+    fn test_digit_separators() {
+        let size = SizeSpec { sign: Sign::Plus, value: 1_000_000, suffix: Some(SizeSuffix::Bytes) };
+        assert_eq!(parse("-size +1_000_000c"), Expr::Test(Test::Size(size)));
+        assert_eq!(parse("-mtime -1_000"), Expr::Test(Test::Mtime(TimeSpec { sign: Sign::Minus, value: 1000 })));
+        assert_eq!(parse("-used +2"), Expr::Test(Test::Used(TimeSpec { sign: Sign::Plus, value: 2 })));
+        assert_eq!(parse("-uid 1_000"), Expr::Test(Test::Uid(1000)));
+        assert_eq!(parse_byte_count("1_024k").unwrap(), 1024 * 1024);
+
+        // Only single underscores between digits
+        for input in ["-size 1__0", "-size _10", "-size 10_", "-mtime 1__0", "-size 1_k"] {
+            assert!(try_parse(input).is_err(), "{}", input);
+        }
+        assert!(try_parse("-uid 1__0").is_err());
+        // Modes are octal digits only
+        assert!(try_parse("-perm 0_644").is_err());
+    }
+
+    #[test]
+    fn test_lone_sign_is_an_error() {
+        for (input, expected) in [("-size +", "`+' to `-size'"), ("-mtime -", "`-' to `-mtime'")] {
+            let err = try_parse(input).unwrap_err();
+            assert!(matches!(err, ParseError::InvalidArgument { .. }), "{}", input);
+            assert_eq!(err.to_string(), format!("invalid argument {}", expected));
+        }
+        assert_eq!(
+            try_parse("-size 5x").unwrap_err().to_string(),
+            "invalid argument `5x' to `-size'"
+        );
+        assert!(matches!(try_parse("-amin -true"), Err(ParseError::InvalidArgument { .. })));
+
+        let size = SizeSpec { sign: Sign::Plus, value: 5, suffix: None };
+        assert_eq!(parse("-size +5"), Expr::Test(Test::Size(size)));
+        assert_eq!(
+            parse("( -mtime -2)"),
+            Expr::Test(Test::Mtime(TimeSpec { sign: Sign::Minus, value: 2 }))
+        );
+    }
+
+    #[test]
+    fn test_lenient_type_aliases() {
+        let lenient = ParseOptions { lenient_types: true, ..ParseOptions::default() };
+        let parse_lenient = |input: &str| parse_expression_with(input, &lenient).unwrap();
+        let file_type = |t: FileType| Expr::Test(Test::Type(t));
+
+        assert_eq!(parse_lenient("-type F"), file_type(FileType::RegularFile));
+        assert_eq!(parse_lenient("-type file"), file_type(FileType::RegularFile));
+        assert_eq!(parse_lenient("-type DIR"), file_type(FileType::Directory));
+        assert_eq!(parse_lenient("-type d"), file_type(FileType::Directory));
+        assert_eq!(parse_lenient("-type link"), file_type(FileType::SymbolicLink));
+        assert_eq!(parse_lenient("-type Symlink"), file_type(FileType::SymbolicLink));
+        assert!(parse_expression_with("-type folder", &lenient).is_err());
+
+        // Without the option only GNU's single lowercase letters are accepted
+        for input in ["-type F", "-type dir", "-type fl"] {
+            assert!(matches!(try_parse(input), Err(ParseError::InvalidArgument { .. })), "{}", input);
+        }
+        assert_eq!(
+            try_parse("-t file").unwrap_err().to_string(),
+            "invalid argument `file' to `-t'"
+        );
+    }
+
+    #[test]
+    fn test_magic_parsing() {
+        assert_eq!(parse("-magic image/png"), Expr::Test(Test::Magic("image/png".to_string())));
+        assert!(matches!(try_parse("-magic"), Err(ParseError::MissingArgument(_))));
+    }
+
+    #[test]
+    fn test_tests_display_as_written() {
+        let shown = |args: &[&str]| match parse_arguments(args).unwrap() {
+            Expr::Test(test) => test.to_string(),
+            other => panic!("not a single test: {:?}", other),
+        };
+        for args in [
+            &["-name", "a.txt"][..],
+            &["-type", "f"],
+            &["-size", "+10k"],
+            &["-mtime", "-3"],
+            &["-links", "2"],
+            &["-perm", "-644"],
+            &["-perm", "/u+w,g=r"],
+            &["-empty"],
+            &["-maxdepth", "2"],
+        ] {
+            assert_eq!(shown(args), args.join(" "));
+        }
+        // Arguments a shell would split or expand are quoted
+        assert_eq!(shown(&["-regex", ".*a.txt"]), "-regex '.*a.txt'");
+        assert_eq!(shown(&["-name", "it's here"]), "-name 'it'\\''s here'");
+        assert_eq!(shown(&["-name", ""]), "-name ''");
+    }
+
+    #[test]
+    fn test_perm_parsing() {
+        let numeric = |prefix, mode| Expr::Test(Test::Perm(PermSpec { prefix, term: PermTerm::Numeric(mode) }));
+        assert_eq!(parse("-perm 644"), numeric(None, 0o644));
+        assert_eq!(parse("-perm -2755"), numeric(Some(PermPrefix::AllMode), 0o2755));
+        assert!(matches!(parse("-perm /111 -type f"), Expr::And(..)));
+        assert!(matches!(
+            parse("-perm u+w,g-w"),
+            Expr::Test(Test::Perm(PermSpec { term: PermTerm::Symbolic(ref statements), .. }))
+                if statements.len() == 2
+        ));
+
+        // Leaving out the privileges is allowed, but not a statement after a comma
+        let statement = |principal, operator, privileges| SymPermStatement { principal, operator, privileges };
+        let symbolic = |statements| Expr::Test(Test::Perm(PermSpec { prefix: None, term: PermTerm::Symbolic(statements) }));
+        assert_eq!(parse("-perm u="), symbolic(vec![statement(SymPrincipal::User, SymPermOperator::Set, vec![])]));
+        assert_eq!(
+            parse("-perm a=rwx"),
+            symbolic(vec![statement(
+                SymPrincipal::All,
+                SymPermOperator::Set,
+                vec![SymPermPriv::Read, SymPermPriv::Write, SymPermPriv::Execute],
+            )])
+        );
+        assert!(matches!(parse("-perm g+,o= -type f"), Expr::And(..)));
+        assert_eq!(
+            parse("-perm go-w"),
+            symbolic(vec![
+                statement(SymPrincipal::Group, SymPermOperator::Remove, vec![SymPermPriv::Write]),
+                statement(SymPrincipal::Other, SymPermOperator::Remove, vec![SymPermPriv::Write]),
+            ])
+        );
+
+        for mode in ["648", "644,755", "64444", "-79", "u+w,", "u=r,,g=r", "u", "=r"] {
+            let err = try_parse(&format!("-perm {}", mode)).unwrap_err();
+            assert!(
+                matches!(err, ParseError::InvalidMode(ref m) if m == mode),
+                "{}: {:?}",
+                mode,
+                err
+            );
+        }
+        let message = try_parse("-perm 644,755").unwrap_err().to_string();
+        assert!(message.starts_with("invalid mode `644,755' to `-perm'"));
+        assert!(message.contains("only symbolic modes"));
+    }
+
+    #[test]
+    fn test_parse_byte_count() {
+        assert_eq!(parse_byte_count("1000").unwrap(), 1000);
+        assert_eq!(parse_byte_count("1000c").unwrap(), 1000);
+        assert_eq!(parse_byte_count("2b").unwrap(), 1024);
+        assert_eq!(parse_byte_count("10M").unwrap(), 10 * 1024 * 1024);
+        for bad in ["", "+10M", "-1", "10X", "10 M", "M", "99999999999999999999G"] {
+            assert!(parse_byte_count(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_grep_parsing() {
+        match parse("-grep fn\\s+main") {
+            Expr::Test(Test::Grep(pattern)) => {
+                assert_eq!(pattern.source, "fn\\s+main");
+                assert!(pattern.regex.is_match("pub fn  main() {"));
+            }
+            other => panic!("Unexpected AST: {:?}", other),
+        }
+        assert!(matches!(try_parse("-grep a[b"), Err(ParseError::InvalidRegex { .. })));
+        assert!(matches!(try_parse("-grep"), Err(ParseError::MissingArgument(_))));
+    }
+
+    #[test]
+    fn test_missing_argument_names_the_predicate() {
+        let predicates = [
+            "-name", "-iname", "-path", "-regex", "-type", "-size", "-mtime", "-user", "-uid",
+            "-perm", "-printf",
+        ];
+        for predicate in predicates {
+            let err = try_parse(predicate).unwrap_err();
+            assert!(
+                matches!(err, ParseError::MissingArgument(ref p) if p == predicate),
+                "{}",
+                predicate
+            );
+            assert_eq!(err.to_string(), format!("missing argument to `{}'", predicate));
+        }
+
+        let err = try_parse("-type f -a ( -n )").unwrap_err();
+        assert!(matches!(err, ParseError::MissingArgument(ref p) if p == "-n"));
+    }
+
+    #[test]
+    fn test_parse_expr_str_complete() {
+        let (expr, rest) = parse_expr_str("-name x -o ( -type f -print )").unwrap();
+        assert_eq!(expr, parse("-name x -o ( -type f -print )"));
+        assert_eq!(rest, "");
+        assert_eq!(parse_expr_str("-name x  ").unwrap().1, "");
+    }
+
+    #[test]
+    fn test_parse_expr_str_partial() {
+        let name = Expr::Test(Test::Name("x".to_string()));
+        // An operator still waiting for its right side is left over
+        assert_eq!(parse_expr_str("-name x -o").unwrap(), (name.clone(), "-o"));
+        assert_eq!(parse_expr_str("-name x -and ").unwrap(), (name.clone(), "-and "));
+        // As is an unclosed parenthesis, or whatever isn't a predicate
+        assert_eq!(parse_expr_str("-name x ( -type f").unwrap(), (name.clone(), "( -type f"));
+        assert_eq!(parse_expr_str("-name x stray -print").unwrap(), (name.clone(), "stray -print"));
+        assert_eq!(parse_expr_str("-name x -bogus").unwrap(), (name, "-bogus"));
+
+        let input = "-type f -name a -o";
+        let (_, rest) = parse_expr_str(input).unwrap();
+        assert_eq!(input.len() - rest.len(), 16);
+    }
+
+    #[test]
+    fn test_parse_expr_str_errors() {
+        // Nothing that starts an expression
+        let err = parse_expr_str("stray -name x").unwrap_err();
+        assert!(matches!(err.error, ParseError::StrayArgument(ref word) if word == "stray"));
+        assert_eq!(err.span, 0..5);
+        let err = parse_expr_str("  -bogus").unwrap_err();
+        assert!(matches!(err.error, ParseError::UnknownPredicate(_)));
+        assert_eq!(err.span, 2..8);
+        assert_eq!(parse_expr_str("").unwrap_err().span, 0..0);
+
+        // A predicate that was read but is invalid is pointed at
+        let input = "-name x -o -regex a[b -print";
+        let err = parse_expr_str(input).unwrap_err();
+        assert!(matches!(err.error, ParseError::InvalidRegex { .. }));
+        assert_eq!(&input[err.span.clone()], "-regex a[b");
+        let err = parse_expr_str("-name x -o ( -type q )").unwrap_err();
+        assert_eq!(err.span, 13..20);
+        let err = parse_expr_str("-o -name x").unwrap_err();
+        assert!(matches!(err.error, ParseError::OperatorWithNothingBefore(_)));
+        assert_eq!(err.span, 0..2);
+        assert!(err.to_string().ends_with("(at 0..2)"));
+    }
+
+    #[test]
+    fn test_exec_parsing() {
+        let exec = |words: &[&str]| Expr::Action(Action::Exec(words.iter().map(|word| word.to_string()).collect()));
+        assert_eq!(parse("-exec cp {} {}.bak \\;"), exec(&["cp", "{}", "{}.bak"]));
+        assert_eq!(parse("-exec echo prefix-{} ;"), exec(&["echo", "prefix-{}"]));
+        // Everything up to the ; belongs to the command, even parentheses and operators
+        assert_eq!(parse("-exec test ( -o ) ! \\; -print"), Expr::And(
+            Box::new(exec(&["test", "(", "-o", ")", "!"])),
+            Box::new(Expr::Action(Action::Print)),
+        ));
+        assert_eq!(parse_arguments(&["-exec", "echo", "", "''", ";;x", ";"]).unwrap(), exec(&["echo", "", "''", ";;x"]));
+        assert!(parse("-name x -exec rm {} ;").has_action());
+
+        for input in ["-exec", "-exec ;", "-exec echo {}", "-exec echo {} +"] {
+            assert!(matches!(try_parse(input), Err(ParseError::MissingArgument(ref p)) if p == "-exec"), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_delete_parsing() {
+        let expr = parse("-name *.o -delete");
+        assert_eq!(expr, Expr::And(
+            Box::new(Expr::Test(Test::Name("*.o".to_string()))),
+            Box::new(Expr::Action(Action::Delete)),
+        ));
+        assert!(expr.has_action());
+        assert!(expr.deletes());
+        assert!(parse("-name a -o ! ( -type d -delete )").deletes());
+        assert!(!parse("-name a -print").deletes());
+    }
+
+    #[test]
+    fn test_parse_arguments_keeps_each_whole() {
+        let name = |pattern: &str| Expr::Test(Test::Name(pattern.to_string()));
+        assert_eq!(parse_arguments(&["-name", "b c.txt"]).unwrap(), name("b c.txt"));
+        assert_eq!(
+            parse_arguments(&["-name", "a b", "-o", "-name", "c"]).unwrap(),
+            Expr::Or(Box::new(name("a b")), Box::new(name("c"))),
+        );
+        // A word inside an argument is never taken for a predicate
+        assert_eq!(parse_arguments(&["-name", "x -o -name y"]).unwrap(), name("x -o -name y"));
+        assert!(matches!(
+            parse_arguments(&["-name", "a", "stray word"]),
+            Err(ParseError::StrayArgument(ref word)) if word == "stray word"
+        ));
+        assert!(matches!(
+            parse_arguments(&["-name a"]),
+            Err(ParseError::UnknownPredicate(ref word)) if word == "-name a"
+        ));
+    }
+}
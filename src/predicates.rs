@@ -0,0 +1,101 @@
+use std::collections::HashMap;
+use std::fmt;
+use walkdir::DirEntry;
+use crate::ast::{Expr, Test};
+
+/// A predicate supplied from Rust code rather than built into findr
+pub type CustomPredicate = Box<dyn Fn(&DirEntry) -> bool + Send + Sync>;
+
+/// Custom predicates, looked up by name when a `-custom <name>` test is evaluated.
+///
+/// Only `-custom` tests consult the registry, so the built-in predicates cost the
+/// same whether or not anything is registered.
+#[derive(Default)]
+pub struct PredicateRegistry {
+    predicates: HashMap<String, CustomPredicate>,
+}
+
+impl PredicateRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a predicate under `name`, replacing any earlier one with that name
+    pub fn register<F>(&mut self, name: &str, predicate: F)
+    where
+        F: Fn(&DirEntry) -> bool + Send + Sync + 'static,
+    {
+        self.predicates.insert(name.to_string(), Box::new(predicate));
+    }
+
+    /// Evaluates the named predicate; unknown names never match
+    pub fn evaluate(&self, name: &str, entry: &DirEntry) -> bool {
+        self.predicates.get(name).is_some_and(|predicate| predicate(entry))
+    }
+
+    /// Returns the first `-custom` name in the expression that isn't registered, so a
+    /// typo can be reported before walking instead of silently matching nothing
+    pub fn first_unregistered<'a>(&self, expr: &'a Expr) -> Option<&'a str> {
+        match expr {
+            Expr::Not(inner) => self.first_unregistered(inner),
+            Expr::And(left, right) | Expr::Or(left, right) => {
+                self.first_unregistered(left).or_else(|| self.first_unregistered(right))
+            }
+            Expr::Test(Test::Custom(name)) if !self.predicates.contains_key(name) => Some(name),
+            Expr::Test(_) | Expr::Action(_) => None,
+        }
+    }
+}
+
+impl fmt::Debug for PredicateRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.predicates.keys()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::Interpreter;
+    use crate::parser::parse_expression;
+    use std::fs;
+    use std::sync::Arc;
+    use std::time::SystemTime;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_registered_predicate_is_evaluated() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(temp_dir.path().join("keep.txt"), "").unwrap();
+        fs::write(temp_dir.path().join("skip.txt"), "").unwrap();
+
+        let mut registry = PredicateRegistry::new();
+        registry.register("keepers", |entry| entry.file_name().to_string_lossy().starts_with("keep"));
+
+        let expr = parse_expression("-type f -custom keepers").unwrap();
+        assert_eq!(registry.first_unregistered(&expr), None);
+
+        let interpreter = Interpreter::new(temp_dir.path(), SystemTime::now())
+            .with_predicates(Arc::new(registry));
+        let matched: Vec<String> = walkdir::WalkDir::new(temp_dir.path())
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter(|entry| interpreter.matches(&expr, entry))
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(matched, vec!["keep.txt"]);
+    }
+
+    #[test]
+    fn test_unregistered_predicate() {
+        let mut registry = PredicateRegistry::new();
+        registry.register("known", |_| true);
+
+        let expr = parse_expression("-custom known -o ! -custom unknown").unwrap();
+        assert_eq!(registry.first_unregistered(&expr), Some("unknown"));
+
+        let entry = walkdir::WalkDir::new(".").into_iter().next().unwrap().unwrap();
+        assert!(registry.evaluate("known", &entry));
+        assert!(!registry.evaluate("unknown", &entry));
+    }
+}
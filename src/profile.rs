@@ -0,0 +1,93 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::time::Duration;
+
+/// How often one predicate ran, how often it was true, and how long it took in total
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct PredicateStats {
+    pub evaluations: u64,
+    pub matches: u64,
+    pub time: Duration,
+}
+
+/// Evaluation counts and timings per predicate, collected for `--profile`.
+///
+/// Tests are grouped by predicate, so two `-name` tests in one expression share a row.
+/// Displayed as a table with the most expensive predicate first.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Profile {
+    stats: HashMap<&'static str, PredicateStats>,
+}
+
+impl Profile {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counts one evaluation of the predicate
+    pub fn record(&mut self, predicate: &'static str, matched: bool, elapsed: Duration) {
+        let stats = self.stats.entry(predicate).or_default();
+        stats.evaluations += 1;
+        stats.matches += u64::from(matched);
+        stats.time += elapsed;
+    }
+
+    /// Adds the counts from another profile, e.g. one kept for another start path
+    pub fn merge(&mut self, other: Profile) {
+        for (predicate, other) in other.stats {
+            let stats = self.stats.entry(predicate).or_default();
+            stats.evaluations += other.evaluations;
+            stats.matches += other.matches;
+            stats.time += other.time;
+        }
+    }
+
+    /// Returns the counts for a predicate such as "-name", if it was ever evaluated
+    pub fn get(&self, predicate: &str) -> Option<&PredicateStats> {
+        self.stats.get(predicate)
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut rows: Vec<_> = self.stats.iter().collect();
+        rows.sort_by(|a, b| b.1.time.cmp(&a.1.time).then(a.0.cmp(b.0)));
+
+        writeln!(f, "{:<12} {:>12} {:>12} {:>12}", "predicate", "evaluations", "matches", "time")?;
+        for (predicate, stats) in rows {
+            let time = format!("{:.3?}", stats.time);
+            writeln!(f, "{:<12} {:>12} {:>12} {:>12}", predicate, stats.evaluations, stats.matches, time)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_and_merge() {
+        let mut profile = Profile::new();
+        profile.record("-name", true, Duration::from_millis(1));
+        profile.record("-name", false, Duration::from_millis(2));
+        profile.record("-type", true, Duration::from_millis(5));
+
+        let mut other = Profile::new();
+        other.record("-name", true, Duration::from_millis(4));
+        profile.merge(other);
+
+        let name = profile.get("-name").unwrap();
+        assert_eq!((name.evaluations, name.matches, name.time), (3, 2, Duration::from_millis(7)));
+        assert_eq!(profile.get("-type").unwrap().evaluations, 1);
+        assert!(profile.get("-regex").is_none());
+
+        // Most expensive first
+        let table = profile.to_string();
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("predicate"));
+        assert!(lines[1].starts_with("-name") && lines[1].contains("7.000ms"));
+        assert!(lines[2].starts_with("-type"));
+    }
+}
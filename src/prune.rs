@@ -0,0 +1,233 @@
+use std::path::Path;
+use walkdir::DirEntry;
+use crate::ast::{Expr, Test};
+use crate::interpreter::Interpreter;
+
+/// Skips directories that cannot contain a match for a required `-path` test.
+///
+/// Pruning only kicks in when the expression can be true only if some `-path` test is
+/// true, i.e. the test is reached through `-a` alone and no action runs before it.
+/// The literal prefix of that glob (everything before the first `*`, `?`, `[` or
+/// escaping `\`) must then start every matching path, so a directory whose path is
+/// neither under that prefix nor on the way to it is not descended. `-ipath` and
+/// tests under `-o` or `-not` never prune, and start paths are always walked.
+pub struct PathPruner {
+    prefixes: Vec<String>,
+    strip_dot_slash: bool,
+}
+
+impl PathPruner {
+    /// Returns a pruner for the expression, or None if nothing can be pruned.
+    /// `strip_dot_slash` says whether -path also matches without a leading "./"
+    pub fn new(expr: &Expr, strip_dot_slash: bool) -> Option<Self> {
+        let mut prefixes = Vec::new();
+        Self::collect_prefixes(expr, &mut prefixes);
+        if prefixes.is_empty() {
+            None
+        } else {
+            Some(PathPruner { prefixes, strip_dot_slash })
+        }
+    }
+
+    /// Returns false for directories whose subtree can't match, for use with `filter_entry`
+    pub fn may_contain_matches(&self, entry: &DirEntry) -> bool {
+        if entry.depth() == 0 || !entry.file_type().is_dir() {
+            return true;
+        }
+
+        let path = entry.path().to_string_lossy();
+        // Compare with forward slashes, as -path does on Windows
+        #[cfg(windows)]
+        let path = path.replace('\\', "/");
+
+        // With --strip-dot-slash -path also matches without a leading "./", so either
+        // form may lead to a match
+        let stripped = path.strip_prefix("./").filter(|_| self.strip_dot_slash);
+        let candidates = [Some(&path[..]), stripped];
+        self.prefixes.iter().all(|prefix| {
+            candidates.iter().flatten().any(|candidate| Self::on_prefix(candidate, prefix))
+        })
+    }
+
+    // True if the directory lies under the prefix, or the prefix continues below it
+    fn on_prefix(dir: &str, prefix: &str) -> bool {
+        if dir.starts_with(prefix) {
+            return true;
+        }
+        let dir = dir.trim_end_matches('/');
+        prefix.strip_prefix(dir).is_some_and(|rest| rest.starts_with('/'))
+    }
+
+    fn collect_prefixes(expr: &Expr, prefixes: &mut Vec<String>) {
+        match expr {
+            Expr::Test(Test::Path(pattern)) => {
+                let prefix = Self::literal_prefix(pattern);
+                if !prefix.is_empty() {
+                    prefixes.push(prefix);
+                }
+            }
+            Expr::And(left, right) => {
+                Self::collect_prefixes(left, prefixes);
+                // An action on the left runs for entries the right side would reject
+                if !left.has_action() {
+                    Self::collect_prefixes(right, prefixes);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn literal_prefix(pattern: &str) -> String {
+        #[cfg(windows)]
+        let pattern = pattern.replace('\\', "/");
+        let end = pattern.find(['*', '?', '[', '\\']).unwrap_or(pattern.len());
+        pattern[..end].to_string()
+    }
+}
+
+/// Skips every path matching one of the `--exclude` globs, along with everything below it.
+///
+/// The globs match the whole path the way `-path` does, and are checked before the
+/// expression, so an excluded directory is never opened. Start paths are always walked.
+/// A walk that yields each directory after its contents, as for `-delete`, has to use
+/// `allows_within` instead, since `filter_entry` can't skip what it has already yielded.
+pub struct Excludes {
+    patterns: Vec<String>,
+    strip_dot_slash: bool,
+}
+
+impl Excludes {
+    /// Returns the filter for the given globs, or None if there are none.
+    /// `strip_dot_slash` says whether they also match without a leading "./"
+    pub fn new(patterns: &[String], strip_dot_slash: bool) -> Option<Self> {
+        if patterns.is_empty() {
+            None
+        } else {
+            Some(Excludes { patterns: patterns.to_vec(), strip_dot_slash })
+        }
+    }
+
+    /// Returns false for entries matching any of the globs, for use with `filter_entry`
+    pub fn allows(&self, entry: &DirEntry) -> bool {
+        entry.depth() == 0 || !self.excluded(entry.path())
+    }
+
+    /// Like `allows`, but also false for entries below an excluded directory, each of
+    /// which is checked. That costs a match per directory between the start path and
+    /// the entry, but is what keeps the contents of an excluded directory from -delete
+    pub fn allows_within(&self, entry: &DirEntry) -> bool {
+        !entry.path().ancestors().take(entry.depth()).any(|path| self.excluded(path))
+    }
+
+    fn excluded(&self, path: &Path) -> bool {
+        self.patterns.iter().any(|pattern| Interpreter::match_path(pattern, path, false, self.strip_dot_slash))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{Action, FormatDirective};
+    use std::fs;
+    use tempfile::TempDir;
+    use walkdir::WalkDir;
+
+    fn path_test(pattern: &str) -> Box<Expr> {
+        Box::new(Expr::Test(Test::Path(pattern.to_string())))
+    }
+
+    #[test]
+    fn test_prefixes_only_from_required_path_tests() {
+        let required = Expr::And(Box::new(Expr::Test(Test::True)), path_test("./src/a*"));
+        assert_eq!(PathPruner::new(&required, false).unwrap().prefixes, vec!["./src/a"]);
+
+        assert!(PathPruner::new(&Expr::Or(path_test("./src/*"), path_test("./b/*")), false).is_none());
+        assert!(PathPruner::new(&Expr::Not(path_test("./src/*")), false).is_none());
+        assert!(PathPruner::new(&Expr::Test(Test::Ipath("./src/*".to_string())), false).is_none());
+        assert!(PathPruner::new(&Expr::Test(Test::Path("*.rs".to_string())), false).is_none());
+
+        let printf = Box::new(Expr::Action(Action::Printf(vec![FormatDirective::Path])));
+        assert!(PathPruner::new(&Expr::And(printf, path_test("./src/*")), false).is_none());
+    }
+
+    #[test]
+    fn test_on_prefix() {
+        assert!(PathPruner::on_prefix("./src", "./src/specific/"));
+        assert!(PathPruner::on_prefix("./src/specific", "./src/specific/"));
+        assert!(PathPruner::on_prefix("./src/specific/deeper", "./src/specific/"));
+        assert!(PathPruner::on_prefix("dir/", "dir/sub"));
+        assert!(!PathPruner::on_prefix("./tests", "./src/specific/"));
+        assert!(!PathPruner::on_prefix("./src/spec", "./src/specific/"));
+    }
+
+    #[test]
+    fn test_pruned_directories_are_not_descended() {
+        let temp_dir = TempDir::new().unwrap();
+        for dir in ["keep/inner", "skip/inner", "kee"] {
+            fs::create_dir_all(temp_dir.path().join(dir)).unwrap();
+        }
+        fs::write(temp_dir.path().join("skip/inner/file.txt"), "").unwrap();
+
+        let pattern = format!("{}/keep/*", temp_dir.path().display());
+        let pruner = PathPruner::new(&Expr::Test(Test::Path(pattern)), false).unwrap();
+
+        let mut visited: Vec<String> = WalkDir::new(temp_dir.path())
+            .into_iter()
+            .filter_entry(|e| pruner.may_contain_matches(e))
+            .filter_map(|e| e.ok())
+            .map(|e| e.path().strip_prefix(temp_dir.path()).unwrap().display().to_string())
+            .collect();
+        visited.sort();
+
+        assert_eq!(visited, vec!["", "keep", "keep/inner"]);
+    }
+
+    #[test]
+    fn test_dot_slash_is_only_stripped_when_asked() {
+        let walk_a = |pruner: &PathPruner| {
+            WalkDir::new("./tests/inputs")
+                .into_iter()
+                .filter_map(|e| e.ok())
+                .filter(|e| e.path().ends_with("a") && e.file_type().is_dir())
+                .all(|e| pruner.may_contain_matches(&e))
+        };
+
+        let expr = Expr::Test(Test::Path("tests/inputs/a/*".to_string()));
+        assert!(!walk_a(&PathPruner::new(&expr, false).unwrap()));
+        assert!(walk_a(&PathPruner::new(&expr, true).unwrap()));
+    }
+
+    #[test]
+    fn test_excluded_directories_are_not_descended() {
+        let temp_dir = TempDir::new().unwrap();
+        for dir in ["target/debug", "crate/target/debug", "src"] {
+            fs::create_dir_all(temp_dir.path().join(dir)).unwrap();
+        }
+        fs::write(temp_dir.path().join("src/lib.rs"), "").unwrap();
+
+        assert!(Excludes::new(&[], false).is_none());
+        let excludes = Excludes::new(&["*/target/*".to_string(), "*.rs".to_string()], false).unwrap();
+
+        // Count errors too, so an unreadable excluded directory would still show up
+        let mut visited: Vec<String> = WalkDir::new(temp_dir.path())
+            .into_iter()
+            .filter_entry(|e| excludes.allows(e))
+            .map(|e| e.unwrap().path().strip_prefix(temp_dir.path()).unwrap().display().to_string())
+            .collect();
+        visited.sort();
+
+        // The target directories themselves don't match */target/*, but nothing in them is visited
+        assert_eq!(visited, vec!["", "crate", "crate/target", "src", "target"]);
+
+        // Contents first, the excluded directories are only reached after what they hold,
+        // so allows_within has to turn that away as well
+        let mut visited: Vec<String> = WalkDir::new(temp_dir.path())
+            .contents_first(true)
+            .into_iter()
+            .filter_entry(|e| excludes.allows_within(e))
+            .map(|e| e.unwrap().path().strip_prefix(temp_dir.path()).unwrap().display().to_string())
+            .collect();
+        visited.sort();
+        assert_eq!(visited, vec!["", "crate", "crate/target", "src", "target"]);
+    }
+}
@@ -0,0 +1,365 @@
+//! Regular expression dialects for `-regextype`, translated to the syntax of the regex
+//! crate before compiling.
+
+/// A syntax -regex and -iregex patterns can be written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RegexType {
+    /// The regex crate's own syntax, findr's default. It is close to posix-extended,
+    /// with Perl classes such as `\d` as well
+    #[default]
+    Native,
+    /// GNU Emacs syntax, GNU find's default: `\(` `\)` group and `\|` alternates, while
+    /// `+`, `?` and `*` are operators and there are no intervals
+    Emacs,
+    /// POSIX basic syntax with the GNU extensions, as grep, sed and ed use it: `\(` `\)`,
+    /// `\{m,n\}`, `\|`, `\+` and `\?` are the operators, and unescaped they are literal
+    Basic,
+    /// POSIX extended syntax, as egrep uses it: `(`, `|`, `{m,n}`, `+` and `?` unescaped
+    Extended,
+    /// Extended syntax as awk uses it, where a backslash also escapes within brackets
+    Awk,
+}
+
+/// The names -regextype accepts, those of GNU find
+pub const REGEX_TYPES: &[&str] = &[
+    "native", "findutils-default", "emacs", "grep", "ed", "sed", "posix-basic",
+    "posix-minimal-basic", "egrep", "posix-egrep", "posix-extended", "awk", "gnu-awk",
+    "posix-awk",
+];
+
+impl RegexType {
+    /// Looks up a dialect by its GNU find name, or "native" for findr's own
+    pub fn from_name(name: &str) -> Option<RegexType> {
+        match name {
+            "native" => Some(RegexType::Native),
+            "findutils-default" | "emacs" => Some(RegexType::Emacs),
+            "grep" | "ed" | "sed" | "posix-basic" | "posix-minimal-basic" => Some(RegexType::Basic),
+            "egrep" | "posix-egrep" | "posix-extended" => Some(RegexType::Extended),
+            "awk" | "gnu-awk" | "posix-awk" => Some(RegexType::Awk),
+            _ => None,
+        }
+    }
+
+    /// Rewrites a pattern in this dialect as the regex crate spells it, or explains what
+    /// is wrong with it. Back-references, which the regex crate can't match, are errors.
+    pub fn translate(self, pattern: &str) -> Result<String, String> {
+        if self == RegexType::Native {
+            return Ok(pattern.to_string());
+        }
+        Translator { dialect: self, chars: pattern.chars().collect(), pos: 0, out: String::new() }.run()
+    }
+
+    // Whether `(`, `)`, `|`, `{` and `}` are operators unescaped, rather than escaped
+    fn bare_operators(self) -> bool {
+        matches!(self, RegexType::Extended | RegexType::Awk)
+    }
+}
+
+struct Translator {
+    dialect: RegexType,
+    chars: Vec<char>,
+    pos: usize,
+    out: String,
+}
+
+impl Translator {
+    fn run(mut self) -> Result<String, String> {
+        // Where an expression starts, `*` has nothing to repeat and, outside extended
+        // syntax, is literal; `^` is an anchor there and in basic syntax literal elsewhere
+        let mut at_start = true;
+        while let Some(c) = self.next() {
+            let starts_next = match c {
+                '\\' => self.escape()?,
+                '[' => {
+                    self.bracket()?;
+                    false
+                }
+                '*' if at_start => {
+                    self.out.push_str(r"\*");
+                    false
+                }
+                '^' if at_start || self.dialect.bare_operators() => {
+                    self.out.push('^');
+                    true
+                }
+                '$' if self.at_end_of_branch() || self.dialect.bare_operators() => {
+                    self.out.push('$');
+                    false
+                }
+                '^' | '$' => {
+                    self.out.push('\\');
+                    self.out.push(c);
+                    false
+                }
+                '(' | '|' if self.dialect.bare_operators() => {
+                    self.out.push(c);
+                    true
+                }
+                ')' if self.dialect.bare_operators() => {
+                    self.out.push(c);
+                    false
+                }
+                '{' if self.dialect.bare_operators() && self.interval_follows() => {
+                    self.copy_interval();
+                    false
+                }
+                '.' => {
+                    self.out.push('.');
+                    false
+                }
+                '*' => {
+                    self.out.push('*');
+                    false
+                }
+                '+' | '?' if self.dialect != RegexType::Basic => {
+                    if at_start {
+                        self.out.push('\\');
+                    }
+                    self.out.push(c);
+                    false
+                }
+                _ => {
+                    self.push_literal(c);
+                    false
+                }
+            };
+            at_start = starts_next;
+        }
+        Ok(self.out)
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.chars.get(self.pos).copied();
+        self.pos += 1;
+        c
+    }
+
+    fn peek(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.pos + offset).copied()
+    }
+
+    // A `$` ends the expression, a group or an alternative, so it is an anchor
+    fn at_end_of_branch(&self) -> bool {
+        match (self.peek(0), self.peek(1)) {
+            (None, _) => true,
+            (Some('\\'), Some(')' | '|')) => !self.dialect.bare_operators(),
+            _ => false,
+        }
+    }
+
+    // The text after a `\`. Returns whether what follows starts a new expression
+    fn escape(&mut self) -> Result<bool, String> {
+        let c = self.next().ok_or("trailing backslash")?;
+        let grouping = !self.dialect.bare_operators();
+        match c {
+            '(' | '|' if grouping => self.out.push(c),
+            ')' if grouping => self.out.push(c),
+            '{' if self.dialect == RegexType::Basic => {
+                if !self.interval_follows() {
+                    return Err("invalid interval after \\{".to_string());
+                }
+                self.copy_interval();
+            }
+            '+' | '?' if self.dialect == RegexType::Basic => self.out.push(c),
+            '<' => self.out.push_str(r"\b{start}"),
+            '>' => self.out.push_str(r"\b{end}"),
+            '`' => self.out.push_str(r"\A"),
+            '\'' => self.out.push_str(r"\z"),
+            'w' | 'W' | 'b' | 'B' | 's' | 'S' => {
+                self.out.push('\\');
+                self.out.push(c);
+            }
+            '1'..='9' => return Err(format!("back-reference \\{} is not supported", c)),
+            _ => self.push_literal(c),
+        }
+        Ok(grouping && matches!(c, '(' | '|'))
+    }
+
+    // Whether `m}`, `m,}` or `m,n}` comes next, the rest of an interval, with `\}` to
+    // close it in basic syntax
+    fn interval_follows(&self) -> bool {
+        let mut offset = 0;
+        let mut digits = 0;
+        while let Some(c) = self.peek(offset) {
+            match c {
+                '0'..='9' | ',' => {
+                    digits += usize::from(c != ',');
+                    offset += 1;
+                }
+                '\\' if self.dialect == RegexType::Basic => return digits > 0 && self.peek(offset + 1) == Some('}'),
+                '}' if self.dialect != RegexType::Basic => return digits > 0,
+                _ => return false,
+            }
+        }
+        false
+    }
+
+    fn copy_interval(&mut self) {
+        self.out.push('{');
+        while let Some(c) = self.next() {
+            match c {
+                '\\' => {}
+                '}' => break,
+                _ => self.out.push(c),
+            }
+        }
+        self.out.push('}');
+    }
+
+    fn push_literal(&mut self, c: char) {
+        self.out.push_str(&regex::escape(c.encode_utf8(&mut [0; 4])));
+    }
+
+    // Copies a bracket expression, whose `[` has been read. A `]` first is a member,
+    // POSIX classes are kept, and only awk lets a backslash escape. Characters the regex
+    // crate treats specially in a class are escaped
+    fn bracket(&mut self) -> Result<(), String> {
+        self.out.push('[');
+        if self.peek(0) == Some('^') {
+            self.pos += 1;
+            self.out.push('^');
+        }
+        let mut first = true;
+        loop {
+            let c = self.next().ok_or("unmatched [")?;
+            match c {
+                ']' if !first => break,
+                '[' if matches!(self.peek(0), Some(':' | '=' | '.')) => self.bracket_class()?,
+                '\\' if self.dialect == RegexType::Awk => {
+                    let escaped = self.next().ok_or("unmatched [")?;
+                    self.push_member(escaped);
+                }
+                '-' if !first && self.peek(0) != Some(']') => self.out.push('-'),
+                _ => self.push_member(c),
+            }
+            first = false;
+        }
+        self.out.push(']');
+        Ok(())
+    }
+
+    // `[:alpha:]` is passed on; `[=c=]` and `[.c.]` name the single character c
+    fn bracket_class(&mut self) -> Result<(), String> {
+        let kind = self.next().ok_or("unmatched [")?;
+        let start = self.pos;
+        while !(self.peek(0) == Some(kind) && self.peek(1) == Some(']')) {
+            if self.next().is_none() {
+                return Err("unmatched [".to_string());
+            }
+        }
+        let name: String = self.chars[start..self.pos].iter().collect();
+        self.pos += 2;
+        match kind {
+            ':' => self.out.push_str(&format!("[:{}:]", name)),
+            _ => {
+                let mut chars = name.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => self.push_member(c),
+                    _ => return Err(format!("unsupported collating element [{}{}{}]", kind, name, kind)),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn push_member(&mut self, c: char) {
+        if matches!(c, '\\' | '[' | ']' | '^' | '-' | '&' | '~') {
+            self.out.push('\\');
+        }
+        self.out.push(c);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regex::Regex;
+
+    fn translate(dialect: &str, pattern: &str) -> String {
+        RegexType::from_name(dialect).unwrap().translate(pattern).unwrap()
+    }
+
+    // Whether the pattern, in the dialect, matches the whole of `path`
+    fn matches(dialect: &str, pattern: &str, path: &str) -> bool {
+        Regex::new(&format!("^(?:{})$", translate(dialect, pattern))).unwrap().is_match(path)
+    }
+
+    #[test]
+    fn test_names() {
+        assert_eq!(RegexType::from_name("findutils-default"), Some(RegexType::Emacs));
+        assert_eq!(RegexType::from_name("posix-minimal-basic"), Some(RegexType::Basic));
+        assert_eq!(RegexType::from_name("posix-egrep"), Some(RegexType::Extended));
+        assert_eq!(RegexType::from_name("gnu-awk"), Some(RegexType::Awk));
+        assert_eq!(RegexType::from_name("perl"), None);
+        for name in REGEX_TYPES {
+            assert!(RegexType::from_name(name).is_some(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_emacs() {
+        assert_eq!(translate("emacs", r".*\.\(rs\|toml\)"), r".*\.(rs|toml)");
+        assert!(matches("emacs", r".*\.\(rs\|toml\)", "./src/main.rs"));
+        assert!(matches("emacs", r"./Cargo\.toml", "./Cargo.toml"));
+        assert!(!matches("emacs", r".*\.\(rs\|toml\)", "./README.md"));
+        // Unescaped parentheses, bars and braces are just characters
+        assert!(matches("emacs", "./(a|b){1}", "./(a|b){1}"));
+        assert!(matches("emacs", "./a+b?", "./aab"));
+        // A leading * is literal
+        assert!(matches("emacs", "*x", "*x"));
+        assert!(matches("emacs", r".*\<main\.rs", "./src/main.rs"));
+        assert!(!matches("emacs", r".*\<ain\.rs", "./src/main.rs"));
+    }
+
+    #[test]
+    fn test_basic() {
+        assert_eq!(translate("grep", r"a\{2,3\}\(b\|c\)"), "a{2,3}(b|c)");
+        assert!(matches("grep", r".*/a\{2\}\.txt", "./aa.txt"));
+        assert!(!matches("grep", r".*/a\{2\}\.txt", "./aaa.txt"));
+        assert!(matches("sed", r".*/x\+", "./xxx"));
+        // Unescaped operators are literal
+        assert!(matches("posix-basic", "./a+b?(c)", "./a+b?(c)"));
+        assert!(matches("ed", "./a{1}", "./a{1}"));
+        // ^ only anchors at the start, and $ at the end
+        assert!(matches("grep", r"^./a^b$c$", "./a^b$c"));
+        assert!(matches("grep", r"\(^./a\|^./b\)$", "./b"));
+    }
+
+    #[test]
+    fn test_extended() {
+        assert_eq!(translate("posix-extended", r".*\.(rs|toml)"), r".*\.(rs|toml)");
+        assert!(matches("egrep", "./a{2}(b|c)+", "./aabcb"));
+        // A brace that doesn't start an interval is literal
+        assert!(matches("posix-egrep", "./a{b}", "./a{b}"));
+        // Escaped operators are literal
+        assert!(matches("egrep", r"./\(a\)", "./(a)"));
+    }
+
+    #[test]
+    fn test_brackets() {
+        // A backslash is a member of the list, except in awk
+        assert!(matches("emacs", r"./[\]x", r"./\x"));
+        assert!(matches("egrep", r"./[\n]", "./n"));
+        assert!(matches("awk", r"./[\]]", "./]"));
+        assert!(matches("grep", "./[]a]", "./]"));
+        assert!(matches("grep", "./[^]a]", "./b"));
+        assert!(matches("grep", "./[[:digit:]-]*", "./1-2"));
+        assert!(matches("grep", "./[a-c&~]*", "./b&~"));
+        assert!(matches("grep", "./[[=x=][.y.]]*", "./xy"));
+    }
+
+    #[test]
+    fn test_errors() {
+        for (dialect, pattern) in [
+            ("emacs", r"\(a\)\1"),
+            ("grep", "[abc"),
+            ("grep", r"a\{x\}"),
+            ("egrep", "a\\"),
+            ("grep", "[[:alpha:]"),
+        ] {
+            assert!(RegexType::from_name(dialect).unwrap().translate(pattern).is_err(), "{} {}", dialect, pattern);
+        }
+        // The native syntax is passed on untouched
+        assert_eq!(RegexType::Native.translate(r"\d+\1").unwrap(), r"\d+\1");
+    }
+}
@@ -0,0 +1,162 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Seek, SeekFrom, Write};
+
+/// Sorts output lines for `--sort`.
+///
+/// Lines are buffered in memory until the buffer reaches `threshold` entries, at which
+/// point the buffer is sorted and spilled to an anonymous temp file as a sorted run.
+/// Small result sets never touch the disk; large ones are finished with a k-way merge
+/// of the runs, so memory use stays bounded by the threshold.
+pub struct ExternalSorter {
+    buffer: Vec<Vec<u8>>,
+    threshold: usize,
+    runs: Vec<File>,
+}
+
+impl ExternalSorter {
+    /// Creates a sorter that spills to disk once `threshold` lines are buffered
+    pub fn new(threshold: usize) -> Self {
+        ExternalSorter {
+            buffer: Vec::new(),
+            threshold: threshold.max(1),
+            runs: Vec::new(),
+        }
+    }
+
+    /// Adds a line, spilling the buffer to a sorted run if it is full. Lines are bytes,
+    /// so paths that aren't UTF-8 sort and print as they are
+    pub fn push(&mut self, line: impl Into<Vec<u8>>) -> io::Result<()> {
+        self.buffer.push(line.into());
+        if self.buffer.len() >= self.threshold {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    /// Writes all lines in sorted order, one per line
+    pub fn finish<W: Write>(mut self, out: &mut W) -> io::Result<()> {
+        if self.runs.is_empty() {
+            // Fast path: everything fit in memory
+            self.buffer.sort_unstable();
+            for line in &self.buffer {
+                out.write_all(line)?;
+                out.write_all(b"\n")?;
+            }
+            return Ok(());
+        }
+
+        if !self.buffer.is_empty() {
+            self.spill()?;
+        }
+        Self::merge(self.runs, out)
+    }
+
+    // Sorts the buffer and writes it to a temp file. Runs are NUL-separated since
+    // paths can legitimately contain newlines but never NUL bytes.
+    fn spill(&mut self) -> io::Result<()> {
+        self.buffer.sort_unstable();
+        let mut file = tempfile::tempfile()?;
+        {
+            let mut writer = BufWriter::new(&mut file);
+            for line in self.buffer.drain(..) {
+                writer.write_all(&line)?;
+                writer.write_all(b"\0")?;
+            }
+            writer.flush()?;
+        }
+        file.seek(SeekFrom::Start(0))?;
+        self.runs.push(file);
+        Ok(())
+    }
+
+    // k-way merge of the sorted runs using a min-heap keyed by each run's next line
+    fn merge<W: Write>(runs: Vec<File>, out: &mut W) -> io::Result<()> {
+        let mut readers: Vec<BufReader<File>> = runs.into_iter().map(BufReader::new).collect();
+        let mut heap = BinaryHeap::new();
+
+        for (index, reader) in readers.iter_mut().enumerate() {
+            if let Some(line) = Self::read_line(reader)? {
+                heap.push(Reverse((line, index)));
+            }
+        }
+
+        while let Some(Reverse((line, index))) = heap.pop() {
+            out.write_all(&line)?;
+            out.write_all(b"\n")?;
+            if let Some(next) = Self::read_line(&mut readers[index])? {
+                heap.push(Reverse((next, index)));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn read_line(reader: &mut BufReader<File>) -> io::Result<Option<Vec<u8>>> {
+        let mut bytes = Vec::new();
+        if reader.read_until(0, &mut bytes)? == 0 {
+            return Ok(None);
+        }
+        if bytes.last() == Some(&0) {
+            bytes.pop();
+        }
+        Ok(Some(bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted_output(lines: &[&str], threshold: usize) -> (String, usize) {
+        let mut sorter = ExternalSorter::new(threshold);
+        for line in lines {
+            sorter.push(line.to_string()).unwrap();
+        }
+        let runs = sorter.runs.len();
+        let mut out = Vec::new();
+        sorter.finish(&mut out).unwrap();
+        (String::from_utf8(out).unwrap(), runs)
+    }
+
+    #[test]
+    fn test_in_memory_sort() {
+        let (out, runs) = sorted_output(&["c", "a", "b"], 100);
+        assert_eq!(runs, 0);
+        assert_eq!(out, "a\nb\nc\n");
+    }
+
+    #[test]
+    fn test_spilled_sort() {
+        let lines = ["q", "d", "x", "a", "m", "z", "b", "k", "a"];
+        let (out, runs) = sorted_output(&lines, 2);
+        assert_eq!(runs, 4);
+        assert_eq!(out, "a\na\nb\nd\nk\nm\nq\nx\nz\n");
+    }
+
+    #[test]
+    fn test_spilled_sort_preserves_newlines_in_paths() {
+        let (out, runs) = sorted_output(&["b\nc", "a", "c"], 1);
+        assert_eq!(runs, 3);
+        assert_eq!(out, "a\nb\nc\nc\n");
+    }
+
+    #[test]
+    fn test_spilled_sort_keeps_bytes() {
+        let mut sorter = ExternalSorter::new(1);
+        for line in [&b"b\xff"[..], b"a\xfe", b"a"] {
+            sorter.push(line).unwrap();
+        }
+        let mut out = Vec::new();
+        sorter.finish(&mut out).unwrap();
+        assert_eq!(out, b"a\na\xfe\nb\xff\n");
+    }
+
+    #[test]
+    fn test_empty_sort() {
+        let (out, runs) = sorted_output(&[], 1);
+        assert_eq!(runs, 0);
+        assert_eq!(out, "");
+    }
+}
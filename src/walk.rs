@@ -0,0 +1,204 @@
+use std::cell::RefCell;
+use std::path::Path;
+use std::sync::Arc;
+use walkdir::{DirEntry, WalkDir};
+use crate::filesystem::FileSystem;
+use crate::options::{SymlinkMode, WalkOrder};
+
+/// Builds the directory walk for one start path.
+///
+/// `-maxdepth` is applied here with `WalkDir::max_depth` rather than by filtering
+/// entries afterwards, so directories at the limit are never opened and nothing
+/// below them is read or stat'ed.
+///
+/// With `WalkOrder::Name` each directory's children are sorted as it is read. Only
+/// one directory's entries are held at a time, unlike `--sort`, which buffers every
+/// result; the output is ordered within each directory rather than as a whole.
+///
+/// With `contents_first`, as for `-delete`, each directory comes after everything
+/// below it rather than before.
+pub fn walk_dir(
+    dir: &Path,
+    symlinks: SymlinkMode,
+    max_depth: Option<usize>,
+    order: WalkOrder,
+    contents_first: bool,
+) -> WalkDir {
+    let mut walker = WalkDir::new(dir)
+        .follow_links(symlinks == SymlinkMode::Always)
+        .contents_first(contents_first);
+    if let Some(depth) = max_depth {
+        walker = walker.max_depth(depth);
+    }
+    match order {
+        WalkOrder::Name => walker.sort_by_file_name(),
+        WalkOrder::None => walker,
+    }
+}
+
+/// The device a start path is on, which `-xdev` keeps the walk below it to.
+///
+/// Like GNU find, each start path is its own boundary: `findr / /mnt/usb -xdev` walks
+/// both, each without leaving its device. A directory on another device is still
+/// tested, as the mount point it is, but not descended into. A bind mount of the same
+/// file system has the same device id and is crossed, as with GNU find. Device ids are
+/// only read on Unix, so elsewhere every directory is on the same device.
+pub struct DeviceBoundary {
+    device: u64,
+    file_system: Arc<dyn FileSystem>,
+    /// Whether each directory skipped is noted, for `-D search`
+    reporting: bool,
+    /// Notes on the directories skipped, kept until collected with take_notes
+    notes: RefCell<Vec<String>>,
+}
+
+impl DeviceBoundary {
+    /// Takes the device of the start path, following it if it is a symlink as the walk
+    /// does. None if it can't be stat'ed, in which case the walk reports the error
+    pub fn new(start: &Path, file_system: Arc<dyn FileSystem>) -> Option<Self> {
+        let device = file_system.metadata(start).ok()?.dev;
+        Some(DeviceBoundary { device, file_system, reporting: false, notes: RefCell::new(Vec::new()) })
+    }
+
+    /// The device of the start path
+    pub fn device(&self) -> u64 {
+        self.device
+    }
+
+    /// Notes each mount point the walk doesn't descend into, naming it and its device
+    pub fn with_reporting(mut self) -> Self {
+        self.reporting = true;
+        self
+    }
+
+    /// Returns the notes made since the last call, if reporting
+    pub fn take_notes(&self) -> Vec<String> {
+        self.notes.take()
+    }
+
+    /// Whether the walk should not descend into this entry: a directory below the start
+    /// path on another device. One that can't be stat'ed is left to the walk, which
+    /// reports the error when it fails to read it
+    pub fn excludes(&self, entry: &DirEntry) -> bool {
+        if entry.depth() == 0 || !entry.file_type().is_dir() {
+            return false;
+        }
+        // A directory reached through a followed symlink is on its target's device
+        let metadata = if entry.path_is_symlink() {
+            self.file_system.metadata(entry.path())
+        } else {
+            self.file_system.symlink_metadata(entry.path())
+        };
+        match metadata {
+            Ok(metadata) if metadata.dev != self.device => {
+                if self.reporting {
+                    self.notes.borrow_mut().push(format!(
+                        "{}: not descending into a mount point on device {}, the start path is on device {}",
+                        entry.path().display(),
+                        metadata.dev,
+                        self.device
+                    ));
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_max_depth_stops_descent() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir_all(temp_dir.path().join("1/2/3/4")).unwrap();
+        let root = temp_dir.path();
+
+        // Count everything the walk yields, errors included, to show that nothing
+        // below the limit is visited at all
+        let visited: Vec<usize> = walk_dir(root, SymlinkMode::Never, Some(2), WalkOrder::None, false)
+            .into_iter()
+            .map(|entry| entry.unwrap().depth())
+            .collect();
+        assert_eq!(visited, vec![0, 1, 2]);
+
+        assert_eq!(walk_dir(root, SymlinkMode::Never, None, WalkOrder::None, false).into_iter().count(), 5);
+        assert_eq!(walk_dir(root, SymlinkMode::Never, Some(0), WalkOrder::None, false).into_iter().count(), 1);
+
+        // Contents first, each directory follows everything below it
+        let visited: Vec<usize> = walk_dir(root, SymlinkMode::Never, Some(2), WalkOrder::None, true)
+            .into_iter()
+            .map(|entry| entry.unwrap().depth())
+            .collect();
+        assert_eq!(visited, vec![2, 1, 0]);
+    }
+
+    #[test]
+    fn test_name_order_sorts_children() {
+        let temp_dir = TempDir::new().unwrap();
+        for name in ["b", "a/z", "a/m", "c", "Z"] {
+            let path = temp_dir.path().join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+        let root = temp_dir.path();
+
+        let visited: Vec<String> = walk_dir(root, SymlinkMode::Never, None, WalkOrder::Name, false)
+            .into_iter()
+            .skip(1)
+            .map(|entry| {
+                let entry = entry.unwrap();
+                entry.path().strip_prefix(temp_dir.path()).unwrap().to_string_lossy().replace('\\', "/")
+            })
+            .collect();
+        // Each directory's children are visited in byte order, depth first
+        assert_eq!(visited, vec!["Z", "a", "a/m", "a/z", "b", "c"]);
+    }
+
+    #[test]
+    fn test_device_boundary_is_per_start_path() {
+        use crate::ast::FileType;
+        use crate::filesystem::{FileStat, MockFileSystem};
+
+        let dir = |dev| FileStat { file_type: Some(FileType::Directory), dev, ..FileStat::default() };
+        // tests/inputs is a mount of device 2 on device 1, with device 1 mounted again
+        // on tests/inputs/d
+        let mock: Arc<dyn FileSystem> = Arc::new(MockFileSystem::new()
+            .with_file("tests", dir(1))
+            .with_file("tests/inputs", dir(2))
+            .with_file("tests/inputs/a", dir(2))
+            .with_file("tests/inputs/d", dir(1)));
+        let entry = |start: &str, path: &str| {
+            WalkDir::new(start).into_iter()
+                .map(|entry| entry.unwrap())
+                .find(|entry| entry.path() == Path::new(path))
+                .unwrap()
+        };
+
+        let from_tests = DeviceBoundary::new(Path::new("tests"), mock.clone()).unwrap();
+        assert!(from_tests.excludes(&entry("tests", "tests/inputs")));
+        // Files aren't descended into whatever their device
+        assert!(!from_tests.excludes(&entry("tests", "tests/cli.rs")));
+
+        // Started from the mount point, its own device is the boundary, not its parent's
+        let from_inputs = DeviceBoundary::new(Path::new("tests/inputs"), mock.clone()).unwrap();
+        assert!(!from_inputs.excludes(&entry("tests/inputs", "tests/inputs")));
+        assert!(!from_inputs.excludes(&entry("tests/inputs", "tests/inputs/a")));
+        assert!(from_inputs.excludes(&entry("tests/inputs", "tests/inputs/d")));
+        assert!(DeviceBoundary::new(Path::new("missing"), mock.clone()).is_none());
+
+        // Skipped mount points are only noted when asked for
+        assert!(from_tests.take_notes().is_empty());
+        let reporting = DeviceBoundary::new(Path::new("tests"), mock).unwrap().with_reporting();
+        assert!(!reporting.excludes(&entry("tests", "tests/cli.rs")));
+        assert!(reporting.take_notes().is_empty());
+        assert!(reporting.excludes(&entry("tests", "tests/inputs")));
+        let note = "not descending into a mount point on device 2, the start path is on device 1";
+        assert_eq!(reporting.take_notes(), [format!("{}: {}", Path::new("tests/inputs").display(), note)]);
+        assert!(reporting.take_notes().is_empty());
+    }
+}
@@ -30,7 +30,7 @@ fn skips_bad_dir() -> Result<()> {
     Command::cargo_bin(PRG)?
         .arg(&bad)
         .assert()
-        .success()
+        .failure()
         .stderr(predicate::str::is_match(expected)?);
     Ok(())
 }
@@ -60,14 +60,14 @@ fn dies_bad_type() -> Result<()> {
 
 // --------------------------------------------------
 #[cfg(windows)]
-fn format_file_name(expected_file: &str) -> Cow<str> {
+fn format_file_name(expected_file: &str) -> Cow<'_, str> {
     // Equivalent to: Cow::Owned(format!("{}.windows", expected_file))
     format!("{}.windows", expected_file).into()
 }
 
 // --------------------------------------------------
 #[cfg(not(windows))]
-fn format_file_name(expected_file: &str) -> Cow<str> {
+fn format_file_name(expected_file: &str) -> Cow<'_, str> {
     // Equivalent to: Cow::Borrowed(expected_file)
     expected_file.into()
 }
@@ -312,3 +312,1321 @@ fn unreadable_dir() -> Result<()> {
     assert!(stderr.contains("cant-touch-this: Permission denied"));
     Ok(())
 }
+
+// --------------------------------------------------
+#[test]
+fn name_a_escaped_parens() -> Result<()> {
+    run(
+        &["tests/inputs", "\\(", "-name", "a*", "\\)"],
+        "tests/expected/name_a.txt",
+    )
+}
+
+// --------------------------------------------------
+fn run_sorted(args: &[&str], expected_file: &str) -> Result<()> {
+    let file = format_file_name(expected_file);
+    let contents = fs::read_to_string(file.as_ref())?;
+    let mut expected: Vec<&str> =
+        contents.split('\n').filter(|s| !s.is_empty()).collect();
+    expected.sort();
+
+    let cmd = Command::cargo_bin(PRG)?.args(args).assert().success();
+    let out = cmd.get_output();
+    let stdout = String::from_utf8(out.stdout.clone())?;
+    let lines: Vec<&str> =
+        stdout.split('\n').filter(|s| !s.is_empty()).collect();
+
+    assert_eq!(lines, expected);
+
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn sort_in_memory() -> Result<()> {
+    run_sorted(&["tests/inputs", "--sort"], "tests/expected/path1.txt")
+}
+
+// --------------------------------------------------
+#[test]
+fn sort_spills_to_disk() -> Result<()> {
+    run_sorted(
+        &["--sort", "--sort-buffer", "2", "tests/inputs"],
+        "tests/expected/path1.txt",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn printf_inode() -> Result<()> {
+    use std::os::unix::fs::MetadataExt;
+
+    let file = "tests/inputs/g.csv";
+    let expected = format!("{}\t{}\n", fs::metadata(file)?.ino(), file);
+    Command::cargo_bin(PRG)?
+        .args([file, "-printf", "%i\\t%p\\n"])
+        .assert()
+        .success()
+        .stdout(expected);
+
+    // The format is one argument, spaces and all
+    let meta = fs::metadata(file)?;
+    let expected = format!("{} {} ({})\n", file, meta.ino(), meta.nlink());
+    Command::cargo_bin(PRG)?
+        .args([file, "-printf", "%p %i (%n)\\n"])
+        .assert()
+        .success()
+        .stdout(expected);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_regex() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs", "-regex", "a[b"])
+        .assert()
+        .failure()
+        .stdout("")
+        .stderr(predicate::str::contains("invalid regex: a[b"));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn unique_overlapping_paths() -> Result<()> {
+    let cmd = Command::cargo_bin(PRG)?
+        .args(["tests/inputs/a", "tests/inputs/a", "--unique", "-name", "*.txt"])
+        .assert()
+        .success();
+    let stdout = String::from_utf8(cmd.get_output().stdout.clone())?;
+    assert_eq!(stdout, "tests/inputs/a/a.txt\n");
+
+    // So is what actions print, and commands run only once
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs/a", "tests/inputs/a", "--unique", "-name", "a.txt", "-print"])
+        .assert()
+        .success()
+        .stdout("tests/inputs/a/a.txt\n");
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs/a", "tests/inputs/a", "--unique", "-name", "a.txt", "-printf", "%f\\n"])
+        .args(["-exec", "echo", "ran", "{}", ";"])
+        .assert()
+        .success()
+        .stdout("a.txt\nran tests/inputs/a/a.txt\n");
+
+    // Without --unique each start path is walked and printed in full
+    let cmd = Command::cargo_bin(PRG)?
+        .args(["tests/inputs/a", "tests/inputs/a", "-name", "*.txt"])
+        .assert()
+        .success();
+    let stdout = String::from_utf8(cmd.get_output().stdout.clone())?;
+    assert_eq!(stdout, "tests/inputs/a/a.txt\ntests/inputs/a/a.txt\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn newer_reference_relative_to_cwd() -> Result<()> {
+    // The reference resolves against the working directory...
+    Command::cargo_bin(PRG)?
+        .current_dir("tests")
+        .args(["inputs/a", "-newer", "inputs/g.csv"])
+        .assert()
+        .success();
+
+    // ...not against the start path
+    Command::cargo_bin(PRG)?
+        .current_dir("tests")
+        .args(["inputs/a", "-newer", "a.txt"])
+        .assert()
+        .failure()
+        .stdout("")
+        .stderr(predicate::str::contains("-newer reference a.txt: "));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_missing_newer_reference() -> Result<()> {
+    let bad = gen_bad_file();
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs", "-newer", &bad])
+        .assert()
+        .failure()
+        .stdout("")
+        .stderr(predicate::str::contains(format!("-newer reference {}: ", bad)));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_missing_anewer_reference() -> Result<()> {
+    let bad = gen_bad_file();
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs", "-type", "f", "-o", "-anewer", &bad])
+        .assert()
+        .failure()
+        .stdout("")
+        .stderr(predicate::str::contains(format!("-anewer reference {}: ", bad)));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_dangling_operator() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs", "-name", "x", "-and"])
+        .assert()
+        .failure()
+        .stdout("")
+        .stderr(predicate::str::contains(
+            "binary operator '-and' with nothing after it",
+        ));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_missing_argument() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs", "-type", "f", "-name"])
+        .assert()
+        .failure()
+        .stdout("")
+        .stderr("findr: missing argument to `-name'\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn or_skips_actions_after_true_branch() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args([
+            "tests/inputs/a/a.txt",
+            "tests/inputs/a/b/b.csv",
+            "(", "-name", "a.txt", "-printf", "first:%f\\n", ")",
+            "-o", "-printf", "second:%f\\n",
+        ])
+        .assert()
+        .success()
+        .stdout("first:a.txt\nsecond:b.csv\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn and_skips_actions_after_false_branch() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs/a", "-false", "-printf", "ran:%p\\n"])
+        .assert()
+        .success()
+        .stdout("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn line_buffered() -> Result<()> {
+    run(&["--line-buffered", "tests/inputs"], "tests/expected/path1.txt")
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn follow_links_reports_loops() -> Result<()> {
+    let dir = tempfile::TempDir::new()?;
+    let sub = dir.path().join("sub");
+    fs::create_dir(&sub)?;
+    fs::write(sub.join("file.txt"), "")?;
+    std::os::unix::fs::symlink(dir.path(), sub.join("loop"))?;
+
+    let expected = format!(
+        "findr: File system loop detected; '{}' is part of the same file system loop as '{}'.\n",
+        sub.join("loop").display(),
+        dir.path().display()
+    );
+    Command::cargo_bin(PRG)?
+        .args(["-L", &dir.path().to_string_lossy(), "-name", "file.txt"])
+        .timeout(std::time::Duration::from_secs(10))
+        .assert()
+        .success()
+        .stdout(format!("{}\n", sub.join("file.txt").display()))
+        .stderr(expected);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn max_depth() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs", "-maxdepth", "1", "-type", "f"])
+        .assert()
+        .success()
+        .stdout("tests/inputs/g.csv\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn empty_start_path() -> Result<()> {
+    let dir = tempfile::TempDir::new()?;
+    let root = dir.path().to_string_lossy().to_string();
+
+    Command::cargo_bin(PRG)?
+        .args([&root, "-empty"])
+        .assert()
+        .success()
+        .stdout(format!("{}\n", root));
+
+    fs::write(dir.path().join("file.txt"), "content")?;
+    Command::cargo_bin(PRG)?
+        .args([&root, "-empty"])
+        .assert()
+        .success()
+        .stdout("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn printf_start_points() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args([
+            "tests/inputs/a",
+            "tests/inputs/f",
+            "-name",
+            "*.txt",
+            "-printf",
+            "%H|%P\\n",
+        ])
+        .assert()
+        .success()
+        .stdout("tests/inputs/a|a.txt\ntests/inputs/f|f.txt\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_unknown_custom_predicate() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs", "-custom", "nothing"])
+        .assert()
+        .failure()
+        .stdout("")
+        .stderr("findr: unknown custom predicate 'nothing'\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn prune_or_print() -> Result<()> {
+    run(
+        &["tests/prune", "-name", "skip", "-prune", "-o", "-type", "f", "-print"],
+        "tests/expected/prune_or_print.txt",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn prune_implicit_print() -> Result<()> {
+    // -prune is not an action, so the pruned directory itself is printed too
+    run(
+        &["tests/prune", "-name", "skip", "-prune", "-o", "-type", "f"],
+        "tests/expected/prune_implicit_print.txt",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn prune_only() -> Result<()> {
+    run(
+        &["tests/prune", "-name", "skip", "-prune", "-o", "-true"],
+        "tests/expected/prune_only.txt",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn explain() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs/a", "--explain", "-name", "a.txt", "-type", "f"])
+        .assert()
+        .success()
+        .stdout(format!("{}\n", Path::new("tests/inputs/a").join("a.txt").display()))
+        .stderr(predicate::str::contains(
+            "a.txt matched: -name a.txt, -type f\n",
+        ));
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs/a", "--explain", "-regex", ".*a.txt", "-size", "-2k"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("a.txt matched: -regex '.*a.txt', -size -2k\n"));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_oversized_regex() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs", "--regex-size-limit", "1000", "-regex", "[ab]{500}"])
+        .assert()
+        .failure()
+        .stdout("")
+        .stderr("findr: regex too large: compiled size exceeds the limit of 1000 bytes\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn printf_nul_separated() -> Result<()> {
+    let output = Command::cargo_bin(PRG)?
+        .args(["tests/inputs/a", "-name", "a.txt", "-printf", "%f\\0[\\t]\\0"])
+        .output()?;
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"a.txt\0[\t]\0");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn missing_start_path_among_others() -> Result<()> {
+    let bad = gen_bad_file();
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs/a/b/c", &bad, "tests/inputs/f", "-type", "f"])
+        .assert()
+        .failure()
+        .stdout(format!(
+            "{}\n{}\n",
+            Path::new("tests/inputs/a/b/c").join("c.mp3").display(),
+            Path::new("tests/inputs/f").join("f.txt").display()
+        ))
+        .stderr(predicate::str::is_match(format!(
+            "^findr: {}: .* [(]os error [23][)]\n$",
+            bad
+        ))?);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn start_paths_in_turn() -> Result<()> {
+    // Everything under one start path comes before anything under the next
+    let keep = Path::new("tests/prune/keep");
+    let f = Path::new("tests/inputs/f");
+    Command::cargo_bin(PRG)?
+        .args(["tests/prune/keep", "tests/inputs/f"])
+        .assert()
+        .success()
+        .stdout(format!(
+            "{}\n{}\n{}\n{}\n",
+            keep.display(),
+            keep.join("a.txt").display(),
+            f.display(),
+            f.join("f.txt").display()
+        ));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn start_paths_interleaved() -> Result<()> {
+    let keep = Path::new("tests/prune/keep");
+    let f = Path::new("tests/inputs/f");
+    Command::cargo_bin(PRG)?
+        .args(["--interleave", "tests/prune/keep", "tests/inputs/f"])
+        .assert()
+        .success()
+        .stdout(format!(
+            "{}\n{}\n{}\n{}\n",
+            keep.display(),
+            f.display(),
+            keep.join("a.txt").display(),
+            f.join("f.txt").display()
+        ));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn output_file() -> Result<()> {
+    let dir = tempfile::TempDir::new()?;
+    let out = dir.path().join("found.txt");
+    // The file is truncated, so nothing from an earlier run is left behind
+    fs::write(&out, "left over from an earlier scan, longer than the results\n")?;
+
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs/a", "--output-file", &out.to_string_lossy(), "-name", "*.txt"])
+        .assert()
+        .success()
+        .stdout("");
+    let a = Path::new("tests/inputs/a").join("a.txt");
+    assert_eq!(fs::read_to_string(&out)?, format!("{}\n", a.display()));
+
+    // -printf output goes to the file too, NULs and all
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs/a", "--output-file", &out.to_string_lossy(), "-type", "f", "-printf", "%f\\0"])
+        .assert()
+        .success()
+        .stdout("");
+    let mut names: Vec<String> = fs::read_to_string(&out)?
+        .split_terminator('\0')
+        .map(str::to_string)
+        .collect();
+    names.sort();
+    assert_eq!(names, ["a.txt", "b.csv", "c.mp3"]);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_unwritable_output_file() -> Result<()> {
+    let out = format!("{}/found.txt", gen_bad_file());
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs/a", "--output-file", &out])
+        .assert()
+        .failure()
+        .stdout("")
+        .stderr(predicate::str::starts_with(format!("findr: {}: ", out)));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn samefile_symlink_reference() -> Result<()> {
+    let dir = tempfile::TempDir::new()?;
+    let target = dir.path().join("target.txt");
+    let hard_link = dir.path().join("hard.txt");
+    let link = dir.path().join("link");
+    fs::write(&target, "")?;
+    fs::hard_link(&target, &hard_link)?;
+    std::os::unix::fs::symlink("target.txt", &link)?;
+    let root = dir.path().to_string_lossy();
+    let link_arg = link.to_string_lossy();
+
+    // Under -P (the default) the symlink is only the same file as itself
+    Command::cargo_bin(PRG)?
+        .args([&root, "-samefile", &link_arg])
+        .assert()
+        .success()
+        .stdout(format!("{}\n", link.display()));
+
+    // Under -L it stands for its target, which the link itself now also resolves to
+    let output = Command::cargo_bin(PRG)?
+        .args(["-L", &root, "--sort", "-samefile", &link_arg])
+        .output()?;
+    assert!(output.status.success());
+    let mut expected = [hard_link, link, target].map(|path| path.display().to_string());
+    expected.sort();
+    assert_eq!(String::from_utf8(output.stdout)?, format!("{}\n", expected.join("\n")));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_stray_word() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs", "-type", "f", "foo"])
+        .assert()
+        .failure()
+        .stdout("")
+        .stderr("findr: paths must precede expression: `foo'\n");
+
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs", "-frobnicate"])
+        .assert()
+        .failure()
+        .stderr("findr: unknown predicate `-frobnicate'\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn arguments_with_spaces_stay_whole() -> Result<()> {
+    let dir = tempfile::TempDir::new()?;
+    fs::write(dir.path().join("b c.txt"), "")?;
+    fs::write(dir.path().join("c.txt"), "")?;
+
+    Command::cargo_bin(PRG)?
+        .args([&dir.path().to_string_lossy(), "-name", "b c.txt"])
+        .assert()
+        .success()
+        .stdout(format!("{}\n", dir.path().join("b c.txt").display()));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn start_path_with_dash_inside() -> Result<()> {
+    let dir = tempfile::TempDir::new()?;
+    let root = dir.path().join("with-dash");
+    fs::create_dir(&root)?;
+    fs::write(root.join("file.txt"), "")?;
+
+    Command::cargo_bin(PRG)?
+        .args([&root.to_string_lossy(), "-type", "f"])
+        .assert()
+        .success()
+        .stdout(format!("{}\n", root.join("file.txt").display()));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn profile() -> Result<()> {
+    // Six entries reach -type, and only the three files go on to -name
+    let a = Path::new("tests/inputs/a").join("a.txt");
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs/a", "--profile", "-type", "f", "-name", "*.txt"])
+        .assert()
+        .success()
+        .stdout(format!("{}\n", a.display()))
+        .stderr(predicate::str::is_match(r"(?m)^predicate +evaluations +matches +time$")?)
+        .stderr(predicate::str::is_match(r"(?m)^-type +6 +3 +\S+$")?)
+        .stderr(predicate::str::is_match(r"(?m)^-name +3 +1 +\S+$")?);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn empty_patterns_match_nothing() -> Result<()> {
+    for predicate in ["-name", "-iname", "-path", "-ipath", "-regex", "-iregex"] {
+        Command::cargo_bin(PRG)?
+            .args(["tests/inputs/a", predicate, ""])
+            .assert()
+            .success()
+            .stdout("")
+            .stderr(format!("findr: warning: `{}' with an empty pattern matches nothing\n", predicate));
+    }
+
+    // The rest of the expression still applies
+    let a = Path::new("tests/inputs/a").join("a.txt");
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs/a", "-name", "", "-o", "-name", "a.txt"])
+        .assert()
+        .success()
+        .stdout(format!("{}\n", a.display()));
+
+    // Two quotes are a pattern like any other, not an empty one
+    let dir = tempfile::TempDir::new()?;
+    fs::write(dir.path().join("''"), "")?;
+    Command::cargo_bin(PRG)?
+        .args([&dir.path().to_string_lossy(), "-name", "''"])
+        .assert()
+        .success()
+        .stdout(format!("{}\n", dir.path().join("''").display()))
+        .stderr("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn regex_relative() -> Result<()> {
+    let b = Path::new("tests/inputs/a/b");
+    let expected = format!("{}\n{}\n", b.join("b.csv").display(), b.join("c/c.mp3").display());
+
+    // Normally the regex sees the start path too
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs", "--sort", "-regex", r"a/b/.*\.[a-z0-9]+"])
+        .assert()
+        .success()
+        .stdout("");
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs", "--sort", "-regex", r"tests/inputs/a/b/.*\.[a-z0-9]+"])
+        .assert()
+        .success()
+        .stdout(expected.clone());
+
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs", "--sort", "--regex-relative", "-regex", r"a/b/.*\.[a-z0-9]+"])
+        .assert()
+        .success()
+        .stdout(expected);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn ls_numeric_ids() -> Result<()> {
+    let run = |numeric: bool| -> Result<String> {
+        let mut cmd = Command::cargo_bin(PRG)?;
+        cmd.args(["tests/inputs/a", "-name", "a.txt", "-ls"]);
+        if numeric {
+            cmd.arg("--numeric-ids");
+        }
+        let output = cmd.output()?;
+        assert!(output.status.success());
+        Ok(String::from_utf8(output.stdout)?)
+    };
+    let named = run(false)?;
+    let numeric = run(true)?;
+    let named: Vec<&str> = named.split_whitespace().collect();
+    let numeric: Vec<&str> = numeric.split_whitespace().collect();
+
+    // inode blocks mode links owner group size month day time-or-year path
+    assert_eq!(named.len(), 11);
+    assert_eq!(named[6], "2");
+    assert_eq!(named[10], Path::new("tests/inputs/a").join("a.txt").to_string_lossy());
+    assert!(numeric[4].parse::<u32>().is_ok() && numeric[5].parse::<u32>().is_ok());
+    assert_eq!([&named[..4], &named[6..]], [&numeric[..4], &numeric[6..]]);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn quit_stops_every_start_path() -> Result<()> {
+    let paths = |names: &[&str]| -> String {
+        names.iter().map(|name| format!("{}\n", Path::new("tests/inputs").join(name).display())).collect()
+    };
+    let args = ["--order", "name", "tests/inputs/d", "tests/inputs/a", "tests/inputs/f",
+        "-type", "f", "-print", "-name", "a.txt", "-quit"];
+
+    // Everything before the first a.txt is printed, and nothing after it, here or in f
+    Command::cargo_bin(PRG)?
+        .args(args)
+        .assert()
+        .success()
+        .stdout(paths(&["d/d.tsv", "d/d.txt", "d/e/e.mp3", "a/a.txt"]));
+
+    // Taking turns between start paths reaches it before any file in d (d/b.csv is a link)
+    Command::cargo_bin(PRG)?
+        .args(args)
+        .arg("--interleave")
+        .assert()
+        .success()
+        .stdout(paths(&["a/a.txt"]));
+
+    // Without an action the quitting entry isn't printed, as the -print would come after
+    Command::cargo_bin(PRG)?
+        .args(["--order", "name", "tests/inputs/d", "tests/inputs/a", "-type", "f", "-name", "d.txt", "-quit"])
+        .assert()
+        .success()
+        .stdout("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn unique_inodes_skips_hard_links() -> Result<()> {
+    let dir = tempfile::TempDir::new()?;
+    fs::write(dir.path().join("a.txt"), "same")?;
+    fs::hard_link(dir.path().join("a.txt"), dir.path().join("b.txt"))?;
+    fs::write(dir.path().join("c.txt"), "same")?;
+
+    let cmd = Command::cargo_bin(PRG)?
+        .arg(dir.path())
+        .args(["--unique-inodes", "--order", "name", "-type", "f"])
+        .assert()
+        .success();
+    let stdout = String::from_utf8(cmd.get_output().stdout.clone())?;
+    let expected = format!("{0}/a.txt\n{0}/c.txt\n", dir.path().display());
+    assert_eq!(stdout, expected);
+
+    // What actions print is skipped the same way
+    Command::cargo_bin(PRG)?
+        .arg(dir.path())
+        .args(["--unique-inodes", "--order", "name", "-type", "f", "-printf", "%f\\n", "-print"])
+        .assert()
+        .success()
+        .stdout(format!("a.txt\n{0}/a.txt\nc.txt\n{0}/c.txt\n", dir.path().display()));
+
+    // Without it every link is printed
+    let cmd = Command::cargo_bin(PRG)?
+        .arg(dir.path())
+        .args(["--order", "name", "-type", "f"])
+        .assert()
+        .success();
+    assert_eq!(String::from_utf8(cmd.get_output().stdout.clone())?.lines().count(), 3);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn completions() -> Result<()> {
+    for shell in ["bash", "zsh", "fish"] {
+        let cmd = Command::cargo_bin(PRG)?
+            .args(["--completions", shell])
+            .assert()
+            .success();
+        let stdout = String::from_utf8(cmd.get_output().stdout.clone())?;
+        assert!(stdout.contains("name") && stdout.contains("type"), "{}", shell);
+    }
+
+    Command::cargo_bin(PRG)?
+        .args(["--completions", "tcsh"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("unsupported shell for --completions: tcsh"));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn trailing_slash_start_path_must_be_a_directory() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .arg("tests/inputs/a/a.txt/")
+        .assert()
+        .failure()
+        .stdout("")
+        .stderr(predicate::str::contains("tests/inputs/a/a.txt/: Not a directory"));
+
+    // The other start paths are still searched
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs/a/a.txt/", "tests/inputs/a/a.txt"])
+        .assert()
+        .failure()
+        .stdout("tests/inputs/a/a.txt\n");
+
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs/a/", "-name", "a.txt"])
+        .assert()
+        .success()
+        .stdout("tests/inputs/a/a.txt\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn absolute_paths() -> Result<()> {
+    let inputs = std::env::current_dir()?.join("tests/inputs");
+    for start in ["a", "./a", "a/"] {
+        let cmd = Command::cargo_bin(PRG)?
+            .current_dir("tests/inputs")
+            .args([start, "--absolute-paths", "-name", "a.txt"])
+            .assert()
+            .success();
+        let stdout = String::from_utf8(cmd.get_output().stdout.clone())?;
+        assert_eq!(stdout, format!("{}\n", inputs.join("a/a.txt").display()), "{}", start);
+    }
+
+    // Symlinks are printed as found unless -L is following them
+    let cmd = Command::cargo_bin(PRG)?
+        .current_dir("tests/inputs")
+        .args(["d", "--absolute-paths", "-name", "b.csv"])
+        .assert()
+        .success();
+    let stdout = String::from_utf8(cmd.get_output().stdout.clone())?;
+    assert_eq!(stdout, format!("{}\n", inputs.join("d/b.csv").display()));
+
+    let cmd = Command::cargo_bin(PRG)?
+        .current_dir("tests/inputs")
+        .args(["-L", "d", "--absolute-paths", "-name", "b.csv"])
+        .assert()
+        .success();
+    let stdout = String::from_utf8(cmd.get_output().stdout.clone())?;
+    assert_eq!(stdout, format!("{}\n", inputs.join("a/b/b.csv").canonicalize()?.display()));
+
+    // -print and %p print the same absolute path, while %P stays relative
+    let cmd = Command::cargo_bin(PRG)?
+        .current_dir("tests/inputs")
+        .args(["a", "--absolute-paths", "-name", "a.txt", "-print", "-printf", "%p|%P\\n"])
+        .assert()
+        .success();
+    let stdout = String::from_utf8(cmd.get_output().stdout.clone())?;
+    let absolute = inputs.join("a/a.txt");
+    assert_eq!(stdout, format!("{}\n{}|a.txt\n", absolute.display(), absolute.display()));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn regextype() -> Result<()> {
+    for (dialect, pattern) in [
+        ("emacs", r".*/[ab]\.\(csv\|txt\)"),
+        ("posix-basic", r".*/[ab]\.\(csv\|txt\)"),
+        ("posix-extended", r".*/[ab]\.(csv|txt)"),
+    ] {
+        let cmd = Command::cargo_bin(PRG)?
+            .current_dir("tests/inputs")
+            .args(["a", "-regextype", dialect, "-regex", pattern, "-type", "f"])
+            .assert()
+            .success();
+        let mut lines: Vec<_> = std::str::from_utf8(&cmd.get_output().stdout)?.lines().map(String::from).collect();
+        lines.sort();
+        assert_eq!(lines, ["a/a.txt", "a/b/b.csv"], "{}", dialect);
+    }
+
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs", "-regextype", "perl", "-regex", "x"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("invalid argument to -regextype: perl"));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn option_names_as_predicate_arguments() -> Result<()> {
+    let dir = tempfile::TempDir::new()?;
+    fs::write(dir.path().join("-mount"), "")?;
+    let root = dir.path().to_string_lossy();
+    Command::cargo_bin(PRG)?
+        .args([&root, "-name", "-mount"])
+        .assert()
+        .success()
+        .stdout(format!("{}/-mount\n", root));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn from_stdin0() -> Result<()> {
+    // Only the listed paths are tested, without descending into directories
+    Command::cargo_bin(PRG)?
+        .args(["--from-stdin0", "-type", "f"])
+        .write_stdin("tests/inputs/a/a.txt\0tests/inputs/d\0tests/inputs/d/d.txt\0tests/inputs/missing\0")
+        .assert()
+        .failure()
+        .stdout("tests/inputs/a/a.txt\ntests/inputs/d/d.txt\n")
+        .stderr(predicate::str::contains("tests/inputs/missing"));
+
+    Command::cargo_bin(PRG)?
+        .args(["--from-stdin0", "-name", "*.csv"])
+        .write_stdin("tests/inputs/g.csv\0tests/inputs/f/f.txt")
+        .assert()
+        .success()
+        .stdout("tests/inputs/g.csv\n");
+
+    Command::cargo_bin(PRG)?
+        .args(["--from-stdin0", "tests/inputs"])
+        .write_stdin("")
+        .assert()
+        .failure();
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn false_expression_skips_walk() -> Result<()> {
+    for expr in [&["-false"][..], &["-not", "-true"], &["-name", "*.txt", "-false"], &["-false", "-o", "-not", "-true"]] {
+        Command::cargo_bin(PRG)?
+            .arg("tests/inputs")
+            .args(expr)
+            .assert()
+            .success()
+            .stdout("");
+    }
+
+    // Missing start paths are still reported
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs", "tests/inputs/missing", "-false"])
+        .assert()
+        .failure()
+        .stdout("")
+        .stderr(predicate::str::contains("tests/inputs/missing"));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn size_in_blocks_warns() -> Result<()> {
+    // A count of blocks is easily mistaken for bytes
+    for size in ["-10", "-10b"] {
+        Command::cargo_bin(PRG)?
+            .args(["tests/inputs/a/a.txt", "-size", size])
+            .assert()
+            .success()
+            .stdout("tests/inputs/a/a.txt\n")
+            .stderr(format!("findr: warning: `-size {}' counts 512-byte blocks; use `-10c' for bytes\n", size));
+    }
+    // Bytes, other units and zero are unambiguous
+    for size in ["-10c", "-1k", "0"] {
+        Command::cargo_bin(PRG)?
+            .args(["tests/inputs/a/a.txt", "-size", size])
+            .assert()
+            .success()
+            .stderr("");
+    }
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs/a/a.txt", "-nowarn", "-size", "-10", "-name", ""])
+        .assert()
+        .success()
+        .stdout("")
+        .stderr("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn csv_quotes_paths() -> Result<()> {
+    let temp_dir = tempfile::TempDir::new()?;
+    fs::write(temp_dir.path().join("a,b.txt"), "hello")?;
+    let dir = temp_dir.path().display().to_string();
+    let output = Command::cargo_bin(PRG)?
+        .args([dir.as_str(), "--csv", "-type", "f"])
+        .output()?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 2, "{}", stdout);
+    assert_eq!(lines[0], "path,size,type,mtime,uid,gid,mode");
+    let expected = format!("\"{}/a,b.txt\",5,f,", dir);
+    assert!(lines[1].starts_with(&expected), "{}", lines[1]);
+    // The comma in the quoted path doesn't add a column
+    assert_eq!(lines[1][expected.len() - 1..].split(',').count(), 5, "{}", lines[1]);
+
+    // Actions print as they always do, without a header
+    Command::cargo_bin(PRG)?
+        .args([dir.as_str(), "--csv", "-type", "f", "-printf", "%f\\n"])
+        .assert()
+        .success()
+        .stdout("a,b.txt\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn implicit_print_applies_to_whole_expression() -> Result<()> {
+    let run = |expr: &[&str], expected: &str| -> Result<()> {
+        Command::cargo_bin(PRG)?
+            .args(["tests/inputs", "--sort"])
+            .args(expr)
+            .assert()
+            .success()
+            .stdout(expected.to_string());
+        Ok(())
+    };
+    let both = "tests/inputs/a/a.txt\ntests/inputs/d/d.txt\n";
+    run(&["-name", "a.txt", "-o", "-name", "d.txt"], both)?;
+    run(&["(", "-name", "a.txt", "-o", "-name", "d.txt", ")", "-print"], both)?;
+    // An explicit -print binds to the last term only, so it turns off the implicit one
+    run(&["-name", "a.txt", "-o", "-name", "d.txt", "-print"], "tests/inputs/d/d.txt\n")?;
+    // Constants take part like any other term
+    run(&["-name", "a.txt", "-o", "-false"], "tests/inputs/a/a.txt\n")?;
+    run(&["-false", "-o", "-name", "d.txt"], "tests/inputs/d/d.txt\n")?;
+    run(&["-name", "*.txt", "-a", "-true", "-o", "-false"], "tests/inputs/a/a.txt\ntests/inputs/d/d.txt\ntests/inputs/f/f.txt\n")?;
+    // -prune is true, so the pruned directory itself is printed as well
+    run(
+        &["-path", "tests/inputs/d", "-prune", "-o", "-name", "*.txt"],
+        "tests/inputs/a/a.txt\ntests/inputs/d\ntests/inputs/f/f.txt\n",
+    )?;
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn name_matches_start_path_with_trailing_slash() -> Result<()> {
+    for start in ["tests/inputs/a/", "tests/inputs/a//"] {
+        Command::cargo_bin(PRG)?
+            .args([start, "-name", "a"])
+            .assert()
+            .success()
+            .stdout(format!("{}\n", start));
+    }
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs/a/.", "-maxdepth", "0", "-name", "."])
+        .assert()
+        .success()
+        .stdout("tests/inputs/a/.\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn size_suffix_in_other_case_warns() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs/a/a.txt", "-size", "-2K"])
+        .assert()
+        .success()
+        .stdout("tests/inputs/a/a.txt\n")
+        .stderr("findr: warning: `-size -2K' has a suffix GNU find doesn't accept; its spelling is `k'\n");
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs/a/a.txt", "-nowarn", "-size", "-2m"])
+        .assert()
+        .success()
+        .stdout("tests/inputs/a/a.txt\n")
+        .stderr("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn path_stars_cross_directories() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs", "--sort", "-path", "*a*c*"])
+        .assert()
+        .success()
+        .stdout("tests/inputs/a/b/b.csv\ntests/inputs/a/b/c\ntests/inputs/a/b/c/c.mp3\n");
+    // A double star is a single one, so this needs a directory between
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs", "--sort", "-path", "tests/**/*.mp3"])
+        .assert()
+        .success()
+        .stdout("tests/inputs/a/b/c/c.mp3\ntests/inputs/d/e/e.mp3\n");
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs", "-path", "tests/inputs/**/g.csv"])
+        .assert()
+        .success()
+        .stdout("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn path_keeps_leading_dot_slash() -> Result<()> {
+    // As in GNU find, the ./ from a start path of . is part of what -path sees
+    Command::cargo_bin(PRG)?
+        .current_dir("tests/inputs")
+        .args([".", "-path", "a/b/*.csv"])
+        .assert()
+        .success()
+        .stdout("");
+    Command::cargo_bin(PRG)?
+        .current_dir("tests/inputs")
+        .args([".", "-path", "./a/b/*.csv"])
+        .assert()
+        .success()
+        .stdout("./a/b/b.csv\n");
+    Command::cargo_bin(PRG)?
+        .current_dir("tests/inputs")
+        .args([".", "--strip-dot-slash", "-path", "a/b/*.csv"])
+        .assert()
+        .success()
+        .stdout("./a/b/b.csv\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn printf_adds_no_newline() -> Result<()> {
+    // Unlike -print, -printf prints exactly its format, so paths run together
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs/f", "-printf", "%p"])
+        .assert()
+        .success()
+        .stdout("tests/inputs/ftests/inputs/f/f.txt");
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs/f", "-printf", "%p\\n"])
+        .assert()
+        .success()
+        .stdout("tests/inputs/f\ntests/inputs/f/f.txt\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn printf_sparseness_and_human_sizes() -> Result<()> {
+    // %S is GNU find's sparseness, whatever the file system allocated for the file
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs/f/f.txt", "-printf", "%S\\n"])
+        .assert()
+        .success()
+        .stdout(predicate::str::is_match(r"^([0-9.]+(e[+-][0-9]+)?|inf)\n$")?)
+        .stderr("");
+
+    let dir = tempfile::TempDir::new()?;
+    std::fs::write(dir.path().join("big"), vec![b'x'; 1536])?;
+    Command::cargo_bin(PRG)?
+        .args([dir.path().join("big").to_str().unwrap(), "--human-sizes", "-printf", "%s\\n"])
+        .assert()
+        .success()
+        .stdout("1.5K\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn exec_substitutes_within_arguments() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs/a/a.txt", "-exec", "echo", "prefix-{}", "{}.bak", ";"])
+        .assert()
+        .success()
+        .stdout("prefix-tests/inputs/a/a.txt tests/inputs/a/a.txt.bak\n");
+    // The command's status decides what follows it, and its output keeps its place
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs/f", "-print", "-exec", "test", "-d", "{}", ";", "-printf", "dir:%f\\n"])
+        .assert()
+        .success()
+        .stdout("tests/inputs/f\ndir:f\ntests/inputs/f/f.txt\n");
+    // Each word of the command is an argument as given, spaces and quotes included
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs/a/a.txt", "-exec", "sh", "-c", "echo \"got:$1\"", "_", "{}", ";"])
+        .assert()
+        .success()
+        .stdout("got:tests/inputs/a/a.txt\n");
+    // and what the command prints goes to --output-file in order with the rest
+    let dir = tempfile::TempDir::new()?;
+    let out = dir.path().join("out.txt");
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs/f", "--output-file", &out.to_string_lossy(), "-print", "-exec", "echo", "ran", ";"])
+        .assert()
+        .success()
+        .stdout("");
+    assert_eq!(fs::read_to_string(&out)?, "tests/inputs/f\nran\ntests/inputs/f/f.txt\nran\n");
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs/f", "-exec", "echo", "{}"])
+        .assert()
+        .failure()
+        .stderr("findr: missing argument to `-exec'\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn delete_reports_summary() -> Result<()> {
+    let dir = tempfile::TempDir::new()?;
+    let build = dir.path().join("build");
+    fs::create_dir_all(build.join("sub"))?;
+    for file in ["a.o", "b.o", "sub/c.o"] {
+        fs::write(build.join(file), "")?;
+    }
+    fs::write(dir.path().join("keep.txt"), "")?;
+
+    // Each directory's contents go first, so the directories are empty in time
+    Command::cargo_bin(PRG)?
+        .args([&build.to_string_lossy(), "-delete", "--verbose"])
+        .assert()
+        .success()
+        .stdout("")
+        .stderr("findr: deleted 3 files and 2 directories, 0 failed\n");
+    assert!(!build.exists());
+
+    // A directory that isn't empty is reported, counted and fails the run
+    Command::cargo_bin(PRG)?
+        .args([&dir.path().to_string_lossy(), "-type", "d", "-delete", "--verbose"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("findr: cannot delete "))
+        .stderr(predicate::str::ends_with("findr: deleted 0 files and 0 directories, 1 failed\n"));
+
+    // Without --verbose nothing is said
+    fs::create_dir(&build)?;
+    Command::cargo_bin(PRG)?
+        .args([&build.to_string_lossy(), "-delete"])
+        .assert()
+        .success()
+        .stderr("");
+    assert!(!build.exists() && dir.path().join("keep.txt").exists());
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn delete_refuses_prune() -> Result<()> {
+    let dir = tempfile::TempDir::new()?;
+    for sub in ["keep", "junk"] {
+        fs::create_dir(dir.path().join(sub))?;
+        fs::write(dir.path().join(sub).join("important"), "")?;
+    }
+
+    // -delete visits keep/important before keep, too late for -prune to skip it
+    Command::cargo_bin(PRG)?
+        .current_dir(dir.path())
+        .args([".", "-path", "./keep", "-prune", "-o", "-delete"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("use --exclude"));
+    assert!(dir.path().join("keep/important").exists() && dir.path().join("junk/important").exists());
+
+    // --exclude keeps the directory and everything in it
+    Command::cargo_bin(PRG)?
+        .current_dir(dir.path())
+        .args([".", "--exclude", "./keep", "-delete"])
+        .assert()
+        .success();
+    assert!(dir.path().join("keep/important").exists() && !dir.path().join("junk").exists());
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn non_utf8_names_print_as_they_are() -> Result<()> {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let dir = tempfile::TempDir::new()?;
+    let name = OsStr::from_bytes(b"caf\xe9.txt");
+    if fs::write(dir.path().join(name), "").is_err() {
+        // Some file systems only take UTF-8 names
+        return Ok(());
+    }
+    let root = dir.path().to_string_lossy();
+    let path = [root.as_bytes(), b"/caf\xe9.txt\n"].concat();
+    for args in [&["-type", "f"][..], &["-type", "f", "--sort"], &["-type", "f", "-print"]] {
+        Command::cargo_bin(PRG)?
+            .arg(&*root)
+            .args(args)
+            .assert()
+            .success()
+            .stdout(path.clone());
+    }
+    Command::cargo_bin(PRG)?
+        .args([&root, "-type", "f", "-printf", "%f|%P\\n"])
+        .assert()
+        .success()
+        .stdout(&b"caf\xe9.txt|caf\xe9.txt\n"[..]);
+
+    // --from-stdin0 takes such a path too
+    Command::cargo_bin(PRG)?
+        .args(["--from-stdin0", "-type", "f"])
+        .write_stdin([&path[..path.len() - 1], b"\0"].concat())
+        .assert()
+        .success()
+        .stdout(path);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn csv_keeps_non_utf8_names() -> Result<()> {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let dir = tempfile::TempDir::new()?;
+    let name = OsStr::from_bytes(b"caf\xe9,1.txt");
+    if fs::write(dir.path().join(name), "hi").is_err() {
+        // Some file systems only take UTF-8 names
+        return Ok(());
+    }
+    let root = dir.path().to_string_lossy();
+    let output = Command::cargo_bin(PRG)?
+        .args([&root, "--csv", "-type", "f"])
+        .output()?;
+    assert!(output.status.success());
+    // The name is written byte for byte, and quoted for its comma
+    let expected = [b"path,size,type,mtime,uid,gid,mode\n\"", root.as_bytes(), b"/caf\xe9,1.txt\",2,f,"].concat();
+    assert!(output.stdout.starts_with(&expected), "{}", String::from_utf8_lossy(&output.stdout));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn newermt_local_times_around_clock_changes() -> Result<()> {
+    use std::time::{Duration, SystemTime};
+
+    let dir = tempfile::TempDir::new()?;
+    let touch = |name: &str, seconds: u64| -> Result<()> {
+        let file = fs::File::create(dir.path().join(name))?;
+        file.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(seconds))?;
+        Ok(())
+    };
+    // 07:30:30 UTC on the day New York skips from 02:00 to 03:00, and 05:45 UTC on the
+    // day it repeats 01:00 to 02:00
+    touch("spring", 1_710_055_830)?;
+    touch("autumn", 1_730_612_700)?;
+    let root = dir.path().to_string_lossy();
+    let newer = |time: &str| -> Result<String> {
+        let output = Command::cargo_bin(PRG)?
+            .env("TZ", "America/New_York")
+            .args([&root, "-type", "f", "-newermt", time, "-printf", "%f\\n"])
+            .output()?;
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        let mut names: Vec<String> = String::from_utf8(output.stdout)?.lines().map(String::from).collect();
+        names.sort();
+        Ok(names.join(" "))
+    };
+
+    // 02:30 never happened that day, so it is taken as 03:30, 07:30 UTC
+    assert_eq!(newer("2024-03-10T02:30")?, "autumn spring");
+    assert_eq!(newer("2024-03-10T03:31")?, "autumn");
+    // 01:30 happened twice, and the first time, 05:30 UTC, is the one meant
+    assert_eq!(newer("2024-11-03T01:30")?, "autumn");
+    assert_eq!(newer("2024-11-03T01:46")?, "");
+    Ok(())
+}